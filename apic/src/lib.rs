@@ -5,7 +5,6 @@
  * Replaces the legacy 8259 PIC with modern APIC architecture.
  */
 
-#![feature(abi_x86_interrupt)]
 #![no_std]
 
 use core::sync::atomic::{AtomicU64, Ordering};
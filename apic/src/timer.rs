@@ -3,10 +3,12 @@
  *
  * Implements Local APIC timer for periodic interrupts and timekeeping.
  */
+use core::arch::naked_asm;
 use core::sync::atomic::{AtomicU64, Ordering};
 use crate::{lapic_reg, send_eoi};
+use idt::TrapFrame;
 use task;
-use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::VirtAddr;
 use task::scheduler::TIME_SLICE_TICKS;
 
 /* Timer configuration constants */
@@ -14,41 +16,80 @@ pub const TIMER_VECTOR: u8 = 0x31;
 pub const TIMER_DIVIDE_CONFIG: u32 = 0x3; /* Divide by 16 */
 pub const TIMER_INITIAL_COUNT: u32 = 100_000; /* Timer interval */
 
-/* Global tick counter */
-static mut TICKS: u64 = 0;
-
 /*
 	TICK_COUNT - Monotonic tick counter incremented on every timer interrupt
 
-	Used to gate scheduling to once per TIME_SLICE_TICKS ticks
+	Used to gate scheduling to once per TIME_SLICE_TICKS ticks and to
+	drive the kernel timer queue (task::timer)
 	Wrapping add is intentional - overflow is harmless here
  */
 static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
 
 
 /*
- * timer_interrupt - Timer interrupt handler
- * @_stack_frame: Interrupt stack frame (unused)
+ * timer_entry - Timer interrupt entry stub
  *
- * Increments the global tick counter and sends EOI to LAPIC.
+ * Saves every general purpose register as an idt::TrapFrame on top of
+ * the CPU's interrupt frame, so the return-to-user hook can rewrite the
+ * state a preempted Ring 3 task resumes with (signal delivery).
  */
-extern "x86-interrupt" fn timer_interrupt(_stack_frame: InterruptStackFrame) {
-	unsafe {
-		TICKS += 1;
-		/* Signal end of interrupt to LAPIC */
-		send_eoi();
-	}
-	task::schedule();
+#[unsafe(naked)]
+unsafe extern "C" fn timer_entry() {
+	naked_asm!(
+		"push rax",
+		"push rcx",
+		"push rdx",
+		"push rsi",
+		"push rdi",
+		"push r8",
+		"push r9",
+		"push r10",
+		"push r11",
+		"push rbx",
+		"push rbp",
+		"push r12",
+		"push r13",
+		"push r14",
+		"push r15",
+
+		/* 5 CPU + 15 saved qwords keep RSP 16-byte aligned for the call */
+		"mov rdi, rsp",
+		"call {handler}",
+
+		"pop r15",
+		"pop r14",
+		"pop r13",
+		"pop r12",
+		"pop rbp",
+		"pop rbx",
+		"pop r11",
+		"pop r10",
+		"pop r9",
+		"pop r8",
+		"pop rdi",
+		"pop rsi",
+		"pop rdx",
+		"pop rcx",
+		"pop rax",
+		"iretq",
+		handler = sym timer_interrupt_handler,
+	)
 }
 
 /*
  * timer_interrupt_handler - Timer interrupt handler with task preemption
- * @_stack_frame: Interrupt stack frame (unused)
+ * @frame: Registers saved by timer_entry
  *
- * Preempts the current task and sends EOI to LAPIC.
+ * Fires expired kernel timers, preempts the current task, sends EOI to
+ * LAPIC and runs the return-to-user work if the interrupt was taken in
+ * Ring 3.
  */
-pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+extern "C" fn timer_interrupt_handler(frame: &mut TrapFrame) {
 	let ticks = TICK_COUNT.fetch_add(1,Ordering::Relaxed)+1;
+	idt::count_interrupt(TIMER_VECTOR);
+
+	/* Wake sleepers whose deadline has passed */
+	task::timer::tick(ticks);
 
 	if ticks % TIME_SLICE_TICKS == 0 {
 		task::schedule();
@@ -58,6 +99,7 @@ pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptSta
 	unsafe {
 		send_eoi();
 	}
+	idt::return_to_user(frame);
 }
 
 /*
@@ -66,7 +108,10 @@ pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptSta
  * Must be called before IDT is loaded.
  */
 pub unsafe fn register_handler() {
-	idt::register_interrupt_handler(TIMER_VECTOR, timer_interrupt_handler);
+	unsafe {
+		idt::register_raw_handler(TIMER_VECTOR, VirtAddr::new(timer_entry as *const () as u64));
+	}
+	idt::name_interrupt(TIMER_VECTOR, "lapic-timer");
 }
 
 /*
@@ -95,5 +140,5 @@ pub unsafe fn init_hardware() {
  * Returns the number of timer ticks since boot.
  */
 pub fn ticks() -> u64 {
-	unsafe { TICKS }
+	TICK_COUNT.load(Ordering::Relaxed)
 }
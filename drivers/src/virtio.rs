@@ -106,6 +106,7 @@ const VIRTIO_BLK_VECTOR: u8 = 34;
 extern "x86-interrupt" fn virtio_blk_interrupt_handler(
 	_frame: InterruptStackFrame,
 ) {
+	idt::count_interrupt(VIRTIO_BLK_VECTOR);
 	let isr_addr = ISR_CFG_ADDR.load(Ordering::Relaxed);
 	if isr_addr != 0 {
 		/* Reading ISR status acknowledges the interrupt */
@@ -143,6 +144,7 @@ pub fn register_interrupt() {
 				virtio_blk_interrupt_handler,
 			);
 		}
+		idt::name_interrupt(VIRTIO_BLK_VECTOR, "virtio-blk");
 		hal::serial_println!(
 			"VirtIO: IRQ {} → vector {}, interrupt registered",
 			irq, VIRTIO_BLK_VECTOR,
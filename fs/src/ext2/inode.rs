@@ -23,6 +23,10 @@ pub struct Inode {
 	pub mode:        u16,
 	pub links_count: u16,  /* must be ≥1 for allocated inodes (e2fsck) */
 	pub size:        u32,
+	pub atime:       u32,  /* Unix seconds of last access */
+	pub ctime:       u32,  /* Unix seconds of last inode change */
+	pub mtime:       u32,  /* Unix seconds of last data change */
+	pub dtime:       u32,  /* Unix seconds of deletion, 0 while in use */
 	pub blocks:      u32,  /* 512-byte units */
 	pub block:       [u32; 15],
 	pub ino:         u32,
@@ -33,6 +37,37 @@ impl Inode {
 	pub fn is_dir(&self)  -> bool { self.mode & 0xF000 == EXT2_S_IFDIR }
 	pub fn is_file(&self) -> bool { self.mode & 0xF000 == EXT2_S_IFREG }
 	pub fn size(&self)    -> usize { self.size as usize }
+
+	/* touch_modified - Record a data change: mtime and ctime become now */
+	pub fn touch_modified(&mut self) {
+		let t = now();
+		self.mtime = t;
+		self.ctime = t;
+	}
+
+	/*
+	 * touch_accessed - Record a read, relatime style.
+	 *
+	 * atime only moves if it is older than mtime/ctime or a day old, so
+	 * most reads do not cost an inode write.
+	 *
+	 * Returns true if the inode needs to be written back.
+	 */
+	pub fn touch_accessed(&mut self) -> bool {
+		let t = now();
+		if self.atime > self.mtime && self.atime > self.ctime && t < self.atime.saturating_add(86_400) {
+			return false;
+		}
+		self.atime = t;
+		true
+	}
+}
+
+/*
+ * now - Current wall-clock time as an ext2 timestamp (Unix seconds).
+ */
+pub fn now() -> u32 {
+	hal::clock::unix_time() as u32
 }
 
 /* ------------------------------------------------------------------ */
@@ -101,6 +136,10 @@ impl Inode {
 		let links_count = u16::from_le_bytes([r[26], r[27]]);
 		let size        = u32::from_le_bytes([r[4],  r[5],  r[6],  r[7]]);
 		let blocks      = u32::from_le_bytes([r[28], r[29], r[30], r[31]]);
+		let atime       = u32::from_le_bytes([r[8],  r[9],  r[10], r[11]]);
+		let ctime       = u32::from_le_bytes([r[12], r[13], r[14], r[15]]);
+		let mtime       = u32::from_le_bytes([r[16], r[17], r[18], r[19]]);
+		let dtime       = u32::from_le_bytes([r[20], r[21], r[22], r[23]]);
 
 		let mut block = [0u32; 15];
 		for i in 0..15 {
@@ -108,7 +147,7 @@ impl Inode {
 			block[i] = u32::from_le_bytes([r[base], r[base+1], r[base+2], r[base+3]]);
 		}
 
-		Some(Inode { mode, links_count, size, blocks, block, ino })
+		Some(Inode { mode, links_count, size, atime, ctime, mtime, dtime, blocks, block, ino })
 	}
 
 	/*
@@ -133,6 +172,10 @@ impl Inode {
 		dst[0..2].copy_from_slice(&self.mode.to_le_bytes());
 		dst[26..28].copy_from_slice(&self.links_count.to_le_bytes());
 		dst[4..8].copy_from_slice(&self.size.to_le_bytes());
+		dst[8..12].copy_from_slice(&self.atime.to_le_bytes());
+		dst[12..16].copy_from_slice(&self.ctime.to_le_bytes());
+		dst[16..20].copy_from_slice(&self.mtime.to_le_bytes());
+		dst[20..24].copy_from_slice(&self.dtime.to_le_bytes());
 		dst[28..32].copy_from_slice(&self.blocks.to_le_bytes());
 		for i in 0..15 {
 			let base = 40 + i * 4;
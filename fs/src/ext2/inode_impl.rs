@@ -71,7 +71,7 @@ impl INode for Ext2FileINode {
 		if buf.is_empty() { return 0; }
 		let st  = self.state.lock();
 		let dev = Arc::clone(&st.dev);
-		let inode = match Inode::read(dev.as_ref(), &st.sb, &st.bgdt, self.ino) {
+		let mut inode = match Inode::read(dev.as_ref(), &st.sb, &st.bgdt, self.ino) {
 			Some(i) => i, None => return 0,
 		};
 		let file_size = inode.size();
@@ -93,6 +93,9 @@ impl INode for Ext2FileINode {
 			buf[done..done + avail].copy_from_slice(&block[blk_off..blk_off + avail]);
 			done += avail;
 		}
+		if done > 0 && inode.touch_accessed() {
+			inode.write(dev.as_ref(), &st.sb, &st.bgdt);
+		}
 		done
 	}
 
@@ -135,6 +138,7 @@ impl INode for Ext2FileINode {
 
 		let new_end = offset + done;
 		if new_end > inode.size() { inode.size = new_end as u32; }
+		if done > 0 { inode.touch_modified(); }
 		inode.write(dev.as_ref(), &e.sb, &e.bgdt);
 		done
 	}
@@ -163,6 +167,7 @@ impl INode for Ext2DirINode {
 	fn read(&self, _offset: usize, _buf: &mut [u8]) -> usize { 0 }
 	fn write(&self, _offset: usize, _buf: &[u8]) -> usize { 0 }
 	fn metadata(&self) -> FileType { FileType::Directory }
+	fn fs_type(&self) -> &'static str { "ext2" }
 
 	fn lookup(&self, name: &str) -> Option<Arc<dyn INode>> {
 		let st  = self.state.lock();
@@ -221,10 +226,15 @@ impl INode for Ext2DirINode {
 			dev.write_block(sec + s as u64, &buf);
 		}
 
+		let now = super::inode::now();
 		let mut child = Inode {
 			mode:        super::inode::EXT2_S_IFDIR | 0o755,
 			links_count: 2,   /* parent entry + '.' in self */
 			size:        bsz as u32,
+			atime:       now,
+			ctime:       now,
+			mtime:       now,
+			dtime:       0,
 			blocks:      spb as u32,
 			block:       [0u32; 15],
 			ino:         child_ino,
@@ -238,6 +248,8 @@ impl INode for Ext2DirINode {
 			dev.as_ref(), &mut e.sb, &mut e.bgdt,
 			&mut parent_inode, name, child_ino, dir::EXT2_FT_DIR,
 		);
+		parent_inode.touch_modified();
+		parent_inode.write(dev.as_ref(), &e.sb, &e.bgdt);
 
 		let g = e.sb.inode_block_group(self.ino) as usize;
 		e.bgdt.get_mut(g).used_dirs += 1;
@@ -254,10 +266,15 @@ impl INode for Ext2DirINode {
 		let child_ino = ext2_alloc::alloc_inode(dev.as_ref(), &mut e.sb, &mut e.bgdt)
 			.ok_or("no free inodes")?;
 
+		let now = super::inode::now();
 		let child = Inode {
 			mode:        super::inode::EXT2_S_IFREG | 0o644,
 			links_count: 1,
 			size:        0,
+			atime:       now,
+			ctime:       now,
+			mtime:       now,
+			dtime:       0,
 			blocks:      0,
 			block:       [0u32; 15],
 			ino:         child_ino,
@@ -270,6 +287,8 @@ impl INode for Ext2DirINode {
 			dev.as_ref(), &mut e.sb, &mut e.bgdt,
 			&mut parent_inode, name, child_ino, dir::EXT2_FT_REG_FILE,
 		);
+		parent_inode.touch_modified();
+		parent_inode.write(dev.as_ref(), &e.sb, &e.bgdt);
 
 		let state = Arc::clone(&self.state);
 		Ok(Arc::new(Ext2FileINode { ino: child_ino, state }))
@@ -284,11 +303,11 @@ impl INode for Ext2DirINode {
 		let dev     = Arc::clone(&st.dev);
 		let e       = &mut *st;
 
-		let dir_inode = Inode::read(dev.as_ref(), &e.sb, &e.bgdt, self.ino)
+		let mut dir_inode = Inode::read(dev.as_ref(), &e.sb, &e.bgdt, self.ino)
 			.ok_or("dir inode read failed")?;
 		let child_ino = dir::lookup_in_dir(dev.as_ref(), &e.sb, &e.bgdt, &dir_inode, name)
 			.ok_or("not found")?;
-		let child_inode = Inode::read(dev.as_ref(), &e.sb, &e.bgdt, child_ino)
+		let mut child_inode = Inode::read(dev.as_ref(), &e.sb, &e.bgdt, child_ino)
 			.ok_or("child inode read failed")?;
 
 		let bsz      = e.sb.block_size();
@@ -298,11 +317,17 @@ impl INode for Ext2DirINode {
 				ext2_alloc::free_block(dev.as_ref(), &mut e.sb, &mut e.bgdt, phys);
 			}
 		}
+		/* e2fsck expects a deletion time on freed inodes */
+		child_inode.links_count = 0;
+		child_inode.dtime = super::inode::now();
+		child_inode.write(dev.as_ref(), &e.sb, &e.bgdt);
 		ext2_alloc::free_inode(dev.as_ref(), &mut e.sb, &mut e.bgdt, child_ino);
 
 		if !dir::remove_entry(dev.as_ref(), &e.sb, &e.bgdt, &dir_inode, name) {
 			return Err("entry not found");
 		}
+		dir_inode.touch_modified();
+		dir_inode.write(dev.as_ref(), &e.sb, &e.bgdt);
 		Ok(())
 	}
 }
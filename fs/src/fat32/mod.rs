@@ -37,14 +37,19 @@ const ATTR_LFN: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID
 /*  Timestamps                                                          */
 /* ------------------------------------------------------------------ */
 
+/*
+ * fat32_timestamp - Current wall-clock time in FAT format
+ *
+ * FAT stores local time with 2-second resolution and years 1980..=2107;
+ * the RTC runs in UTC, which is what Linux assumes without tz=.
+ *
+ * Return: (time word, date word)
+ */
 fn fat32_timestamp() -> (u16, u16) {
-	let ticks = apic::timer::ticks();
-	let secs = ticks / 625;
-	let hours = (secs / 3600) % 24;
-	let minutes = (secs / 60) % 60;
-	let two_secs = (secs % 60) / 2;
-	let time_word = ((hours << 11) | (minutes << 5) | two_secs) as u16;
-	let date_word: u16 = (0 << 9) | (1 << 5) | 1;
+	let now = hal::rtc::DateTime::from_unix(hal::clock::unix_time());
+	let year = now.year.clamp(1980, 2107) - 1980;
+	let time_word = ((now.hour as u16) << 11) | ((now.minute as u16) << 5) | (now.second as u16 / 2);
+	let date_word = (year << 9) | ((now.month as u16) << 5) | now.day as u16;
 	(time_word, date_word)
 }
 
@@ -322,17 +327,21 @@ fn file_write(bpb: &Bpb, first_cluster: u32, old_size: u32, offset: usize, data:
 			file_pos += SECTOR_SIZE;
 		}
 	}
+	/* Update size, modification time and access date in the dir entry */
 	let new_size = core::cmp::max(old_size as usize, needed_size) as u32;
-	if new_size != old_size {
-		let mut entry_sec_buf = [0u8; SECTOR_SIZE];
-		read_sector(entry_sector, &mut entry_sec_buf);
-		let size_bytes = new_size.to_le_bytes();
-		entry_sec_buf[entry_offset+28] = size_bytes[0];
-		entry_sec_buf[entry_offset+29] = size_bytes[1];
-		entry_sec_buf[entry_offset+30] = size_bytes[2];
-		entry_sec_buf[entry_offset+31] = size_bytes[3];
-		write_sector(entry_sector, &entry_sec_buf);
-	}
+	let mut entry_sec_buf = [0u8; SECTOR_SIZE];
+	read_sector(entry_sector, &mut entry_sec_buf);
+	let size_bytes = new_size.to_le_bytes();
+	entry_sec_buf[entry_offset+28] = size_bytes[0];
+	entry_sec_buf[entry_offset+29] = size_bytes[1];
+	entry_sec_buf[entry_offset+30] = size_bytes[2];
+	entry_sec_buf[entry_offset+31] = size_bytes[3];
+	let (time_word, date_word) = fat32_timestamp();
+	let t = time_word.to_le_bytes(); let d = date_word.to_le_bytes();
+	entry_sec_buf[entry_offset+18] = d[0]; entry_sec_buf[entry_offset+19] = d[1];
+	entry_sec_buf[entry_offset+22] = t[0]; entry_sec_buf[entry_offset+23] = t[1];
+	entry_sec_buf[entry_offset+24] = d[0]; entry_sec_buf[entry_offset+25] = d[1];
+	write_sector(entry_sector, &entry_sec_buf);
 	done
 }
 
@@ -407,6 +416,7 @@ fn create_dir_entry(bpb: &Bpb, dir_cluster: u32, name: &str, attr: u8, first_clu
 	let (time_word, date_word) = fat32_timestamp();
 	let t = time_word.to_le_bytes(); let d = date_word.to_le_bytes();
 	sfn_entry[14] = t[0]; sfn_entry[15] = t[1]; sfn_entry[16] = d[0]; sfn_entry[17] = d[1];
+	sfn_entry[18] = d[0]; sfn_entry[19] = d[1];
 	sfn_entry[22] = t[0]; sfn_entry[23] = t[1]; sfn_entry[24] = d[0]; sfn_entry[25] = d[1];
 	sfn_entry[20] = ((first_cluster >> 16) & 0xFF) as u8;
 	sfn_entry[21] = ((first_cluster >> 24) & 0xFF) as u8;
@@ -457,6 +467,7 @@ impl INode for FatDirINode {
 	fn read(&self, _offset: usize, _buf: &mut [u8]) -> usize { 0 }
 	fn write(&self, _offset: usize, _buf: &[u8]) -> usize { 0 }
 	fn metadata(&self) -> FileType { FileType::Directory }
+	fn fs_type(&self) -> &'static str { "vfat" }
 
 	fn lookup(&self, name: &str) -> Option<Arc<dyn INode>> {
 		let guard = FAT32.get()?.lock();
@@ -500,12 +511,16 @@ impl INode for FatDirINode {
 		let mut dot = [0u8; 32];
 		dot[0..8].copy_from_slice(b".       "); dot[8..11].copy_from_slice(b"   "); dot[11] = ATTR_DIRECTORY;
 		dot[14] = t[0]; dot[15] = t[1]; dot[16] = d[0]; dot[17] = d[1];
+		dot[18] = d[0]; dot[19] = d[1];
+		dot[22] = t[0]; dot[23] = t[1]; dot[24] = d[0]; dot[25] = d[1];
 		dot[20] = ((new_cluster >> 16) & 0xFF) as u8; dot[21] = ((new_cluster >> 24) & 0xFF) as u8;
 		dot[26] = (new_cluster & 0xFF) as u8; dot[27] = ((new_cluster >> 8) & 0xFF) as u8;
 		write_dir_entry(bpb, start_sector, 0, &dot);
 		let mut dotdot = [0u8; 32];
 		dotdot[0..8].copy_from_slice(b"..      "); dotdot[8..11].copy_from_slice(b"   "); dotdot[11] = ATTR_DIRECTORY;
 		dotdot[14] = t[0]; dotdot[15] = t[1]; dotdot[16] = d[0]; dotdot[17] = d[1];
+		dotdot[18] = d[0]; dotdot[19] = d[1];
+		dotdot[22] = t[0]; dotdot[23] = t[1]; dotdot[24] = d[0]; dotdot[25] = d[1];
 		dotdot[20] = ((self.cluster >> 16) & 0xFF) as u8; dotdot[21] = ((self.cluster >> 24) & 0xFF) as u8;
 		dotdot[26] = (self.cluster & 0xFF) as u8; dotdot[27] = ((self.cluster >> 8) & 0xFF) as u8;
 		write_dir_entry(bpb, start_sector, 32, &dotdot);
@@ -116,6 +116,36 @@ pub fn probe_and_mount(dev: Arc<dyn BlockDev>) -> Option<Arc<dyn INode>> {
 	None
 }
 
+/* ------------------------------------------------------------------ */
+/*  Block device registry                                              */
+/* ------------------------------------------------------------------ */
+
+static BLOCK_DEVS: Mutex<Vec<(&'static str, Arc<dyn BlockDev>)>> = Mutex::new(Vec::new());
+
+/*
+ * register_block_dev - Make a block device findable by its /dev path
+ * @path: Path of its device node, e.g. "/dev/sda"
+ * @dev: The device
+ *
+ * Lets callers that must not go through the VFS (swap) reach the device
+ * behind a node. A second registration under @path replaces the first.
+ */
+pub fn register_block_dev(path: &'static str, dev: Arc<dyn BlockDev>) {
+	let mut devs = BLOCK_DEVS.lock();
+	devs.retain(|(p, _)| *p != path);
+	devs.push((path, dev));
+}
+
+/*
+ * block_dev - Look up a registered block device
+ * @path: Absolute path of its device node
+ *
+ * Return: The device, or None if @path is not a block device node
+ */
+pub fn block_dev(path: &str) -> Option<Arc<dyn BlockDev>> {
+	BLOCK_DEVS.lock().iter().find(|(p, _)| *p == path).map(|(_, d)| Arc::clone(d))
+}
+
 /* ------------------------------------------------------------------ */
 /*  VirtioBlockDev                                                      */
 /* ------------------------------------------------------------------ */
@@ -0,0 +1,135 @@
+/*
+ * clock.rs - Monotonic and Wall-Clock Time
+ *
+ * The monotonic clock counts TSC cycles since init(), converted to
+ * nanoseconds with a frequency measured against PIT channel 2. The
+ * wall clock is the RTC time read once at init() plus the monotonic
+ * time elapsed since, so it advances smoothly at TSC resolution instead
+ * of in whole RTC seconds.
+ *
+ * Both clocks read 0 until init() has run.
+ */
+
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::io::{inb, outb};
+use crate::rtc;
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/* PIT input clock and the channel 2 ports used for calibration */
+const PIT_HZ: u64 = 1_193_182;
+const PIT_CH2_DATA: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_CH2_ONESHOT: u8 = 0xB0; /* Channel 2, lo/hi byte, mode 0 */
+const PORT_B: u16 = 0x61;
+const PORT_B_GATE: u8 = 0x01;     /* Channel 2 gate */
+const PORT_B_SPEAKER: u8 = 0x02;  /* Speaker data enable */
+const PORT_B_OUT: u8 = 0x20;      /* Channel 2 output */
+
+/* Calibration window: 10 ms of PIT input clocks */
+const CALIBRATION_COUNT: u16 = (PIT_HZ / 100) as u16;
+
+/* Used if calibration fails (e.g. no PIT); a typical QEMU TSC rate */
+const FALLBACK_TSC_HZ: u64 = 2_000_000_000;
+
+/* CPUID.80000007H:EDX bit 8 - TSC runs at a constant rate */
+const CPUID_EDX_INVARIANT_TSC: u32 = 1 << 8;
+
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+static TSC_BASE: AtomicU64 = AtomicU64::new(0);
+static BOOT_UNIX: AtomicU64 = AtomicU64::new(0);
+
+/*
+ * calibrate_tsc - Measure the TSC frequency with PIT channel 2
+ *
+ * Programs a 10 ms one-shot countdown with the speaker disconnected and
+ * counts TSC cycles until its output goes high, as Linux does.
+ *
+ * Return: TSC frequency in Hz, or None if the PIT never fired
+ */
+fn calibrate_tsc() -> Option<u64> {
+	unsafe {
+		let saved = inb(PORT_B);
+		outb(PORT_B, (saved & !PORT_B_SPEAKER) | PORT_B_GATE);
+		outb(PIT_COMMAND, PIT_CH2_ONESHOT);
+		outb(PIT_CH2_DATA, CALIBRATION_COUNT as u8);
+
+		/* Loading the high byte starts the count */
+		outb(PIT_CH2_DATA, (CALIBRATION_COUNT >> 8) as u8);
+		let start = _rdtsc();
+		let mut fired = false;
+		for _ in 0..10_000_000u32 {
+			if inb(PORT_B) & PORT_B_OUT != 0 {
+				fired = true;
+				break;
+			}
+		}
+		let end = _rdtsc();
+		outb(PORT_B, saved);
+
+		if !fired || end <= start {
+			return None;
+		}
+		Some((end - start) * PIT_HZ / CALIBRATION_COUNT as u64)
+	}
+}
+
+/*
+ * init - Calibrate the TSC and latch the wall-clock time
+ *
+ * Must run once on the BSP during early boot, before interrupts are
+ * enabled so the calibration loop is not stretched.
+ */
+pub fn init() {
+	let hz = calibrate_tsc().unwrap_or_else(|| {
+		crate::serial_println!("[CLOCK] TSC calibration failed, assuming {} Hz", FALLBACK_TSC_HZ);
+		FALLBACK_TSC_HZ
+	});
+	let invariant = __cpuid(0x8000_0007).edx & CPUID_EDX_INVARIANT_TSC != 0;
+	if !invariant {
+		crate::serial_println!("[CLOCK] TSC is not invariant; monotonic time may drift");
+	}
+
+	let now = rtc::read();
+	TSC_BASE.store(unsafe { _rdtsc() }, Ordering::Relaxed);
+	BOOT_UNIX.store(now.to_unix(), Ordering::Relaxed);
+	TSC_HZ.store(hz, Ordering::Release);
+
+	crate::serial_println!(
+		"[CLOCK] TSC {}.{:03} MHz, RTC {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+		hz / 1_000_000, hz / 1_000 % 1_000,
+		now.year, now.month, now.day, now.hour, now.minute, now.second
+	);
+}
+
+/*
+ * monotonic_ns - Nanoseconds since init()
+ *
+ * Never goes backwards and is unaffected by wall-clock changes.
+ */
+pub fn monotonic_ns() -> u64 {
+	let hz = TSC_HZ.load(Ordering::Acquire);
+	if hz == 0 {
+		return 0;
+	}
+	let cycles = unsafe { _rdtsc() }.saturating_sub(TSC_BASE.load(Ordering::Relaxed));
+	(cycles as u128 * NS_PER_SEC as u128 / hz as u128) as u64
+}
+
+/*
+ * realtime_ns - Nanoseconds since the Unix epoch (UTC)
+ */
+pub fn realtime_ns() -> u64 {
+	BOOT_UNIX.load(Ordering::Relaxed)
+		.saturating_mul(NS_PER_SEC)
+		.saturating_add(monotonic_ns())
+}
+
+/*
+ * unix_time - Whole seconds since the Unix epoch (UTC)
+ */
+pub fn unix_time() -> u64 {
+	realtime_ns() / NS_PER_SEC
+}
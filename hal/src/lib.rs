@@ -3,16 +3,21 @@
  *
  * Provides low-level hardware access and initialization including:
  * - CPU control (interrupts, halt)
+ * - Monotonic and wall-clock time (TSC, CMOS RTC)
  * - Port I/O operations
  * - Serial console
  * - CPU topology detection
+ * - Random numbers
  */
 
 #![allow(dead_code)]
 #![no_std]
 
+pub mod clock;
 pub mod cpu;
 pub mod io;
+pub mod random;
+pub mod rtc;
 pub mod serial;
 pub mod topology;
 
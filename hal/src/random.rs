@@ -0,0 +1,89 @@
+/*
+ * random.rs - Kernel Random Numbers
+ *
+ * Uses RDRAND when the CPU supports it and falls back to a TSC-seeded
+ * Xorshift64 generator otherwise. Good enough for AT_RANDOM and address
+ * randomisation; this is not a cryptographic RNG.
+ */
+
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Once;
+
+/* CPUID.01H:ECX bit 30 - RDRAND supported */
+const CPUID_ECX_RDRAND: u32 = 1 << 30;
+
+static HAS_RDRAND: Once<bool> = Once::new();
+static XORSHIFT_STATE: AtomicU64 = AtomicU64::new(0);
+
+/*
+ * rdrand64 - Read one value from the hardware RNG
+ *
+ * Return: Random value, or None if RDRAND kept failing
+ */
+fn rdrand64() -> Option<u64> {
+	for _ in 0..10 {
+		let val: u64;
+		let ok: u8;
+		unsafe {
+			core::arch::asm!(
+				"rdrand {val}",
+				"setc {ok}",
+				val = out(reg) val,
+				ok = out(reg_byte) ok,
+				options(nomem, nostack),
+			);
+		}
+		if ok != 0 {
+			return Some(val);
+		}
+	}
+	None
+}
+
+/*
+ * xorshift64 - Advance the fallback generator
+ *
+ * Seeds from the TSC on first use and mixes the TSC into every step,
+ * so successive boots and calls diverge.
+ */
+fn xorshift64() -> u64 {
+	let mut s = XORSHIFT_STATE.load(Ordering::Relaxed);
+	s ^= unsafe { _rdtsc() };
+	if s == 0 {
+		s = 0x9E37_79B9_7F4A_7C15;
+	}
+	s ^= s << 13;
+	s ^= s >> 7;
+	s ^= s << 17;
+	XORSHIFT_STATE.store(s, Ordering::Relaxed);
+	s
+}
+
+/*
+ * random_u64 - Get a random 64-bit value
+ */
+pub fn random_u64() -> u64 {
+	let has_rdrand = *HAS_RDRAND.call_once(|| {
+		let leaf = __cpuid(1);
+		leaf.ecx & CPUID_ECX_RDRAND != 0
+	});
+
+	if has_rdrand {
+		if let Some(v) = rdrand64() {
+			return v;
+		}
+	}
+	xorshift64()
+}
+
+/*
+ * fill_bytes - Fill a buffer with random bytes
+ * @buf: Destination buffer
+ */
+pub fn fill_bytes(buf: &mut [u8]) {
+	for chunk in buf.chunks_mut(8) {
+		let bytes = random_u64().to_ne_bytes();
+		chunk.copy_from_slice(&bytes[..chunk.len()]);
+	}
+}
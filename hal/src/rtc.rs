@@ -0,0 +1,199 @@
+/*
+ * rtc.rs - CMOS Real-Time Clock
+ *
+ * Reads the battery-backed wall clock through the CMOS index/data ports
+ * and converts between calendar dates and Unix time. The RTC is assumed
+ * to run in UTC, as on QEMU and most PC firmware set up by Linux.
+ *
+ * Only the current date and time are read; alarms and the periodic
+ * interrupt (IRQ 8) are left alone.
+ */
+
+use crate::io::{inb, outb};
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+/* CMOS registers */
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UIP: u8 = 0x80;    /* Update in progress */
+const STATUS_B_24H: u8 = 0x02;    /* Hours in 24-hour format */
+const STATUS_B_BINARY: u8 = 0x04; /* Values in binary, not BCD */
+const HOUR_PM: u8 = 0x80;         /* PM flag in 12-hour format */
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/*
+ * struct DateTime - A calendar date and time (UTC)
+ * @year: Full year, e.g. 2025
+ * @month: 1..=12
+ * @day: 1..=31
+ * @hour: 0..=23
+ * @minute: 0..=59
+ * @second: 0..=59
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+	pub year: u16,
+	pub month: u8,
+	pub day: u8,
+	pub hour: u8,
+	pub minute: u8,
+	pub second: u8,
+}
+
+impl DateTime {
+	/*
+	 * to_unix - Seconds since 1970-01-01 00:00:00 UTC
+	 *
+	 * Dates before the epoch clamp to 0.
+	 */
+	pub fn to_unix(&self) -> u64 {
+		let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+		if days < 0 {
+			return 0;
+		}
+		days as u64 * SECS_PER_DAY
+			+ self.hour as u64 * 3600
+			+ self.minute as u64 * 60
+			+ self.second as u64
+	}
+
+	/*
+	 * from_unix - Calendar date of a Unix timestamp
+	 * @secs: Seconds since the epoch
+	 */
+	pub fn from_unix(secs: u64) -> Self {
+		let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+		let rem = secs % SECS_PER_DAY;
+		Self {
+			year: year as u16,
+			month: month as u8,
+			day: day as u8,
+			hour: (rem / 3600) as u8,
+			minute: (rem / 60 % 60) as u8,
+			second: (rem % 60) as u8,
+		}
+	}
+}
+
+/*
+ * days_from_civil - Days since 1970-01-01 of a proleptic Gregorian date
+ *
+ * Howard Hinnant's algorithm; eras are 400-year cycles starting in March.
+ */
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+	let y = if m <= 2 { y - 1 } else { y };
+	let era = y.div_euclid(400);
+	let yoe = y - era * 400;
+	let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era * 146_097 + doe - 719_468
+}
+
+/*
+ * civil_from_days - Inverse of days_from_civil()
+ *
+ * Return: (year, month, day)
+ */
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+	let z = z + 719_468;
+	let era = z.div_euclid(146_097);
+	let doe = z - era * 146_097;
+	let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let d = doy - (153 * mp + 2) / 5 + 1;
+	let m = if mp < 10 { mp + 3 } else { mp - 9 };
+	(yoe + era * 400 + (m <= 2) as i64, m, d)
+}
+
+/*
+ * cmos_read - Read one CMOS register
+ * @reg: Register index
+ */
+fn cmos_read(reg: u8) -> u8 {
+	unsafe {
+		outb(CMOS_INDEX, reg);
+		inb(CMOS_DATA)
+	}
+}
+
+/*
+ * read_raw - Snapshot the time registers once the RTC is not updating
+ *
+ * Return: [sec, min, hour, day, month, year] as stored (maybe BCD)
+ */
+fn read_raw() -> [u8; 6] {
+	while cmos_read(REG_STATUS_A) & STATUS_A_UIP != 0 {
+		core::hint::spin_loop();
+	}
+	[
+		cmos_read(REG_SECONDS),
+		cmos_read(REG_MINUTES),
+		cmos_read(REG_HOURS),
+		cmos_read(REG_DAY),
+		cmos_read(REG_MONTH),
+		cmos_read(REG_YEAR),
+	]
+}
+
+fn bcd_to_bin(v: u8) -> u8 {
+	(v & 0x0F) + (v >> 4) * 10
+}
+
+/*
+ * read - Read the current date and time from the RTC
+ *
+ * Reads until two consecutive snapshots agree so an update cannot tear
+ * the result, then decodes BCD and 12-hour formats. Two-digit years
+ * are taken as 1970..=2069.
+ */
+pub fn read() -> DateTime {
+	let mut raw = read_raw();
+	loop {
+		let again = read_raw();
+		if again == raw {
+			break;
+		}
+		raw = again;
+	}
+
+	let status_b = cmos_read(REG_STATUS_B);
+	let [mut sec, mut min, hour_raw, mut day, mut month, mut year] = raw;
+	let pm = hour_raw & HOUR_PM != 0;
+	let mut hour = hour_raw & !HOUR_PM;
+
+	if status_b & STATUS_B_BINARY == 0 {
+		sec = bcd_to_bin(sec);
+		min = bcd_to_bin(min);
+		hour = bcd_to_bin(hour);
+		day = bcd_to_bin(day);
+		month = bcd_to_bin(month);
+		year = bcd_to_bin(year);
+	}
+	if status_b & STATUS_B_24H == 0 {
+		/* 12 AM is hour 0, 12 PM is hour 12 */
+		hour %= 12;
+		if pm {
+			hour += 12;
+		}
+	}
+
+	DateTime {
+		year: if year < 70 { 2000 + year as u16 } else { 1900 + year as u16 },
+		month,
+		day,
+		hour,
+		minute: min,
+		second: sec,
+	}
+}
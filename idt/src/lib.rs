@@ -3,15 +3,27 @@
  *
  * Configures CPU exception handlers and device interrupt handlers.
  * Provides a global IDT for handling faults, traps, and interrupts.
+ *
+ * Exceptions raised in Ring 3 are handed to the kernel's user fault
+ * hook, which terminates the offending task. Exceptions in Ring 0 are
+ * kernel bugs and still oops().
+ *
+ * Interrupt stubs that save a full TrapFrame call return_to_user()
+ * before their iretq, giving the kernel a chance to deliver pending
+ * signals to the interrupted Ring 3 task.
  */
 
 #![feature(abi_x86_interrupt)]
 #![no_std]
 
+use core::arch::naked_asm;
 use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU64, Ordering};
 use hal::serial_println;
 use lazy_static::lazy_static;
+use spin::{Mutex, Once};
 use util::panic::oops;
+use x86_64::VirtAddr;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 
 /*
@@ -33,6 +45,10 @@ lazy_static! {
 	static ref IDT: IdtWrapper = {
 		let mut idt = InterruptDescriptorTable::new();
 		idt.divide_error.set_handler_fn(divide_by_zero_handler);
+		idt.debug.set_handler_fn(debug_handler);
+		idt.breakpoint.set_handler_fn(breakpoint_handler);
+		idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+		idt.alignment_check.set_handler_fn(alignment_check_handler);
 		idt.page_fault.set_handler_fn(page_fault_handler);
 		idt.double_fault.set_handler_fn(double_fault_handler);
 		idt.general_protection_fault.set_handler_fn(gpf_handler);
@@ -47,32 +63,260 @@ lazy_static! {
 	};
 }
 
+/*
+ * PageFaultHook - Callback given a chance to resolve a page fault
+ *
+ * Receives the faulting address (CR2), the error code and the
+ * interrupted frame. Returns true if the mapping was fixed up and the
+ * faulting instruction can simply be retried.
+ */
+pub type PageFaultHook = fn(VirtAddr, PageFaultErrorCode, &InterruptStackFrame) -> bool;
+
+static PAGE_FAULT_HOOK: Once<PageFaultHook> = Once::new();
+
+/*
+ * register_page_fault_hook - Install the kernel's page fault resolver
+ * @hook: Resolver for demand paging / copy-on-write faults
+ *
+ * Lives here (rather than calling into the kernel directly) to avoid a
+ * circular dependency between idt and the kernel crate.
+ */
+pub fn register_page_fault_hook(hook: PageFaultHook) {
+	PAGE_FAULT_HOOK.call_once(|| hook);
+}
+
+/*
+ * enum UserFault - CPU exception raised by a Ring 3 task
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserFault {
+	DivideError,
+	Debug,
+	Breakpoint,
+	InvalidOpcode,
+	GeneralProtection,
+	PageFault,
+	AlignmentCheck,
+}
+
+/*
+ * UserFaultHook - Callback terminating a task that faulted in Ring 3
+ *
+ * Receives the exception, the interrupted frame, the error code (0 if
+ * the exception has none) and, for page faults, the faulting address.
+ * Never returns: the task is gone and another one is switched in.
+ */
+pub type UserFaultHook = fn(UserFault, &InterruptStackFrame, u64, Option<VirtAddr>) -> !;
+
+static USER_FAULT_HOOK: Once<UserFaultHook> = Once::new();
+
+/*
+ * register_user_fault_hook - Install the kernel's Ring 3 fault handler
+ * @hook: Called for every unresolved exception with CPL=3
+ */
+pub fn register_user_fault_hook(hook: UserFaultHook) {
+	USER_FAULT_HOOK.call_once(|| hook);
+}
+
+/*
+ * user_fault - Hand a Ring 3 exception to the kernel
+ * @kind: Exception type
+ * @stack: Interrupted frame
+ * @err: Error code (0 if none)
+ * @addr: Faulting address for page faults
+ *
+ * Returns only if the exception came from Ring 0 (or no hook is
+ * registered yet), in which case the caller oopses.
+ */
+fn user_fault(kind: UserFault, stack: &InterruptStackFrame, err: u64, addr: Option<VirtAddr>) {
+	if stack.code_segment.rpl() != x86_64::PrivilegeLevel::Ring3 {
+		return;
+	}
+	if let Some(hook) = USER_FAULT_HOOK.get() {
+		hook(kind, stack, err, addr);
+	}
+}
+
+/*
+ * struct TrapFrame - Register state saved by a full interrupt stub
+ *
+ * The general purpose registers pushed by the stub (lowest address
+ * first) followed by the frame pushed by the CPU. Written back on
+ * iretq, so changes made by the return-to-user hook take effect.
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrapFrame {
+	pub r15: u64,
+	pub r14: u64,
+	pub r13: u64,
+	pub r12: u64,
+	pub rbp: u64,
+	pub rbx: u64,
+	pub r11: u64,
+	pub r10: u64,
+	pub r9: u64,
+	pub r8: u64,
+	pub rdi: u64,
+	pub rsi: u64,
+	pub rdx: u64,
+	pub rcx: u64,
+	pub rax: u64,
+	pub rip: u64,
+	pub cs: u64,
+	pub rflags: u64,
+	pub rsp: u64,
+	pub ss: u64,
+}
+
+/*
+ * ReturnToUserHook - Callback run before an interrupt returns to Ring 3
+ *
+ * May rewrite the frame, e.g. to enter a signal handler, or terminate
+ * the task and never return.
+ */
+pub type ReturnToUserHook = fn(&mut TrapFrame);
+
+static RETURN_TO_USER_HOOK: Once<ReturnToUserHook> = Once::new();
+
+/*
+ * register_return_to_user_hook - Install the kernel's return-to-user work
+ * @hook: Called with the saved frame of every interrupt taken in Ring 3
+ */
+pub fn register_return_to_user_hook(hook: ReturnToUserHook) {
+	RETURN_TO_USER_HOOK.call_once(|| hook);
+}
+
+/*
+ * return_to_user - Run the return-to-user hook for an interrupted frame
+ * @frame: Frame about to be restored by iretq
+ *
+ * Does nothing if the interrupt was taken in Ring 0.
+ */
+pub fn return_to_user(frame: &mut TrapFrame) {
+	if frame.cs & 3 != 3 {
+		return;
+	}
+	if let Some(hook) = RETURN_TO_USER_HOOK.get() {
+		hook(frame);
+	}
+}
+
+/*
+ * trap_return - Restore a TrapFrame and iretq to it
+ * @frame: Complete register state to resume (rdi)
+ *
+ * Abandons the current kernel stack: RSP is pointed at @frame, which
+ * must itself live on that stack, and everything below it is dropped.
+ * Used to return to Ring 3 with every register restored, which sysretq
+ * cannot do since it clobbers RCX and R11.
+ */
+#[unsafe(naked)]
+pub unsafe extern "C" fn trap_return(frame: *const TrapFrame) -> ! {
+	naked_asm!(
+		"cli",
+		"mov rsp, rdi",
+		"pop r15",
+		"pop r14",
+		"pop r13",
+		"pop r12",
+		"pop rbp",
+		"pop rbx",
+		"pop r11",
+		"pop r10",
+		"pop r9",
+		"pop r8",
+		"pop rdi",
+		"pop rsi",
+		"pop rdx",
+		"pop rcx",
+		"pop rax",
+		"iretq",
+	)
+}
+
 /*
  * divide_by_zero_handler - Handle division by zero exception
- * @_stack: Interrupt stack frame (unused)
+ * @stack: Interrupt stack frame
  */
-extern "x86-interrupt" fn divide_by_zero_handler(_stack: InterruptStackFrame) {
+extern "x86-interrupt" fn divide_by_zero_handler(stack: InterruptStackFrame) {
+	user_fault(UserFault::DivideError, &stack, 0, None);
 	oops("Divide by Zero exception");
 }
 
+/*
+ * debug_handler - Handle debug exception (single step, hardware breakpoint)
+ * @stack: Interrupt stack frame
+ */
+extern "x86-interrupt" fn debug_handler(stack: InterruptStackFrame) {
+	user_fault(UserFault::Debug, &stack, 0, None);
+	oops("Debug exception");
+}
+
+/*
+ * breakpoint_handler - Handle INT3
+ * @stack: Interrupt stack frame
+ */
+extern "x86-interrupt" fn breakpoint_handler(stack: InterruptStackFrame) {
+	user_fault(UserFault::Breakpoint, &stack, 0, None);
+	serial_println!(
+		"Breakpoint at instruction pointer: {:#x}",
+		stack.instruction_pointer.as_u64()
+	);
+	oops("Breakpoint exception");
+}
+
+/*
+ * invalid_opcode_handler - Handle undefined instruction (#UD)
+ * @stack: Interrupt stack frame
+ */
+extern "x86-interrupt" fn invalid_opcode_handler(stack: InterruptStackFrame) {
+	user_fault(UserFault::InvalidOpcode, &stack, 0, None);
+	serial_println!(
+		"Invalid opcode at instruction pointer: {:#x}",
+		stack.instruction_pointer.as_u64()
+	);
+	oops("Invalid Opcode exception");
+}
+
+/*
+ * alignment_check_handler - Handle unaligned access with AC set (#AC)
+ * @stack: Interrupt stack frame
+ * @err: Error code (always 0)
+ */
+extern "x86-interrupt" fn alignment_check_handler(stack: InterruptStackFrame, err: u64) {
+	user_fault(UserFault::AlignmentCheck, &stack, err, None);
+	oops("Alignment Check exception");
+}
+
 /*
  * page_fault_handler - Handle page fault exception
  * @stack: Interrupt stack frame with fault information
  * @err: Page fault error code
  *
- * Prints diagnostic information and halts the system.
+ * Offers the fault to the registered hook first; if it cannot be
+ * resolved, kills the task for a Ring 3 fault, otherwise prints
+ * diagnostic information and halts the system.
  */
 extern "x86-interrupt" fn page_fault_handler(stack: InterruptStackFrame, err: PageFaultErrorCode) {
-	serial_println!(
-		"Page fault at instruction pointer: {:#x}",
-		stack.instruction_pointer.as_u64()
-	);
-
 	/* Read CR2 to get faulting address */
 	let cr2: u64;
 	unsafe {
 		core::arch::asm!("mov {}, cr2", out(reg) cr2);
 	}
+
+	count_interrupt(PAGE_FAULT_VECTOR);
+	if let Some(hook) = PAGE_FAULT_HOOK.get() {
+		if hook(VirtAddr::new_truncate(cr2), err, &stack) {
+			return;
+		}
+	}
+	user_fault(UserFault::PageFault, &stack, err.bits(), Some(VirtAddr::new_truncate(cr2)));
+
+	serial_println!(
+		"Page fault at instruction pointer: {:#x}",
+		stack.instruction_pointer.as_u64()
+	);
 	serial_println!("Page fault address: {:#x}", cr2);
 	serial_println!("Error Code: {:?}", err);
 
@@ -102,6 +346,8 @@ extern "x86-interrupt" fn double_fault_handler(_stack: InterruptStackFrame, _err
  * GPF often occurs during ring 3 entry with bad segment selectors.
  */
 extern "x86-interrupt" fn gpf_handler(stack: InterruptStackFrame, err: u64) {
+	user_fault(UserFault::GeneralProtection, &stack, err, None);
+
 	serial_println!(
 		"General Protection Fault at instruction pointer: {:#x}",
 		stack.instruction_pointer.as_u64()
@@ -197,3 +443,75 @@ pub fn register_interrupt_handler(
 		}
 	}
 }
+
+/*
+ * register_raw_handler - Register a naked interrupt entry stub
+ * @vector: Interrupt vector number (0-255)
+ * @addr: Address of the stub
+ *
+ * For stubs that save a full TrapFrame themselves instead of using the
+ * x86-interrupt ABI. Reloads the IDT if it was already loaded.
+ *
+ * Safety: @addr must be an interrupt entry point ending in iretq.
+ */
+pub unsafe fn register_raw_handler(vector: u8, addr: VirtAddr) {
+	unsafe {
+		let idt = &mut *IDT.idt.get();
+		idt[vector].set_handler_addr(addr);
+
+		/* Reload IDT if already loaded */
+		if *IDT.loaded.get() {
+			idt.load();
+		}
+	}
+}
+
+/*
+ * Per-vector interrupt statistics, reported by /proc/interrupts
+ *
+ * Handlers call count_interrupt() on entry. Device drivers name their
+ * vector with name_interrupt() when they register it.
+ */
+const PAGE_FAULT_VECTOR: u8 = 14;
+
+static IRQ_COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
+static IRQ_NAMES: Mutex<[Option<&'static str>; 256]> = Mutex::new({
+	let mut names = [None; 256];
+	names[PAGE_FAULT_VECTOR as usize] = Some("page-fault");
+	names
+});
+
+/*
+ * count_interrupt - Record one delivery of a vector
+ * @vector: Interrupt vector number
+ *
+ * Lock-free, safe to call from any interrupt handler.
+ */
+pub fn count_interrupt(vector: u8) {
+	IRQ_COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/*
+ * name_interrupt - Give a vector a name for /proc/interrupts
+ * @vector: Interrupt vector number
+ * @name: Short device name, e.g. "keyboard"
+ */
+pub fn name_interrupt(vector: u8, name: &'static str) {
+	IRQ_NAMES.lock()[vector as usize] = Some(name);
+}
+
+/*
+ * for_each_interrupt - Visit every named or counted vector
+ * @f: Called with (vector, name, count) in vector order; the name is
+ *     "-" for vectors that fired without being named
+ */
+pub fn for_each_interrupt(mut f: impl FnMut(u8, &'static str, u64)) {
+	let names = *IRQ_NAMES.lock();
+	for (vector, name) in names.iter().enumerate() {
+		let count = IRQ_COUNTS[vector].load(Ordering::Relaxed);
+		if name.is_some() || count != 0 {
+			f(vector as u8, name.unwrap_or("-"), count);
+		}
+	}
+}
@@ -6,13 +6,16 @@
  */
 
 #![no_std]
+#![feature(allocator_api)]
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
 use spin::Mutex;
+use memory::slab::{KmemCache, arc_layout};
 use spin::lock_api::RwLock;
-use task::TaskCB;
+use task::TaskArc;
 
 /*
  * IPC Constants
@@ -49,15 +52,34 @@ impl Default for Message {
 	}
 }
 
+/* Slab cache for queued messages */
+pub static MSG_CACHE: KmemCache = KmemCache::new(
+	"ipc_msg",
+	core::mem::size_of::<Message>(),
+	core::mem::align_of::<Message>(),
+	None,
+);
+
+/* Slab cache for ports */
+pub static PORT_CACHE: KmemCache = {
+	let (size, align) = arc_layout::<Port>();
+	KmemCache::new("ipc_port", size, align, None)
+};
+
+/*
+ * PortArc - Shared handle to a port, allocated from PORT_CACHE
+ */
+pub type PortArc = Arc<Port, &'static KmemCache>;
+
 /*
  * struct Port - Communication port
  * @id: Port identifier
- * @queue: Message queue
+ * @queue: Message queue; each message is a MSG_CACHE object
  */
 pub struct Port {
 	id: u64,
-	queue: Mutex<VecDeque<Message>>,
-	waiting_receivers: Mutex<VecDeque<Arc<Mutex<TaskCB>>>>,
+	queue: Mutex<VecDeque<Box<Message, &'static KmemCache>>>,
+	waiting_receivers: Mutex<VecDeque<TaskArc>>,
 }
 
 impl Port {
@@ -82,13 +104,16 @@ impl Port {
 	 * If any tasks are blocked waiting for messages on this port,
 	 * the first waiter is woken and re-enqueued on the RunQueue.
 	 *
-	 * Return: true if successful, false if queue full
+	 * Return: true if successful, false if queue full or out of memory
 	 */
 	pub fn send(&self, msg: Message) -> bool {
 		let mut q = self.queue.lock();
 		if q.len() >= PORT_QUEUE_LEN {
 			return false;
 		}
+		let Ok(msg) = Box::try_new_in(msg, &MSG_CACHE) else {
+			return false;
+		};
 		q.push_back(msg);
 		drop(q);
 
@@ -107,7 +132,7 @@ impl Port {
 	 */
 	pub fn receive(&self) -> Option<Message> {
 		let mut q = self.queue.lock();
-		q.pop_front()
+		q.pop_front().map(|msg| *msg)
 	}
 
 	/*
@@ -126,7 +151,7 @@ impl Port {
 		loop {
 			/* Fast path: message already available */
 			if let Some(msg) = self.queue.lock().pop_front() {
-				return msg;
+				return *msg;
 			}
 
 			/* Queue empty — block current task */
@@ -156,7 +181,7 @@ impl Port {
  * @ports: Map of port IDs to port objects
  */
 pub struct IpcSpace {
-	ports: RwLock<BTreeMap<u64, Arc<Port>>>,
+	ports: RwLock<BTreeMap<u64, PortArc>>,
 }
 
 impl IpcSpace {
@@ -177,9 +202,9 @@ impl IpcSpace {
 	 *
 	 * Return: Arc reference to the new port
 	 */
-	pub fn create_port(&self, id: u64) -> Arc<Port> {
+	pub fn create_port(&self, id: u64) -> PortArc {
 		let mut ports = self.ports.write();
-		let port = Arc::new(Port::new(id));
+		let port = Arc::new_in(Port::new(id), &PORT_CACHE);
 		ports.insert(id, port.clone());
 		port
 	}
@@ -190,7 +215,7 @@ impl IpcSpace {
 	 *
 	 * Return: Some(port) if found, None otherwise
 	 */
-	pub fn get_port(&self, id: u64) -> Option<Arc<Port>> {
+	pub fn get_port(&self, id: u64) -> Option<PortArc> {
 		let ports = self.ports.read();
 		ports.get(&id).cloned()
 	}
@@ -0,0 +1,173 @@
+/*
+ * fault.rs - User Page Fault Resolution
+ *
+ * Registered with idt::register_page_fault_hook(). Resolves faults that
+ * are part of normal user address space management so the faulting
+ * instruction can be retried:
+ *
+ * - first touch of an anonymous page inside a VMA (demand paging)
+ * - write to a copy-on-write page after fork
+ * - access to a page that was swapped out (swap.rs)
+ *
+ * Every fault is checked against the current process's VmaList first.
+ * Anything it does not recognise falls through to the idt handler,
+ * which passes Ring 3 faults to handle_user_fault() and oopses on
+ * kernel faults.
+ */
+
+use x86_64::VirtAddr;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
+use idt::UserFault;
+use task::vma::{PROT_EXEC, PROT_NONE, PROT_WRITE, VmaKind};
+
+use crate::signal::{self, SIGBUS, SIGFPE, SIGILL, SIGSEGV, SIGTRAP};
+use crate::swap::ReclaimAlloc;
+use crate::syscall::USER_SPACE_END;
+
+/*
+ * handle_page_fault - Try to resolve a page fault in the active address space
+ * @addr: Faulting virtual address (CR2)
+ * @err: Page fault error code
+ * @_stack: Interrupted frame
+ *
+ * Both Ring 3 faults and kernel faults on user addresses (e.g. a syscall
+ * copying into a user buffer) are handled, since either may hit a COW or
+ * not-yet-populated page.
+ *
+ * The task, its VMA list and PAGE_ALLOC are only try_lock()ed, since
+ * the fault may have cut into their holder. Busy is not failure: the
+ * holder is usually a preempted task (kshell runs with interrupts on),
+ * so the handler yields to it and lets the instruction fault again.
+ *
+ * Return: true if the fault was resolved or is to be retried
+ */
+pub fn handle_page_fault(addr: VirtAddr, err: PageFaultErrorCode, _stack: &InterruptStackFrame) -> bool {
+	if addr.as_u64() >= USER_SPACE_END {
+		return false;
+	}
+
+	/* 1. The access must be allowed by a VMA of the current task */
+	let task_arc = match task::scheduler::current_task_arc() {
+		Some(t) => t,
+		None => return false,
+	};
+	let vmas = match task_arc.try_lock() {
+		Some(t) => t.vmas.clone(),
+		None => return retry_later(),
+	};
+	let vma = match vmas.try_lock() {
+		Some(list) => match list.find(addr.as_u64()) {
+			Some(v) => *v,
+			None => return false,
+		},
+		None => return retry_later(),
+	};
+	let write = err.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+	let fetch = err.contains(PageFaultErrorCode::INSTRUCTION_FETCH);
+	if vma.prot == PROT_NONE
+		|| (write && vma.prot & PROT_WRITE == 0)
+		|| (fetch && vma.prot & PROT_EXEC == 0)
+	{
+		return false;
+	}
+
+	let page_alloc = match memory::PAGE_ALLOC.get() {
+		Some(p) => p,
+		None => return false,
+	};
+	let mut alloc_guard = match page_alloc.try_lock() {
+		Some(g) => g,
+		None => return retry_later(),
+	};
+
+	let (pml4, _) = Cr3::read();
+	let offset = memory::hhdm_offset();
+	let mut alloc = ReclaimAlloc::new(&mut alloc_guard, None);
+
+	/* 2. Present page: only a write to a COW page is resolvable */
+	if err.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+		if !write {
+			return false;
+		}
+		return unsafe {
+			memory::uvm::handle_cow_fault(pml4, addr, &mut alloc, offset)
+		};
+	}
+
+	/* 3. Swapped-out page: read it back */
+	if let Some(slot) = unsafe { crate::swap::swapped(pml4, addr, offset) } {
+		return unsafe { crate::swap::swap_in(pml4, addr, slot, vma.page_flags(), &mut alloc, offset) };
+	}
+
+	/* 4. Missing page: populate anonymous memory on demand */
+	if vma.kind != VmaKind::Anon {
+		return false;
+	}
+	unsafe {
+		memory::uvm::map_zeroed_page(pml4, addr, vma.page_flags(), &mut alloc, offset)
+	}
+}
+
+/*
+ * retry_later - Give up on a fault for now because a lock is busy
+ *
+ * Switches to another ready task, which lets a preempted lock holder
+ * finish; the faulting instruction runs again when this task is next
+ * scheduled.
+ *
+ * Return: true, so the fault counts as handled
+ */
+fn retry_later() -> bool {
+	task::schedule();
+	true
+}
+
+/*
+ * handle_user_fault - Terminate a task that took an exception in Ring 3
+ * @kind: Exception type
+ * @stack: Interrupted user frame
+ * @err: Exception error code (0 if none)
+ * @addr: Faulting address for page faults
+ *
+ * Logs a report and exits the whole process with a signal wait status,
+ * as if it had been killed by the matching signal. Never returns.
+ */
+pub fn handle_user_fault(
+	kind: UserFault,
+	stack: &InterruptStackFrame,
+	err: u64,
+	addr: Option<VirtAddr>,
+) -> ! {
+	let sig = match kind {
+		UserFault::DivideError => SIGFPE,
+		UserFault::Debug | UserFault::Breakpoint => SIGTRAP,
+		UserFault::InvalidOpcode => SIGILL,
+		UserFault::GeneralProtection | UserFault::PageFault => SIGSEGV,
+		UserFault::AlignmentCheck => SIGBUS,
+	};
+
+	let (pid, name) = match task::scheduler::current_task_arc() {
+		Some(t) => {
+			let t = t.lock();
+			(t.id.0, t.name)
+		}
+		None => (0, "?"),
+	};
+
+	hal::serial_println!(
+		"[FAULT] pid={} ({}) killed by {}: {:?} at rip={:#x} rsp={:#x} err={:#x}",
+		pid,
+		name,
+		signal::signal_name(sig),
+		kind,
+		stack.instruction_pointer.as_u64(),
+		stack.stack_pointer.as_u64(),
+		err,
+	);
+	if let Some(a) = addr {
+		hal::serial_println!("[FAULT]   fault address {:#x}", a.as_u64());
+	}
+
+	crate::process::exit_group(signal::wait_status_signaled(sig));
+}
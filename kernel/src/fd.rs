@@ -1,19 +1,42 @@
 /*
- * fd.rs - Global File Descriptor Table
+ * fd.rs - Per-process File Descriptor Tables
  *
- * Maps (task_id, fd) pairs to open file state. FDs 0-2 are reserved
- * (stdin, stdout, stderr); user files start at fd 3.
+ * Every process owns an FdTable mapping descriptor numbers to open
+ * files. New descriptors get the lowest free number, as POSIX requires,
+ * so closing fd 0 and opening a file makes that file the new stdin.
+ *
+ * An OpenFile is the open file description: the inode and the shared
+ * cursor. Descriptors created by dup()/dup2() and inherited across
+ * fork() or spawn() point at the same OpenFile. Per-descriptor flags
+ * (FD_CLOEXEC) live in the table slot instead and are never shared.
+ *
+ * Tables are created by init_stdio(), fork_for_task() or, for
+ * SYS_SPAWN, spawn_table() + install(), and freed by cleanup() when the
+ * process exits. Threads of one process do not get a table of their
+ * own: share() files the same table under the new thread's ID, so an fd
+ * opened by one thread is valid in all of them.
  */
 
 extern crate alloc;
 
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use memory::slab::{KmemCache, arc_layout};
 use spin::Mutex;
 use vfs::INode;
 
+use crate::syscall::{ERRNO_EBADF, ERRNO_EINVAL, ERRNO_EMFILE, ERRNO_ENOENT};
+
+/* Per-descriptor flags (SYS_FCNTL F_GETFD / F_SETFD) */
+pub const FD_CLOEXEC: u64 = 1;
+
+/* Highest descriptor number + 1 a process may use (RLIMIT_NOFILE) */
+pub const MAX_FDS: usize = 256;
+
 /*
- * struct OpenFile - Per-fd state
+ * struct OpenFile - Open file description shared by duplicated fds
  * @inode: VFS node backing this descriptor
  * @offset: Current read/write cursor position
  */
@@ -22,37 +45,187 @@ pub struct OpenFile {
 	pub offset: Mutex<usize>,
 }
 
+/* Slab cache for open file descriptions */
+static FILE_CACHE: KmemCache = {
+	let (size, align) = arc_layout::<OpenFile>();
+	KmemCache::new("open_file", size, align, None)
+};
+
+/*
+ * FileArc - Shared handle to an OpenFile, allocated from FILE_CACHE
+ */
+pub type FileArc = Arc<OpenFile, &'static KmemCache>;
+
+impl OpenFile {
+	/*
+	 * new - Open file description with the cursor at 0
+	 * @inode: VFS node to wrap
+	 */
+	pub fn new(inode: Arc<dyn INode>) -> FileArc {
+		Arc::new_in(Self { inode, offset: Mutex::new(0) }, &FILE_CACHE)
+	}
+}
+
+/*
+ * struct FdEntry - One slot of an FdTable
+ * @file: Open file description
+ * @flags: FD_CLOEXEC
+ */
+#[derive(Clone)]
+struct FdEntry {
+	file: FileArc,
+	flags: u64,
+}
+
+/*
+ * struct FdTable - Descriptor table of one process
+ * @slots: Indexed by fd; None marks a free number
+ */
+#[derive(Clone, Default)]
+pub struct FdTable {
+	slots: Vec<Option<FdEntry>>,
+}
+
+impl FdTable {
+	/*
+	 * lowest_free - Lowest unused descriptor number
+	 * @min: Smallest acceptable number
+	 *
+	 * Return: fd, or None if all numbers below MAX_FDS are taken
+	 */
+	fn lowest_free(&self, min: usize) -> Option<usize> {
+		(min..MAX_FDS).find(|&fd| !matches!(self.slots.get(fd), Some(Some(_))))
+	}
+
+	/*
+	 * install - Put an entry at a given descriptor number
+	 * @fd: Descriptor number, below MAX_FDS
+	 * @entry: New slot contents
+	 *
+	 * Return: The entry previously at @fd, which the caller drops
+	 *         after releasing the table lock
+	 */
+	fn install(&mut self, fd: usize, entry: FdEntry) -> Option<FdEntry> {
+		if self.slots.len() <= fd {
+			self.slots.resize(fd + 1, None);
+		}
+		self.slots[fd].replace(entry)
+	}
+
+	/*
+	 * alloc - Install an entry at the lowest free descriptor
+	 * @min: Smallest acceptable number
+	 * @entry: New slot contents
+	 *
+	 * Return: The descriptor, or ERRNO_EMFILE
+	 */
+	fn alloc(&mut self, min: usize, entry: FdEntry) -> Result<u64, u64> {
+		let fd = self.lowest_free(min).ok_or(ERRNO_EMFILE)?;
+		self.install(fd, entry);
+		Ok(fd as u64)
+	}
+
+	fn get(&self, fd: u64) -> Option<&FdEntry> {
+		self.slots.get(fd as usize)?.as_ref()
+	}
+
+	fn remove(&mut self, fd: u64) -> Option<FdEntry> {
+		let entry = self.slots.get_mut(fd as usize)?.take();
+		/* Keep the table short once the highest fds are closed */
+		while matches!(self.slots.last(), Some(None)) {
+			self.slots.pop();
+		}
+		entry
+	}
+
+	/*
+	 * without_cloexec - Copy of the table as seen after exec
+	 */
+	fn without_cloexec(&self) -> Self {
+		let slots = self.slots.iter()
+			.map(|s| s.clone().filter(|e| e.flags & FD_CLOEXEC == 0))
+			.collect();
+		let mut t = Self { slots };
+		while matches!(t.slots.last(), Some(None)) {
+			t.slots.pop();
+		}
+		t
+	}
+}
+
+/*
+ * enum FileAction - One posix_spawn-style file action for SYS_SPAWN
+ *
+ * Applied in order to the child's copy of the parent's table, before
+ * close-on-exec descriptors are dropped.
+ */
+#[derive(Debug, Clone)]
+pub enum FileAction {
+	/* Make child fd @to refer to what @from refers to (clears FD_CLOEXEC) */
+	Dup2 { from: u64, to: u64 },
+	/* Close @fd if open */
+	Close(u64),
+	/* Open @path as @fd, replacing whatever @fd was */
+	Open { fd: u64, path: String, flags: u64 },
+}
+
 /*
- * FD_TABLE - Global file descriptor table keyed by (task_id, fd)
+ * TABLES - Descriptor table of every process, keyed by task ID
  */
-static FD_TABLE: Mutex<BTreeMap<(u64, u64), Arc<OpenFile>>> =
-	Mutex::new(BTreeMap::new());
+static TABLES: Mutex<BTreeMap<u64, Arc<Mutex<FdTable>>>> = Mutex::new(BTreeMap::new());
 
 /*
- * NEXT_FD - Per-task next fd counter (simple global counter for now)
+ * table - Look up the descriptor table of a task
+ * @task_id: Owning task
  */
-static NEXT_FD: Mutex<u64> = Mutex::new(3);
+fn table(task_id: u64) -> Option<Arc<Mutex<FdTable>>> {
+	TABLES.lock().get(&task_id).cloned()
+}
+
+/*
+ * with_table - Run @f on the descriptor table of a task
+ * @task_id: Owning task
+ *
+ * Return: The result of @f, or ERRNO_EBADF if the task has no table
+ */
+fn with_table<R>(task_id: u64, f: impl FnOnce(&mut FdTable) -> Result<R, u64>) -> Result<R, u64> {
+	let t = table(task_id).ok_or(ERRNO_EBADF)?;
+	let mut guard = t.lock();
+	f(&mut guard)
+}
+
+/*
+ * entry_flags - Translate open()-style flags into fd flags
+ * @open_flags: O_CLOEXEC or 0
+ */
+fn entry_flags(open_flags: u64) -> u64 {
+	if open_flags & crate::syscall::O_CLOEXEC != 0 { FD_CLOEXEC } else { 0 }
+}
 
 /*
  * open - Open a VFS path and return a file descriptor
  * @task_id: Calling task's ID
  * @path: Absolute path to open
+ * @flags: O_CLOEXEC or 0
  *
- * Return: fd on success, None if path not found
+ * Return: fd on success, ERRNO_ENOENT or ERRNO_EMFILE
  */
-pub fn open(task_id: u64, path: &str) -> Option<u64> {
-	let inode = vfs::lookup_path(path)?;
-	let mut next = NEXT_FD.lock();
-	let fd = *next;
-	*next += 1;
-
-	let file = Arc::new(OpenFile {
-		inode,
-		offset: Mutex::new(0),
-	});
+pub fn open(task_id: u64, path: &str, flags: u64) -> Result<u64, u64> {
+	let inode = vfs::lookup_path(path).ok_or(ERRNO_ENOENT)?;
+	insert_inode(task_id, inode, flags)
+}
 
-	FD_TABLE.lock().insert((task_id, fd), file);
-	Some(fd)
+/*
+ * insert_inode - Insert an already-constructed INode as a new fd
+ * @task_id: Owning task
+ * @inode: INode to wrap
+ * @flags: O_CLOEXEC or 0
+ *
+ * Return: The lowest free fd, or ERRNO_EMFILE / ERRNO_EBADF
+ */
+pub fn insert_inode(task_id: u64, inode: Arc<dyn INode>, flags: u64) -> Result<u64, u64> {
+	let entry = FdEntry { file: OpenFile::new(inode), flags: entry_flags(flags) };
+	with_table(task_id, |t| t.alloc(0, entry))
 }
 
 /*
@@ -63,7 +236,8 @@ pub fn open(task_id: u64, path: &str) -> Option<u64> {
  * Return: true if fd existed and was closed
  */
 pub fn close(task_id: u64, fd: u64) -> bool {
-	FD_TABLE.lock().remove(&(task_id, fd)).is_some()
+	/* The entry is dropped after the table lock is released */
+	with_table(task_id, |t| Ok(t.remove(fd))).ok().flatten().is_some()
 }
 
 /*
@@ -73,8 +247,23 @@ pub fn close(task_id: u64, fd: u64) -> bool {
  *
  * Return: Reference to OpenFile if fd is valid
  */
-pub fn get(task_id: u64, fd: u64) -> Option<Arc<OpenFile>> {
-	FD_TABLE.lock().get(&(task_id, fd)).cloned()
+pub fn get(task_id: u64, fd: u64) -> Option<FileArc> {
+	with_table(task_id, |t| Ok(t.get(fd).map(|e| e.file.clone()))).ok().flatten()
+}
+
+/*
+ * list - Snapshot of every open descriptor of a task
+ * @task_id: Owning task
+ *
+ * Return: (fd, open file, fd flags) in fd order, or None if the task
+ *         has no table
+ */
+pub fn list(task_id: u64) -> Option<Vec<(u64, FileArc, u64)>> {
+	with_table(task_id, |t| {
+		Ok(t.slots.iter().enumerate()
+			.filter_map(|(fd, s)| s.as_ref().map(|e| (fd as u64, e.file.clone(), e.flags)))
+			.collect())
+	}).ok()
 }
 
 /*
@@ -95,49 +284,50 @@ pub fn seek(task_id: u64, fd: u64, offset: usize) -> bool {
 }
 
 /*
- * insert_inode - Insert an already-constructed INode as a new fd
+ * get_flags - Read the per-descriptor flags (F_GETFD)
  * @task_id: Owning task
- * @inode: INode to wrap
+ * @fd: File descriptor
  *
- * Return: Allocated fd number
+ * Return: FD_CLOEXEC or 0, or ERRNO_EBADF
  */
-pub fn insert_inode(task_id: u64, inode: Arc<dyn INode>) -> u64 {
-	let mut table = FD_TABLE.lock();
-	let fd = next_fd(task_id, &table);
-	table.insert((task_id, fd), Arc::new(OpenFile {
-		inode,
-		offset: Mutex::new(0),
-	}));
-	fd
+pub fn get_flags(task_id: u64, fd: u64) -> Result<u64, u64> {
+	with_table(task_id, |t| t.get(fd).map(|e| e.flags).ok_or(ERRNO_EBADF))
 }
 
 /*
- * next_fd - Find the lowest available fd >= 3 for a task
- * @task_id: Task to allocate fd for
+ * set_flags - Replace the per-descriptor flags (F_SETFD)
+ * @task_id: Owning task
+ * @fd: File descriptor
+ * @flags: FD_CLOEXEC or 0; other bits are ignored
  *
- * Return: Lowest available file descriptor number
+ * Return: Ok, or ERRNO_EBADF
  */
-fn next_fd(task_id: u64, table: &BTreeMap<(u64, u64), Arc<OpenFile>>) -> u64 {
-	let mut fd = 3u64;
-	while table.contains_key(&(task_id, fd)) {
-		fd += 1;
-	}
-	fd
+pub fn set_flags(task_id: u64, fd: u64, flags: u64) -> Result<(), u64> {
+	with_table(task_id, |t| {
+		let slot = t.slots.get_mut(fd as usize).and_then(|s| s.as_mut()).ok_or(ERRNO_EBADF)?;
+		slot.flags = flags & FD_CLOEXEC;
+		Ok(())
+	})
 }
 
 /*
- * dup - Duplicate a file descriptor to the next available fd
+ * dup - Duplicate a file descriptor to the lowest free fd
  * @task_id: Calling task's ID
  * @old_fd: File descriptor to duplicate
+ * @min: Smallest acceptable new fd (0 for SYS_DUP, arg for F_DUPFD)
+ * @flags: O_CLOEXEC or 0, applied to the new fd only
  *
- * Return: New fd sharing the same OpenFile, None if old_fd not found
+ * Return: New fd sharing the same OpenFile, ERRNO_EINVAL if @min is not
+ *         below MAX_FDS, or ERRNO_EBADF / ERRNO_EMFILE
  */
-pub fn dup(task_id: u64, old_fd: u64) -> Option<u64> {
-	let mut table = FD_TABLE.lock();
-	let file = table.get(&(task_id, old_fd))?.clone();
-	let new_fd = next_fd(task_id, &table);
-	table.insert((task_id, new_fd), file);
-	Some(new_fd)
+pub fn dup(task_id: u64, old_fd: u64, min: u64, flags: u64) -> Result<u64, u64> {
+	if min >= MAX_FDS as u64 {
+		return Err(ERRNO_EINVAL);
+	}
+	with_table(task_id, |t| {
+		let file = t.get(old_fd).ok_or(ERRNO_EBADF)?.file.clone();
+		t.alloc(min as usize, FdEntry { file, flags: entry_flags(flags) })
+	})
 }
 
 /*
@@ -145,80 +335,150 @@ pub fn dup(task_id: u64, old_fd: u64) -> Option<u64> {
  * @task_id: Calling task's ID
  * @old_fd: Source file descriptor
  * @new_fd: Target file descriptor (closed if already open)
+ * @flags: O_CLOEXEC or 0, applied to @new_fd
  *
- * Return: new_fd on success, None if old_fd not found
+ * Return: new_fd on success, or ERRNO_EBADF
  */
-pub fn dup2(task_id: u64, old_fd: u64, new_fd: u64) -> Option<u64> {
-	if old_fd == new_fd {
-		/* Verify old_fd exists */
-		let table = FD_TABLE.lock();
-		return if table.contains_key(&(task_id, old_fd)) { Some(new_fd) } else { None };
+pub fn dup2(task_id: u64, old_fd: u64, new_fd: u64, flags: u64) -> Result<u64, u64> {
+	if new_fd as usize >= MAX_FDS {
+		return Err(ERRNO_EBADF);
 	}
-	let mut table = FD_TABLE.lock();
-	let file = table.get(&(task_id, old_fd))?.clone();
-	table.remove(&(task_id, new_fd)); /* silently close if open */
-	table.insert((task_id, new_fd), file);
-	Some(new_fd)
-}
-
-/*
- * clone_for_task - Copy all fds from src_task to dst_task
- * @src_task: Task to copy from (parent on spawn)
- * @dst_task: Task to copy into (child)
- *
- * Each cloned fd shares the same INode but gets its own offset cursor.
- */
-pub fn clone_for_task(src_task: u64, dst_task: u64) {
-	let table = FD_TABLE.lock();
-	let entries: alloc::vec::Vec<_> = table
-		.iter()
-		.filter(|&(&(tid, _), _)| tid == src_task)
-		.map(|(&(_, fd), file)| {
-			/* New OpenFile with independent offset, shared INode */
-			let new_file = Arc::new(OpenFile {
-				inode: file.inode.clone(),
-				offset: spin::Mutex::new(*file.offset.lock()),
-			});
-			(fd, new_file)
-		})
-		.collect();
-	drop(table);
-	let mut table = FD_TABLE.lock();
-	for (fd, file) in entries {
-		table.insert((dst_task, fd), file);
+	let replaced = with_table(task_id, |t| {
+		let file = t.get(old_fd).ok_or(ERRNO_EBADF)?.file.clone();
+		if old_fd == new_fd {
+			return Ok(None);
+		}
+		Ok(t.install(new_fd as usize, FdEntry { file, flags: entry_flags(flags) }))
+	})?;
+	/* Silently close whatever @new_fd referred to, outside the lock */
+	drop(replaced);
+	Ok(new_fd)
+}
+
+/*
+ * close_on_exec - Close every close-on-exec descriptor of a task
+ * @task_id: Task that is replacing its image
+ */
+pub fn close_on_exec(task_id: u64) {
+	let old = with_table(task_id, |t| {
+		let kept = t.without_cloexec();
+		Ok(core::mem::replace(t, kept))
+	});
+	drop(old);
+}
+
+/*
+ * fork_for_task - Give a forked child a copy of its parent's table
+ * @src_task: Forking task
+ * @dst_task: Newly forked child
+ *
+ * Each fd refers to the same OpenFile, so parent and child share the
+ * file offset as after a POSIX fork(). Close-on-exec flags are
+ * inherited.
+ */
+pub fn fork_for_task(src_task: u64, dst_task: u64) {
+	let copy = table(src_task).map(|t| t.lock().clone()).unwrap_or_default();
+	TABLES.lock().insert(dst_task, Arc::new(Mutex::new(copy)));
+}
+
+/*
+ * share - Give a new thread its process's descriptor table
+ * @src_task: Creating thread
+ * @dst_task: New thread of the same process
+ *
+ * Unlike fork_for_task() nothing is copied: both IDs refer to one table
+ * from now on.
+ */
+pub fn share(src_task: u64, dst_task: u64) {
+	if let Some(t) = table(src_task) {
+		TABLES.lock().insert(dst_task, t);
+	}
+}
+
+/*
+ * spawn_table - Build the descriptor table of a process being spawned
+ * @src_task: Spawning task
+ * @actions: File actions to apply, in order
+ *
+ * Like fork followed by exec: the child starts with the parent's
+ * descriptors, @actions rewire them, then close-on-exec descriptors are
+ * dropped. A parent without a table (a kernel task) starts the child
+ * from a fresh stdin/stdout/stderr instead.
+ *
+ * Return: The table for install(), or ERRNO_EBADF / ERRNO_ENOENT from
+ *         the first action that failed
+ */
+pub fn spawn_table(src_task: u64, actions: &[FileAction]) -> Result<FdTable, u64> {
+	let mut t = match table(src_task) {
+		Some(t) => t.lock().clone(),
+		None => stdio_table(),
+	};
+
+	for action in actions {
+		match action {
+			FileAction::Dup2 { from, to } => {
+				let file = t.get(*from).ok_or(ERRNO_EBADF)?.file.clone();
+				if *to as usize >= MAX_FDS {
+					return Err(ERRNO_EBADF);
+				}
+				t.install(*to as usize, FdEntry { file, flags: 0 });
+			}
+			FileAction::Close(fd) => {
+				t.remove(*fd);
+			}
+			FileAction::Open { fd, path, flags } => {
+				if *fd as usize >= MAX_FDS {
+					return Err(ERRNO_EBADF);
+				}
+				let inode = vfs::lookup_path(path).ok_or(ERRNO_ENOENT)?;
+				t.install(*fd as usize, FdEntry { file: OpenFile::new(inode), flags: entry_flags(*flags) });
+			}
+		}
 	}
+	Ok(t.without_cloexec())
 }
 
 /*
- * cleanup - Remove all file descriptors owned by a task
+ * install - Make a table the descriptor table of a task
+ * @task_id: New process
+ * @fds: Table built by spawn_table()
+ */
+pub fn install(task_id: u64, fds: FdTable) {
+	TABLES.lock().insert(task_id, Arc::new(Mutex::new(fds)));
+}
+
+/*
+ * cleanup - Free the descriptor table of a task
  * @task_id: Task whose fds to remove
  *
- * Called on task exit to release all open file descriptors.
+ * Called on task exit. A thread only drops its reference to the table
+ * it shares with the rest of its process. Files are closed once no
+ * other process shares them.
  */
 pub fn cleanup(task_id: u64) {
-	FD_TABLE.lock().retain(|&(tid, _), _| tid != task_id);
+	let t = TABLES.lock().remove(&task_id);
+	drop(t);
 }
 
 /*
- * init_stdio - Insert fd 0/1/2 into the FD table for a task
+ * stdio_table - A table holding only fd 0/1/2 on the console
+ */
+fn stdio_table() -> FdTable {
+	use crate::stdio::{StdinINode, StderrINode, StdoutINode};
+
+	let mut t = FdTable::default();
+	t.install(0, FdEntry { file: OpenFile::new(Arc::new(StdinINode)), flags: 0 });
+	t.install(1, FdEntry { file: OpenFile::new(Arc::new(StdoutINode)), flags: 0 });
+	t.install(2, FdEntry { file: OpenFile::new(Arc::new(StderrINode)), flags: 0 });
+	t
+}
+
+/*
+ * init_stdio - Create a table holding only fd 0/1/2 for a task
  * @task_id: Target task ID
  *
  * Must be called before the task uses read()/write() on stdio fds.
  */
 pub fn init_stdio(task_id: u64) {
-	use crate::stdio::{StdinINode, StderrINode, StdoutINode};
-
-	let mut table = FD_TABLE.lock();
-	table.insert((task_id, 0), Arc::new(OpenFile {
-		inode: Arc::new(StdinINode),
-		offset: Mutex::new(0),
-	}));
-	table.insert((task_id, 1), Arc::new(OpenFile {
-		inode: Arc::new(StdoutINode),
-		offset: Mutex::new(0),
-	}));
-	table.insert((task_id, 2), Arc::new(OpenFile {
-		inode: Arc::new(StderrINode),
-		offset: Mutex::new(0),
-	}));
+	install(task_id, stdio_table());
 }
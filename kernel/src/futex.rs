@@ -0,0 +1,248 @@
+/*
+ * futex.rs - Fast Userspace Locking
+ *
+ * Implements SYS_FUTEX. A futex is an aligned 32-bit word in user
+ * memory: userspace takes and releases its locks with atomics on the
+ * word and only enters the kernel to sleep (FUTEX_WAIT) or to wake
+ * sleepers (FUTEX_WAKE) once there is contention.
+ *
+ * Waiters are keyed by the physical address of the word, so threads of
+ * a process and processes sharing a frame meet on the same key. A word
+ * in a writable mapping is written (with its own value) before the key
+ * is taken; this breaks copy-on-write first, so a frame still shared
+ * with a fork()ed relative never hands two processes the same key.
+ *
+ * FUTEX_WAIT checks the word and queues the caller with interrupts off,
+ * and the caller is the only CPU, so no FUTEX_WAKE can slip in between
+ * (no lost wakeups). Sleeps are Blocked, with an optional timeout from
+ * the kernel timer queue; a signal cuts them short.
+ */
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+use task::TaskArc;
+use task::vma::{PROT_READ, PROT_WRITE};
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{PhysFrame, Translate};
+
+use crate::syscall::{ERRNO_EAGAIN, ERRNO_EFAULT, ERRNO_EINTR, ERRNO_EINVAL, ERRNO_ENOSYS,
+	ERRNO_ETIMEDOUT};
+use crate::time::Timespec;
+
+/* Operations (Linux values) */
+pub const FUTEX_WAIT: u64 = 0;
+pub const FUTEX_WAKE: u64 = 1;
+
+/* Accepted and ignored: every futex is looked up by physical address */
+pub const FUTEX_PRIVATE_FLAG: u64 = 128;
+
+/*
+ * struct Waiter - A task sleeping in FUTEX_WAIT
+ * @key: Physical address of the futex word
+ * @task: The sleeping task
+ */
+struct Waiter {
+	key: u64,
+	task: TaskArc,
+}
+
+/* Every sleeping waiter, oldest first so FUTEX_WAKE is FIFO per key */
+static WAITERS: Mutex<Vec<Waiter>> = Mutex::new(Vec::new());
+
+/*
+ * lookup - Resolve a futex word of the calling process
+ * @uaddr: User address of the word
+ *
+ * The word must be 4-byte aligned and covered by a readable VMA; it is
+ * faulted in (and unshared if writable) so it has a frame to key on.
+ *
+ * Return: (key, word), ERRNO_EINVAL if misaligned or ERRNO_EFAULT
+ */
+fn lookup(uaddr: u64) -> Result<(u64, &'static AtomicU32), u64> {
+	if uaddr % 4 != 0 {
+		return Err(ERRNO_EINVAL);
+	}
+	let task_arc = task::scheduler::current_task_arc().ok_or(ERRNO_EFAULT)?;
+	let (pml4, vmas) = {
+		let t = task_arc.lock();
+		(t.pml4_frame.ok_or(ERRNO_EFAULT)?, t.vmas.clone())
+	};
+	/* The VMA lock must be released before the word is touched */
+	let prot = vmas.lock().find(uaddr).map(|v| v.prot).ok_or(ERRNO_EFAULT)?;
+	if prot & PROT_READ == 0 {
+		return Err(ERRNO_EFAULT);
+	}
+
+	let word = unsafe { &*(uaddr as *const AtomicU32) };
+	if prot & PROT_WRITE != 0 {
+		word.fetch_or(0, Ordering::SeqCst);
+	} else {
+		word.load(Ordering::SeqCst);
+	}
+
+	let mapper = unsafe { memory::create_mapper(pml4, memory::hhdm_offset()) };
+	let phys = mapper.translate_addr(VirtAddr::new(uaddr)).ok_or(ERRNO_EFAULT)?;
+	Ok((phys.as_u64(), word))
+}
+
+/*
+ * frame_in_use - Check whether a frame holds a futex word with waiters
+ * @frame: Physical frame
+ *
+ * Waiters are keyed by physical address, so swap (swap.rs) must not move
+ * such a frame. A contended list counts as in use.
+ */
+pub fn frame_in_use(frame: PhysFrame) -> bool {
+	let base = frame.start_address().as_u64();
+	match WAITERS.try_lock() {
+		Some(waiters) => waiters.iter().any(|w| w.key & !0xFFF == base),
+		None => true,
+	}
+}
+
+/*
+ * queued - Check whether a task is still on the waiter list
+ * @task: Task to look for
+ *
+ * Return: false once a FUTEX_WAKE has taken it off
+ */
+fn queued(task: &TaskArc) -> bool {
+	WAITERS.lock().iter().any(|w| Arc::ptr_eq(&w.task, task))
+}
+
+/*
+ * dequeue - Drop a task from the waiter list
+ * @task: Task to remove
+ *
+ * Return: true if it was still queued, i.e. no FUTEX_WAKE picked it
+ */
+fn dequeue(task: &TaskArc) -> bool {
+	let mut waiters = WAITERS.lock();
+	let before = waiters.len();
+	waiters.retain(|w| !Arc::ptr_eq(&w.task, task));
+	waiters.len() != before
+}
+
+/*
+ * wait - Sleep while a futex word holds a value (FUTEX_WAIT)
+ * @uaddr: User address of the word
+ * @val: Value the caller last saw in the word
+ * @timeout: Longest time to sleep, or None to sleep until woken
+ *
+ * Return: Ok(0) once woken; Err(ERRNO_EAGAIN) if the word no longer
+ *         holds @val, Err(ERRNO_ETIMEDOUT), Err(ERRNO_EINTR) if a signal
+ *         arrived, or Err(ERRNO_EINVAL / ERRNO_EFAULT) for a bad word
+ */
+pub fn wait(uaddr: u64, val: u32, timeout: Option<&Timespec>) -> Result<u64, u64> {
+	let deadline = match timeout {
+		Some(ts) => {
+			let ns = ts.to_ns().ok_or(ERRNO_EINVAL)?;
+			Some(task::timer::now().saturating_add(task::timer::ns_to_ticks(ns)))
+		}
+		None => None,
+	};
+	let me = task::scheduler::current_task_arc().ok_or(ERRNO_EINVAL)?;
+
+	interrupts::without_interrupts(|| {
+		let (key, word) = lookup(uaddr)?;
+		if word.load(Ordering::SeqCst) != val {
+			return Err(ERRNO_EAGAIN);
+		}
+		if crate::signal::interrupted() {
+			return Err(ERRNO_EINTR);
+		}
+		WAITERS.lock().push(Waiter { key, task: Arc::clone(&me) });
+
+		loop {
+			let timed_out = task::timer::block_current_until(deadline);
+			if !dequeue(&me) {
+				return Ok(0);
+			}
+			if timed_out {
+				return Err(ERRNO_ETIMEDOUT);
+			}
+			if crate::signal::interrupted() {
+				return Err(ERRNO_EINTR);
+			}
+			/* Nothing else was runnable: stay queued until the next tick */
+			WAITERS.lock().push(Waiter { key, task: Arc::clone(&me) });
+			interrupts::enable_and_hlt();
+			interrupts::disable();
+			/*
+			 * A FUTEX_WAKE run from that tick found this task still
+			 * Running, so it only took it off the list
+			 */
+			if !queued(&me) {
+				return Ok(0);
+			}
+		}
+	})
+}
+
+/*
+ * wake - Wake tasks sleeping on a futex word (FUTEX_WAKE)
+ * @uaddr: User address of the word
+ * @count: Most tasks to wake
+ *
+ * Return: Ok(number woken), or Err(ERRNO_EINVAL / ERRNO_EFAULT)
+ */
+pub fn wake(uaddr: u64, count: u32) -> Result<u64, u64> {
+	interrupts::without_interrupts(|| {
+		let (key, _) = lookup(uaddr)?;
+		let woken: Vec<TaskArc> = {
+			let mut waiters = WAITERS.lock();
+			let mut woken = Vec::new();
+			waiters.retain(|w| {
+				if w.key != key || woken.len() >= count as usize {
+					return true;
+				}
+				woken.push(Arc::clone(&w.task));
+				false
+			});
+			woken
+		};
+		let n = woken.len() as u64;
+		for t in woken {
+			/* A waiter that found nothing else to run is still current */
+			let blocked = t.lock().state == task::TaskState::Blocked;
+			if blocked {
+				task::scheduler::wake_task(t);
+			}
+		}
+		Ok(n)
+	})
+}
+
+/*
+ * futex - SYS_FUTEX entry point
+ * @uaddr: User address of the futex word
+ * @op: FUTEX_WAIT or FUTEX_WAKE, optionally with FUTEX_PRIVATE_FLAG
+ * @val: Expected value (WAIT) or most tasks to wake (WAKE)
+ * @timeout: User pointer to a relative Timespec (WAIT only, 0 = none)
+ *
+ * Return: 0 or the number of tasks woken, or an errno
+ */
+pub fn futex(uaddr: u64, op: u64, val: u64, timeout: u64) -> u64 {
+	let res = match op & !FUTEX_PRIVATE_FLAG {
+		FUTEX_WAIT => {
+			let ts = if timeout == 0 {
+				None
+			} else {
+				let size = core::mem::size_of::<Timespec>();
+				if !crate::syscall::is_user_accessible(timeout as *const u8, size) {
+					return ERRNO_EFAULT;
+				}
+				Some(unsafe { (timeout as *const Timespec).read_unaligned() })
+			};
+			wait(uaddr, val as u32, ts.as_ref())
+		}
+		FUTEX_WAKE => wake(uaddr, val as u32),
+		_ => Err(ERRNO_ENOSYS),
+	};
+	res.unwrap_or_else(|e| e)
+}
@@ -9,7 +9,8 @@
  * Spawned by spawn_kshell() which allocates a kernel stack and enqueues
  * the task before the timer starts.
  *
- * Commands: help, echo, ls, cat, write, mkdir, rm, mount, umount, halt, reboot
+ * Commands: help, echo, ls, cat, pwd, cd, write, mkdir, rm, mount, umount,
+ *           run, kill, halt, reboot
  * I/O:      cmd > file   (overwrite)
  *           cmd >> file  (append)
  */
@@ -102,6 +103,7 @@ fn read_byte() -> u8 {
 			return b;
 		}
 
+		crate::signal::post_console_interrupt();
 		core::hint::spin_loop();
 	}
 }
@@ -144,7 +146,7 @@ fn dispatch(line: &str) {
 	/* Commands that produce text output — buffered so we can redirect */
 	let is_output_cmd = {
 		let first = cmd_line.split_whitespace().next().unwrap_or("");
-		matches!(first, "ls" | "cat" | "echo" | "help")
+		matches!(first, "ls" | "cat" | "echo" | "help" | "pwd")
 	};
 
 	if is_output_cmd {
@@ -157,7 +159,7 @@ fn dispatch(line: &str) {
 				graphics::kprint!("{}", out);
 			}
 			Some((mode, path)) => {
-				write_to_file(path, out.as_bytes(), mode);
+				write_to_file(&abs_path(path), out.as_bytes(), mode);
 			}
 		}
 	} else {
@@ -182,11 +184,17 @@ fn run_output_command(line: &str, out: &mut String) {
 			let _ = writeln!(out, "  echo <text>          - print text");
 			let _ = writeln!(out, "  ls [path]            - list directory");
 			let _ = writeln!(out, "  cat <file>           - print file contents");
+			let _ = writeln!(out, "  pwd                  - print working directory");
+			let _ = writeln!(out, "  cd [path]            - change directory (default /)");
 			let _ = writeln!(out, "  write <file> <data>  - write data to file");
 			let _ = writeln!(out, "  mkdir <path>         - create directory");
 			let _ = writeln!(out, "  rm <path>            - remove file");
 			let _ = writeln!(out, "  mount <dev> <path>   - mount filesystem");
 			let _ = writeln!(out, "  umount <path>        - unmount filesystem");
+			let _ = writeln!(out, "  swapon [dev]         - swap to a block device / show swap");
+			let _ = writeln!(out, "  heaplimit [MiB]      - set / show how far the kernel heap may grow");
+			let _ = writeln!(out, "  run [-l] <elf> [args] - start a user program (-l: Linux ABI)");
+			let _ = writeln!(out, "  kill <pid> [sig]     - send a signal (default SIGTERM)");
 			let _ = writeln!(out, "  halt                 - stop the CPU");
 			let _ = writeln!(out, "  reboot               - triple-fault reboot");
 		}
@@ -196,7 +204,7 @@ fn run_output_command(line: &str, out: &mut String) {
 		}
 
 		"ls" => {
-			let p = if args.is_empty() { String::from("/") } else { abs_path(args) };
+			let p = abs_path(args);
 			match vfs::lookup_path(&p) {
 				None => {
 					let _ = writeln!(out, "ls: {}: not found", p);
@@ -225,6 +233,10 @@ fn run_output_command(line: &str, out: &mut String) {
 			}
 		}
 
+		"pwd" => {
+			let _ = writeln!(out, "{}", abs_path("."));
+		}
+
 		"cat" => {
 			if args.is_empty() {
 				let _ = writeln!(out, "usage: cat <file>");
@@ -274,6 +286,19 @@ fn run_side_effect_command(line: &str) {
 	let arg2 = parts.next().unwrap_or("").trim();
 
 	match cmd {
+		"cd" => {
+			let p = abs_path(if arg1.is_empty() { "/" } else { arg1 });
+			match vfs::lookup_path(&p) {
+				Some(node) if node.metadata() == vfs::FileType::Directory => {
+					if let Some(t) = task::scheduler::current_task_arc() {
+						t.lock().cwd = p;
+					}
+				}
+				Some(_) => graphics::kprintln!("cd: {}: not a directory", p),
+				None    => graphics::kprintln!("cd: {}: not found", p),
+			}
+		}
+
 		"write" => {
 			/* write <file> <data...> */
 			if arg1.is_empty() {
@@ -338,11 +363,13 @@ fn run_side_effect_command(line: &str) {
 				graphics::kprintln!("usage: mount <dev> <path>");
 				return;
 			}
+			let dev = abs_path(arg1);
+			let mnt = abs_path(arg2);
 			/* Look up the block device node */
-			let dev_node = match vfs::lookup_path(arg1) {
+			let dev_node = match vfs::lookup_path(&dev) {
 				Some(n) => n,
 				None => {
-					graphics::kprintln!("mount: {}: not found", arg1);
+					graphics::kprintln!("mount: {}: not found", dev);
 					return;
 				}
 			};
@@ -351,11 +378,11 @@ fn run_side_effect_command(line: &str) {
 			let block_dev = alloc::sync::Arc::new(VfsBlockDevAdapter(dev_node));
 			match fs::probe_and_mount(block_dev) {
 				Some(root) => {
-					vfs::mount(arg2, root);
-					graphics::kprintln!("mount: {} mounted at {}", arg1, arg2);
+					vfs::mount(&mnt, root);
+					graphics::kprintln!("mount: {} mounted at {}", dev, mnt);
 				}
 				None => {
-					graphics::kprintln!("mount: {}: no filesystem recognised", arg1);
+					graphics::kprintln!("mount: {}: no filesystem recognised", dev);
 				}
 			}
 		}
@@ -365,12 +392,125 @@ fn run_side_effect_command(line: &str) {
 				graphics::kprintln!("usage: umount <path>");
 				return;
 			}
-			match vfs::umount(arg1) {
-				Ok(()) => graphics::kprintln!("umount: {} unmounted", arg1),
+			let p = abs_path(arg1);
+			match vfs::umount(&p) {
+				Ok(()) => graphics::kprintln!("umount: {} unmounted", p),
 				Err(e) => graphics::kprintln!("umount: {}", e),
 			}
 		}
 
+		"swapon" => {
+			/* swapon <dev> - without an argument, show the swap area */
+			if arg1.is_empty() {
+				match crate::swap::stats() {
+					Some(s) => graphics::kprintln!("{}: {} kB, {} kB used",
+						s.path, s.total * 4, s.used * 4),
+					None => graphics::kprintln!("swapon: no swap area"),
+				}
+				return;
+			}
+			/*
+			 * Only block devices: a file would be read and written through
+			 * its filesystem from the fault path, which may already hold
+			 * that filesystem's locks.
+			 */
+			let dev = abs_path(arg1);
+			let block_dev = match fs::block_dev(&dev) {
+				Some(d) => d,
+				None => {
+					if vfs::lookup_path(&dev).is_some() {
+						graphics::kprintln!("swapon: {}: not a block device", dev);
+					} else {
+						graphics::kprintln!("swapon: {}: not found", dev);
+					}
+					return;
+				}
+			};
+			match crate::swap::swapon(block_dev, &dev) {
+				Ok(pages) => graphics::kprintln!("swapon: {}: {} kB", dev, pages * 4),
+				Err(e) => graphics::kprintln!("swapon: {}: {}", dev, e),
+			}
+		}
+
+		"heaplimit" => {
+			/* heaplimit <MiB> - without an argument, show the heap and its limit */
+			if !arg1.is_empty() {
+				match arg1.parse::<usize>() {
+					Ok(mib) => {
+						let bytes = mib.saturating_mul(1024 * 1024);
+						x86_64::instructions::interrupts::without_interrupts(|| {
+							memory::heap::set_heap_limit(bytes)
+						});
+					}
+					Err(_) => {
+						graphics::kprintln!("usage: heaplimit [MiB]");
+						return;
+					}
+				}
+			}
+			let heap = x86_64::instructions::interrupts::without_interrupts(memory::heap::heap_stats);
+			graphics::kprintln!("heap: {} kB mapped, {} kB used, limit {} kB",
+				heap.size / 1024, heap.used / 1024, heap.limit / 1024);
+		}
+
+		"run" => {
+			/*
+			 * run [-l] <elf> [args...] - argv[0] is the path as typed;
+			 * -l runs the program under the Linux syscall personality
+			 */
+			let (personality, elf, rest) = if arg1 == "-l" {
+				let mut more = arg2.splitn(2, ' ');
+				let elf = more.next().unwrap_or("");
+				(task::Personality::Linux, elf, more.next().unwrap_or("").trim())
+			} else {
+				(task::Personality::Serix, arg1, arg2)
+			};
+			if elf.is_empty() {
+				graphics::kprintln!("usage: run [-l] <elf> [args]");
+				return;
+			}
+			let p = abs_path(elf);
+			let mut argv: Vec<String> = Vec::new();
+			argv.push(String::from(elf));
+			argv.extend(rest.split_whitespace().map(String::from));
+			let fds = match crate::fd::spawn_table(0, &[]) {
+				Ok(t) => t,
+				Err(_) => {
+					graphics::kprintln!("run: cannot set up stdio");
+					return;
+				}
+			};
+			match crate::process::spawn_user_process(&p, &argv, &[], 0, fds, personality) {
+				Ok(pid) => graphics::kprintln!("run: {} started, pid={}", p, pid),
+				Err(e) => graphics::kprintln!("run: {}: {}", p, e),
+			}
+		}
+
+		"kill" => {
+			/* kill <pid> [signo] - SIGTERM asks a program to shut down cleanly */
+			let pid = match arg1.parse::<u64>() {
+				Ok(p) => p,
+				Err(_) => {
+					graphics::kprintln!("usage: kill <pid> [sig]");
+					return;
+				}
+			};
+			let sig = if arg2.is_empty() {
+				crate::signal::SIGTERM
+			} else {
+				match arg2.parse::<u8>() {
+					Ok(s) => s,
+					Err(_) => {
+						graphics::kprintln!("kill: {}: invalid signal", arg2);
+						return;
+					}
+				}
+			};
+			if crate::signal::send(pid, sig).is_err() {
+				graphics::kprintln!("kill: ({}) - cannot signal process", pid);
+			}
+		}
+
 		"halt" => {
 			graphics::kprintln!("Halting system.");
 			x86_64::instructions::interrupts::disable();
@@ -430,18 +570,16 @@ impl fs::BlockDev for VfsBlockDevAdapter {
 /* ------------------------------------------------------------------ */
 
 /*
- * abs_path - Return path with a leading '/' if not already present.
+ * abs_path - Resolve a path against the shell's working directory.
  *
- * kshell treats all paths as absolute from '/'.  This lets users type
- * 'ls etc' instead of 'ls /etc' without confusion.
+ * Relative paths are taken from the cwd set with 'cd'; the result is
+ * canonical, so 'ls ..' and 'cat ./x' work as expected.  An empty path
+ * names the cwd itself.
  */
 fn abs_path(path: &str) -> String {
-	if path.starts_with('/') {
-		String::from(path)
-	} else {
-		let mut s = String::from("/");
-		s.push_str(path);
-		s
+	match task::scheduler::current_task_arc() {
+		Some(t) => vfs::resolve_path(&t.lock().cwd, path),
+		None    => vfs::resolve_path("/", path),
 	}
 }
 
@@ -523,13 +661,19 @@ pub fn spawn_kshell() -> Result<u64, &'static str> {
 		kstack,
 		ustack:            None,
 		name:              "kshell",
+		tgid:              id_val,
 		parent_id:         0,
 		exit_status:       None,
 		pml4_frame:        None,
 		children:          alloc::vec::Vec::new(),
 		waiting_for_child: false,
+		joiner:            0,
+		vmas:              alloc::sync::Arc::new(spin::Mutex::new(task::vma::VmaList::new())),
+		signals:           task::signal::SignalState::new(),
+		cwd:               String::from("/"),
+		personality:       task::Personality::Serix,
 	};
 
-	task::scheduler::enqueue_task(alloc::sync::Arc::new(spin::Mutex::new(tcb)));
+	task::scheduler::enqueue_task(tcb.into_arc());
 	Ok(id_val)
 }
@@ -0,0 +1,877 @@
+/*
+ * linux.rs - Linux x86_64 Syscall Personality
+ *
+ * Tasks running with task::Personality::Linux (spawned with SPAWN_LINUX,
+ * or from an ELF carrying the Linux ABI note) trap here instead of the
+ * native table. Every Linux call is translated onto the native
+ * implementation: calls with the same arguments and semantics go
+ * straight to syscall::dispatch(), the others convert NUL-terminated
+ * strings, struct layouts and flag values first.
+ *
+ * Coverage targets statically linked musl programs such as a hello
+ * world and busybox applets: file I/O, the *at() calls relative to
+ * AT_FDCWD, stat, brk/mmap, fork/execve/wait4, signals, futexes, time,
+ * terminal ioctls on the console and identity calls answered as root.
+ * Anything else fails with ENOSYS.
+ *
+ * Errno values are shared with the native ABI (negative Linux errnos).
+ */
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::syscall::{
+	self, ERRNO_E2BIG, ERRNO_EBADF, ERRNO_EEXIST, ERRNO_EFAULT, ERRNO_EINVAL, ERRNO_EISDIR,
+	ERRNO_ENODEV, ERRNO_ENOENT, ERRNO_ENOSYS, ERRNO_ENOTDIR, ERRNO_ENOTTY, O_CLOEXEC,
+	is_user_accessible,
+};
+
+/* Linux x86_64 syscall numbers */
+const NR_READ: u64 = 0;
+const NR_WRITE: u64 = 1;
+const NR_OPEN: u64 = 2;
+const NR_CLOSE: u64 = 3;
+const NR_STAT: u64 = 4;
+const NR_FSTAT: u64 = 5;
+const NR_LSTAT: u64 = 6;
+const NR_LSEEK: u64 = 8;
+const NR_MMAP: u64 = 9;
+const NR_MPROTECT: u64 = 10;
+const NR_MUNMAP: u64 = 11;
+const NR_BRK: u64 = 12;
+const NR_RT_SIGACTION: u64 = 13;
+const NR_RT_SIGPROCMASK: u64 = 14;
+const NR_RT_SIGRETURN: u64 = 15;
+const NR_IOCTL: u64 = 16;
+const NR_READV: u64 = 19;
+const NR_WRITEV: u64 = 20;
+const NR_ACCESS: u64 = 21;
+const NR_PIPE: u64 = 22;
+const NR_SCHED_YIELD: u64 = 24;
+const NR_MADVISE: u64 = 28;
+const NR_DUP: u64 = 32;
+const NR_DUP2: u64 = 33;
+const NR_NANOSLEEP: u64 = 35;
+const NR_GETPID: u64 = 39;
+const NR_CLONE: u64 = 56;
+const NR_FORK: u64 = 57;
+const NR_VFORK: u64 = 58;
+const NR_EXECVE: u64 = 59;
+const NR_EXIT: u64 = 60;
+const NR_WAIT4: u64 = 61;
+const NR_KILL: u64 = 62;
+const NR_UNAME: u64 = 63;
+const NR_FCNTL: u64 = 72;
+const NR_GETCWD: u64 = 79;
+const NR_CHDIR: u64 = 80;
+const NR_MKDIR: u64 = 83;
+const NR_RMDIR: u64 = 84;
+const NR_UNLINK: u64 = 87;
+const NR_READLINK: u64 = 89;
+const NR_UMASK: u64 = 95;
+const NR_GETTIMEOFDAY: u64 = 96;
+const NR_GETUID: u64 = 102;
+const NR_GETGID: u64 = 104;
+const NR_GETEUID: u64 = 107;
+const NR_GETEGID: u64 = 108;
+const NR_GETPPID: u64 = 110;
+const NR_ARCH_PRCTL: u64 = 158;
+const NR_GETTID: u64 = 186;
+const NR_TIME: u64 = 201;
+const NR_FUTEX: u64 = 202;
+const NR_GETDENTS64: u64 = 217;
+const NR_SET_TID_ADDRESS: u64 = 218;
+const NR_CLOCK_GETTIME: u64 = 228;
+const NR_EXIT_GROUP: u64 = 231;
+const NR_OPENAT: u64 = 257;
+const NR_MKDIRAT: u64 = 258;
+const NR_NEWFSTATAT: u64 = 262;
+const NR_UNLINKAT: u64 = 263;
+const NR_READLINKAT: u64 = 267;
+const NR_FACCESSAT: u64 = 269;
+const NR_SET_ROBUST_LIST: u64 = 273;
+const NR_DUP3: u64 = 292;
+const NR_PIPE2: u64 = 293;
+const NR_GETRANDOM: u64 = 318;
+
+/* open() flags */
+const O_ACCMODE: u64 = 0o3;
+const O_RDONLY: u64 = 0o0;
+const O_RDWR: u64 = 0o2;
+const O_CREAT: u64 = 0o100;
+const O_EXCL: u64 = 0o200;
+const O_TRUNC: u64 = 0o1000;
+const O_APPEND: u64 = 0o2000;
+const O_DIRECTORY: u64 = 0o200000;
+
+/* *at() arguments */
+const AT_FDCWD: i64 = -100;
+const AT_EMPTY_PATH: u64 = 0x1000;
+
+/* lseek() whence */
+const SEEK_SET: u64 = 0;
+const SEEK_CUR: u64 = 1;
+const SEEK_END: u64 = 2;
+
+/* fcntl() commands not known to the native SYS_FCNTL */
+const F_GETFL: u64 = 3;
+const F_SETFL: u64 = 4;
+
+/* mmap() flags that are only hints here */
+const MAP_NORESERVE: u64 = 0x4000;
+const MAP_POPULATE: u64 = 0x8000;
+const MAP_STACK: u64 = 0x20000;
+
+/* clone() with only an exit signal is plain fork() */
+const CLONE_SIGNAL_MASK: u64 = 0xFF;
+
+/* Terminal ioctls */
+const TCGETS: u64 = 0x5401;
+const TCSETS: u64 = 0x5402;
+const TCSETSW: u64 = 0x5403;
+const TCSETSF: u64 = 0x5404;
+
+/* Clock IDs folded onto the two native clocks */
+const CLOCK_REALTIME_COARSE: u64 = 5;
+const CLOCK_MONOTONIC_RAW: u64 = 4;
+const CLOCK_MONOTONIC_COARSE: u64 = 6;
+const CLOCK_BOOTTIME: u64 = 7;
+
+/* Longest path accepted from a Linux program, including the NUL */
+const PATH_MAX: usize = 4096;
+
+/* Most iovecs accepted by readv()/writev() */
+const IOV_MAX: usize = 1024;
+
+/* sizeof(struct rusage), zeroed by wait4() */
+const RUSAGE_SIZE: usize = 144;
+
+/* st_mode file types and the permissions reported for them */
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/*
+ * struct Stat - Linux x86_64 struct stat
+ *
+ * The VFS has no inode numbers, owners or timestamps, so those read 0.
+ */
+#[repr(C)]
+#[derive(Default)]
+struct Stat {
+	st_dev: u64,
+	st_ino: u64,
+	st_nlink: u64,
+	st_mode: u32,
+	st_uid: u32,
+	st_gid: u32,
+	__pad0: u32,
+	st_rdev: u64,
+	st_size: i64,
+	st_blksize: i64,
+	st_blocks: i64,
+	st_atime: [i64; 2],
+	st_mtime: [i64; 2],
+	st_ctime: [i64; 2],
+	__unused: [i64; 3],
+}
+
+/*
+ * struct Termios - Kernel struct termios as used by TCGETS/TCSETS
+ */
+#[repr(C)]
+struct Termios {
+	c_iflag: u32,
+	c_oflag: u32,
+	c_cflag: u32,
+	c_lflag: u32,
+	c_line: u8,
+	c_cc: [u8; 19],
+}
+
+/*
+ * struct IoVec - One readv()/writev() buffer
+ */
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IoVec {
+	base: u64,
+	len: u64,
+}
+
+/*
+ * struct UtsName - uname() result, six NUL-padded 65-byte fields
+ */
+#[repr(C)]
+struct UtsName {
+	sysname: [u8; 65],
+	nodename: [u8; 65],
+	release: [u8; 65],
+	version: [u8; 65],
+	machine: [u8; 65],
+	domainname: [u8; 65],
+}
+
+/*
+ * dispatch - Entry point for tasks with the Linux personality
+ * @nr: Linux syscall number
+ * @a: Arguments in Linux order (RDI, RSI, RDX, R10, R8, R9)
+ *
+ * Return: Result or negative errno, as the native dispatcher
+ */
+pub fn dispatch(nr: u64, a: [u64; 6]) -> u64 {
+	match nr {
+		NR_READ => native(syscall::SYS_READ, &a[..3]),
+		NR_WRITE => native(syscall::SYS_WRITE, &a[..3]),
+		NR_CLOSE => native(syscall::SYS_CLOSE, &a[..1]),
+		NR_OPEN => openat(AT_FDCWD as u64, a[0], a[1]),
+		NR_OPENAT => openat(a[0], a[1], a[2]),
+		NR_STAT | NR_LSTAT => stat_path(AT_FDCWD as u64, a[0], a[1]),
+		NR_FSTAT => fstat(a[0], a[1]),
+		NR_NEWFSTATAT => {
+			if a[3] & AT_EMPTY_PATH != 0 && user_strlen(a[1]) == Ok(0) {
+				fstat(a[0], a[2])
+			} else {
+				stat_path(a[0], a[1], a[2])
+			}
+		}
+		NR_ACCESS => access(AT_FDCWD as u64, a[0]),
+		NR_FACCESSAT => access(a[0], a[1]),
+		NR_LSEEK => lseek(a[0], a[1] as i64, a[2]),
+		NR_READV => readv_writev(syscall::SYS_READ, a[0], a[1], a[2]),
+		NR_WRITEV => readv_writev(syscall::SYS_WRITE, a[0], a[1], a[2]),
+		NR_IOCTL => ioctl(a[0], a[1], a[2]),
+		NR_GETDENTS64 => native(syscall::SYS_GETDENTS, &a[..3]),
+		NR_FCNTL => fcntl(a[0], a[1], a[2]),
+		NR_DUP => native(syscall::SYS_DUP, &a[..1]),
+		NR_DUP2 => native(syscall::SYS_DUP2, &[a[0], a[1], 0]),
+		NR_DUP3 => {
+			if a[0] == a[1] || a[2] & !O_CLOEXEC != 0 {
+				return ERRNO_EINVAL;
+			}
+			native(syscall::SYS_DUP2, &a[..3])
+		}
+		NR_PIPE => pipe2(a[0], 0),
+		NR_PIPE2 => pipe2(a[0], a[1]),
+
+		NR_GETCWD => native(syscall::SYS_GETCWD, &a[..2]),
+		NR_CHDIR => path_call(syscall::SYS_CHDIR, AT_FDCWD as u64, a[0]),
+		NR_MKDIR => path_call(syscall::SYS_MKDIR, AT_FDCWD as u64, a[0]),
+		NR_MKDIRAT => path_call(syscall::SYS_MKDIR, a[0], a[1]),
+		NR_UNLINK | NR_RMDIR => path_call(syscall::SYS_UNLINK, AT_FDCWD as u64, a[0]),
+		NR_UNLINKAT => path_call(syscall::SYS_UNLINK, a[0], a[1]),
+		/* There are no symbolic links */
+		NR_READLINK | NR_READLINKAT => ERRNO_EINVAL,
+
+		NR_MMAP => {
+			if a[3] & crate::mm::MAP_ANONYMOUS == 0 {
+				return ERRNO_ENODEV;
+			}
+			let flags = a[3] & !(MAP_NORESERVE | MAP_POPULATE | MAP_STACK);
+			crate::mm::mmap(a[0], a[1], a[2], flags).unwrap_or_else(|e| e)
+		}
+		NR_MUNMAP => crate::mm::munmap(a[0], a[1]).unwrap_or_else(|e| e),
+		NR_MPROTECT => crate::mm::mprotect(a[0], a[1], a[2]).unwrap_or_else(|e| e),
+		NR_BRK => crate::mm::brk(a[0]),
+		NR_MADVISE => 0,
+
+		NR_GETPID => native(syscall::SYS_GETPID, &[]),
+		NR_GETTID | NR_SET_TID_ADDRESS => native(syscall::SYS_GETTID, &[]),
+		NR_GETPPID => native(syscall::SYS_GETPPID, &[]),
+		NR_GETUID | NR_GETGID | NR_GETEUID | NR_GETEGID => 0,
+		NR_UMASK => 0o022,
+		NR_SCHED_YIELD => native(syscall::SYS_YIELD, &[]),
+		NR_FORK | NR_VFORK => native(syscall::SYS_FORK, &[]),
+		NR_CLONE => {
+			if a[0] & !CLONE_SIGNAL_MASK != 0 {
+				return ERRNO_ENOSYS;
+			}
+			native(syscall::SYS_FORK, &[])
+		}
+		NR_EXECVE => execve(a[0], a[1], a[2]),
+		NR_EXIT => native(syscall::SYS_THREAD_EXIT, &a[..1]),
+		NR_EXIT_GROUP => native(syscall::SYS_EXIT, &a[..1]),
+		NR_WAIT4 => wait4(a[0] as i64, a[1], a[2], a[3]),
+		NR_KILL => native(syscall::SYS_KILL, &a[..2]),
+		NR_UNAME => uname(a[0]),
+		NR_ARCH_PRCTL => native(syscall::SYS_ARCH_PRCTL, &a[..2]),
+		NR_FUTEX => native(syscall::SYS_FUTEX, &a[..4]),
+		NR_SET_ROBUST_LIST => 0,
+
+		NR_RT_SIGACTION => rt_sigaction(a[0], a[1], a[2], a[3]),
+		NR_RT_SIGPROCMASK => rt_sigprocmask(a[0], a[1], a[2], a[3]),
+		NR_RT_SIGRETURN => native(syscall::SYS_SIGRETURN, &[]),
+
+		NR_NANOSLEEP => native(syscall::SYS_NANOSLEEP, &a[..2]),
+		NR_CLOCK_GETTIME => {
+			let clock = match a[0] {
+				CLOCK_REALTIME_COARSE => crate::time::CLOCK_REALTIME,
+				CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
+					crate::time::CLOCK_MONOTONIC
+				}
+				c => c,
+			};
+			native(syscall::SYS_CLOCK_GETTIME, &[clock, a[1]])
+		}
+		NR_GETTIMEOFDAY => gettimeofday(a[0]),
+		NR_TIME => {
+			let now = hal::clock::unix_time();
+			if a[0] != 0 && put_user(a[0], &now).is_err() {
+				return ERRNO_EFAULT;
+			}
+			now
+		}
+		NR_GETRANDOM => {
+			let len = a[1] as usize;
+			if len == 0 {
+				return 0;
+			}
+			if !is_user_accessible(a[0] as *const u8, len) {
+				return ERRNO_EFAULT;
+			}
+			hal::random::fill_bytes(unsafe { core::slice::from_raw_parts_mut(a[0] as *mut u8, len) });
+			len as u64
+		}
+
+		_ => {
+			hal::serial_println!("[LINUX] Unimplemented syscall: {}", nr);
+			ERRNO_ENOSYS
+		}
+	}
+}
+
+/* ---------------------------------------------------------------------- */
+
+/*
+ * native - Forward a call to the native table
+ * @nr: Native SYS_* number
+ * @args: Leading arguments; the rest are passed as 0
+ */
+fn native(nr: u64, args: &[u64]) -> u64 {
+	let mut a = [0u64; 6];
+	a[..args.len()].copy_from_slice(args);
+	syscall::dispatch(nr, a[0], a[1], a[2], a[3], a[4], a[5])
+}
+
+/*
+ * put_user - Copy a value out to userspace
+ * @addr: User destination address
+ * @val: Value to copy
+ */
+fn put_user<T>(addr: u64, val: &T) -> Result<(), u64> {
+	let size = core::mem::size_of::<T>();
+	if !is_user_accessible(addr as *const u8, size) {
+		return Err(ERRNO_EFAULT);
+	}
+	unsafe {
+		core::ptr::copy_nonoverlapping(val as *const T as *const u8, addr as *mut u8, size);
+	}
+	Ok(())
+}
+
+/*
+ * user_strlen - Length of a NUL-terminated user string
+ * @ptr: User address of the string
+ *
+ * Return: Length without the NUL, EFAULT if unreadable, or
+ *         ERRNO_EINVAL if no NUL appears within PATH_MAX bytes
+ */
+fn user_strlen(ptr: u64) -> Result<u64, u64> {
+	for i in 0..PATH_MAX as u64 {
+		let p = ptr.checked_add(i).ok_or(ERRNO_EFAULT)?;
+		if !is_user_accessible(p as *const u8, 1) {
+			return Err(ERRNO_EFAULT);
+		}
+		if unsafe { *(p as *const u8) } == 0 {
+			return Ok(i);
+		}
+	}
+	Err(ERRNO_EINVAL)
+}
+
+/*
+ * copy_cstr - Copy a NUL-terminated user string
+ * @ptr: User address of the string
+ */
+fn copy_cstr(ptr: u64) -> Result<String, u64> {
+	let len = user_strlen(ptr)?;
+	syscall::copy_user_str(ptr, len)
+}
+
+/*
+ * copy_cstr_array - Copy a NULL-terminated char *[] (argv/envp)
+ * @ptr: User address of the pointer array (0 = empty)
+ * @budget: Remaining process::ARG_MAX bytes, charged as SYS_EXEC does
+ */
+fn copy_cstr_array(ptr: u64, budget: &mut usize) -> Result<Vec<String>, u64> {
+	let mut out = Vec::new();
+	if ptr == 0 {
+		return Ok(out);
+	}
+	for i in 0.. {
+		let slot = ptr.checked_add(i * 8).ok_or(ERRNO_EFAULT)?;
+		if !is_user_accessible(slot as *const u8, 8) {
+			return Err(ERRNO_EFAULT);
+		}
+		let s = unsafe { (slot as *const u64).read_unaligned() };
+		if s == 0 {
+			break;
+		}
+		let arg = copy_cstr(s)?;
+		let cost = arg.len() + 1 + 8;
+		if cost > *budget {
+			return Err(ERRNO_E2BIG);
+		}
+		*budget -= cost;
+		out.push(arg);
+	}
+	Ok(out)
+}
+
+/*
+ * at_path - Resolve the path argument of an *at() call
+ * @dirfd: AT_FDCWD, or a directory fd (only for absolute paths)
+ * @ptr: User address of the NUL-terminated path
+ *
+ * Open files do not remember their path, so a relative path is only
+ * accepted against AT_FDCWD.
+ *
+ * Return: Canonical absolute path, or an errno
+ */
+fn at_path(dirfd: u64, ptr: u64) -> Result<String, u64> {
+	let path = copy_cstr(ptr)?;
+	if path.is_empty() {
+		return Err(ERRNO_ENOENT);
+	}
+	if !path.starts_with('/') && dirfd as i64 != AT_FDCWD {
+		return Err(ERRNO_EINVAL);
+	}
+	Ok(syscall::resolve(&path))
+}
+
+/*
+ * path_call - Forward a path-only call to its native counterpart
+ * @nr: Native SYS_* number taking (path_ptr, path_len)
+ * @dirfd: AT_FDCWD, or a directory fd (only for absolute paths)
+ * @ptr: User address of the NUL-terminated path
+ */
+fn path_call(nr: u64, dirfd: u64, ptr: u64) -> u64 {
+	let len = match user_strlen(ptr) {
+		Ok(l) => l,
+		Err(e) => return e,
+	};
+	if dirfd as i64 != AT_FDCWD && (len == 0 || unsafe { *(ptr as *const u8) } != b'/') {
+		return ERRNO_EINVAL;
+	}
+	native(nr, &[ptr, len])
+}
+
+/* ---------------------------------------------------------------------- */
+
+/*
+ * openat - Open or create a file
+ * @dirfd: AT_FDCWD, or a directory fd (only for absolute paths)
+ * @path_ptr: User address of the NUL-terminated path
+ * @flags: O_* flags
+ *
+ * The VFS has no truncate, so O_TRUNC on a non-empty file replaces it
+ * with a fresh one. O_APPEND only positions the offset at the end.
+ *
+ * Return: New fd, or an errno
+ */
+fn openat(dirfd: u64, path_ptr: u64, flags: u64) -> u64 {
+	let path = match at_path(dirfd, path_ptr) {
+		Ok(p) => p,
+		Err(e) => return e,
+	};
+	let (parent_path, name) = vfs::split_parent(&path);
+
+	let mut node = match vfs::lookup_path(&path) {
+		Some(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => return ERRNO_EEXIST,
+		Some(n) => n,
+		None if flags & O_CREAT != 0 => {
+			match vfs::lookup_path(parent_path).map(|dir| dir.create_file(name)) {
+				Some(Ok(n)) => n,
+				Some(Err(_)) => return ERRNO_EINVAL,
+				None => return ERRNO_ENOENT,
+			}
+		}
+		None => return ERRNO_ENOENT,
+	};
+
+	let is_dir = node.metadata() == vfs::FileType::Directory;
+	if flags & O_DIRECTORY != 0 && !is_dir {
+		return ERRNO_ENOTDIR;
+	}
+	if is_dir && flags & O_ACCMODE != O_RDONLY {
+		return ERRNO_EISDIR;
+	}
+	if flags & O_TRUNC != 0 && node.metadata() == vfs::FileType::File && node.size() > 0 {
+		let Some(dir) = vfs::lookup_path(parent_path) else {
+			return ERRNO_ENOENT;
+		};
+		node = match dir.unlink(name).and_then(|_| dir.create_file(name)) {
+			Ok(n) => n,
+			Err(_) => return ERRNO_EINVAL,
+		};
+	}
+
+	let task_id = task::scheduler::current_task_id();
+	let size = node.size();
+	let fd = match crate::fd::insert_inode(task_id, node, flags & O_CLOEXEC) {
+		Ok(fd) => fd,
+		Err(e) => return e,
+	};
+	if flags & O_APPEND != 0 {
+		crate::fd::seek(task_id, fd, size);
+	}
+	fd
+}
+
+/*
+ * write_stat - Fill a Linux struct stat for a VFS node
+ * @node: Node to describe
+ * @buf: User address of the struct stat
+ */
+fn write_stat(node: &dyn vfs::INode, buf: u64) -> u64 {
+	let mode = match node.metadata() {
+		vfs::FileType::File => S_IFREG | 0o644,
+		vfs::FileType::Directory => S_IFDIR | 0o755,
+		vfs::FileType::Device => S_IFCHR | 0o666,
+	};
+	let size = node.size() as i64;
+	let st = Stat {
+		st_nlink: 1,
+		st_mode: mode,
+		st_size: size,
+		st_blksize: 4096,
+		st_blocks: (size + 511) / 512,
+		..Stat::default()
+	};
+	match put_user(buf, &st) {
+		Ok(()) => 0,
+		Err(e) => e,
+	}
+}
+
+/*
+ * stat_path - stat()/newfstatat() on a path
+ */
+fn stat_path(dirfd: u64, path_ptr: u64, buf: u64) -> u64 {
+	let path = match at_path(dirfd, path_ptr) {
+		Ok(p) => p,
+		Err(e) => return e,
+	};
+	match vfs::lookup_path(&path) {
+		Some(node) => write_stat(&*node, buf),
+		None => ERRNO_ENOENT,
+	}
+}
+
+/*
+ * fstat - stat() on an open fd
+ */
+fn fstat(fd: u64, buf: u64) -> u64 {
+	match crate::fd::get(task::scheduler::current_task_id(), fd) {
+		Some(file) => write_stat(&*file.inode, buf),
+		None => ERRNO_EBADF,
+	}
+}
+
+/*
+ * access - access()/faccessat(): existence check only, as root
+ */
+fn access(dirfd: u64, path_ptr: u64) -> u64 {
+	match at_path(dirfd, path_ptr) {
+		Ok(path) if vfs::lookup_path(&path).is_some() => 0,
+		Ok(_) => ERRNO_ENOENT,
+		Err(e) => e,
+	}
+}
+
+/*
+ * lseek - Reposition an fd with SEEK_SET, SEEK_CUR or SEEK_END
+ *
+ * Return: New offset, or an errno
+ */
+fn lseek(fd: u64, off: i64, whence: u64) -> u64 {
+	let Some(file) = crate::fd::get(task::scheduler::current_task_id(), fd) else {
+		return ERRNO_EBADF;
+	};
+	let mut pos = file.offset.lock();
+	let base = match whence {
+		SEEK_SET => 0,
+		SEEK_CUR => *pos as i64,
+		SEEK_END => file.inode.size() as i64,
+		_ => return ERRNO_EINVAL,
+	};
+	match base.checked_add(off) {
+		Some(new) if new >= 0 => {
+			*pos = new as usize;
+			new as u64
+		}
+		_ => ERRNO_EINVAL,
+	}
+}
+
+/*
+ * readv_writev - Scatter/gather I/O through native SYS_READ / SYS_WRITE
+ * @nr: SYS_READ or SYS_WRITE
+ * @fd: File descriptor
+ * @iov: User address of the IoVec array
+ * @cnt: Number of IoVecs
+ *
+ * Stops at the first short transfer. An error is only reported if
+ * nothing was transferred before it.
+ */
+fn readv_writev(nr: u64, fd: u64, iov: u64, cnt: u64) -> u64 {
+	let cnt = cnt as usize;
+	if cnt > IOV_MAX {
+		return ERRNO_EINVAL;
+	}
+	let size = cnt * core::mem::size_of::<IoVec>();
+	if cnt > 0 && !is_user_accessible(iov as *const u8, size) {
+		return ERRNO_EFAULT;
+	}
+	let mut total = 0u64;
+	for i in 0..cnt {
+		let v = unsafe { (iov as *const IoVec).add(i).read_unaligned() };
+		if v.len == 0 {
+			continue;
+		}
+		let n = native(nr, &[fd, v.base, v.len]);
+		if n > -4096i64 as u64 {
+			return if total == 0 { n } else { total };
+		}
+		total += n;
+		if n < v.len {
+			break;
+		}
+	}
+	total
+}
+
+/*
+ * ioctl - Terminal ioctls on the console
+ *
+ * TCGETS reports a cooked 8-bit terminal; TCSETS* are accepted and
+ * ignored since the console has no modes to change.
+ */
+fn ioctl(fd: u64, cmd: u64, arg: u64) -> u64 {
+	let Some(file) = crate::fd::get(task::scheduler::current_task_id(), fd) else {
+		return ERRNO_EBADF;
+	};
+	if !file.inode.is_tty() {
+		return ERRNO_ENOTTY;
+	}
+	match cmd {
+		TCGETS => {
+			let mut c_cc = [0u8; 19];
+			c_cc[0] = 0x03; /* VINTR: ^C */
+			c_cc[2] = 0x7F; /* VERASE: DEL */
+			c_cc[4] = 0x04; /* VEOF: ^D */
+			c_cc[6] = 1;    /* VMIN */
+			let t = Termios {
+				c_iflag: 0o400,             /* ICRNL */
+				c_oflag: 0o5,               /* OPOST | ONLCR */
+				c_cflag: 0o277,             /* B38400 | CS8 | CREAD */
+				c_lflag: 0o100073,          /* ISIG | ICANON | ECHO | ECHOE | ECHOK | IEXTEN */
+				c_line: 0,
+				c_cc,
+			};
+			match put_user(arg, &t) {
+				Ok(()) => 0,
+				Err(e) => e,
+			}
+		}
+		TCSETS | TCSETSW | TCSETSF => 0,
+		_ => ERRNO_ENOTTY,
+	}
+}
+
+/*
+ * fcntl - Native SYS_FCNTL plus F_GETFL / F_SETFL
+ *
+ * Files carry no status flags, so F_GETFL reports O_RDWR and F_SETFL
+ * accepts and ignores its argument.
+ */
+fn fcntl(fd: u64, cmd: u64, arg: u64) -> u64 {
+	match cmd {
+		F_GETFL | F_SETFL => {
+			if crate::fd::get(task::scheduler::current_task_id(), fd).is_none() {
+				return ERRNO_EBADF;
+			}
+			if cmd == F_GETFL { O_RDWR } else { 0 }
+		}
+		_ => native(syscall::SYS_FCNTL, &[fd, cmd, arg]),
+	}
+}
+
+/*
+ * pipe2 - Create a pipe, storing the two fds as C ints
+ */
+fn pipe2(fds_ptr: u64, flags: u64) -> u64 {
+	if flags & !O_CLOEXEC != 0 {
+		return ERRNO_EINVAL;
+	}
+	if !is_user_accessible(fds_ptr as *const u8, 8) {
+		return ERRNO_EFAULT;
+	}
+	let task_id = task::scheduler::current_task_id();
+	match crate::pipe::create_pipe(task_id, flags) {
+		Ok((r, w)) => match put_user(fds_ptr, &[r as i32, w as i32]) {
+			Ok(()) => 0,
+			Err(e) => e,
+		},
+		Err(e) => e,
+	}
+}
+
+/*
+ * execve - Replace the image with char *[] argv/envp
+ *
+ * The Linux personality carries over into the new image.
+ */
+fn execve(path_ptr: u64, argv_ptr: u64, envp_ptr: u64) -> u64 {
+	let path = match at_path(AT_FDCWD as u64, path_ptr) {
+		Ok(p) => p,
+		Err(e) => return e,
+	};
+	let mut budget = crate::process::ARG_MAX;
+	let argv = match copy_cstr_array(argv_ptr, &mut budget) {
+		Ok(v) => v,
+		Err(e) => return e,
+	};
+	let envp = match copy_cstr_array(envp_ptr, &mut budget) {
+		Ok(v) => v,
+		Err(e) => return e,
+	};
+
+	let task_id = task::scheduler::current_task_id();
+	let kstack = match task::scheduler::current_task_arc() {
+		Some(arc) => arc.lock().kstack,
+		None => return ERRNO_EINVAL,
+	};
+	let frame = unsafe { &mut *syscall::frame_at(kstack) };
+	match crate::process::exec_process(task_id, &path, &argv, &envp, frame) {
+		Ok(()) => 0,
+		Err(e) => e,
+	}
+}
+
+/*
+ * wait4 - Native SYS_WAIT with Linux pid and rusage conventions
+ *
+ * There are no process groups: pid 0 and pid < -1 wait for any child.
+ * Only WNOHANG is honoured; resource usage is reported as zero.
+ */
+fn wait4(pid: i64, status: u64, options: u64, rusage: u64) -> u64 {
+	let pid = if pid > 0 { pid } else { -1 };
+	if rusage != 0 && put_user(rusage, &[0u8; RUSAGE_SIZE]).is_err() {
+		return ERRNO_EFAULT;
+	}
+	native(syscall::SYS_WAIT, &[pid as u64, status, options & 1])
+}
+
+/*
+ * uname - Describe the system
+ */
+fn uname(buf: u64) -> u64 {
+	fn field(s: &str) -> [u8; 65] {
+		let mut f = [0u8; 65];
+		f[..s.len()].copy_from_slice(s.as_bytes());
+		f
+	}
+	let uts = UtsName {
+		sysname: field("Serix"),
+		nodename: field("serix"),
+		release: field(env!("CARGO_PKG_VERSION")),
+		version: field("#1"),
+		machine: field("x86_64"),
+		domainname: field("(none)"),
+	};
+	match put_user(buf, &uts) {
+		Ok(()) => 0,
+		Err(e) => e,
+	}
+}
+
+/*
+ * Linux numbers signal N with mask bit N-1; native masks use bit N.
+ */
+fn mask_from_linux(mask: u64) -> u64 {
+	(mask << 1) & 0xFFFF_FFFE
+}
+
+fn mask_to_linux(mask: u64) -> u64 {
+	mask >> 1
+}
+
+/*
+ * rt_sigaction - Native sigaction with Linux sigset bit numbering
+ *
+ * Linux's kernel struct sigaction has the native SigAction layout.
+ */
+fn rt_sigaction(sig: u64, act_ptr: u64, old_ptr: u64, sigsetsize: u64) -> u64 {
+	use task::signal::SigAction;
+
+	if sigsetsize != 8 || sig > u8::MAX as u64 {
+		return ERRNO_EINVAL;
+	}
+	let size = core::mem::size_of::<SigAction>();
+	if act_ptr != 0 && !is_user_accessible(act_ptr as *const u8, size) {
+		return ERRNO_EFAULT;
+	}
+	let act = (act_ptr != 0).then(|| {
+		let a = unsafe { (act_ptr as *const SigAction).read_unaligned() };
+		SigAction { mask: mask_from_linux(a.mask), ..a }
+	});
+	match crate::signal::sigaction(sig as u8, act) {
+		Ok(old) if old_ptr != 0 => {
+			match put_user(old_ptr, &SigAction { mask: mask_to_linux(old.mask), ..old }) {
+				Ok(()) => 0,
+				Err(e) => e,
+			}
+		}
+		Ok(_) => 0,
+		Err(e) => e,
+	}
+}
+
+/*
+ * rt_sigprocmask - Native sigprocmask with Linux sigset bit numbering
+ */
+fn rt_sigprocmask(how: u64, set_ptr: u64, old_ptr: u64, sigsetsize: u64) -> u64 {
+	if sigsetsize != 8 {
+		return ERRNO_EINVAL;
+	}
+	if set_ptr != 0 && !is_user_accessible(set_ptr as *const u8, 8) {
+		return ERRNO_EFAULT;
+	}
+	let set = (set_ptr != 0).then(|| mask_from_linux(unsafe { (set_ptr as *const u64).read_unaligned() }));
+	match crate::signal::sigprocmask(how, set) {
+		Ok(old) if old_ptr != 0 => match put_user(old_ptr, &mask_to_linux(old)) {
+			Ok(()) => 0,
+			Err(e) => e,
+		},
+		Ok(_) => 0,
+		Err(e) => e,
+	}
+}
+
+/*
+ * gettimeofday - Wall-clock time as struct timeval
+ */
+fn gettimeofday(tv: u64) -> u64 {
+	if tv == 0 {
+		return 0;
+	}
+	let ns = hal::clock::realtime_ns();
+	let val = [(ns / 1_000_000_000) as i64, (ns % 1_000_000_000 / 1_000) as i64];
+	match put_user(tv, &val) {
+		Ok(()) => 0,
+		Err(e) => e,
+	}
+}
@@ -6,17 +6,27 @@
  */
 
 #![feature(abi_x86_interrupt)]
+#![feature(allocator_api)]
 #![no_std]
 #![no_main]
 
 extern crate alloc;
 pub mod fd;
+mod fault;
+mod futex;
 mod gdt;
 mod kshell;
+mod linux;
+mod mm;
 pub mod pipe;
 pub mod process;
+mod procfs;
+mod shm;
+mod signal;
 pub mod stdio;
+mod swap;
 mod syscall;
+mod time;
 
 use capability::CapabilityStore;
 use core::panic::PanicInfo;
@@ -27,7 +37,8 @@ use hal::serial_println;
 use limine::BaseRevision;
 use limine::request::{FramebufferRequest, HhdmRequest, MemoryMapRequest};
 use loader::LoadableSegment;
-use memory::heap::{StaticBootFrameAllocator, init_heap};
+use memory::buddy::BuddyAllocator;
+use memory::heap::init_heap;
 use spin::{Mutex, Once};
 use task::{Scheduler, TaskCB};
 use task::init_executor;
@@ -36,7 +47,7 @@ use vfs::INode;
 use x86_64::instructions::hlt;
 use x86_64::structures::idt::InterruptStackFrame;
 use x86_64::structures::paging::{
-	FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+	FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
 };
 use x86_64::{PhysAddr, VirtAddr};
 /* Limine protocol requests */
@@ -76,6 +87,8 @@ pub fn panic(info: &PanicInfo) -> ! {
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
 	use x86_64::instructions::port::Port;
 
+	idt::count_interrupt(33);
+
 	/* Read scancode from keyboard data port (0x60) */
 	let mut port = Port::new(0x60);
 	let scancode: u8 = unsafe { port.read() };
@@ -355,6 +368,9 @@ pub extern "C" fn _start() -> ! {
 	hal::init_serial();
 	serial_println!("Serix Kernel v0.0.6 Starting.....");
 
+	/* Calibrate the TSC and read the RTC while interrupts are still off */
+	hal::clock::init();
+
 	/* Initialize Global Descriptor Table */
 	gdt::init();
 	task::register_switch_hook(|kstack| {
@@ -372,12 +388,20 @@ pub extern "C" fn _start() -> ! {
 		apic::timer::register_handler();
 		/* Register keyboard handler (defined in this module to avoid circular deps) */
 		idt::register_interrupt_handler(33, keyboard_interrupt_handler);
+		idt::name_interrupt(33, "keyboard");
+		/* Copy-on-write and demand paging of user memory */
+		idt::register_page_fault_hook(fault::handle_page_fault);
+		/* Ring 3 exceptions kill the task instead of the kernel */
+		idt::register_user_fault_hook(fault::handle_user_fault);
+		/* Pending signals are delivered whenever an interrupt returns to Ring 3 */
+		idt::register_return_to_user_hook(signal::deliver_trap);
 	}
 
 	/* Setup CPU exception handlers and load IDT */
 	idt::init_idt();
 
-	/* Initialize keyboard */
+	/* Initialize keyboard; Ctrl+C raises SIGINT on the console's task */
+	keyboard::register_interrupt_hook(signal::console_interrupt);
 	serial_println!("Keyboard ready for input!");
 
 	/* Enable interrupts globally */
@@ -405,34 +429,8 @@ pub extern "C" fn _start() -> ! {
 	memory::set_hhdm_offset(phys_mem_offset);
 	let mut mapper = unsafe { memory::init_offset_page_table(phys_mem_offset) };
 
-	/*
-	 * Preallocate all usable physical frames before heap mapping.
-	 * This populates the boot frame allocator with available memory.
-	 */
-	let mut frame_count = 0;
-	for region in entries
-		.iter()
-		.filter(|r| r.entry_type == limine::memory_map::EntryType::USABLE)
-	{
-		let start = region.base;
-		let end = region.base + region.length;
-		let start_frame = PhysFrame::containing_address(PhysAddr::new(start));
-		let end_frame = PhysFrame::containing_address(PhysAddr::new(end - 1));
-		for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
-			if frame_count >= memory::heap::MAX_BOOT_FRAMES {
-				break;
-			}
-			unsafe {
-				memory::heap::BOOT_FRAMES[frame_count] = Some(frame);
-			}
-			frame_count += 1;
-		}
-		if frame_count >= memory::heap::MAX_BOOT_FRAMES {
-			break;
-		}
-	}
-
-	let mut frame_alloc = StaticBootFrameAllocator::new(frame_count);
+	/* Hand every usable frame to the buddy allocator before heap mapping */
+	let mut frame_alloc = BuddyAllocator::new(entries, phys_mem_offset);
 	hal::cpu::enable_interrupts();
 
 	/* Initialize kernel heap with identity-mapped pages */
@@ -530,14 +528,18 @@ pub extern "C" fn _start() -> ! {
 	fb_println!("VFS: / and /dev/ ready");
 
 	/* Expose VirtIO block device as /dev/sda */
-	let sda: alloc::sync::Arc<dyn INode> = alloc::sync::Arc::new(
-		fs::BlockDevINode(alloc::sync::Arc::new(fs::VirtioBlockDev))
-	);
+	let sda_dev: alloc::sync::Arc<dyn fs::BlockDev> = alloc::sync::Arc::new(fs::VirtioBlockDev);
+	fs::register_block_dev("/dev/sda", alloc::sync::Arc::clone(&sda_dev));
+	let sda: alloc::sync::Arc<dyn INode> = alloc::sync::Arc::new(fs::BlockDevINode(sda_dev));
 	if let Some(dev_dir) = vfs::lookup_path("/dev/") {
 		dev_dir.insert("sda", sda).ok();
 		serial_println!("VFS: /dev/sda available");
 		fb_println!("VFS: /dev/sda ready — run 'mount /dev/sda /' to attach ext2");
 	}
+	/* Kernel and process information at /proc/ */
+	procfs::init();
+	serial_println!("VFS: /proc mounted");
+
 	/* Wire up fd 0/1/2 for the init task */
 	fd::init_stdio(0);
 	serial_println!("FD: stdio initialized for task 0");
@@ -559,9 +561,7 @@ pub extern "C" fn _start() -> ! {
 	 * The first context switch saves _start's context into boot_task
 	 * (which is never re-enqueued), then jumps to the first task.
 	 */
-	let boot_task = alloc::sync::Arc::new(spin::Mutex::new(
-		TaskCB::running_task(),
-	));
+	let boot_task = TaskCB::running_task().into_arc();
 	task::scheduler::global().lock().current = Some(boot_task);
 
 	/* Spawn the built-in kernel shell */
@@ -587,5 +587,6 @@ pub extern "C" fn _start() -> ! {
 	/* Idle loop — timer interrupts drive preemptive scheduling */
 	loop {
 		hlt();
+		signal::post_console_interrupt();
 	}
 }
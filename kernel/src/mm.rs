@@ -0,0 +1,210 @@
+/*
+ * mm.rs - User Memory Management System Calls
+ *
+ * Implements SYS_MMAP, SYS_MUNMAP and SYS_MPROTECT on top of the
+ * calling process's VmaList. Only private anonymous memory is supported.
+ * mmap() just records a VMA; pages are allocated zero-filled by the
+ * page fault handler (fault.rs) on first touch.
+ *
+ * brk() grows the same kind of anonymous area upwards from the end of
+ * the ELF image, for programs (and libcs) that still use a heap break.
+ */
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use spin::Mutex;
+use task::vma::{PROT_EXEC, PROT_READ, PROT_WRITE, Vma, VmaKind, VmaList};
+use x86_64::structures::paging::PhysFrame;
+
+use crate::syscall::{ERRNO_EINVAL, ERRNO_ENOMEM, USER_SPACE_END};
+
+/* mmap flags (Linux values) */
+pub const MAP_SHARED: u64 = 0x01;
+pub const MAP_PRIVATE: u64 = 0x02;
+pub const MAP_FIXED: u64 = 0x10;
+pub const MAP_ANONYMOUS: u64 = 0x20;
+
+/* Window searched for mmap() placements without MAP_FIXED */
+pub(crate) const MMAP_BASE: u64 = 0x0000_1000_0000_0000;
+pub(crate) const MMAP_END: u64 = 0x0000_7000_0000_0000;
+
+const PAGE_SIZE: u64 = 4096;
+
+/*
+ * user_range - Validate a page-aligned user range
+ * @addr: Start address
+ * @len: Length in bytes (rounded up to whole pages)
+ *
+ * Return: (start, end) or ERRNO_EINVAL
+ */
+fn user_range(addr: u64, len: u64) -> Result<(u64, u64), u64> {
+	if addr % PAGE_SIZE != 0 || len == 0 {
+		return Err(ERRNO_EINVAL);
+	}
+	let len = len.checked_add(PAGE_SIZE - 1).ok_or(ERRNO_EINVAL)? & !(PAGE_SIZE - 1);
+	let end = addr.checked_add(len).ok_or(ERRNO_EINVAL)?;
+	if end > USER_SPACE_END {
+		return Err(ERRNO_EINVAL);
+	}
+	Ok((addr, end))
+}
+
+/*
+ * current_space - Address space of the calling task
+ *
+ * Return: The PML4 and the VMA list the task shares with the other
+ *         threads of its process, or ERRNO_EINVAL for a kernel task
+ */
+pub(crate) fn current_space() -> Result<(PhysFrame, Arc<Mutex<VmaList>>), u64> {
+	let task_arc = task::scheduler::current_task_arc().ok_or(ERRNO_EINVAL)?;
+	let task = task_arc.lock();
+	Ok((task.pml4_frame.ok_or(ERRNO_EINVAL)?, task.vmas.clone()))
+}
+
+/*
+ * unmap_pages - Free the pages backing [start, end) in the active space
+ */
+fn unmap_pages(pml4: PhysFrame, start: u64, end: u64) -> Result<(), u64> {
+	let mut alloc_guard = memory::PAGE_ALLOC.get().ok_or(ERRNO_ENOMEM)?.lock();
+	unsafe {
+		memory::uvm::unmap_range(pml4, start, end, &mut alloc_guard.frame_alloc, memory::hhdm_offset());
+	}
+	Ok(())
+}
+
+/*
+ * mmap - Map anonymous memory into the calling process
+ * @addr: Placement hint, or exact address with MAP_FIXED
+ * @len: Length in bytes
+ * @prot: PROT_* bits
+ * @flags: MAP_PRIVATE | MAP_ANONYMOUS, optionally MAP_FIXED
+ *
+ * Return: Start of the new area, or an errno
+ */
+pub fn mmap(addr: u64, len: u64, prot: u64, flags: u64) -> Result<u64, u64> {
+	if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+		return Err(ERRNO_EINVAL);
+	}
+	if flags & !(MAP_SHARED | MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS) != 0
+		|| flags & MAP_ANONYMOUS == 0
+		|| flags & (MAP_SHARED | MAP_PRIVATE) != MAP_PRIVATE
+	{
+		return Err(ERRNO_EINVAL);
+	}
+
+	let (pml4, vmas) = current_space()?;
+	let mut vmas = vmas.lock();
+
+	let (start, end) = if flags & MAP_FIXED != 0 {
+		let (start, end) = user_range(addr, len)?;
+		/* MAP_FIXED replaces whatever was there */
+		vmas.remove(start, end);
+		unmap_pages(pml4, start, end)?;
+		(start, end)
+	} else {
+		let (_, span) = user_range(0, len).map_err(|_| ERRNO_ENOMEM)?;
+		let hinted = user_range(addr, len)
+			.ok()
+			.filter(|&(s, e)| addr != 0 && vmas.is_free(s, e));
+		match hinted {
+			Some(r) => r,
+			None => {
+				let start = vmas.find_free(span, MMAP_BASE, MMAP_END).ok_or(ERRNO_ENOMEM)?;
+				(start, start + span)
+			}
+		}
+	};
+
+	vmas.insert(Vma { start, end, prot, kind: VmaKind::Anon });
+	Ok(start)
+}
+
+/*
+ * munmap - Remove mappings from the calling process
+ * @addr: Page-aligned start address
+ * @len: Length in bytes
+ *
+ * Unmapping a range that is not mapped is not an error.
+ *
+ * Return: 0, or an errno
+ */
+pub fn munmap(addr: u64, len: u64) -> Result<u64, u64> {
+	let (start, end) = user_range(addr, len)?;
+	let (pml4, vmas) = current_space()?;
+	let mut vmas = vmas.lock();
+
+	vmas.remove(start, end);
+	unmap_pages(pml4, start, end)?;
+	Ok(0)
+}
+
+/*
+ * brk - Move the program break of the calling process
+ * @addr: Requested break, or 0 to query the current one
+ *
+ * The heap covers [heap_start, brk) rounded up to whole pages. A break
+ * below heap_start, or growth that would run into another mapping or
+ * the mmap() window, leaves the break unchanged.
+ *
+ * Return: The new break, or the unchanged one on failure (as Linux does)
+ */
+pub fn brk(addr: u64) -> u64 {
+	let Ok((pml4, vmas)) = current_space() else {
+		return 0;
+	};
+	let mut vmas = vmas.lock();
+	let cur = vmas.brk;
+	if addr < vmas.heap_start || addr > MMAP_BASE {
+		return cur;
+	}
+
+	let old_end = (cur + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+	let new_end = (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+	if new_end > old_end {
+		if !vmas.is_free(old_end, new_end) {
+			return cur;
+		}
+		vmas.insert(Vma {
+			start: old_end,
+			end: new_end,
+			prot: PROT_READ | PROT_WRITE,
+			kind: VmaKind::Anon,
+		});
+	} else if new_end < old_end {
+		if unmap_pages(pml4, new_end, old_end).is_err() {
+			return cur;
+		}
+		vmas.remove(new_end, old_end);
+	}
+	vmas.brk = addr;
+	addr
+}
+
+/*
+ * mprotect - Change the protection of mapped memory
+ * @addr: Page-aligned start address
+ * @len: Length in bytes
+ * @prot: New PROT_* bits
+ *
+ * Return: 0, or ERRNO_ENOMEM if part of the range is not mapped
+ */
+pub fn mprotect(addr: u64, len: u64, prot: u64) -> Result<u64, u64> {
+	if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+		return Err(ERRNO_EINVAL);
+	}
+	let (start, end) = user_range(addr, len)?;
+	let (pml4, vmas) = current_space()?;
+	let mut vmas = vmas.lock();
+
+	if !vmas.covers(start, end) {
+		return Err(ERRNO_ENOMEM);
+	}
+	vmas.protect(start, end, prot);
+
+	let flags = Vma { start, end, prot, kind: VmaKind::Anon }.page_flags();
+	unsafe {
+		memory::uvm::protect_range(pml4, start, end, flags, memory::hhdm_offset());
+	}
+	Ok(0)
+}
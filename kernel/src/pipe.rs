@@ -8,6 +8,7 @@
  * Blocking behaviour:
  * - Read on empty pipe with open write-end: blocks until data arrives
  * - Read on empty pipe with closed write-end: returns 0 (EOF)
+ * - A signal arriving while blocked in read makes it return EINTR
  * - Write to pipe with closed read-end: returns EPIPE
  */
 
@@ -30,7 +31,7 @@ struct PipeInner {
 	write_closed: bool,
 	read_closed: bool,
 	/* Arcs of blocked reader tasks — woken when data is written */
-	waiters: Vec<alloc::sync::Arc<spin::Mutex<task::TaskCB>>>,
+	waiters: Vec<task::TaskArc>,
 }
 
 impl PipeInner {
@@ -95,9 +96,19 @@ impl INode for PipeReadEnd {
 			if inner.write_closed {
 				return 0; /* EOF */
 			}
+			let me = task::scheduler::current_task_arc();
+			if crate::signal::interrupted() {
+				/* Forget a wait cut short by a signal so no stale wakeup follows */
+				if let Some(ref arc) = me {
+					inner.waiters.retain(|w| !Arc::ptr_eq(w, arc));
+				}
+				return crate::signal::READ_INTERRUPTED;
+			}
 			/* Block: save our task Arc and yield */
-			if let Some(arc) = task::scheduler::current_task_arc() {
-				inner.waiters.push(arc);
+			if let Some(arc) = me {
+				if !inner.waiters.iter().any(|w| Arc::ptr_eq(w, &arc)) {
+					inner.waiters.push(arc);
+				}
 			}
 			drop(inner);
 			x86_64::instructions::interrupts::without_interrupts(|| {
@@ -154,16 +165,22 @@ impl Drop for PipeWriteEnd {
 /*
  * create_pipe - Allocate a new pipe and insert both ends into task's fd table
  * @task_id: Task that owns the pipe fds
+ * @flags: O_CLOEXEC or 0, applied to both fds
  *
- * Return: (read_fd, write_fd) on success
+ * Return: (read_fd, write_fd) on success, ERRNO_EMFILE if the table is full
  */
-pub fn create_pipe(task_id: u64) -> (u64, u64) {
-	use crate::fd::OpenFile;
+pub fn create_pipe(task_id: u64, flags: u64) -> Result<(u64, u64), u64> {
 	let inner = Arc::new(Mutex::new(PipeInner::new()));
 	let read_end: Arc<dyn INode> = Arc::new(PipeReadEnd(Arc::clone(&inner)));
 	let write_end: Arc<dyn INode> = Arc::new(PipeWriteEnd(inner));
 
-	let read_fd = crate::fd::insert_inode(task_id, read_end);
-	let write_fd = crate::fd::insert_inode(task_id, write_end);
-	(read_fd, write_fd)
+	let read_fd = crate::fd::insert_inode(task_id, read_end, flags)?;
+	let write_fd = match crate::fd::insert_inode(task_id, write_end, flags) {
+		Ok(fd) => fd,
+		Err(e) => {
+			crate::fd::close(task_id, read_fd);
+			return Err(e);
+		}
+	};
+	Ok((read_fd, write_fd))
 }
@@ -2,42 +2,127 @@
  * process.rs - User Process Creation
  *
  * Provides spawn_user_process() which loads an ELF from the VFS,
- * creates a new address space, and enqueues the task for scheduling.
+ * creates a new address space, and enqueues the task for scheduling,
+ * fork_process() which duplicates a running process copy-on-write,
+ * exec_process() which replaces the image of a running process,
+ * thread_create() which starts another thread in the calling process,
+ * and exit_current() / exit_group() which turn the calling thread or
+ * the whole process into a zombie.
  *
- * Ring 3 entry is performed by user_entry_trampoline, a naked function
- * that context_switch ret's into on the task's first time slice.
+ * A process is a group of TaskCBs sharing one tgid, PML4, VMA list and
+ * fd table. The first one is the main thread, whose task ID is the
+ * process ID; it is the only one a parent sees through SYS_WAIT. Other
+ * threads are reaped by SYS_THREAD_JOIN, or dropped with the process.
+ * Capabilities live in the global store and are shared by every task.
+ *
+ * Reaping a task frees its kernel stack and, once no thread uses it any
+ * more, its address space (release_task()). Children of an exiting task
+ * are adopted by init; a process nobody will wait for (started by the
+ * kernel, or orphaned with no init left) is reaped as soon as it exits.
+ *
+ * New images start with the System V x86_64 initial stack: argc, argv,
+ * envp and the auxiliary vector, built by setup_initial_stack().
+ *
+ * Address space layout is randomised: static-PIE images load at a
+ * random page above PIE_BASE and the stack top sits a random number of
+ * pages below USER_STACK_TOP, both chosen afresh on every spawn and exec.
+ *
+ * Ring 3 entry is performed by user_entry_trampoline (fresh images) or
+ * fork_return (forked children), naked functions that context_switch
+ * ret's into on the task's first time slice.
  */
 
 extern crate alloc;
 
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::arch::naked_asm;
-use loader::LoadableSegment;
+use core::sync::atomic::{AtomicU64, Ordering};
+use loader::{LoadableSegment, LoadedImage};
 use spin::Mutex;
 use x86_64::VirtAddr;
-use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{
+	FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB, Translate,
+};
+
+use crate::syscall::{
+	SyscallFrame, ERRNO_E2BIG, ERRNO_EACCES, ERRNO_EINVAL, ERRNO_ENOENT, ERRNO_ENOEXEC, ERRNO_ENOMEM,
+};
+use task::vma::{PROT_EXEC, PROT_READ, PROT_WRITE, Vma, VmaKind, VmaList};
+
+/* Kernel stack of every user task */
+const KSTACK_SIZE: usize = 1024 * 1024;
+
+/* User stack geometry; the top is slid down by up to 2^STACK_RND_BITS pages */
+const USER_STACK_TOP: u64 = 0x0000_7FFF_FFFF_F000;
+const USER_STACK_SIZE: u64 = 16384;
+const STACK_RND_BITS: u32 = 22;
+
+/*
+ * ET_DYN images load at PIE_BASE plus up to 2^PIE_RND_BITS pages. The
+ * whole range, and the brk heap that follows the image, stays below the
+ * mmap() window (mm::MMAP_BASE), so the break can grow up to it.
+ */
+const PIE_BASE: u64 = 0x0000_0555_5555_4000;
+const PIE_RND_BITS: u32 = 28;
+
+/*
+ * ARG_MAX - Byte budget for argv + envp of a new image
+ *
+ * Each string costs its length, its NUL terminator and one pointer
+ * slot. Keeps the initial stack block well inside the user stack.
+ */
+pub const ARG_MAX: usize = 6144;
+
+/* Auxiliary vector entry types (System V x86_64 ABI) */
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+const AT_RANDOM: u64 = 25;
+
+/* Longest "#!" line accepted, as Linux's BINPRM_BUF_SIZE */
+const SHEBANG_MAX: usize = 256;
+
+/* How many scripts may chain through "#!" before the ELF is reached */
+const SHEBANG_MAX_DEPTH: usize = 4;
+
+/*
+ * INIT_PID - The process that adopts orphans, or 0 if there is none
+ *
+ * The first process the kernel starts (normally /init, from kshell's
+ * run) becomes init. Once it exits, the next one the kernel starts
+ * takes over.
+ */
+static INIT_PID: AtomicU64 = AtomicU64::new(0);
+
+/* Exited tasks nobody will wait for, freed by reap_dead() */
+static DEAD: Mutex<Vec<task::TaskArc>> = Mutex::new(Vec::new());
 
 /*
  * user_entry_trampoline - Ring 0 → Ring 3 bridge for newly spawned tasks
  *
  * context_switch() ret's here on the task's first scheduled time slice.
- * At entry: kernel stack is valid, r12 = user entry point, r13 = user RSP.
- * Builds the iretq frame and switches to Ring 3.
+ * At entry: kernel stack is valid, r12 = user entry point, r13 = user RSP,
+ * r14 = first argument. Builds the iretq frame and switches to Ring 3
+ * with RDI = r14: a new image's _start(sp) receives a pointer to argc,
+ * a new thread its start argument.
  *
  * GS invariant (this kernel): GS_BASE is ALWAYS 0 in both Ring 0 and Ring 3
  * normal execution. KernelGsBase holds PER_CPU_DATA. swapgs only happens
- * inside syscall_entry/syscall_exit to temporarily make PER_CPU_DATA live.
- *
- * Therefore this trampoline must always be reached with GS_BASE=0. Callers
- * in syscall context (SYS_EXIT, SYS_WAIT) must issue swapgs before calling
- * context_switch so that GS is restored before we arrive here.
+ * in the first few instructions of syscall_entry, to find the kernel stack,
+ * so this trampoline is always reached with GS_BASE=0.
  */
 #[unsafe(naked)]
 pub unsafe extern "C" fn user_entry_trampoline() -> ! {
 	naked_asm!(
 		"cli",
+		"mov rdi, r14",       /* _start(sp) / thread argument */
 		"push 0x23",          /* SS  — user data RPL 3 */
 		"push r13",           /* RSP — user stack pointer */
 		"push 0x202",         /* RFLAGS — IF=1, reserved=1 */
@@ -47,19 +132,58 @@ pub unsafe extern "C" fn user_entry_trampoline() -> ! {
 	)
 }
 
+/*
+ * fork_return - First code a forked child runs
+ *
+ * context_switch() ret's here with RSP pointing at the child's copy of
+ * the parent's SyscallFrame. Restores the user registers from it and
+ * returns to Ring 3 with RAX = 0, at the instruction following the
+ * parent's SYSCALL.
+ */
+#[unsafe(naked)]
+pub unsafe extern "C" fn fork_return() -> ! {
+	naked_asm!(
+		"cli",
+		"pop r15",
+		"pop r14",
+		"pop r13",
+		"pop r12",
+		"pop rbx",
+		"pop rbp",
+		"add rsp, 8",         /* saved RAX (syscall number) */
+		"xor eax, eax",       /* fork() returns 0 in the child */
+		"pop rdi",
+		"pop rsi",
+		"pop rdx",
+		"pop r10",
+		"pop r8",
+		"pop r9",
+		"pop rcx",            /* User RIP */
+		"pop r11",            /* User RFLAGS */
+		"pop rsp",            /* User RSP */
+		"sysretq",
+	)
+}
+
 /*
  * map_segment - Map one ELF PT_LOAD segment into a user address space
  * @mapper:     Mapper for the target PML4 (not necessarily the active one)
  * @allocator:  Physical frame allocator
  * @segment:    Segment descriptor from the loader
  * @phys_offset: HHDM offset used to access frame contents
+ *
+ * Return: Err string if out of memory; pages mapped so far stay mapped
+ *         and go with the address space
  */
-pub unsafe fn map_segment(
+pub unsafe fn map_segment<A>(
 	mapper: &mut impl Mapper<Size4KiB>,
-	allocator: &mut impl FrameAllocator<Size4KiB>,
+	allocator: &mut A,
 	segment: &LoadableSegment,
 	phys_offset: VirtAddr,
-) {
+) -> Result<(), &'static str>
+where
+	A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
 	use x86_64::structures::paging::mapper::MapToError;
 
 	let start = segment.virtual_address;
@@ -76,11 +200,17 @@ pub unsafe fn map_segment(
 	}
 
 	for page in Page::range_inclusive(start_page, end_page) {
-		let new_frame = allocator.allocate_frame().expect("OOM during segment load");
+		let new_frame = allocator.allocate_frame().ok_or("OOM during segment load")?;
 		let (frame, freshly_mapped) = match mapper.map_to(page, new_frame, flags, allocator) {
 			Ok(f) => { f.flush(); (new_frame, true) }
-			Err(MapToError::PageAlreadyMapped(existing)) => (existing, false),
-			Err(e) => panic!("map_segment: {:?}", e),
+			Err(MapToError::PageAlreadyMapped(existing)) => {
+				allocator.deallocate_frame(new_frame);
+				(existing, false)
+			}
+			Err(_) => {
+				allocator.deallocate_frame(new_frame);
+				return Err("OOM during segment load");
+			}
 		};
 
 		let frame_virt = phys_offset + frame.start_address().as_u64();
@@ -107,6 +237,23 @@ pub unsafe fn map_segment(
 			);
 		}
 	}
+	Ok(())
+}
+
+/*
+ * random_pages - Random page-aligned offset
+ * @bits: Number of random page-number bits
+ */
+fn random_pages(bits: u32) -> u64 {
+	(hal::random::random_u64() & ((1u64 << bits) - 1)) * 4096
+}
+
+/*
+ * load_image - Parse an ELF, sliding a static-PIE to a random base
+ * @data: Whole ELF file
+ */
+fn load_image(data: &[u8]) -> Result<LoadedImage, &'static str> {
+	loader::load_elf(data, PIE_BASE + random_pages(PIE_RND_BITS))
 }
 
 /*
@@ -115,47 +262,283 @@ pub unsafe fn map_segment(
  * @allocator:  Physical frame allocator
  * @phys_offset: HHDM offset
  *
- * Return: Virtual address of the stack top (initial user RSP)
+ * The top is a random page within 2^STACK_RND_BITS pages below
+ * USER_STACK_TOP.
+ *
+ * Return: Virtual address of the stack top (initial user RSP), or Err
+ *         string if out of memory
  */
-pub unsafe fn allocate_user_stack(
+pub unsafe fn allocate_user_stack<A>(
 	mapper: &mut impl Mapper<Size4KiB>,
-	allocator: &mut impl FrameAllocator<Size4KiB>,
+	allocator: &mut A,
 	phys_offset: VirtAddr,
-) -> VirtAddr {
-	let stack_top = VirtAddr::new(0x0000_7FFF_FFFF_F000);
-	let stack_bottom = stack_top - 16384u64;
+) -> Result<VirtAddr, &'static str>
+where
+	A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+	let stack_top = VirtAddr::new(USER_STACK_TOP - random_pages(STACK_RND_BITS));
+	let stack_bottom = stack_top - USER_STACK_SIZE;
 
 	let start_page = Page::<Size4KiB>::containing_address(stack_bottom);
 	let end_page = Page::<Size4KiB>::containing_address(stack_top - 1u64);
 	let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
 
 	for page in Page::range_inclusive(start_page, end_page) {
-		let frame = allocator.allocate_frame().expect("OOM: user stack");
-		if let Ok(r) = mapper.map_to(page, frame, flags, allocator) {
-			r.flush();
+		let frame = allocator.allocate_frame().ok_or("OOM: user stack")?;
+		match mapper.map_to(page, frame, flags, allocator) {
+			Ok(r) => r.flush(),
+			Err(_) => {
+				allocator.deallocate_frame(frame);
+				return Err("OOM: user stack");
+			}
 		}
 		let ptr = (phys_offset + frame.start_address().as_u64()).as_mut_ptr::<u8>();
 		ptr.write_bytes(0, 4096);
 	}
-	stack_top
+	Ok(stack_top)
 }
 
 /*
- * spawn_user_process - Create and enqueue a new user-mode process
- * @path:      VFS path to the ELF binary
- * @parent_id: Task ID of the spawning task (0 = kernel)
+ * build_image - Map a loaded ELF and its initial stack
+ * @mapper:      Mapper for the target PML4, with no user mappings yet
+ * @alloc:       Frame allocator
+ * @image:       Loaded ELF
+ * @argv:        Argument strings
+ * @envp:        Environment strings
+ * @phys_offset: HHDM offset
  *
- * Return: child task ID on success, Err string on failure
+ * Return: (initial user RSP, stack top), or Err string if out of memory
+ *         or the arguments do not fit; what was mapped stays mapped
+ */
+fn build_image<M, A>(
+	mapper: &mut M,
+	alloc: &mut A,
+	image: &LoadedImage,
+	argv: &[String],
+	envp: &[String],
+	phys_offset: VirtAddr,
+) -> Result<(VirtAddr, VirtAddr), &'static str>
+where
+	M: Mapper<Size4KiB> + Translate,
+	A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+	for seg in &image.segments {
+		unsafe { map_segment(mapper, alloc, seg, phys_offset)? };
+	}
+	let ust = unsafe { allocate_user_stack(mapper, alloc, phys_offset)? };
+	let sp = setup_initial_stack(mapper, ust, argv, envp, image, phys_offset)?;
+	Ok((sp, ust))
+}
+
+/*
+ * image_vmas - Describe a freshly built address space
+ * @image: Loaded ELF whose segments were mapped
+ * @stack_top: Top of the user stack from allocate_user_stack()
+ *
+ * Segments are widened to page boundaries; where two of them share a
+ * page, the later one starts at the end of the earlier.
+ *
+ * Return: VMAs for every PT_LOAD segment plus the user stack, with an
+ *         empty brk heap starting right after the image
+ */
+fn image_vmas(image: &LoadedImage, stack_top: VirtAddr) -> VmaList {
+	let mut vmas = VmaList::new();
+	let mut segs: Vec<&LoadableSegment> = image.segments.iter().filter(|s| s.size > 0).collect();
+	segs.sort_by_key(|s| s.virtual_address.as_u64());
+
+	let mut last_end = 0u64;
+	for seg in segs {
+		let start = (seg.virtual_address.as_u64() & !0xFFF).max(last_end);
+		let end = (seg.virtual_address.as_u64() + seg.size + 0xFFF) & !0xFFF;
+		if start >= end {
+			continue;
+		}
+		let mut prot = PROT_READ;
+		if seg.flags.writable {
+			prot |= PROT_WRITE;
+		}
+		if seg.flags.executable {
+			prot |= PROT_EXEC;
+		}
+		vmas.insert(Vma { start, end, prot, kind: VmaKind::Image });
+		last_end = end;
+	}
+
+	vmas.insert(Vma {
+		start: stack_top.as_u64() - USER_STACK_SIZE,
+		end: stack_top.as_u64(),
+		prot: PROT_READ | PROT_WRITE,
+		kind: VmaKind::Stack,
+	});
+	vmas.heap_start = last_end;
+	vmas.brk = last_end;
+	vmas
+}
+
+/*
+ * copy_to_user_space - Copy bytes into a (possibly inactive) address space
+ * @mapper:      Translator for the target PML4
+ * @dst:         Destination user virtual address (must be mapped)
+ * @src:         Bytes to copy
+ * @phys_offset: HHDM offset
  *
- * Allocates a new PML4, maps ELF segments, sets up a user stack and
- * kernel stack, initialises stdio fds, and enqueues on the RunQueue.
+ * Return: Err string if part of the destination is not mapped
+ */
+fn copy_to_user_space(
+	mapper: &impl Translate,
+	dst: VirtAddr,
+	src: &[u8],
+	phys_offset: VirtAddr,
+) -> Result<(), &'static str> {
+	let mut done = 0usize;
+	while done < src.len() {
+		let addr = dst + done as u64;
+		let phys = mapper.translate_addr(addr).ok_or("initial stack not mapped")?;
+		let chunk = core::cmp::min(src.len() - done, 4096 - usize::from(addr.page_offset()));
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				src.as_ptr().add(done),
+				(phys_offset + phys.as_u64()).as_mut_ptr::<u8>(),
+				chunk,
+			);
+		}
+		done += chunk;
+	}
+	Ok(())
+}
+
+/*
+ * image_auxv - Auxiliary vector for a new image, ending with AT_NULL
+ * @image:       Loaded ELF
+ * @random_addr: User address of the 16 AT_RANDOM bytes
  */
-pub fn spawn_user_process(path: &str, parent_id: u64) -> Result<u64, &'static str> {
-	/* 1. Read ELF from VFS */
-	let inode = vfs::lookup_path(path).ok_or("spawn: path not found")?;
+fn image_auxv(image: &LoadedImage, random_addr: u64) -> Vec<(u64, u64)> {
+	let mut auxv: Vec<(u64, u64)> = Vec::new();
+	if let Some(phdr) = image.phdr {
+		auxv.push((AT_PHDR, phdr.as_u64()));
+	}
+	auxv.push((AT_PHENT, image.phent as u64));
+	auxv.push((AT_PHNUM, image.phnum as u64));
+	auxv.push((AT_PAGESZ, 4096));
+	auxv.push((AT_ENTRY, image.entry_point.as_u64()));
+	auxv.push((AT_RANDOM, random_addr));
+	auxv.push((AT_NULL, 0));
+	auxv
+}
+
+/*
+ * initial_stack_fits - Check that setup_initial_stack() will succeed
+ * @argv:  Argument strings
+ * @envp:  Environment strings
+ * @image: Loaded ELF
+ *
+ * Return: true if the block fits in half the user stack
+ */
+fn initial_stack_fits(argv: &[String], envp: &[String], image: &LoadedImage) -> bool {
+	let strings_len: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+	let nwords = 1 + argv.len() + 1 + envp.len() + 1 + 2 * image_auxv(image, 0).len();
+	/* The stack top is page aligned, so the block is a multiple of 16 */
+	let size = (16 + strings_len as u64 + 8 * nwords as u64 + 0xF) & !0xF;
+	size <= USER_STACK_SIZE / 2
+}
+
+/*
+ * setup_initial_stack - Lay out argc/argv/envp/auxv on a fresh user stack
+ * @mapper:      Translator for the target PML4
+ * @stack_top:   Top of the mapped user stack
+ * @argv:        Argument strings
+ * @envp:        Environment strings ("KEY=value")
+ * @image:       Loaded ELF, for the AT_PHDR/AT_ENTRY entries
+ * @phys_offset: HHDM offset
+ *
+ * From the top of the stack down: 16 random bytes for AT_RANDOM, the
+ * NUL-terminated strings, then the 16-byte aligned vector block
+ *
+ *   argc, argv[0..argc], NULL, envp[..], NULL, (type, value)..., AT_NULL
+ *
+ * Return: Initial user RSP (pointing at argc), or Err string if the
+ *         block does not fit in half the user stack
+ */
+fn setup_initial_stack(
+	mapper: &impl Translate,
+	stack_top: VirtAddr,
+	argv: &[String],
+	envp: &[String],
+	image: &LoadedImage,
+	phys_offset: VirtAddr,
+) -> Result<VirtAddr, &'static str> {
+	let random_addr = stack_top.as_u64() - 16;
+	let strings_len: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+	let strings_base = random_addr - strings_len as u64;
+
+	let auxv = image_auxv(image, random_addr);
+	let nwords = 1 + argv.len() + 1 + envp.len() + 1 + 2 * auxv.len();
+	if !initial_stack_fits(argv, envp, image) {
+		return Err("argument list too long");
+	}
+	let sp = (strings_base - 8 * nwords as u64) & !0xF;
+
+	/* Build the whole block in kernel memory, then copy it out once */
+	let mut block = vec![0u8; (stack_top.as_u64() - sp) as usize];
+	let at = |addr: u64| (addr - sp) as usize;
+
+	hal::random::fill_bytes(&mut block[at(random_addr)..at(random_addr) + 16]);
+
+	let mut words: Vec<u64> = Vec::with_capacity(nwords);
+	words.push(argv.len() as u64);
+
+	let mut str_addr = strings_base;
+	for (i, s) in argv.iter().chain(envp).enumerate() {
+		if i == argv.len() {
+			words.push(0); /* argv terminator */
+		}
+		let off = at(str_addr);
+		block[off..off + s.len()].copy_from_slice(s.as_bytes());
+		words.push(str_addr);
+		str_addr += s.len() as u64 + 1;
+	}
+	if envp.is_empty() {
+		words.push(0); /* argv terminator */
+	}
+	words.push(0); /* envp terminator */
+	for (key, val) in auxv {
+		words.push(key);
+		words.push(val);
+	}
+
+	for (i, w) in words.iter().enumerate() {
+		let off = i * 8;
+		block[off..off + 8].copy_from_slice(&w.to_ne_bytes());
+	}
+
+	copy_to_user_space(mapper, VirtAddr::new(sp), &block, phys_offset)?;
+	Ok(VirtAddr::new(sp))
+}
+
+/*
+ * image_personality - Syscall ABI a new image runs under
+ * @image:     Loaded ELF
+ * @requested: Personality asked for by the spawner, or kept across exec
+ */
+fn image_personality(image: &LoadedImage, requested: task::Personality) -> task::Personality {
+	if image.linux_abi { task::Personality::Linux } else { requested }
+}
+
+/*
+ * read_elf - Read a whole executable from the VFS
+ * @path: VFS path to the ELF binary
+ *
+ * Return: File contents, or ERRNO_ENOENT if missing, ERRNO_EACCES if
+ *         not a regular file, ERRNO_ENOEXEC if empty
+ */
+fn read_elf(path: &str) -> Result<Vec<u8>, u64> {
+	let inode = vfs::lookup_path(path).ok_or(ERRNO_ENOENT)?;
+	if inode.metadata() != vfs::FileType::File {
+		return Err(ERRNO_EACCES);
+	}
 	let size = inode.size();
 	if size == 0 {
-		return Err("spawn: ELF is empty");
+		return Err(ERRNO_ENOEXEC);
 	}
 	let mut data: Vec<u8> = vec![0u8; size];
 	let mut off = 0usize;
@@ -164,43 +547,154 @@ pub fn spawn_user_process(path: &str, parent_id: u64) -> Result<u64, &'static st
 		if n == 0 { break; }
 		off += n;
 	}
+	Ok(data)
+}
+
+/*
+ * parse_shebang - Split a script's "#!" line
+ * @data: File contents
+ *
+ * As on Linux the line is "#!interpreter [argument]": everything after
+ * the blanks following the interpreter is a single optional argument.
+ *
+ * Return: Ok(None) if @data is not a script, Ok((interpreter, argument))
+ *         or Err string for an unusable "#!" line
+ */
+fn parse_shebang(data: &[u8]) -> Result<Option<(String, Option<String>)>, &'static str> {
+	if !data.starts_with(b"#!") {
+		return Ok(None);
+	}
+	let head = &data[2..data.len().min(SHEBANG_MAX)];
+	let line = match head.iter().position(|&b| b == b'\n') {
+		Some(n) => &head[..n],
+		None if data.len() <= SHEBANG_MAX => head,
+		None => return Err("#! line too long"),
+	};
+	let line = core::str::from_utf8(line).map_err(|_| "#! line is not UTF-8")?;
+	let blank = |c: char| c == ' ' || c == '\t' || c == '\r';
+	let line = line.trim_matches(blank);
+	let (interp, arg) = match line.find(blank) {
+		Some(i) => (&line[..i], line[i..].trim_matches(blank)),
+		None => (line, ""),
+	};
+	if interp.is_empty() {
+		return Err("#! line names no interpreter");
+	}
+	let arg = if arg.is_empty() { None } else { Some(String::from(arg)) };
+	Ok(Some((String::from(interp), arg)))
+}
+
+/*
+ * read_executable - Read an ELF, following "#!" interpreter lines
+ * @path: VFS path of an ELF binary or a script
+ * @argv: Argument vector as requested
+ *
+ * A script is run by its interpreter with argv rewritten as Linux does:
+ * [interpreter, argument if any, @path, @argv[1..]]. Interpreter paths
+ * are resolved against the caller's cwd and may themselves be scripts,
+ * up to SHEBANG_MAX_DEPTH levels.
+ *
+ * Return: ELF contents and the argv to start it with, or Err string
+ */
+fn read_executable(path: &str, argv: &[String]) -> Result<(Vec<u8>, Vec<String>), &'static str> {
+	let mut path = String::from(path);
+	let mut argv = argv.to_vec();
+	for _ in 0..=SHEBANG_MAX_DEPTH {
+		let data = read_elf(&path).map_err(|e| match e {
+			ERRNO_ENOENT => "path not found",
+			ERRNO_EACCES => "not a regular file",
+			_ => "ELF is empty",
+		})?;
+		let Some((interp, arg)) = parse_shebang(&data)? else {
+			return Ok((data, argv));
+		};
+		hal::serial_println!("[SPAWN] {} is a script for {}", path, interp);
+
+		let mut script_argv = vec![interp.clone()];
+		script_argv.extend(arg);
+		script_argv.push(path);
+		script_argv.extend(argv.iter().skip(1).cloned());
+		argv = script_argv;
+		path = crate::syscall::resolve(&interp);
+	}
+	Err("too many levels of #! interpreters")
+}
+
+/*
+ * spawn_user_process - Create and enqueue a new user-mode process
+ * @path:      VFS path to the ELF binary, or to a "#!" script
+ * @argv:      Argument vector for the new process
+ * @envp:      Environment for the new process
+ * @parent_id: Task ID of the spawning task (0 = kernel)
+ * @fds:       Descriptor table for the child, from fd::spawn_table()
+ * @personality: Syscall ABI requested by the caller; an ELF that
+ *             declares the Linux ABI always runs as Linux
+ *
+ * Return: child task ID on success, Err string on failure
+ *
+ * Allocates a new PML4, maps ELF segments, sets up a user stack holding
+ * @argv, @envp and the auxiliary vector plus a kernel stack, installs
+ * @fds, and enqueues on the RunQueue. A script starts its interpreter
+ * instead (see read_executable()).
+ */
+pub fn spawn_user_process(
+	path: &str,
+	argv: &[String],
+	envp: &[String],
+	parent_id: u64,
+	fds: crate::fd::FdTable,
+	personality: task::Personality,
+) -> Result<u64, &'static str> {
+	reap_dead();
+
+	/* 1. Read ELF from VFS, or the interpreter of a script */
+	let (data, argv) = read_executable(path, argv)?;
+	let argv = argv.as_slice();
 
 	/* 2. Parse ELF segments */
-	let image = loader::load_elf(&data)?;
+	let image = load_image(&data)?;
 
 	/* 3-6. Build address space under PAGE_ALLOC lock */
 	let phys_offset = memory::hhdm_offset();
 
-	let (pml4_frame, user_stack_top, kstack): (PhysFrame, VirtAddr, VirtAddr) = {
+	let (pml4_frame, user_sp, stack_top, kstack): (PhysFrame, VirtAddr, VirtAddr, VirtAddr) = {
 		let mut alloc_guard = memory::PAGE_ALLOC
 			.get()
 			.ok_or("spawn: PAGE_ALLOC not ready")?
 			.lock();
 
+		/* The new address space is in no task yet, so reclaim cannot see it */
+		let mut alloc = crate::swap::ReclaimAlloc::new(&mut alloc_guard, None);
+
 		let pml4 = unsafe {
-			memory::create_user_page_table(&mut alloc_guard.frame_alloc, phys_offset)
+			memory::create_user_page_table(&mut alloc, phys_offset)
 				.ok_or("spawn: OOM creating PML4")?
 		};
 
 		let mut user_mapper = unsafe { memory::create_mapper(pml4, phys_offset) };
+		let built = build_image(&mut user_mapper, &mut alloc, &image, argv, envp, phys_offset);
+		drop(alloc); /* release before kernel stack alloc */
+		drop(alloc_guard);
 
-		for seg in &image.segments {
-			unsafe {
-				map_segment(&mut user_mapper, &mut alloc_guard.frame_alloc, seg, phys_offset);
+		/* Allocate kernel stack from dedicated fixed range (always mapped) */
+		let ks = built.and_then(|v| {
+			memory::kstack::alloc_kernel_stack(KSTACK_SIZE)
+				.ok_or("spawn: OOM kernel stack")
+				.map(|ks| (v, ks))
+		});
+		let ((sp, ust), ks) = match ks {
+			Ok(v) => v,
+			Err(e) => {
+				/* Nothing refers to the new address space yet */
+				let mut alloc_guard = memory::PAGE_ALLOC.get().ok_or(e)?.lock();
+				unsafe {
+					memory::uvm::free_address_space(pml4, &mut alloc_guard.frame_alloc, phys_offset);
+				}
+				return Err(e);
 			}
-		}
-
-		let ust = unsafe {
-			allocate_user_stack(&mut user_mapper, &mut alloc_guard.frame_alloc, phys_offset)
 		};
 
-		drop(alloc_guard); /* release before kernel stack alloc */
-
-		/* Allocate kernel stack from dedicated fixed range (always mapped) */
-		let ks = memory::kstack::alloc_kernel_stack(1024 * 1024)
-			.ok_or("spawn: OOM kernel stack")?;
-
-		(pml4, ust, ks)
+		(pml4, sp, ust, ks)
 	};
 
 	/* 7. Build CPUContext — trampoline runs in Ring 0, then iretq to Ring 3 */
@@ -208,15 +702,20 @@ pub fn spawn_user_process(path: &str, parent_id: u64) -> Result<u64, &'static st
 	ctx.rsp = kstack.as_u64();
 	ctx.rip = user_entry_trampoline as u64;
 	ctx.r12 = image.entry_point.as_u64();  /* user entry point, read by trampoline */
-	ctx.r13 = user_stack_top.as_u64();     /* user RSP, read by trampoline */
+	ctx.r13 = user_sp.as_u64();            /* user RSP, read by trampoline */
+	ctx.r14 = user_sp.as_u64();            /* _start(sp) argument */
 	ctx.cr3 = pml4_frame.start_address().as_u64();
+	ctx.fs_base = image.thread_pointer.map_or(0, |tp| tp.as_u64());
 	ctx.cs = 0x08; /* kernel CS — trampoline is Ring 0 code */
 	ctx.ss = 0x10;
 	ctx.rflags = 0x202;
 
-	/* 8. Allocate task ID and build TaskCB */
+	/* 8. Allocate task ID and build TaskCB; the cwd is the spawner's */
 	let child_id_val = task::TaskId::new();
 	let child_id = child_id_val.0;
+	let cwd = task::scheduler::current_task_arc()
+		.map(|t| t.lock().cwd.clone())
+		.unwrap_or_else(|| String::from("/"));
 
 	let tcb = task::TaskCB {
 		id: child_id_val,
@@ -224,30 +723,562 @@ pub fn spawn_user_process(path: &str, parent_id: u64) -> Result<u64, &'static st
 		sched_class: task::SchedClass::Fair(120),
 		context: ctx,
 		kstack,
-		ustack: Some(user_stack_top),
+		ustack: Some(stack_top),
 		name: "user_proc",
+		tgid: child_id,
 		parent_id,
 		exit_status: None,
 		pml4_frame: Some(pml4_frame),
 		children: alloc::vec::Vec::new(),
 		waiting_for_child: false,
+		joiner: 0,
+		vmas: Arc::new(Mutex::new(image_vmas(&image, stack_top))),
+		signals: task::signal::SignalState::new(),
+		cwd,
+		personality: image_personality(&image, personality),
 	};
 
-	/* 9. Initialise stdio fds */
-	crate::fd::init_stdio(child_id);
+	/* 9. Install the fd table prepared by the caller */
+	crate::fd::install(child_id, fds);
 
-	/* 10. Register child in parent's children list */
+	/* 10. Register child in parent's children list; the kernel's first child is init */
 	if parent_id != 0 {
 		if let Some(parent_arc) = task::scheduler::find_task_by_id(parent_id) {
 			parent_arc.lock().children.push(child_id);
 		}
+	} else if INIT_PID.compare_exchange(0, child_id, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+		hal::serial_println!("[SPAWN] pid={} is init", child_id);
 	}
 
 	/* 11. Enqueue */
-	task::scheduler::enqueue_task(Arc::new(Mutex::new(tcb)));
+	task::scheduler::enqueue_task(tcb.into_arc());
+
+	hal::serial_println!("[SPAWN] pid={} entry={:#x} bias={:#x} cr3={:#x}",
+		child_id, image.entry_point.as_u64(), image.load_bias, pml4_frame.start_address().as_u64());
 
-	hal::serial_println!("[SPAWN] pid={} entry={:#x} cr3={:#x}",
-		child_id, image.entry_point.as_u64(), pml4_frame.start_address().as_u64());
+	Ok(child_id)
+}
+
+/*
+ * fork_process - Duplicate a user process
+ * @parent_id: Task ID of the forking task (must be the current task)
+ * @frame:     User registers saved by syscall_entry for the SYS_FORK call
+ *
+ * Return: child task ID on success, Err string on failure
+ *
+ * Clones the parent's address space copy-on-write, gives the child its
+ * own kernel stack holding a copy of @frame, shares all open file
+ * descriptors and enqueues the child. The child resumes in fork_return.
+ * Only the calling thread is duplicated; the child is single-threaded.
+ */
+pub fn fork_process(parent_id: u64, frame: &SyscallFrame) -> Result<u64, &'static str> {
+	let parent_arc = task::scheduler::current_task_arc().ok_or("fork: no current task")?;
+	let (parent_pml4, sched_class, ustack, name, vmas, signals, cwd, personality) = {
+		let p = parent_arc.lock();
+		(p.pml4_frame, p.sched_class, p.ustack, p.name, p.vmas.lock().clone(),
+			p.signals.fork_copy(), p.cwd.clone(), p.personality)
+	};
+	let parent_pml4 = parent_pml4.ok_or("fork: kernel tasks cannot fork")?;
+	reap_dead();
+
+	/* 1. Clone the address space; parent pages become read-only COW */
+	let phys_offset = memory::hhdm_offset();
+	let page_alloc = memory::PAGE_ALLOC.get().ok_or("fork: PAGE_ALLOC not ready")?;
+	let pml4_frame = {
+		let mut alloc_guard = page_alloc.lock();
+		let mut alloc = crate::swap::ReclaimAlloc::new(&mut alloc_guard, Some(parent_pml4));
+		unsafe { memory::uvm::clone_address_space(parent_pml4, &mut alloc, phys_offset) }
+	};
+	x86_64::instructions::tlb::flush_all();
+	let pml4_frame = pml4_frame.ok_or("fork: OOM cloning address space")?;
+
+	/* 2. Kernel stack with a copy of the parent's syscall frame */
+	let Some(kstack) = memory::kstack::alloc_kernel_stack(KSTACK_SIZE) else {
+		/* Nothing refers to the copy yet */
+		let mut alloc_guard = page_alloc.lock();
+		unsafe {
+			memory::uvm::free_address_space(pml4_frame, &mut alloc_guard.frame_alloc, phys_offset);
+		}
+		return Err("fork: OOM kernel stack");
+	};
+	let child_frame = crate::syscall::frame_at(kstack);
+	unsafe { child_frame.write(*frame); }
+
+	let mut ctx = task::CPUContext::default();
+	ctx.rsp = child_frame as u64;
+	ctx.rip = fork_return as u64;
+	ctx.cr3 = pml4_frame.start_address().as_u64();
+	/* The parent's CPUContext only holds FS_BASE while it is switched out */
+	ctx.fs_base = x86_64::registers::model_specific::FsBase::read().as_u64();
+	ctx.cs = 0x08;
+	ctx.ss = 0x10;
+	ctx.rflags = 0x202;
+
+	/* 3. Build the child TaskCB */
+	let child_id_val = task::TaskId::new();
+	let child_id = child_id_val.0;
+
+	let tcb = task::TaskCB {
+		id: child_id_val,
+		state: task::TaskState::Ready,
+		sched_class,
+		context: ctx,
+		kstack,
+		ustack,
+		name,
+		tgid: child_id,
+		parent_id,
+		exit_status: None,
+		pml4_frame: Some(pml4_frame),
+		children: alloc::vec::Vec::new(),
+		waiting_for_child: false,
+		joiner: 0,
+		vmas: Arc::new(Mutex::new(vmas)),
+		signals,
+		cwd,
+		personality,
+	};
+
+	/* 4. Share open files, register with the parent and enqueue */
+	crate::fd::fork_for_task(parent_id, child_id);
+	parent_arc.lock().children.push(child_id);
+	task::scheduler::enqueue_task(tcb.into_arc());
+
+	hal::serial_println!("[FORK] pid={} -> child={} cr3={:#x}",
+		parent_id, child_id, pml4_frame.start_address().as_u64());
 
 	Ok(child_id)
 }
+
+/*
+ * exec_process - Replace the calling process's image with a new ELF
+ * @task_id: Task ID of the caller (must be the current task)
+ * @path:    VFS path to the new ELF binary
+ * @argv:    Argument vector for the new image
+ * @envp:    Environment for the new image
+ * @frame:   Caller's SyscallFrame, rewritten to enter the new image
+ *
+ * Return: Ok once the new image is in place, or ERRNO_ENOENT,
+ *         ERRNO_EACCES, ERRNO_ENOEXEC, ERRNO_E2BIG, ERRNO_EINVAL or
+ *         ERRNO_ENOMEM
+ *
+ * The ELF is read and parsed before anything is torn down, so a failed
+ * exec returns to the unchanged caller. Once the old image is torn
+ * down there is nothing to return to: running out of memory after that
+ * point kills the process with SIGKILL. @argv and @envp must already be
+ * copied into kernel memory, since the old user pages are freed. On
+ * success the user half of the PML4 is cleared and rebuilt in place,
+ * close-on-exec fds are closed and @frame is pointed at the new entry
+ * point, so the SYSRET ending this syscall lands in the new program.
+ * Task ID, parent and children are left untouched. The personality
+ * carries over unless the new ELF declares the Linux ABI, and the
+ * thread pointer (FS_BASE) moves to the new image's TLS block, or 0 if
+ * it has none. Every other thread of the process is killed, so only the
+ * main thread may exec.
+ */
+pub fn exec_process(
+	task_id: u64,
+	path: &str,
+	argv: &[String],
+	envp: &[String],
+	frame: &mut SyscallFrame,
+) -> Result<(), u64> {
+	/* 1. Load the new image while the old one is still intact */
+	let data = read_elf(path)?;
+	let image = load_image(&data).map_err(|e| {
+		hal::serial_println!("[EXEC] {}: {}", path, e);
+		ERRNO_ENOEXEC
+	})?;
+	if !initial_stack_fits(argv, envp, &image) {
+		return Err(ERRNO_E2BIG);
+	}
+
+	let task_arc = task::scheduler::current_task_arc().ok_or(ERRNO_EINVAL)?;
+	let (pml4_frame, main_thread) = {
+		let t = task_arc.lock();
+		/* Kernel tasks have no user image to replace */
+		(t.pml4_frame.ok_or(ERRNO_EINVAL)?, t.is_main_thread())
+	};
+	if !main_thread {
+		return Err(ERRNO_EINVAL);
+	}
+	let phys_offset = memory::hhdm_offset();
+	let page_alloc = memory::PAGE_ALLOC.get().ok_or(ERRNO_ENOMEM)?;
+
+	/* 2. Point of no return: the other threads die before their next user instruction */
+	kill_other_threads(task_id, task_id);
+
+	/* 3. Drop the old mappings and map the new image */
+	let built = {
+		let mut alloc_guard = page_alloc.lock();
+
+		unsafe {
+			memory::uvm::unmap_user_space(pml4_frame, &mut alloc_guard.frame_alloc, phys_offset);
+		}
+		x86_64::instructions::tlb::flush_all();
+
+		/* The old VMAs still cover this address space: keep reclaim out of it */
+		let mut alloc = crate::swap::ReclaimAlloc::new(&mut alloc_guard, Some(pml4_frame));
+		let mut user_mapper = unsafe { memory::create_mapper(pml4_frame, phys_offset) };
+		build_image(&mut user_mapper, &mut alloc, &image, argv, envp, phys_offset)
+	};
+	/* The old image is gone, so there is nothing left to return to */
+	let (user_sp, stack_top) = match built {
+		Ok(v) => v,
+		Err(e) => {
+			hal::serial_println!("[EXEC] pid={} path={}: {}, killed", task_id, path, e);
+			exit_group(crate::signal::wait_status_signaled(crate::signal::SIGKILL));
+		}
+	};
+
+	{
+		let mut t = task_arc.lock();
+		t.ustack = Some(stack_top);
+		*t.vmas.lock() = image_vmas(&image, stack_top);
+		t.signals.reset_on_exec();
+		t.personality = image_personality(&image, t.personality);
+	}
+	x86_64::registers::model_specific::FsBase::write(image.thread_pointer.unwrap_or(VirtAddr::zero()));
+	crate::fd::close_on_exec(task_id);
+
+	/* 4. Return to Ring 3 at the new entry point with a clean register set */
+	*frame = SyscallFrame {
+		rcx: image.entry_point.as_u64(),
+		r11: 0x202,
+		rdi: user_sp.as_u64(),
+		rsp: user_sp.as_u64(),
+		..SyscallFrame::default()
+	};
+
+	hal::serial_println!("[EXEC] pid={} path={} entry={:#x} bias={:#x}",
+		task_id, path, image.entry_point.as_u64(), image.load_bias);
+
+	Ok(())
+}
+
+/*
+ * thread_create - Start another thread in the calling process
+ * @entry:     User address the thread starts at
+ * @stack_top: Top of a user stack the caller has mapped for it
+ * @arg:       Value passed to @entry in RDI
+ * @tp:        Thread pointer (FS_BASE) of the thread's TLS block, or 0
+ *
+ * The thread shares the caller's PML4, VMA list and fd table and joins
+ * its process (tgid). It starts with RSP = @stack_top rounded down to 16
+ * bytes minus 8, as if @entry had been called, and a fresh kernel stack.
+ * Name, parent, cwd and personality are copied from the caller and the
+ * signal dispositions are shared with it; the blocked mask is
+ * inherited, nothing is pending.
+ *
+ * Return: Task ID of the new thread, or Err string on failure
+ */
+pub fn thread_create(entry: u64, stack_top: u64, arg: u64, tp: u64) -> Result<u64, &'static str> {
+	let creator = task::scheduler::current_task_arc().ok_or("thread: no current task")?;
+	let (creator_id, tgid, pml4, sched_class, name, parent_id, vmas, signals, cwd, personality) = {
+		let t = creator.lock();
+		(t.id.0, t.tgid, t.pml4_frame, t.sched_class, t.name, t.parent_id, t.vmas.clone(),
+			t.signals.thread_copy(), t.cwd.clone(), t.personality)
+	};
+	let pml4 = pml4.ok_or("thread: kernel tasks cannot create threads")?;
+
+	let kstack = memory::kstack::alloc_kernel_stack(KSTACK_SIZE)
+		.ok_or("thread: OOM kernel stack")?;
+
+	let mut ctx = task::CPUContext::default();
+	ctx.rsp = kstack.as_u64();
+	ctx.rip = user_entry_trampoline as u64;
+	ctx.r12 = entry;                       /* user entry point, read by trampoline */
+	ctx.r13 = (stack_top & !0xF) - 8;      /* user RSP, read by trampoline */
+	ctx.r14 = arg;                         /* first argument */
+	ctx.cr3 = pml4.start_address().as_u64();
+	ctx.fs_base = tp;
+	ctx.cs = 0x08;
+	ctx.ss = 0x10;
+	ctx.rflags = 0x202;
+
+	let tid_val = task::TaskId::new();
+	let tid = tid_val.0;
+
+	let tcb = task::TaskCB {
+		id: tid_val,
+		state: task::TaskState::Ready,
+		sched_class,
+		context: ctx,
+		kstack,
+		ustack: Some(VirtAddr::new(stack_top)),
+		name,
+		tgid,
+		parent_id,
+		exit_status: None,
+		pml4_frame: Some(pml4),
+		children: alloc::vec::Vec::new(),
+		waiting_for_child: false,
+		joiner: 0,
+		vmas,
+		signals,
+		cwd,
+		personality,
+	};
+
+	crate::fd::share(creator_id, tid);
+	task::scheduler::enqueue_task(tcb.into_arc());
+
+	hal::serial_println!("[THREAD] pid={} tid={} entry={:#x} stack={:#x}", tgid, tid, entry, stack_top);
+
+	Ok(tid)
+}
+
+/*
+ * kill_other_threads - Send SIGKILL to the rest of a process
+ * @tgid:   Thread group ID of the process
+ * @except: Thread to spare, normally the caller
+ *
+ * The victims die on their way back to Ring 3, so none of them runs
+ * another user instruction: a blocked thread is woken and fails its
+ * syscall, a preempted one is caught when its time slice starts.
+ *
+ * Each victim gets its exit status set before the signal, which tells
+ * exit_group() that it is being torn down by someone else and must not
+ * start a group exit of its own (that would kill a thread that just
+ * exec'd).
+ */
+fn kill_other_threads(tgid: u64, except: u64) {
+	let killed = crate::signal::wait_status_signaled(crate::signal::SIGKILL);
+	let victims: Vec<u64> = x86_64::instructions::interrupts::without_interrupts(|| {
+		task::scheduler::all_tasks()
+			.iter()
+			.filter_map(|t| {
+				let mut t = t.lock();
+				let live = t.state != task::TaskState::Zombie;
+				if t.tgid != tgid || t.id.0 == except || !live {
+					return None;
+				}
+				t.exit_status.get_or_insert(killed);
+				Some(t.id.0)
+			})
+			.collect()
+	});
+	for tid in victims {
+		crate::signal::send(tid, crate::signal::SIGKILL).ok();
+	}
+}
+
+/*
+ * exit_group - Terminate the calling process
+ * @wait_status: Status reported to the parent by SYS_WAIT (see signal.rs)
+ *
+ * From the main thread this is exit_current(), which takes the other
+ * threads down with it. From any other thread, @wait_status is handed
+ * to the main thread before it is killed with the rest, so the parent
+ * sees the status the process chose instead of SIGKILL. A thread
+ * already marked by kill_other_threads() (a group exit or an exec in
+ * progress) only exits itself. Used by SYS_EXIT, fatal signals and the
+ * user fault handler; never returns.
+ */
+pub fn exit_group(wait_status: i32) -> ! {
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		let Some(cur) = task::scheduler::current_task_arc() else { return };
+		let (task_id, tgid, doomed) = {
+			let t = cur.lock();
+			(t.id.0, t.tgid, t.exit_status.is_some())
+		};
+		if task_id == tgid || doomed {
+			return;
+		}
+		if let Some(main) = task::scheduler::find_task_by_id(tgid) {
+			main.lock().exit_status.get_or_insert(wait_status);
+		}
+		kill_other_threads(tgid, task_id);
+	});
+	exit_current(wait_status)
+}
+
+/*
+ * release_task - Free what a reaped task still holds
+ * @task: Task removed from the scheduler for good; it never runs again
+ *
+ * Frees the kernel stack, and the user address space and shared
+ * memory capabilities once no other thread shares them. Its fds were
+ * already closed by exit_current(). Must not be called on the current
+ * task.
+ */
+pub fn release_task(task: task::TaskArc) {
+	let (kstack, pml4, tgid, last_user) = {
+		let t = task.lock();
+		(t.kstack, t.pml4_frame, t.tgid, Arc::strong_count(&t.vmas) == 1)
+	};
+	drop(task);
+	let Some(pml4) = pml4 else { return };
+
+	memory::kstack::free_kernel_stack(kstack, KSTACK_SIZE);
+	if !last_user {
+		return;
+	}
+	crate::shm::release_process(tgid);
+	/* A kernel task keeps whatever CR3 it finds, which may be this one */
+	let (active, flags) = Cr3::read();
+	if active == pml4 {
+		unsafe { Cr3::write(memory::kernel_pml4(), flags); }
+	}
+	let Some(pa) = memory::PAGE_ALLOC.get() else { return };
+	let mut alloc_guard = pa.lock();
+	unsafe {
+		memory::uvm::free_address_space(pml4, &mut alloc_guard.frame_alloc, memory::hhdm_offset());
+	}
+}
+
+/*
+ * reap_dead - Release the exited tasks nobody will wait for
+ *
+ * Called on a live task (spawn, fork, exit), so none of the dead
+ * tasks' kernel stacks is in use: each of them switched away for good
+ * when it exited.
+ */
+fn reap_dead() {
+	let dead = x86_64::instructions::interrupts::without_interrupts(|| {
+		core::mem::take(&mut *DEAD.lock())
+	});
+	for t in dead {
+		release_task(t);
+	}
+}
+
+/*
+ * reparent_children - Hand the children of an exiting task to init
+ * @parent_id: Task ID of the exiting task
+ * @children: Its children
+ *
+ * Live children are adopted by init, which waits for them like its
+ * own; with no init they get parent 0 and are reaped as soon as they
+ * exit. Children that are already zombies are released right away,
+ * since nobody is left to collect their status.
+ */
+fn reparent_children(parent_id: u64, children: &[u64]) {
+	while let Some(zombie) = task::scheduler::find_zombie_child(parent_id, -1) {
+		release_task(zombie);
+	}
+
+	let init_pid = INIT_PID.load(Ordering::Relaxed);
+	let init = match init_pid {
+		0 => None,
+		pid if pid == parent_id => None,
+		pid => task::scheduler::find_task_by_id(pid),
+	};
+	let new_parent = if init.is_some() { init_pid } else { 0 };
+	for &pid in children {
+		let Some(child) = task::scheduler::find_task_by_id(pid) else { continue };
+		child.lock().parent_id = new_parent;
+		if let Some(ref init) = init {
+			init.lock().children.push(pid);
+		}
+	}
+}
+
+/*
+ * exit_current - Terminate the calling thread
+ * @wait_status: Status reported to the parent by SYS_WAIT (see signal.rs)
+ *
+ * Marks the task Zombie, drops its fd table reference, hands its
+ * children to init and context-switches away; never returns. Used
+ * directly by SYS_THREAD_EXIT.
+ *
+ * A secondary thread wakes the thread blocked in SYS_THREAD_JOIN on it,
+ * if any, and stays a zombie until joined; once its main thread has
+ * exited nothing can join it, so it is reaped automatically.
+ *
+ * The main thread ends the whole process: it kills the other threads,
+ * releases the unjoined zombie ones, wakes a parent blocked in SYS_WAIT
+ * and sends it SIGCHLD. Without a parent the process is reaped
+ * automatically.
+ */
+pub fn exit_current(wait_status: i32) -> ! {
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		let old_arc = match task::scheduler::take_current() {
+			Some(t) => t,
+			None => loop { hal::cpu::halt(); },
+		};
+		reap_dead();
+
+		let (task_id, tgid, parent_id, joiner, children) = {
+			let mut t = old_arc.lock();
+			/* A group exit begun by another thread has already chosen the status */
+			t.exit_status.get_or_insert(wait_status);
+			t.set_state(task::TaskState::Zombie);
+			(t.id.0, t.tgid, t.parent_id, t.joiner, core::mem::take(&mut t.children))
+		};
+		crate::fd::cleanup(task_id);
+		let _ = INIT_PID.compare_exchange(task_id, 0, Ordering::Relaxed, Ordering::Relaxed);
+		reparent_children(task_id, &children);
+
+		if task_id != tgid {
+			/* Wake the thread blocked in SYS_THREAD_JOIN */
+			if joiner != 0 {
+				if let Some(j) = task::scheduler::find_task_by_id(joiner) {
+					let blocked = j.lock().state == task::TaskState::Blocked;
+					if blocked {
+						task::scheduler::wake_task(j);
+					}
+				}
+			}
+			if task::scheduler::find_task_by_id(tgid).is_some() {
+				task::scheduler::push_zombie(old_arc);
+			} else {
+				/* The process is gone, so nothing will ever join this thread */
+				DEAD.lock().push(old_arc);
+			}
+		} else {
+			kill_other_threads(tgid, task_id);
+			for thread in task::scheduler::take_zombie_threads(tgid) {
+				release_task(thread);
+			}
+
+			let parent = match parent_id {
+				0 => None,
+				pid => task::scheduler::find_task_by_id(pid),
+			};
+			match parent {
+				Some(par) => {
+					/* Wake parent if it is blocked in SYS_WAIT4 */
+					let waiting = {
+						let p = par.lock();
+						p.waiting_for_child && p.state == task::TaskState::Blocked
+					};
+					if waiting {
+						par.lock().waiting_for_child = false;
+						task::scheduler::wake_task(par);
+					}
+					crate::signal::send(parent_id, crate::signal::SIGCHLD).ok();
+					task::scheduler::push_zombie(old_arc);
+				}
+				/* Nobody will wait for this process */
+				None => DEAD.lock().push(old_arc),
+			}
+		}
+
+		/* Switch to next task */
+		if let Some(new_arc) = task::scheduler::pick_next_task() {
+			if let Some(hook) = task::SWITCH_HOOK.get() {
+				let ks = new_arc.lock().kstack;
+				if ks.as_u64() != 0 { hook(ks); }
+			}
+			let new_ctx = {
+				let g = new_arc.lock();
+				&g.context as *const task::CPUContext
+			};
+			/* Use a dummy old context (never resumed) */
+			static mut DUMMY_CTX: task::CPUContext = task::CPUContext {
+				rsp: 0, rbp: 0, rbx: 0, r12: 0, r13: 0,
+				r14: 0, r15: 0, rip: 0, rflags: 0,
+				cs: 0, ss: 0, fs: 0, gs: 0, ds: 0, es: 0,
+				fs_base: 0, gs_base: 0, cr3: 0,
+			};
+			unsafe {
+				task::context_switch::context_switch(
+					core::ptr::addr_of_mut!(DUMMY_CTX),
+					new_ctx,
+				);
+			}
+		}
+	});
+	loop { hal::cpu::halt(); }
+}
@@ -0,0 +1,330 @@
+/*
+ * procfs.rs - Process and Kernel Information Filesystem
+ *
+ * A synthetic filesystem mounted at /proc. Nothing is stored: every
+ * read renders the file from live kernel state, so contents are always
+ * current but may change between two reads of the same file.
+ *
+ *   /proc/<pid>/status   Name, state, parent and scheduling class
+ *   /proc/<pid>/fds      Open descriptors of the task
+ *   /proc/meminfo        Frame allocator, swap, kernel heap and SLUB usage
+ *   /proc/slabinfo       Per-cache slab statistics
+ *   /proc/mounts         The VFS mount table
+ *   /proc/interrupts     Per-vector interrupt counts
+ *
+ * Every file is read-only; writes are ignored.
+ */
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use task::{TaskArc, TaskState};
+use vfs::{FileType, INode};
+use x86_64::instructions::interrupts::without_interrupts;
+
+/* Files directly under /proc */
+const ROOT_FILES: [&str; 4] = ["meminfo", "slabinfo", "mounts", "interrupts"];
+
+/* Files under /proc/<pid> */
+const PID_FILES: [&str; 2] = ["status", "fds"];
+
+/*
+ * enum ProcEntry - What a procfs file shows
+ */
+#[derive(Clone, Copy)]
+enum ProcEntry {
+	Meminfo,
+	Slabinfo,
+	Mounts,
+	Interrupts,
+	Status(u64),
+	Fds(u64),
+}
+
+/*
+ * tasks - Every task by ID
+ *
+ * A task can briefly show up twice in the scheduler's lists while it
+ * switches out; keying by ID drops the duplicate.
+ */
+fn tasks() -> BTreeMap<u64, TaskArc> {
+	without_interrupts(|| {
+		task::scheduler::all_tasks()
+			.into_iter()
+			.map(|t| {
+				let id = t.lock().id.0;
+				(id, t)
+			})
+			.collect()
+	})
+}
+
+fn find_task(pid: u64) -> Option<TaskArc> {
+	tasks().remove(&pid)
+}
+
+fn state_name(state: TaskState) -> &'static str {
+	match state {
+		TaskState::Ready => "R (ready)",
+		TaskState::Running => "R (running)",
+		TaskState::Blocked => "S (blocked)",
+		TaskState::Sleeping => "S (sleeping)",
+		TaskState::Zombie => "Z (zombie)",
+		TaskState::Terminated => "X (dead)",
+	}
+}
+
+/*
+ * render_status - Contents of /proc/<pid>/status
+ */
+fn render_status(pid: u64, out: &mut String) {
+	let Some(t) = find_task(pid) else { return };
+	without_interrupts(|| {
+		let t = t.lock();
+		let vm_bytes: u64 = t.vmas.lock().iter().map(|v| v.end - v.start).sum();
+		let _ = writeln!(out, "Name:\t{}", t.name);
+		let _ = writeln!(out, "State:\t{}", state_name(t.state));
+		let _ = writeln!(out, "Tgid:\t{}", t.tgid);
+		let _ = writeln!(out, "Pid:\t{}", t.id.0);
+		let _ = writeln!(out, "PPid:\t{}", t.parent_id);
+		let _ = writeln!(out, "SchedClass:\t{:?}", t.sched_class);
+		let _ = writeln!(out, "Personality:\t{:?}", t.personality);
+		let _ = writeln!(out, "Cwd:\t{}", t.cwd);
+		let _ = writeln!(out, "VmSize:\t{} kB", vm_bytes / 1024);
+		let _ = write!(out, "Children:\t");
+		for (i, child) in t.children.iter().enumerate() {
+			let _ = write!(out, "{}{}", if i == 0 { "" } else { " " }, child);
+		}
+		out.push('\n');
+		if let Some(code) = t.exit_status {
+			let _ = writeln!(out, "ExitStatus:\t{}", code);
+		}
+	});
+}
+
+/*
+ * render_fds - Contents of /proc/<pid>/fds
+ *
+ * One line per open descriptor: number, node type, FD_CLOEXEC and the
+ * shared cursor position ("-" while a read or write holds it).
+ */
+fn render_fds(pid: u64, out: &mut String) {
+	let Some(fds) = crate::fd::list(pid) else { return };
+	let _ = writeln!(out, "fd\ttype\tflags\toffset");
+	for (fd, file, flags) in fds {
+		let kind = match file.inode.metadata() {
+			_ if file.inode.is_tty() => "tty",
+			FileType::File => "file",
+			FileType::Directory => "dir",
+			FileType::Device => "device",
+		};
+		let flags = if flags & crate::fd::FD_CLOEXEC != 0 { "cloexec" } else { "-" };
+		/* The descriptor this file is being read through holds its own cursor lock */
+		let _ = match file.offset.try_lock() {
+			Some(off) => writeln!(out, "{}\t{}\t{}\t{}", fd, kind, flags, *off),
+			None => writeln!(out, "{}\t{}\t{}\t-", fd, kind, flags),
+		};
+	}
+}
+
+/*
+ * render_meminfo - Contents of /proc/meminfo
+ */
+fn render_meminfo(out: &mut String) {
+	let line = |out: &mut String, name: &str, bytes: usize| {
+		let _ = writeln!(out, "{:<14}{:>10} kB", name, bytes / 1024);
+	};
+	if let Some(pa) = memory::PAGE_ALLOC.get() {
+		let (total, free, used) = without_interrupts(|| {
+			let pa = pa.lock();
+			let fa = &pa.frame_alloc;
+			(fa.total_frames(), fa.free_frames(), fa.used_frames())
+		});
+		line(out, "MemTotal:", total * 4096);
+		line(out, "MemFree:", free * 4096);
+		line(out, "MemUsed:", used * 4096);
+	}
+	let swap = crate::swap::stats();
+	let (swap_total, swap_used) = swap.as_ref().map_or((0, 0), |s| (s.total, s.used));
+	line(out, "SwapTotal:", swap_total * 4096);
+	line(out, "SwapFree:", (swap_total - swap_used) * 4096);
+	if let Some(s) = swap {
+		let _ = writeln!(out, "{:<14}{:>10}", "SwapIns:", s.ins);
+		let _ = writeln!(out, "{:<14}{:>10}", "SwapOuts:", s.outs);
+	}
+	let heap = without_interrupts(memory::heap::heap_stats);
+	line(out, "HeapTotal:", heap.size);
+	line(out, "HeapUsed:", heap.used);
+	line(out, "HeapLimit:", heap.limit);
+	let _ = writeln!(out, "{:<14}{:>10}", "HeapGrows:", heap.grows);
+	let _ = writeln!(out, "{:<14}{:>10}", "HeapFailed:", heap.failed);
+	if let Some((mapped, cached)) = without_interrupts(memory::slub::usage) {
+		line(out, "SlubMapped:", mapped);
+		line(out, "SlubCached:", cached);
+	}
+}
+
+/*
+ * render_slabinfo - Contents of /proc/slabinfo
+ *
+ * One line per slab cache that has been used. "fallback" counts
+ * objects the cache had to take from the kernel heap.
+ */
+fn render_slabinfo(out: &mut String) {
+	let _ = writeln!(out, "{:<14}{:>8}{:>8}{:>8}{:>8}{:>8}{:>8}{:>10}",
+		"name", "active", "total", "objsize", "perslab", "pages", "slabs", "fallback");
+	for c in without_interrupts(memory::slab::stats) {
+		let _ = writeln!(out, "{:<14}{:>8}{:>8}{:>8}{:>8}{:>8}{:>8}{:>10}",
+			c.name, c.active, c.total, c.object_size, c.per_slab, c.pages_per_slab, c.slabs,
+			c.fallbacks);
+	}
+}
+
+/*
+ * render_mounts - Contents of /proc/mounts, in fstab format
+ *
+ * Mounts do not record their source device, so the filesystem type
+ * stands in for it as it does for Linux pseudo filesystems.
+ */
+fn render_mounts(out: &mut String) {
+	for (path, root) in vfs::mounts() {
+		let fs = root.fs_type();
+		let _ = writeln!(out, "{} {} {} rw 0 0", fs, path, fs);
+	}
+}
+
+/*
+ * render_interrupts - Contents of /proc/interrupts
+ */
+fn render_interrupts(out: &mut String) {
+	let _ = writeln!(out, "{:>4}  {:>12}  name", "vec", "count");
+	idt::for_each_interrupt(|vector, name, count| {
+		let _ = writeln!(out, "{:>4}: {:>12}  {}", vector, count, name);
+	});
+}
+
+/* ------------------------------------------------------------------ */
+/*  Files                                                               */
+/* ------------------------------------------------------------------ */
+
+/*
+ * struct ProcFile - A rendered procfs file
+ * @entry: Which file this is
+ */
+struct ProcFile {
+	entry: ProcEntry,
+}
+
+impl ProcFile {
+	fn new(entry: ProcEntry) -> Arc<dyn INode> {
+		Arc::new(Self { entry })
+	}
+
+	fn render(&self) -> String {
+		let mut out = String::new();
+		match self.entry {
+			ProcEntry::Meminfo => render_meminfo(&mut out),
+			ProcEntry::Slabinfo => render_slabinfo(&mut out),
+			ProcEntry::Mounts => render_mounts(&mut out),
+			ProcEntry::Interrupts => render_interrupts(&mut out),
+			ProcEntry::Status(pid) => render_status(pid, &mut out),
+			ProcEntry::Fds(pid) => render_fds(pid, &mut out),
+		}
+		out
+	}
+}
+
+impl INode for ProcFile {
+	fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+		let text = self.render();
+		let bytes = text.as_bytes();
+		if offset >= bytes.len() {
+			return 0;
+		}
+		let len = core::cmp::min(buf.len(), bytes.len() - offset);
+		buf[..len].copy_from_slice(&bytes[offset..offset + len]);
+		len
+	}
+
+	fn write(&self, _offset: usize, _buf: &[u8]) -> usize { 0 }
+	fn metadata(&self) -> FileType { FileType::File }
+	fn size(&self) -> usize { self.render().len() }
+}
+
+/* ------------------------------------------------------------------ */
+/*  Directories                                                         */
+/* ------------------------------------------------------------------ */
+
+/*
+ * struct PidDir - /proc/<pid>
+ * @pid: Task the directory describes
+ */
+struct PidDir {
+	pid: u64,
+}
+
+impl INode for PidDir {
+	fn read(&self, _offset: usize, _buf: &mut [u8]) -> usize { 0 }
+	fn write(&self, _offset: usize, _buf: &[u8]) -> usize { 0 }
+	fn metadata(&self) -> FileType { FileType::Directory }
+
+	fn lookup(&self, name: &str) -> Option<Arc<dyn INode>> {
+		match name {
+			"status" => Some(ProcFile::new(ProcEntry::Status(self.pid))),
+			"fds" => Some(ProcFile::new(ProcEntry::Fds(self.pid))),
+			_ => None,
+		}
+	}
+
+	fn readdir(&self) -> Option<Vec<(String, FileType)>> {
+		Some(PID_FILES.iter().map(|n| (n.to_string(), FileType::File)).collect())
+	}
+}
+
+/*
+ * struct ProcRoot - /proc itself
+ *
+ * Lists the global files plus one directory per task; a task directory
+ * disappears once the task is reaped.
+ */
+pub struct ProcRoot;
+
+impl INode for ProcRoot {
+	fn read(&self, _offset: usize, _buf: &mut [u8]) -> usize { 0 }
+	fn write(&self, _offset: usize, _buf: &[u8]) -> usize { 0 }
+	fn metadata(&self) -> FileType { FileType::Directory }
+	fn fs_type(&self) -> &'static str { "proc" }
+
+	fn lookup(&self, name: &str) -> Option<Arc<dyn INode>> {
+		match name {
+			"meminfo" => Some(ProcFile::new(ProcEntry::Meminfo)),
+			"slabinfo" => Some(ProcFile::new(ProcEntry::Slabinfo)),
+			"mounts" => Some(ProcFile::new(ProcEntry::Mounts)),
+			"interrupts" => Some(ProcFile::new(ProcEntry::Interrupts)),
+			_ => {
+				let pid = name.parse::<u64>().ok()?;
+				find_task(pid)?;
+				Some(Arc::new(PidDir { pid }))
+			}
+		}
+	}
+
+	fn readdir(&self) -> Option<Vec<(String, FileType)>> {
+		let mut list: Vec<(String, FileType)> = ROOT_FILES.iter()
+			.map(|n| (n.to_string(), FileType::File))
+			.collect();
+		list.extend(tasks().keys().map(|pid| (pid.to_string(), FileType::Directory)));
+		Some(list)
+	}
+}
+
+/*
+ * init - Mount procfs at /proc
+ */
+pub fn init() {
+	vfs::mount("/proc/", Arc::new(ProcRoot));
+}
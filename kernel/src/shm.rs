@@ -0,0 +1,313 @@
+/*
+ * shm.rs - Shared Memory Objects
+ *
+ * Implements SYS_SHM_CREATE, SYS_SHM_MAP, SYS_SHM_GRANT and
+ * SYS_SHM_CLOSE, so user servers can hand each other bulk data without
+ * copying it through 128-byte IPC messages.
+ *
+ * A shared memory object is a set of zero-filled frames. Processes name
+ * it by a CapabilityType::MemoryRegion capability: a 128-bit key in the
+ * global capability store, bound here to the object and to the one
+ * process allowed to use it. The creator gets the first capability;
+ * SHM_GRANT mints another for a given task, and the granter passes its
+ * key on, usually in an IPC message. A key is useless to any other
+ * process, so it does not matter who else sees it.
+ *
+ * Every mapping holds a frame_ref reference on each frame, and the
+ * object holds one more until its last capability is closed (a process
+ * exiting closes its own). A frame is freed when the last of these goes,
+ * so the memory stays valid for as long as anybody has it mapped.
+ * Mappings are Shared VMAs whose pages carry PTE_SHARED: fork() hands
+ * the child the same pages rather than copy-on-write ones, and swap
+ * leaves them alone.
+ */
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use capability::{Capability, CapabilityHandle, CapabilityType};
+use spin::Mutex;
+use task::vma::{PROT_EXEC, PROT_READ, PROT_WRITE, Vma, VmaKind};
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, Mapper, Page, PhysFrame, Size4KiB};
+
+use crate::mm::{MMAP_BASE, MMAP_END};
+use crate::swap::ReclaimAlloc;
+use crate::syscall::{ERRNO_EFAULT, ERRNO_EINVAL, ERRNO_ENOMEM, ERRNO_EPERM, ERRNO_ESRCH,
+	is_user_accessible};
+
+/* Largest shared memory object */
+const SHM_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+const PAGE_SIZE: u64 = 4096;
+
+/* Size of a capability key in user memory */
+const KEY_SIZE: usize = 16;
+
+/*
+ * struct Region - A shared memory object
+ * @frames: Its pages, in order
+ */
+struct Region {
+	frames: Vec<PhysFrame>,
+}
+
+impl Drop for Region {
+	/* Drop the object's own reference; frames still mapped live on */
+	fn drop(&mut self) {
+		let Some(pa) = memory::PAGE_ALLOC.get() else { return };
+		let mut alloc_guard = pa.lock();
+		for &frame in &self.frames {
+			if memory::frame_ref::release(frame) {
+				unsafe { alloc_guard.frame_alloc.deallocate_frame(frame) };
+			}
+		}
+	}
+}
+
+/*
+ * struct Grant - What a MemoryRegion capability stands for
+ * @region: The object
+ * @tgid: The only process that may use the capability
+ */
+struct Grant {
+	region: Arc<Region>,
+	tgid: u64,
+}
+
+/* Capability key -> grant, for every MemoryRegion capability */
+static GRANTS: Mutex<BTreeMap<[u8; KEY_SIZE], Grant>> = Mutex::new(BTreeMap::new());
+
+fn current_tgid() -> Result<u64, u64> {
+	task::scheduler::current_task_arc()
+		.map(|t| t.lock().tgid)
+		.ok_or(ERRNO_EINVAL)
+}
+
+fn read_key(ptr: u64) -> Result<[u8; KEY_SIZE], u64> {
+	if !is_user_accessible(ptr as *const u8, KEY_SIZE) {
+		return Err(ERRNO_EFAULT);
+	}
+	Ok(unsafe { (ptr as *const [u8; KEY_SIZE]).read_unaligned() })
+}
+
+fn write_key(ptr: u64, handle: CapabilityHandle) {
+	unsafe { (ptr as *mut [u8; KEY_SIZE]).write_unaligned(handle.key) };
+}
+
+/*
+ * mint - Create a MemoryRegion capability
+ * @region: Object it names
+ * @tgid: Process that will hold it
+ */
+fn mint(region: Arc<Region>, tgid: u64) -> CapabilityHandle {
+	let handle = {
+		let store = crate::global_cap_store().lock();
+		loop {
+			let handle = CapabilityHandle::generate();
+			if store.add_capability(Capability { cap_type: CapabilityType::MemoryRegion, handle }) {
+				break handle;
+			}
+		}
+	};
+	GRANTS.lock().insert(handle.key, Grant { region, tgid });
+	handle
+}
+
+/*
+ * revoke - Remove a capability from the store
+ * @key: Its key, already taken out of GRANTS
+ */
+fn revoke(key: &[u8; KEY_SIZE]) {
+	crate::global_cap_store().lock().remove_capability(key);
+}
+
+/*
+ * lookup - Resolve a capability of the calling process
+ * @cap: User pointer to the key
+ *
+ * Return: (key, object), ERRNO_EFAULT, or ERRNO_EPERM if the key is not
+ *         a MemoryRegion capability held by the caller
+ */
+fn lookup(cap: u64) -> Result<([u8; KEY_SIZE], Arc<Region>), u64> {
+	let key = read_key(cap)?;
+	let tgid = current_tgid()?;
+	let valid = crate::global_cap_store()
+		.lock()
+		.get_capability(&key)
+		.is_some_and(|c| c.cap_type == CapabilityType::MemoryRegion);
+	match GRANTS.lock().get(&key) {
+		Some(g) if valid && g.tgid == tgid => Ok((key, Arc::clone(&g.region))),
+		_ => Err(ERRNO_EPERM),
+	}
+}
+
+/*
+ * map_region - Map a whole object into the calling process
+ * @region: Object to map
+ * @prot: PROT_* bits
+ *
+ * Return: Start address, or ERRNO_ENOMEM
+ */
+fn map_region(region: &Region, prot: u64) -> Result<u64, u64> {
+	let (pml4, vmas) = crate::mm::current_space()?;
+	let mut vmas = vmas.lock();
+	let len = region.frames.len() as u64 * PAGE_SIZE;
+	let start = vmas.find_free(len, MMAP_BASE, MMAP_END).ok_or(ERRNO_ENOMEM)?;
+	let vma = Vma { start, end: start + len, prot, kind: VmaKind::Shared };
+	let flags = vma.page_flags() | memory::uvm::PTE_SHARED;
+	let offset = memory::hhdm_offset();
+
+	let mut alloc_guard = memory::PAGE_ALLOC.get().ok_or(ERRNO_ENOMEM)?.lock();
+	let mut alloc = ReclaimAlloc::new(&mut alloc_guard, None);
+	let mut mapper = unsafe { memory::create_mapper(pml4, offset) };
+	for (i, &frame) in region.frames.iter().enumerate() {
+		let addr = start + i as u64 * PAGE_SIZE;
+		let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+		match unsafe { mapper.map_to(page, frame, flags, &mut alloc) } {
+			Ok(flush) => flush.flush(),
+			Err(_) => {
+				unsafe { memory::uvm::unmap_range(pml4, start, addr, &mut alloc, offset) };
+				return Err(ERRNO_ENOMEM);
+			}
+		}
+		memory::frame_ref::share(frame);
+	}
+	vmas.insert(vma);
+	Ok(start)
+}
+
+fn check_prot(prot: u64) -> Result<(), u64> {
+	if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+		return Err(ERRNO_EINVAL);
+	}
+	Ok(())
+}
+
+/*
+ * create - Create a shared memory object and map it (SYS_SHM_CREATE)
+ * @size: Size in bytes, rounded up to whole pages
+ * @prot: PROT_* bits of the caller's mapping
+ * @cap_out: User pointer receiving the caller's capability key
+ *
+ * Return: Start of the mapping, or ERRNO_EINVAL / ERRNO_EFAULT / ERRNO_ENOMEM
+ */
+pub fn create(size: u64, prot: u64, cap_out: u64) -> Result<u64, u64> {
+	check_prot(prot)?;
+	if size == 0 || size > SHM_MAX_SIZE {
+		return Err(ERRNO_EINVAL);
+	}
+	if !is_user_accessible(cap_out as *const u8, KEY_SIZE) {
+		return Err(ERRNO_EFAULT);
+	}
+	let tgid = current_tgid()?;
+
+	let pages = size.div_ceil(PAGE_SIZE) as usize;
+	let mut frames = Vec::new();
+	frames.try_reserve_exact(pages).map_err(|_| ERRNO_ENOMEM)?;
+	{
+		let mut alloc_guard = memory::PAGE_ALLOC.get().ok_or(ERRNO_ENOMEM)?.lock();
+		let mut alloc = ReclaimAlloc::new(&mut alloc_guard, None);
+		let offset = memory::hhdm_offset();
+		for _ in 0..pages {
+			let Some(frame) = alloc.allocate_frame() else {
+				for frame in frames.drain(..) {
+					unsafe { alloc.deallocate_frame(frame) };
+				}
+				return Err(ERRNO_ENOMEM);
+			};
+			unsafe {
+				(offset + frame.start_address().as_u64()).as_mut_ptr::<u8>().write_bytes(0, 4096);
+			}
+			frames.push(frame);
+		}
+	}
+
+	let region = Arc::new(Region { frames });
+	let start = map_region(&region, prot)?;
+	write_key(cap_out, mint(region, tgid));
+	Ok(start)
+}
+
+/*
+ * map - Map the object a capability names (SYS_SHM_MAP)
+ * @cap: User pointer to a capability key held by the caller
+ * @prot: PROT_* bits of the mapping
+ *
+ * Return: Start of the mapping, or ERRNO_EPERM / ERRNO_EFAULT / ERRNO_ENOMEM
+ */
+pub fn map(cap: u64, prot: u64) -> Result<u64, u64> {
+	check_prot(prot)?;
+	let (_, region) = lookup(cap)?;
+	map_region(&region, prot)
+}
+
+/*
+ * grant - Give another task a capability for an object (SYS_SHM_GRANT)
+ * @cap: User pointer to a capability key held by the caller
+ * @tid: Any task of the receiving process
+ * @cap_out: User pointer receiving the new key, for the caller to pass on
+ *
+ * Return: 0, or ERRNO_EPERM / ERRNO_ESRCH / ERRNO_EFAULT / ERRNO_EINVAL
+ */
+pub fn grant(cap: u64, tid: u64, cap_out: u64) -> Result<u64, u64> {
+	if !is_user_accessible(cap_out as *const u8, KEY_SIZE) {
+		return Err(ERRNO_EFAULT);
+	}
+	let (_, region) = lookup(cap)?;
+	let target = task::scheduler::find_task_by_id(tid).ok_or(ERRNO_ESRCH)?;
+	let tgid = {
+		let t = target.lock();
+		if t.pml4_frame.is_none() {
+			return Err(ERRNO_EINVAL);
+		}
+		t.tgid
+	};
+	write_key(cap_out, mint(region, tgid));
+	Ok(0)
+}
+
+/*
+ * close - Drop a capability of the calling process (SYS_SHM_CLOSE)
+ * @cap: User pointer to the key
+ *
+ * Existing mappings are unaffected; the object goes away with its last
+ * capability, and its memory with the last mapping.
+ *
+ * Return: 0, or ERRNO_EPERM / ERRNO_EFAULT
+ */
+pub fn close(cap: u64) -> Result<u64, u64> {
+	let (key, region) = lookup(cap)?;
+	let grant = GRANTS.lock().remove(&key);
+	revoke(&key);
+	drop(grant);
+	drop(region);
+	Ok(0)
+}
+
+/*
+ * release_process - Close every capability a process holds
+ * @tgid: Process that is gone
+ *
+ * Called when the last task of the process is released. Objects that
+ * lose their last capability are freed here, so the caller must not
+ * hold PAGE_ALLOC.
+ */
+pub fn release_process(tgid: u64) {
+	let gone: Vec<([u8; KEY_SIZE], Grant)> = without_interrupts(|| {
+		let mut grants = GRANTS.lock();
+		let keys: Vec<[u8; KEY_SIZE]> = grants
+			.iter()
+			.filter(|(_, g)| g.tgid == tgid)
+			.map(|(k, _)| *k)
+			.collect();
+		keys.into_iter().filter_map(|k| grants.remove(&k).map(|g| (k, g))).collect()
+	});
+	for (key, grant) in gone {
+		without_interrupts(|| revoke(&key));
+		drop(grant);
+	}
+}
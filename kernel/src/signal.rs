@@ -0,0 +1,632 @@
+/*
+ * signal.rs - POSIX-style Signals
+ *
+ * Linux-compatible signal numbers, SYS_KILL / SYS_SIGACTION /
+ * SYS_SIGPROCMASK / SYS_SIGRETURN and delivery.
+ *
+ * send() only marks a signal pending on the target (waking it if it is
+ * blocked in the kernel). Delivery happens in the target's own context
+ * when it is about to return to Ring 3: at the end of every syscall
+ * (deliver_syscall, called from syscall_entry) and of every interrupt
+ * taken in Ring 3 (deliver_trap, the idt return-to-user hook).
+ *
+ * A caught signal is delivered by pushing a SigFrame onto the user
+ * stack and redirecting the return to the handler:
+ *
+ *   handler(signo, 0, &frame.ctx)      RSP = &frame.restorer
+ *
+ * The handler returns into the restorer registered with sigaction(),
+ * which issues SYS_SIGRETURN. sigreturn() restores the saved context
+ * and mask and resumes the task with iretq, since sysretq cannot
+ * restore RCX and R11.
+ *
+ * A task terminated by a signal is reported to its parent through
+ * SYS_WAIT with the signal number in the low 7 bits of the wait status
+ * (see wait_status_signaled()).
+ */
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use idt::TrapFrame;
+use task::signal::{NSIG, SA_NODEFER, SA_RESETHAND, SA_RESTORER, SIG_DFL, SIG_IGN, SigAction, sig_bit};
+
+use crate::syscall::{ERRNO_EINVAL, ERRNO_EPERM, ERRNO_ESRCH, SyscallFrame, USER_SPACE_END};
+
+pub const SIGHUP: u8 = 1;
+pub const SIGINT: u8 = 2;
+pub const SIGQUIT: u8 = 3;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGABRT: u8 = 6;
+pub const SIGBUS: u8 = 7;
+pub const SIGFPE: u8 = 8;
+pub const SIGKILL: u8 = 9;
+pub const SIGUSR1: u8 = 10;
+pub const SIGSEGV: u8 = 11;
+pub const SIGUSR2: u8 = 12;
+pub const SIGPIPE: u8 = 13;
+pub const SIGALRM: u8 = 14;
+pub const SIGTERM: u8 = 15;
+pub const SIGCHLD: u8 = 17;
+pub const SIGCONT: u8 = 18;
+pub const SIGSTOP: u8 = 19;
+pub const SIGTSTP: u8 = 20;
+pub const SIGTTIN: u8 = 21;
+pub const SIGTTOU: u8 = 22;
+pub const SIGURG: u8 = 23;
+pub const SIGWINCH: u8 = 28;
+
+/* Ctrl+C pressed and not yet sent (see console_interrupt()) */
+static CONSOLE_INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/* SYS_SIGPROCMASK operations */
+pub const SIG_BLOCK: u64 = 0;
+pub const SIG_UNBLOCK: u64 = 1;
+pub const SIG_SETMASK: u64 = 2;
+
+/* Signals that can be neither caught, ignored nor blocked */
+const UNCATCHABLE: u64 = sig_bit(SIGKILL) | sig_bit(SIGSTOP);
+
+/* RFLAGS bits a signal handler may change through sigreturn */
+const USER_RFLAGS: u64 = 0xDD5; /* CF PF AF ZF SF TF DF OF */
+const RFLAGS_IF: u64 = 0x202;   /* IF plus the reserved bit 1 */
+const RFLAGS_TF_DF: u64 = 0x500;
+
+/* Ring 3 selectors, as loaded by sysretq */
+const USER_CS: u64 = 0x2B;
+const USER_SS: u64 = 0x23;
+
+/* Bytes below the interrupted RSP left alone (System V red zone) */
+const RED_ZONE: u64 = 128;
+
+/*
+ * INode::read() result meaning "interrupted by a signal"; SYS_READ
+ * turns it into ERRNO_EINTR
+ */
+pub const READ_INTERRUPTED: usize = usize::MAX - 3;
+
+/*
+ * struct SigContext - User registers saved across a signal handler
+ *
+ * Same order as the general registers of a Linux x86_64 mcontext, so
+ * handlers that inspect the third argument find familiar names.
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigContext {
+	pub r8: u64,
+	pub r9: u64,
+	pub r10: u64,
+	pub r11: u64,
+	pub r12: u64,
+	pub r13: u64,
+	pub r14: u64,
+	pub r15: u64,
+	pub rdi: u64,
+	pub rsi: u64,
+	pub rbp: u64,
+	pub rbx: u64,
+	pub rdx: u64,
+	pub rax: u64,
+	pub rcx: u64,
+	pub rsp: u64,
+	pub rip: u64,
+	pub rflags: u64,
+}
+
+/*
+ * struct SigFrame - What deliver() pushes onto the user stack
+ * @restorer: Return address of the handler (SigAction::restorer)
+ * @ctx: Interrupted user context
+ * @mask: Blocked mask to restore on sigreturn
+ */
+#[repr(C)]
+struct SigFrame {
+	restorer: u64,
+	ctx: SigContext,
+	mask: u64,
+}
+
+impl SigContext {
+	/*
+	 * from_syscall - Context of a task returning from a syscall
+	 *
+	 * RCX and R11 were clobbered by SYSCALL and hold RIP and RFLAGS.
+	 */
+	fn from_syscall(f: &SyscallFrame) -> Self {
+		Self {
+			r8: f.r8, r9: f.r9, r10: f.r10, r11: f.r11,
+			r12: f.r12, r13: f.r13, r14: f.r14, r15: f.r15,
+			rdi: f.rdi, rsi: f.rsi, rbp: f.rbp, rbx: f.rbx,
+			rdx: f.rdx, rax: f.rax, rcx: f.rcx, rsp: f.rsp,
+			rip: f.rcx, rflags: f.r11,
+		}
+	}
+
+	/*
+	 * to_syscall - Write a context back for sysretq
+	 *
+	 * sysretq takes RIP from RCX and RFLAGS from R11, so those two
+	 * registers cannot be restored; only used to enter a handler.
+	 */
+	fn to_syscall(&self, f: &mut SyscallFrame) {
+		*f = SyscallFrame {
+			r15: self.r15, r14: self.r14, r13: self.r13, r12: self.r12,
+			rbx: self.rbx, rbp: self.rbp, rax: self.rax, rdi: self.rdi,
+			rsi: self.rsi, rdx: self.rdx, r10: self.r10, r8: self.r8,
+			r9: self.r9, rcx: self.rip, r11: self.rflags, rsp: self.rsp,
+		};
+	}
+
+	/*
+	 * from_trap - Context of a task interrupted in Ring 3
+	 */
+	fn from_trap(f: &TrapFrame) -> Self {
+		Self {
+			r8: f.r8, r9: f.r9, r10: f.r10, r11: f.r11,
+			r12: f.r12, r13: f.r13, r14: f.r14, r15: f.r15,
+			rdi: f.rdi, rsi: f.rsi, rbp: f.rbp, rbx: f.rbx,
+			rdx: f.rdx, rax: f.rax, rcx: f.rcx, rsp: f.rsp,
+			rip: f.rip, rflags: f.rflags,
+		}
+	}
+
+	/*
+	 * to_trap - Build a complete Ring 3 iretq frame
+	 */
+	fn to_trap(&self) -> TrapFrame {
+		TrapFrame {
+			r15: self.r15, r14: self.r14, r13: self.r13, r12: self.r12,
+			rbp: self.rbp, rbx: self.rbx, r11: self.r11, r10: self.r10,
+			r9: self.r9, r8: self.r8, rdi: self.rdi, rsi: self.rsi,
+			rdx: self.rdx, rcx: self.rcx, rax: self.rax,
+			rip: self.rip, cs: USER_CS, rflags: self.rflags, rsp: self.rsp, ss: USER_SS,
+		}
+	}
+}
+
+/*
+ * enum DefaultAction - What SIG_DFL does for a signal
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DefaultAction {
+	Terminate,
+	Ignore,
+}
+
+/*
+ * default_action - SIG_DFL behaviour of a signal
+ * @sig: Signal number
+ *
+ * There is no job control, so the stop signals are ignored.
+ */
+fn default_action(sig: u8) -> DefaultAction {
+	match sig {
+		SIGCHLD | SIGCONT | SIGURG | SIGWINCH | SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => {
+			DefaultAction::Ignore
+		}
+		_ => DefaultAction::Terminate,
+	}
+}
+
+/*
+ * is_ignored - Check whether raising a signal would have no effect
+ * @sig: Signal number
+ * @act: Current disposition of @sig in the target
+ */
+fn is_ignored(sig: u8, act: &SigAction) -> bool {
+	if sig_bit(sig) & UNCATCHABLE != 0 {
+		return sig == SIGSTOP;
+	}
+	act.handler == SIG_IGN || (act.handler == SIG_DFL && default_action(sig) == DefaultAction::Ignore)
+}
+
+/*
+ * wait_status_exited - Wait status of a task that called SYS_EXIT
+ * @code: Exit code (only the low 8 bits are kept)
+ */
+pub fn wait_status_exited(code: i32) -> i32 {
+	(code & 0xFF) << 8
+}
+
+/*
+ * wait_status_signaled - Wait status of a task killed by a signal
+ * @sig: Terminating signal number
+ */
+pub fn wait_status_signaled(sig: u8) -> i32 {
+	(sig & 0x7F) as i32
+}
+
+/*
+ * signal_name - Short name of a signal for log messages
+ * @sig: Signal number
+ */
+pub fn signal_name(sig: u8) -> &'static str {
+	match sig {
+		SIGHUP => "SIGHUP",
+		SIGINT => "SIGINT",
+		SIGQUIT => "SIGQUIT",
+		SIGILL => "SIGILL",
+		SIGTRAP => "SIGTRAP",
+		SIGABRT => "SIGABRT",
+		SIGBUS => "SIGBUS",
+		SIGFPE => "SIGFPE",
+		SIGKILL => "SIGKILL",
+		SIGUSR1 => "SIGUSR1",
+		SIGSEGV => "SIGSEGV",
+		SIGUSR2 => "SIGUSR2",
+		SIGPIPE => "SIGPIPE",
+		SIGALRM => "SIGALRM",
+		SIGTERM => "SIGTERM",
+		SIGCHLD => "SIGCHLD",
+		SIGCONT => "SIGCONT",
+		SIGSTOP => "SIGSTOP",
+		_ => "signal",
+	}
+}
+
+/* ---------------------------------------------------------------------- */
+
+/*
+ * send - Raise a signal on a task
+ * @pid: Target task ID
+ * @sig: Signal number, or 0 to only check that @pid exists
+ *
+ * Marks @sig pending and, if the target is blocked or sleeping in the
+ * kernel, wakes it so the blocking call can return ERRNO_EINTR. Signals the target
+ * ignores are discarded. A signal sent to a process may be taken by any
+ * of its threads (see group_target()). May be called from interrupt
+ * context.
+ *
+ * Return: Ok, or ERRNO_ESRCH / ERRNO_EINVAL / ERRNO_EPERM
+ */
+pub fn send(pid: u64, sig: u8) -> Result<(), u64> {
+	if sig as usize >= NSIG {
+		return Err(ERRNO_EINVAL);
+	}
+	let mut target = task::scheduler::find_task_by_id(pid).ok_or(ERRNO_ESRCH)?;
+	if sig != 0 {
+		target = group_target(target, pid, sig);
+	}
+
+	let wake = {
+		let mut t = target.lock();
+		if t.pml4_frame.is_none() {
+			/* Kernel tasks have no user context to deliver to */
+			return Err(ERRNO_EPERM);
+		}
+		if sig == 0 || is_ignored(sig, &t.signals.actions.lock()[sig as usize]) {
+			return Ok(());
+		}
+		t.signals.pending |= sig_bit(sig);
+		matches!(t.state, task::TaskState::Blocked | task::TaskState::Sleeping)
+			&& t.signals.deliverable() & sig_bit(sig) != 0
+	};
+	if wake {
+		task::scheduler::wake_task(target);
+	}
+	Ok(())
+}
+
+/*
+ * group_target - Pick the thread that takes a signal
+ * @target: Task named by the sender
+ * @pid:    Its task ID
+ * @sig:    Signal number
+ *
+ * A signal sent to a process (@pid is its main thread) stays with the
+ * main thread unless that blocks @sig, in which case the first other
+ * live thread of the process that does not block it takes it. A signal
+ * sent to any other thread always stays with that thread.
+ */
+fn group_target(target: task::TaskArc, pid: u64, sig: u8) -> task::TaskArc {
+	let redirect = {
+		let t = target.lock();
+		t.tgid == pid && t.signals.blocked & sig_bit(sig) != 0
+	};
+	if !redirect {
+		return target;
+	}
+	let other = x86_64::instructions::interrupts::without_interrupts(|| {
+		task::scheduler::all_tasks().into_iter().find(|t| {
+			let t = t.lock();
+			t.tgid == pid
+				&& t.state != task::TaskState::Zombie
+				&& t.signals.blocked & sig_bit(sig) == 0
+		})
+	});
+	other.unwrap_or(target)
+}
+
+/*
+ * send_tree - Raise a signal on a task and all of its descendants
+ * @pid: Root task ID
+ * @sig: Signal number
+ */
+fn send_tree(pid: u64, sig: u8) {
+	let children = match task::scheduler::find_task_by_id(pid) {
+		Some(t) => t.lock().children.clone(),
+		None => return,
+	};
+	send(pid, sig).ok();
+	for child in children {
+		send_tree(child, sig);
+	}
+}
+
+/*
+ * console_interrupt - Ctrl+C handler registered with the keyboard driver
+ *
+ * Runs in the keyboard IRQ, which may have interrupted a holder of the
+ * scheduler or a task lock, so it only records the keypress; the signal
+ * goes out from post_console_interrupt().
+ */
+pub fn console_interrupt() {
+	CONSOLE_INTERRUPT.store(true, Ordering::Relaxed);
+}
+
+/*
+ * post_console_interrupt - Send a Ctrl+C recorded by console_interrupt()
+ *
+ * Sends SIGINT to the task that last read the console and to everything
+ * it spawned, like a terminal's foreground process group. Called where
+ * no task lock can be held: before returning to Ring 3 (deliver()), and
+ * from the console input loops and the idle loop, which catch it when
+ * no user task is running.
+ */
+pub fn post_console_interrupt() {
+	if !CONSOLE_INTERRUPT.swap(false, Ordering::Relaxed) {
+		return;
+	}
+	let fg = crate::stdio::foreground();
+	if fg != 0 {
+		send_tree(fg, SIGINT);
+	}
+}
+
+/*
+ * interrupted - Check whether the calling task has a signal to handle
+ *
+ * Used by blocking syscalls to bail out with ERRNO_EINTR.
+ */
+pub fn interrupted() -> bool {
+	task::scheduler::current_task_arc()
+		.map(|t| t.lock().signals.deliverable() != 0)
+		.unwrap_or(false)
+}
+
+/* ---------------------------------------------------------------------- */
+
+/*
+ * sigaction - Change the disposition of a signal (SYS_SIGACTION)
+ * @sig: Signal number
+ * @act: New disposition, or None to only query
+ *
+ * A handler must come with SA_RESTORER and a restorer address, since
+ * the kernel has no trampoline of its own to return through.
+ *
+ * Return: The previous disposition, or ERRNO_EINVAL
+ */
+pub fn sigaction(sig: u8, act: Option<SigAction>) -> Result<SigAction, u64> {
+	if sig == 0 || sig as usize >= NSIG {
+		return Err(ERRNO_EINVAL);
+	}
+	if let Some(a) = act {
+		if sig_bit(sig) & UNCATCHABLE != 0 {
+			return Err(ERRNO_EINVAL);
+		}
+		if a.handler != SIG_DFL && a.handler != SIG_IGN
+			&& (a.flags & SA_RESTORER == 0 || a.restorer == 0)
+		{
+			return Err(ERRNO_EINVAL);
+		}
+	}
+
+	let task_arc = task::scheduler::current_task_arc().ok_or(ERRNO_EINVAL)?;
+	let mut t = task_arc.lock();
+	let shared = t.signals.actions.clone();
+	let mut actions = shared.lock();
+	let old = actions[sig as usize];
+	if let Some(a) = act {
+		actions[sig as usize] = SigAction { mask: a.mask & !UNCATCHABLE, ..a };
+		/* Ignoring a signal discards it if already pending */
+		if is_ignored(sig, &a) {
+			t.signals.pending &= !sig_bit(sig);
+		}
+	}
+	Ok(old)
+}
+
+/*
+ * sigprocmask - Change the blocked signal mask (SYS_SIGPROCMASK)
+ * @how: SIG_BLOCK, SIG_UNBLOCK or SIG_SETMASK
+ * @set: Signal set to apply, or None to only query
+ *
+ * SIGKILL and SIGSTOP are silently left unblocked.
+ *
+ * Return: The previous mask, or ERRNO_EINVAL
+ */
+pub fn sigprocmask(how: u64, set: Option<u64>) -> Result<u64, u64> {
+	let task_arc = task::scheduler::current_task_arc().ok_or(ERRNO_EINVAL)?;
+	let mut t = task_arc.lock();
+	let old = t.signals.blocked;
+	if let Some(set) = set {
+		let new = match how {
+			SIG_BLOCK => old | set,
+			SIG_UNBLOCK => old & !set,
+			SIG_SETMASK => set,
+			_ => return Err(ERRNO_EINVAL),
+		};
+		t.signals.blocked = new & !UNCATCHABLE;
+	}
+	Ok(old)
+}
+
+/* ---------------------------------------------------------------------- */
+
+/*
+ * user_range_ok - Check a user range against the current task's VMAs
+ * @vmas: Memory areas of the task
+ * @start: First byte
+ * @len: Length in bytes
+ * @write: Whether the range must be writable
+ *
+ * Validating before touching the stack keeps a bad RSP from turning
+ * into a kernel page fault.
+ */
+fn user_range_ok(vmas: &task::vma::VmaList, start: u64, len: u64, write: bool) -> bool {
+	let end = match start.checked_add(len) {
+		Some(e) if e <= USER_SPACE_END => e,
+		_ => return false,
+	};
+	let need = if write { task::vma::PROT_WRITE } else { task::vma::PROT_READ };
+	[start, end - 1].iter().all(|&a| vmas.find(a).is_some_and(|v| v.prot & need != 0))
+}
+
+/*
+ * terminate - Exit the current process because of a signal
+ * @sig: Signal number reported through SYS_WAIT
+ */
+fn terminate(sig: u8) -> ! {
+	hal::serial_println!("[SIGNAL] pid={} killed by {}",
+		task::scheduler::current_task_id(), signal_name(sig));
+	crate::process::exit_group(wait_status_signaled(sig));
+}
+
+/*
+ * deliver - Act on the pending signals of the current task
+ * @ctx: User context the task is about to resume with
+ *
+ * Ignored signals are dropped, default-fatal ones terminate the task.
+ * The first signal with a handler rewrites @ctx to enter that handler
+ * and stops; the rest stay pending until the handler returns through
+ * sigreturn(). Must be called with interrupts disabled.
+ *
+ * Return: true if @ctx was changed
+ */
+fn deliver(ctx: &mut SigContext) -> bool {
+	post_console_interrupt();
+	let task_arc = match task::scheduler::current_task_arc() {
+		Some(t) => t,
+		None => return false,
+	};
+
+	loop {
+		let mut t = task_arc.lock();
+		let sig = match t.signals.take_next() {
+			Some(s) => s,
+			None => return false,
+		};
+		let act = t.signals.actions.lock()[sig as usize];
+
+		match act.handler {
+			SIG_IGN => continue,
+			SIG_DFL => match default_action(sig) {
+				DefaultAction::Ignore => continue,
+				DefaultAction::Terminate => {
+					drop(t);
+					terminate(sig);
+				}
+			},
+			handler => {
+				/* Build the frame below the red zone, RSP+8 16-byte aligned */
+				let size = core::mem::size_of::<SigFrame>() as u64;
+				let addr = ((ctx.rsp.wrapping_sub(RED_ZONE + size)) & !0xF).wrapping_sub(8);
+				if !user_range_ok(&t.vmas.lock(), addr, size, true) {
+					drop(t);
+					terminate(SIGSEGV);
+				}
+
+				let old_mask = t.signals.blocked;
+				let mut blocked = old_mask | act.mask;
+				if act.flags & SA_NODEFER == 0 {
+					blocked |= sig_bit(sig);
+				}
+				t.signals.blocked = blocked & !UNCATCHABLE;
+				if act.flags & SA_RESETHAND != 0 {
+					t.signals.actions.lock()[sig as usize] = SigAction::default();
+				}
+				drop(t);
+
+				/* The stack may be copy-on-write, so fault it in unlocked */
+				let frame = addr as *mut SigFrame;
+				unsafe {
+					frame.write(SigFrame { restorer: act.restorer, ctx: *ctx, mask: old_mask });
+				}
+
+				ctx.rip = handler;
+				ctx.rsp = addr;
+				ctx.rdi = sig as u64;
+				ctx.rsi = 0;
+				ctx.rdx = addr + 8; /* &frame.ctx */
+				ctx.rax = 0;
+				ctx.rflags = (ctx.rflags & !RFLAGS_TF_DF) | RFLAGS_IF;
+				return true;
+			}
+		}
+	}
+}
+
+/*
+ * deliver_syscall - Deliver signals before returning from a syscall
+ * @frame: The caller's SyscallFrame, RAX already holding the result
+ *
+ * Called by syscall_entry with interrupts disabled.
+ */
+pub extern "C" fn deliver_syscall(frame: &mut SyscallFrame) {
+	let mut ctx = SigContext::from_syscall(frame);
+	if deliver(&mut ctx) {
+		ctx.to_syscall(frame);
+	}
+}
+
+/*
+ * deliver_trap - Deliver signals before an interrupt returns to Ring 3
+ * @frame: Interrupted user registers
+ *
+ * Registered with idt::register_return_to_user_hook().
+ */
+pub fn deliver_trap(frame: &mut TrapFrame) {
+	let mut ctx = SigContext::from_trap(frame);
+	if deliver(&mut ctx) {
+		*frame = ctx.to_trap();
+	}
+}
+
+/*
+ * sigreturn - Return from a signal handler (SYS_SIGRETURN)
+ * @frame: SyscallFrame of the SYS_SIGRETURN call
+ *
+ * The handler's ret has popped SigFrame::restorer, so the user RSP
+ * points at the saved context. Restores it and the blocked mask,
+ * delivers anything that became unblocked, then resumes the task with
+ * every register intact. A corrupted frame kills the task with SIGSEGV.
+ */
+pub fn sigreturn(frame: &SyscallFrame) -> ! {
+	x86_64::instructions::interrupts::disable();
+
+	let task_arc = match task::scheduler::current_task_arc() {
+		Some(t) => t,
+		None => terminate(SIGSEGV),
+	};
+	let addr = frame.rsp;
+	let size = (core::mem::size_of::<SigContext>() + 8) as u64;
+	let vmas = task_arc.lock().vmas.clone();
+	if !user_range_ok(&vmas.lock(), addr, size, false) {
+		terminate(SIGSEGV);
+	}
+	let (mut ctx, mask) = unsafe {
+		let p = addr as *const SigContext;
+		(p.read_unaligned(), (addr as *const u8).add(size as usize - 8).cast::<u64>().read_unaligned())
+	};
+	if ctx.rip >= USER_SPACE_END || ctx.rsp >= USER_SPACE_END {
+		terminate(SIGSEGV);
+	}
+	ctx.rflags = (ctx.rflags & USER_RFLAGS) | RFLAGS_IF;
+	task_arc.lock().signals.blocked = mask & !UNCATCHABLE;
+	drop(task_arc);
+
+	deliver(&mut ctx);
+	let trap = ctx.to_trap();
+	unsafe { idt::trap_return(&trap) }
+}
+
@@ -5,13 +5,25 @@
  * stdout (fd 1), and stderr (fd 2).
  */
 
+use core::sync::atomic::{AtomicU64, Ordering};
 use vfs::{FileType, INode};
 
+/* Task that most recently read the console; receives Ctrl+C (SIGINT) */
+static FOREGROUND: AtomicU64 = AtomicU64::new(0);
+
+/*
+ * foreground - Task ID owning the console, 0 if none has read it yet
+ */
+pub fn foreground() -> u64 {
+	FOREGROUND.load(Ordering::Relaxed)
+}
+
 /*
  * stdin_inode - Terminal input via PS/2 keyboard
  *
  * Characters are buffered by the PS/2 interrupt handler in keyboard::INPUT_BUF.
  * We spin-yield until a key is available so other tasks can run while waiting.
+ * A pending signal interrupts the wait.
  */
 pub struct StdinINode;
 
@@ -20,11 +32,16 @@ impl INode for StdinINode {
 		if buf.is_empty() {
 			return 0;
 		}
+		FOREGROUND.store(task::scheduler::current_task_id(), Ordering::Relaxed);
 		loop {
 			if let Some(b) = keyboard::pop_key() {
 				buf[0] = b;
 				return 1;
 			}
+			crate::signal::post_console_interrupt();
+			if crate::signal::interrupted() {
+				return crate::signal::READ_INTERRUPTED;
+			}
 			/* Yield to other tasks while waiting for input */
 			x86_64::instructions::interrupts::enable();
 			core::hint::spin_loop();
@@ -39,6 +56,10 @@ impl INode for StdinINode {
 	fn metadata(&self) -> FileType {
 		FileType::Device
 	}
+
+	fn is_tty(&self) -> bool {
+		true
+	}
 }
 
 /* stdout_inode - Framebuffer console + serial */
@@ -63,6 +84,10 @@ impl INode for StdoutINode {
 	fn metadata(&self) -> FileType {
 		FileType::Device
 	}
+
+	fn is_tty(&self) -> bool {
+		true
+	}
 }
 
 /* stderr_inode - Serial only */
@@ -86,4 +111,8 @@ impl INode for StderrINode {
 	fn metadata(&self) -> FileType {
 		FileType::Device
 	}
+
+	fn is_tty(&self) -> bool {
+		true
+	}
 }
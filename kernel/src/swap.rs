@@ -0,0 +1,354 @@
+/*
+ * swap.rs - Swapping Anonymous User Pages
+ *
+ * A swap area is a raw block device (fs::BlockDev, found through the
+ * fs::block_dev() registry) split into page-sized slots of
+ * SECTORS_PER_PAGE sectors. Swap files are not supported: slot I/O runs
+ * in the fault and reclaim paths, which may interrupt a filesystem that
+ * holds its own locks while copying to user memory, so it must never go
+ * through a VFS INode. Once swapon() has registered one, user page
+ * allocations go through ReclaimAlloc: when free frames drop below
+ * SWAP_LOW_WATERMARK it writes up to SWAP_CLUSTER cold pages out, turns
+ * their PTEs into swap entries (memory::swap) and frees the frames. The
+ * next access faults, and fault.rs calls swap_in() to read the page back
+ * into a new frame.
+ *
+ * Only the Anon and Stack areas of live processes are swapped, and only
+ * private pages: a frame still shared copy-on-write, or holding a futex
+ * word somebody sleeps on, stays put. Coldness is judged by a clock over
+ * the ACCESSED bit: an accessed page loses the bit and gets a second
+ * chance, a page found without it is evicted. Each scan starts after the
+ * process last evicted from, so the pressure is spread over all of them.
+ *
+ * The watermark leaves frames for the kernel heap, the slab caches and
+ * kernel stacks, which do not reclaim. There is one swap area and no
+ * swapoff yet.
+ */
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use fs::BlockDev;
+use memory::PageAllocator;
+use spin::Mutex;
+use task::TaskState;
+use task::vma::VmaKind;
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{
+	FrameAllocator, FrameDeallocator, PageTableFlags, PhysFrame, Size4KiB,
+};
+
+/* 512-byte sectors per 4 KiB slot */
+const SECTORS_PER_PAGE: u64 = 8;
+
+/* Reclaim starts once fewer frames than this are free (1 MiB) */
+const SWAP_LOW_WATERMARK: usize = 256;
+
+/* Pages written out per reclaim */
+const SWAP_CLUSTER: usize = 32;
+
+/*
+ * struct SwapArea - The active swap area
+ * @dev: Device holding the slots
+ * @path: Path it was enabled with, for display
+ */
+struct SwapArea {
+	dev: Arc<dyn BlockDev>,
+	path: String,
+}
+
+static SWAP: Mutex<Option<SwapArea>> = Mutex::new(None);
+
+/* Process (tgid) the last page was evicted from: the clock hand */
+static HAND: AtomicU64 = AtomicU64::new(0);
+
+static SWAP_INS: AtomicUsize = AtomicUsize::new(0);
+static SWAP_OUTS: AtomicUsize = AtomicUsize::new(0);
+
+/*
+ * struct SwapStats - Swap area statistics
+ * @path: Device backing the area
+ * @total: Slots (pages) in the area
+ * @used: Slots holding a page
+ * @ins: Pages read back since boot
+ * @outs: Pages written out since boot
+ */
+pub struct SwapStats {
+	pub path: String,
+	pub total: usize,
+	pub used: usize,
+	pub ins: usize,
+	pub outs: usize,
+}
+
+impl SwapArea {
+	/*
+	 * write_slot - Write one page to a slot
+	 * @slot: Destination slot
+	 * @page: The page contents (4096 bytes)
+	 */
+	fn write_slot(&self, slot: u64, page: *const u8) -> bool {
+		(0..SECTORS_PER_PAGE).all(|i| {
+			let sector = unsafe { &*(page.add(i as usize * 512) as *const [u8; 512]) };
+			self.dev.write_block(slot * SECTORS_PER_PAGE + i, sector)
+		})
+	}
+
+	/*
+	 * read_slot - Read one page from a slot
+	 * @slot: Source slot
+	 * @page: Destination (4096 bytes)
+	 */
+	fn read_slot(&self, slot: u64, page: *mut u8) -> bool {
+		(0..SECTORS_PER_PAGE).all(|i| {
+			let sector = unsafe { &mut *(page.add(i as usize * 512) as *mut [u8; 512]) };
+			self.dev.read_block(slot * SECTORS_PER_PAGE + i, sector)
+		})
+	}
+}
+
+/*
+ * swapon - Enable swapping to a block device
+ * @dev: Raw block device to hold swapped-out pages; its contents are lost
+ * @path: Name to show in statistics
+ *
+ * Return: Number of pages the area holds, or an error if it is too
+ *         small or a swap area is already active
+ */
+pub fn swapon(dev: Arc<dyn BlockDev>, path: &str) -> Result<usize, &'static str> {
+	let slots = (dev.sector_count() / SECTORS_PER_PAGE) as usize;
+	if slots == 0 {
+		return Err("too small for a swap area");
+	}
+	without_interrupts(|| {
+		let mut swap = SWAP.lock();
+		if swap.is_some() || !memory::swap::init(slots) {
+			return Err("a swap area is already active");
+		}
+		*swap = Some(SwapArea { dev, path: String::from(path) });
+		Ok(slots)
+	})
+}
+
+/*
+ * stats - Statistics of the active swap area
+ *
+ * Return: None if swapping is not enabled
+ */
+pub fn stats() -> Option<SwapStats> {
+	without_interrupts(|| {
+		let swap = SWAP.lock();
+		let area = swap.as_ref()?;
+		let (total, used) = memory::swap::usage();
+		Some(SwapStats {
+			path: area.path.clone(),
+			total,
+			used,
+			ins: SWAP_INS.load(Ordering::Relaxed),
+			outs: SWAP_OUTS.load(Ordering::Relaxed),
+		})
+	})
+}
+
+/*
+ * struct ReclaimAlloc - Frame allocator for user pages that may swap
+ * @pa: The locked page allocator
+ * @skip: Address space being changed by the caller, never scanned
+ *
+ * Passed wherever user pages and their page tables are allocated
+ * (fault resolution, ELF loading, fork) in place of the bare frame
+ * allocator.
+ */
+pub struct ReclaimAlloc<'a> {
+	pa: &'a mut PageAllocator,
+	skip: Option<PhysFrame>,
+}
+
+impl<'a> ReclaimAlloc<'a> {
+	pub fn new(pa: &'a mut PageAllocator, skip: Option<PhysFrame>) -> Self {
+		ReclaimAlloc { pa, skip }
+	}
+}
+
+unsafe impl FrameAllocator<Size4KiB> for ReclaimAlloc<'_> {
+	fn allocate_frame(&mut self) -> Option<PhysFrame> {
+		if self.pa.frame_alloc.free_frames() < SWAP_LOW_WATERMARK {
+			reclaim(self.pa, SWAP_CLUSTER, self.skip);
+		}
+		self.pa.frame_alloc.allocate_frame()
+	}
+}
+
+impl FrameDeallocator<Size4KiB> for ReclaimAlloc<'_> {
+	unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+		unsafe { self.pa.frame_alloc.deallocate_frame(frame) };
+	}
+}
+
+/*
+ * struct Space - A user address space reclaim may scan
+ * @tgid: Process owning it
+ * @pml4: Its page tables
+ * @ranges: Its Anon and Stack areas
+ */
+struct Space {
+	tgid: u64,
+	pml4: PhysFrame,
+	ranges: Vec<(u64, u64)>,
+}
+
+/*
+ * swappable_spaces - Address spaces to scan, starting after the hand
+ * @skip: Address space to leave out
+ *
+ * Tasks and VMA lists that are locked right now are left out too.
+ */
+fn swappable_spaces(skip: Option<PhysFrame>) -> Vec<Space> {
+	let mut spaces: Vec<Space> = Vec::new();
+	for task_arc in task::scheduler::all_tasks() {
+		let Some(t) = task_arc.try_lock() else { continue };
+		let Some(pml4) = t.pml4_frame else { continue };
+		if t.state == TaskState::Zombie || Some(pml4) == skip || spaces.iter().any(|s| s.pml4 == pml4) {
+			continue;
+		}
+		let Some(vmas) = t.vmas.try_lock() else { continue };
+		let ranges = vmas
+			.iter()
+			.filter(|v| matches!(v.kind, VmaKind::Anon | VmaKind::Stack))
+			.map(|v| (v.start, v.end))
+			.collect();
+		spaces.push(Space { tgid: t.tgid, pml4, ranges });
+	}
+	let hand = HAND.load(Ordering::Relaxed);
+	spaces.sort_by_key(|s| (s.tgid <= hand, s.tgid));
+	spaces
+}
+
+/*
+ * reclaim - Write cold user pages to swap and free their frames
+ * @pa: The locked page allocator
+ * @want: Frames to free
+ * @skip: Address space to leave alone
+ *
+ * Makes at most two passes: the first may only clear ACCESSED bits,
+ * and the second then finds those pages cold.
+ *
+ * Return: Frames freed
+ */
+fn reclaim(pa: &mut PageAllocator, want: usize, skip: Option<PhysFrame>) -> usize {
+	let Some(swap) = SWAP.try_lock() else { return 0 };
+	let Some(area) = swap.as_ref() else { return 0 };
+	let (total, used) = memory::swap::usage();
+	if used == total {
+		return 0;
+	}
+
+	let spaces = swappable_spaces(skip);
+	let (active, _) = Cr3::read();
+	let offset = memory::hhdm_offset();
+	let mut freed = 0;
+	let mut stop = false;
+
+	'scan: for _ in 0..2 {
+		for space in &spaces {
+			for &(start, end) in &space.ranges {
+				unsafe {
+					memory::uvm::for_each_leaf(space.pml4, start, end, offset, |va, entry| {
+						let flags = entry.flags();
+						if freed >= want || stop || !flags.contains(PageTableFlags::PRESENT) {
+							return;
+						}
+						if flags.contains(PageTableFlags::ACCESSED) {
+							entry.set_flags(flags - PageTableFlags::ACCESSED);
+							if space.pml4 == active {
+								tlb::flush(va);
+							}
+							return;
+						}
+						let frame = PhysFrame::containing_address(entry.addr());
+						if memory::frame_ref::count(frame) > 1 || crate::futex::frame_in_use(frame) {
+							return;
+						}
+						let Some(slot) = memory::swap::alloc_slot() else {
+							stop = true;
+							return;
+						};
+						let page = (offset + frame.start_address().as_u64()).as_ptr::<u8>();
+						if !area.write_slot(slot, page) {
+							hal::serial_println!("[SWAP] write to slot {} of {} failed", slot, area.path);
+							memory::swap::release(slot);
+							stop = true;
+							return;
+						}
+						memory::swap::set_entry(entry, slot);
+						if space.pml4 == active {
+							tlb::flush(va);
+						}
+						pa.frame_alloc.deallocate_frame(frame);
+						HAND.store(space.tgid, Ordering::Relaxed);
+						freed += 1;
+					});
+				}
+				if freed >= want || stop {
+					break 'scan;
+				}
+			}
+		}
+	}
+	SWAP_OUTS.fetch_add(freed, Ordering::Relaxed);
+	freed
+}
+
+/*
+ * swapped - Find the swap entry for a user address
+ * @pml4: Address space to look in
+ * @addr: Virtual address
+ * @offset: HHDM offset
+ *
+ * Return: The slot holding the page, or None if it is not swapped out
+ */
+pub unsafe fn swapped(pml4: PhysFrame, addr: VirtAddr, offset: VirtAddr) -> Option<u64> {
+	let entry = unsafe { memory::uvm::leaf_entry(pml4, addr, offset)? };
+	memory::swap::entry_slot(entry)
+}
+
+/*
+ * swap_in - Read a swapped-out page back and map it
+ * @pml4: Active address space
+ * @addr: Faulting address
+ * @slot: Slot from swapped()
+ * @flags: Leaf PTE flags for the page (from its VMA)
+ * @alloc: Allocator for the new frame
+ * @offset: HHDM offset
+ *
+ * Return: true if the page is mapped again
+ */
+pub unsafe fn swap_in(
+	pml4: PhysFrame,
+	addr: VirtAddr,
+	slot: u64,
+	flags: PageTableFlags,
+	alloc: &mut ReclaimAlloc,
+	offset: VirtAddr,
+) -> bool {
+	let Some(frame) = alloc.allocate_frame() else { return false };
+	let page = (offset + frame.start_address().as_u64()).as_mut_ptr::<u8>();
+	let read = SWAP.lock().as_ref().is_some_and(|area| area.read_slot(slot, page));
+	/* Reclaim skips swap entries, so the PTE still points at @slot */
+	let entry = unsafe { memory::uvm::leaf_entry(pml4, addr, offset) };
+	let Some(entry) = entry.filter(|_| read) else {
+		hal::serial_println!("[SWAP] read of slot {} failed", slot);
+		unsafe { alloc.deallocate_frame(frame) };
+		return false;
+	};
+	memory::swap::release(slot);
+	entry.set_addr(frame.start_address(), flags);
+	SWAP_INS.fetch_add(1, Ordering::Relaxed);
+	true
+}
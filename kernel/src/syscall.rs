@@ -5,6 +5,9 @@
  * Handles system call entry, register marshaling, and return to userspace.
  */
 
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::arch::naked_asm;
 use hal::serial_println;
 use x86_64::VirtAddr;
@@ -17,12 +20,15 @@ use x86_64::registers::rflags::RFlags;
  * Both this table and ulib/src/lib.rs must be kept in sync.
  *
  * Process group (0–9):
- *   0  EXIT        Terminate the calling process
+ *   0  EXIT        Terminate the calling process (every thread)
  *   1  YIELD       Voluntarily yield the CPU to the scheduler
- *   2  GETPID      Return the calling task's ID
+ *   2  GETPID      Return the calling process's ID (its main thread's task ID)
  *   3  GETPPID     Return the parent task's ID
- *   4  SPAWN       Create a new process from an ELF path on the VFS
+ *   4  SPAWN       Create a new process from an ELF or script path, argv, envp and file actions
  *   5  WAIT        Wait for a child process to exit
+ *   6  FORK        Duplicate the calling process (copy-on-write)
+ *   7  EXEC        Replace the calling process's image with an ELF, argv and envp
+ *   8  ARCH_PRCTL  Set or read the thread pointer (FS_BASE)
  *
  * File I/O group (10–19):
  *   10 OPEN        Open a VFS path, return an fd
@@ -34,15 +40,44 @@ use x86_64::registers::rflags::RFlags;
  *   16 DUP2        Duplicate an fd to a specific descriptor number
  *   17 PIPE        Create a unidirectional pipe, return [read_fd, write_fd]
  *   18 GETDENTS    Read directory entries in dirent64 format
+ *   19 FCNTL       Duplicate an fd or get/set its close-on-exec flag
  *
  * Filesystem group (20–29):
  *   20 MKDIR       Create a directory at the given VFS path
  *   21 UNLINK      Delete a file at the given VFS path
+ *   22 CHDIR       Change the calling task's working directory
+ *   23 GETCWD      Copy the working directory into a user buffer
  *
  * IPC group (30–39):
  *   30 SEND        Send a message to an IPC port
  *   31 RECV        Receive a message from an IPC port (non-blocking)
  *   32 RECV_BLOCK  Receive a message from an IPC port (blocking)
+ *
+ * Memory group (40–49):
+ *   40 MMAP        Map anonymous memory (demand-paged)
+ *   41 MUNMAP      Remove a range of mappings
+ *   42 MPROTECT    Change the protection of mapped memory
+ *   43 SHM_CREATE  Create a shared memory object and map it
+ *   44 SHM_MAP     Map the shared memory object a capability names
+ *   45 SHM_GRANT   Give another process a capability for a shared memory object
+ *   46 SHM_CLOSE   Drop a shared memory capability
+ *
+ * Signal group (50–59):
+ *   50 KILL        Send a signal to a process
+ *   51 SIGACTION   Install or query a signal handler
+ *   52 SIGPROCMASK Change the blocked signal mask
+ *   53 SIGRETURN   Return from a signal handler (called by the restorer)
+ *
+ * Time group (60–69):
+ *   60 NANOSLEEP   Suspend the calling task for a duration
+ *   61 CLOCK_GETTIME Read the realtime or monotonic clock
+ *
+ * Thread group (70–79):
+ *   70 THREAD_CREATE Start a thread sharing the caller's address space and fds
+ *   71 THREAD_EXIT Terminate the calling thread
+ *   72 THREAD_JOIN Wait for a thread of the calling process to exit
+ *   73 GETTID      Return the calling thread's task ID
+ *   74 FUTEX       Sleep on or wake a userspace lock word
  */
 pub const SYS_EXIT: u64        =  0;
 pub const SYS_YIELD: u64       =  1;
@@ -50,6 +85,9 @@ pub const SYS_GETPID: u64      =  2;
 pub const SYS_GETPPID: u64     =  3;
 pub const SYS_SPAWN: u64       =  4;
 pub const SYS_WAIT: u64        =  5;
+pub const SYS_FORK: u64        =  6;
+pub const SYS_EXEC: u64        =  7;
+pub const SYS_ARCH_PRCTL: u64  =  8;
 
 pub const SYS_OPEN: u64        = 10;
 pub const SYS_CLOSE: u64       = 11;
@@ -60,28 +98,91 @@ pub const SYS_DUP: u64         = 15;
 pub const SYS_DUP2: u64        = 16;
 pub const SYS_PIPE: u64        = 17;
 pub const SYS_GETDENTS: u64    = 18;
+pub const SYS_FCNTL: u64       = 19;
 
 pub const SYS_MKDIR: u64       = 20;
 pub const SYS_UNLINK: u64      = 21;
+pub const SYS_CHDIR: u64       = 22;
+pub const SYS_GETCWD: u64      = 23;
 
 pub const SYS_SEND: u64        = 30;
 pub const SYS_RECV: u64        = 31;
 pub const SYS_RECV_BLOCK: u64  = 32;
 
-/* Error codes (negative errno values represented as u64) */
+pub const SYS_MMAP: u64        = 40;
+pub const SYS_MUNMAP: u64      = 41;
+pub const SYS_MPROTECT: u64    = 42;
+pub const SYS_SHM_CREATE: u64  = 43;
+pub const SYS_SHM_MAP: u64     = 44;
+pub const SYS_SHM_GRANT: u64   = 45;
+pub const SYS_SHM_CLOSE: u64   = 46;
+
+pub const SYS_KILL: u64        = 50;
+pub const SYS_SIGACTION: u64   = 51;
+pub const SYS_SIGPROCMASK: u64 = 52;
+pub const SYS_SIGRETURN: u64   = 53;
+
+pub const SYS_NANOSLEEP: u64   = 60;
+pub const SYS_CLOCK_GETTIME: u64 = 61;
+
+pub const SYS_THREAD_CREATE: u64 = 70;
+pub const SYS_THREAD_EXIT: u64 = 71;
+pub const SYS_THREAD_JOIN: u64 = 72;
+pub const SYS_GETTID: u64      = 73;
+pub const SYS_FUTEX: u64       = 74;
+
+/*
+ * Error codes (negative errno values represented as u64)
+ *
+ * The numbers are Linux's, shared with the Linux personality (linux.rs)
+ * and mirrored in ulib/src/errno.rs.
+ */
+pub const ERRNO_EPERM: u64 = u64::MAX;       /* Operation not permitted (errno 1) */
+pub const ERRNO_ENOENT: u64 = u64::MAX - 1;  /* No such file or directory (errno 2) */
+pub const ERRNO_ESRCH: u64 = u64::MAX - 2;  /* No such process (errno 3) */
+pub const ERRNO_EINTR: u64 = u64::MAX - 3;  /* Interrupted by a signal (errno 4) */
+pub const ERRNO_E2BIG: u64 = u64::MAX - 6;  /* Argument list too long (errno 7) */
+pub const ERRNO_ENOEXEC: u64 = u64::MAX - 7; /* Exec format error (errno 8) */
 pub const ERRNO_EBADF: u64 = u64::MAX - 8;  /* Bad file descriptor (errno 9) */
 pub const ERRNO_ECHILD: u64 = u64::MAX - 9;  /* No child processes (errno 10) */
-pub const ERRNO_EAGAIN: u64 = u64::MAX - 11; /* Resource temporarily unavailable */
+pub const ERRNO_EAGAIN: u64 = u64::MAX - 10; /* Resource temporarily unavailable (errno 11) */
 pub const ERRNO_ENOMEM: u64 = u64::MAX - 11; /* Out of memory (errno 12) */
+pub const ERRNO_EACCES: u64 = u64::MAX - 12; /* Permission denied (errno 13) */
 pub const ERRNO_EFAULT: u64 = u64::MAX - 13; /* Bad address (errno 14) */
-pub const ERRNO_ENOENT: u64 = u64::MAX - 2;  /* No such file or entry */
-pub const ERRNO_EINVAL: u64 = u64::MAX - 21; /* Invalid argument (errno 22) */
+pub const ERRNO_EEXIST: u64 = u64::MAX - 16; /* File exists (errno 17) */
+pub const ERRNO_ENODEV: u64 = u64::MAX - 18; /* No such device (errno 19) */
 pub const ERRNO_ENOTDIR: u64 = u64::MAX - 19; /* Not a directory (errno 20) */
+pub const ERRNO_EISDIR: u64 = u64::MAX - 20; /* Is a directory (errno 21) */
+pub const ERRNO_EINVAL: u64 = u64::MAX - 21; /* Invalid argument (errno 22) */
+pub const ERRNO_EMFILE: u64 = u64::MAX - 23; /* Too many open files (errno 24) */
+pub const ERRNO_ENOTTY: u64 = u64::MAX - 24; /* Not a terminal (errno 25) */
 pub const ERRNO_EPIPE: u64 = u64::MAX - 31;  /* Broken pipe (errno 32) */
+pub const ERRNO_ERANGE: u64 = u64::MAX - 33; /* Result too large (errno 34) */
+pub const ERRNO_ENOSYS: u64 = u64::MAX - 37; /* Function not implemented (errno 38) */
+pub const ERRNO_ETIMEDOUT: u64 = u64::MAX - 109; /* Timed out (errno 110) */
+
+/* Flags for SYS_OPEN / SYS_PIPE / SYS_DUP2 */
+pub const O_CLOEXEC: u64 = 0x8_0000; /* Close the fd on SYS_EXEC */
+
+/* SYS_SPAWN flags */
+pub const SPAWN_LINUX: u64 = 0x1; /* Run the child under the Linux personality */
+
+/* SYS_ARCH_PRCTL codes (Linux values) */
+pub const ARCH_SET_FS: u64 = 0x1002;
+pub const ARCH_GET_FS: u64 = 0x1003;
+
+/* SYS_FCNTL commands (Linux values) */
+pub const F_DUPFD: u64 = 0;
+pub const F_GETFD: u64 = 1;
+pub const F_SETFD: u64 = 2;
+pub const F_DUPFD_CLOEXEC: u64 = 1030;
+
+/* Maximum number of entries in a SYS_SPAWN / SYS_EXEC argv or envp array */
+const MAX_ARGS: usize = 256;
 
 /* Userspace memory validation constants */
 const USER_SPACE_START: u64 = 0x0000_0000_0000_0000;
-const USER_SPACE_END: u64 = 0x0000_8000_0000_0000; /* 128 TiB - typical userspace limit */
+pub(crate) const USER_SPACE_END: u64 = 0x0000_8000_0000_0000; /* 128 TiB - typical userspace limit */
 
 /*
  * init_syscalls - Initialize system call support
@@ -138,7 +239,7 @@ pub fn init_syscalls() {
  * Returns true if the entire memory range [ptr, ptr+len) is in valid userspace.
  */
 #[inline]
-fn is_user_accessible(ptr: *const u8, len: usize) -> bool {
+pub(crate) fn is_user_accessible(ptr: *const u8, len: usize) -> bool {
 	let addr = ptr as u64;
 	let end_addr = addr.saturating_add(len as u64);
 
@@ -147,22 +248,237 @@ fn is_user_accessible(ptr: *const u8, len: usize) -> bool {
 }
 
 /*
- * syscall_entry - Low-level syscall entry point
+ * struct UserStr - One argv/envp entry passed to SYS_SPAWN / SYS_EXEC
+ * @ptr: User address of the UTF-8 bytes (0 terminates the array)
+ * @len: Length in bytes, without any NUL terminator
+ */
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UserStr {
+	ptr: u64,
+	len: u64,
+}
+
+/*
+ * copy_str_array - Copy an argv/envp array out of userspace
+ * @ptr: User address of a UserStr array terminated by a null @ptr entry
+ *       (0 = empty array)
+ * @budget: Remaining process::ARG_MAX bytes, charged for every string
  *
- * Naked assembly function that handles the transition from user to kernel mode.
- * Saves user context, switches to kernel stack, and calls the dispatcher.
+ * Return: Kernel copies of the strings, or an errno
+ */
+fn copy_str_array(ptr: u64, budget: &mut usize) -> Result<Vec<String>, u64> {
+	let mut out = Vec::new();
+	if ptr == 0 {
+		return Ok(out);
+	}
+
+	let mut entry = ptr as *const UserStr;
+	loop {
+		if !is_user_accessible(entry as *const u8, core::mem::size_of::<UserStr>()) {
+			return Err(ERRNO_EFAULT);
+		}
+		let us = unsafe { entry.read_unaligned() };
+		if us.ptr == 0 {
+			return Ok(out);
+		}
+		if out.len() >= MAX_ARGS {
+			return Err(ERRNO_E2BIG);
+		}
+
+		let len = us.len as usize;
+		let cost = len.saturating_add(1 + 8);
+		if cost > *budget {
+			return Err(ERRNO_E2BIG);
+		}
+		*budget -= cost;
+
+		let bytes: &[u8] = if len == 0 {
+			&[]
+		} else {
+			if !is_user_accessible(us.ptr as *const u8, len) {
+				return Err(ERRNO_EFAULT);
+			}
+			unsafe { core::slice::from_raw_parts(us.ptr as *const u8, len) }
+		};
+		match core::str::from_utf8(bytes) {
+			Ok(s) if !s.contains('\0') => out.push(String::from(s)),
+			_ => return Err(ERRNO_EINVAL),
+		}
+		entry = unsafe { entry.add(1) };
+	}
+}
+
+/*
+ * copy_user_str - Copy a UTF-8 string argument out of userspace
+ * @ptr: User address of the bytes
+ * @len: Length in bytes
+ *
+ * Return: Kernel copy of the string, or ERRNO_EFAULT / ERRNO_EINVAL
+ */
+pub(crate) fn copy_user_str(ptr: u64, len: u64) -> Result<String, u64> {
+	let len = len as usize;
+	if !is_user_accessible(ptr as *const u8, len) {
+		return Err(ERRNO_EFAULT);
+	}
+	let slice = unsafe { core::slice::from_raw_parts(ptr as *const u8, len) };
+	core::str::from_utf8(slice).map(String::from).map_err(|_| ERRNO_EINVAL)
+}
+
+/*
+ * resolve - Canonicalise a path against the calling task's cwd
+ * @path: Absolute or relative path
+ */
+pub(crate) fn resolve(path: &str) -> String {
+	match task::scheduler::current_task_arc() {
+		Some(t) => vfs::resolve_path(&t.lock().cwd, path),
+		None => vfs::resolve_path("/", path),
+	}
+}
+
+/*
+ * struct UserFileAction - One SYS_SPAWN file action as passed by userspace
+ * @kind: SPAWN_FA_* (SPAWN_FA_END terminates the array)
+ * @fd: Child descriptor the action applies to
+ * @arg: Source fd for SPAWN_FA_DUP2, O_* flags for SPAWN_FA_OPEN
+ * @path_ptr: User address of the path for SPAWN_FA_OPEN
+ * @path_len: Path length in bytes
+ */
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UserFileAction {
+	kind: u64,
+	fd: u64,
+	arg: u64,
+	path_ptr: u64,
+	path_len: u64,
+}
+
+/* UserFileAction kinds */
+const SPAWN_FA_END: u64 = 0;
+const SPAWN_FA_DUP2: u64 = 1;
+const SPAWN_FA_CLOSE: u64 = 2;
+const SPAWN_FA_OPEN: u64 = 3;
+
+/* Maximum number of file actions accepted by SYS_SPAWN */
+const MAX_FILE_ACTIONS: usize = 64;
+
+/*
+ * copy_file_actions - Copy a SYS_SPAWN file action array out of userspace
+ * @ptr: User address of a UserFileAction array terminated by a
+ *       SPAWN_FA_END entry (0 = no actions)
+ *
+ * Return: Kernel copies of the actions, or an errno
+ */
+fn copy_file_actions(ptr: u64) -> Result<Vec<crate::fd::FileAction>, u64> {
+	use crate::fd::FileAction;
+
+	let mut out = Vec::new();
+	if ptr == 0 {
+		return Ok(out);
+	}
+
+	let mut entry = ptr as *const UserFileAction;
+	loop {
+		if !is_user_accessible(entry as *const u8, core::mem::size_of::<UserFileAction>()) {
+			return Err(ERRNO_EFAULT);
+		}
+		let fa = unsafe { entry.read_unaligned() };
+		let action = match fa.kind {
+			SPAWN_FA_END => return Ok(out),
+			SPAWN_FA_DUP2 => FileAction::Dup2 { from: fa.arg, to: fa.fd },
+			SPAWN_FA_CLOSE => FileAction::Close(fa.fd),
+			SPAWN_FA_OPEN => {
+				let path = resolve(&copy_user_str(fa.path_ptr, fa.path_len)?);
+				FileAction::Open { fd: fa.fd, path, flags: fa.arg & O_CLOEXEC }
+			}
+			_ => return Err(ERRNO_EINVAL),
+		};
+		if out.len() >= MAX_FILE_ACTIONS {
+			return Err(ERRNO_E2BIG);
+		}
+		out.push(action);
+		entry = unsafe { entry.add(1) };
+	}
+}
+
+/*
+ * copy_exec_args - Copy the argv/envp arguments of SYS_SPAWN / SYS_EXEC
+ * @path: Already validated executable path
+ * @argv_ptr: User UserStr array, or 0 for argv = [path]
+ * @envp_ptr: User UserStr array, or 0 for an empty environment
+ *
+ * Return: (argv, envp) in kernel memory, or an errno
  */
+fn copy_exec_args(path: &str, argv_ptr: u64, envp_ptr: u64) -> Result<(Vec<String>, Vec<String>), u64> {
+	let mut budget = crate::process::ARG_MAX;
+	let argv = if argv_ptr == 0 {
+		vec![String::from(path)]
+	} else {
+		copy_str_array(argv_ptr, &mut budget)?
+	};
+	let envp = copy_str_array(envp_ptr, &mut budget)?;
+	Ok((argv, envp))
+}
+
+/*
+ * struct SyscallFrame - User register state saved by syscall_entry
+ *
+ * Lives at the very top of the calling task's kernel stack, in the
+ * order syscall_entry pushes it (lowest address first). fork() copies
+ * it onto the child's kernel stack so the child resumes at the same
+ * user instruction.
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyscallFrame {
+	pub r15: u64,
+	pub r14: u64,
+	pub r13: u64,
+	pub r12: u64,
+	pub rbx: u64,
+	pub rbp: u64,
+	pub rax: u64,
+	pub rdi: u64,
+	pub rsi: u64,
+	pub rdx: u64,
+	pub r10: u64,
+	pub r8: u64,
+	pub r9: u64,
+	pub rcx: u64, /* user RIP */
+	pub r11: u64, /* user RFLAGS */
+	pub rsp: u64, /* user RSP */
+}
+
+/*
+ * frame_at - Locate the SyscallFrame on a kernel stack
+ * @kstack: Top of the task's kernel stack
+ *
+ * Return: Pointer to where syscall_entry saves (or fork places) the frame
+ */
+pub fn frame_at(kstack: VirtAddr) -> *mut SyscallFrame {
+	let top = kstack.as_u64() & !0xF;
+	(top - core::mem::size_of::<SyscallFrame>() as u64) as *mut SyscallFrame
+}
+
 /*
  * syscall_entry - Low-level syscall entry point
  *
  * Naked assembly function that handles the transition from user to kernel mode.
- * Saves ALL user context, switches to kernel stack, calls dispatcher,
- * and restores context exactly as it was (except RAX).
+ * Saves ALL user context into a SyscallFrame on the kernel stack, calls
+ * the dispatcher, and restores context exactly as it was (except RAX).
+ * Before restoring, signal::deliver_syscall() may rewrite the frame to
+ * enter a signal handler instead.
+ *
+ * GS is only swapped for the few instructions that need PER_CPU_DATA
+ * to find the kernel stack. The user RSP is copied onto the kernel stack
+ * straight away, so the dispatcher runs with GS_BASE=0 like the rest of
+ * the kernel and may block or context switch freely.
  */
 #[unsafe(naked)]
 unsafe extern "C" fn syscall_entry() {
 	naked_asm!(
-		/* 1. Swap to kernel GS and save user stack */
+		/* 1. Swap to kernel GS and switch to the kernel stack */
 		"swapgs",
 		"mov gs:[16], rsp",      /* Save user stack pointer */
 		"mov rsp, gs:[8]",       /* Load kernel stack pointer */
@@ -170,7 +486,11 @@ unsafe extern "C" fn syscall_entry() {
 		/* 2. Align stack to 16 bytes */
 		"and rsp, ~0xF",
 
-		/* 3. Save User Context (The "Trap Frame") */
+		/* 3. Save the user stack on the frame and restore GS_BASE=0 */
+		"push qword ptr gs:[16]",
+		"swapgs",
+
+		/* 4. Save User Context (The "Trap Frame") */
 		/* We must save registers that we clobber or that the ABI expects preserved */
 		"push r11",              /* User RFLAGS (clobbered by syscall) */
 		"push rcx",              /* User RIP (clobbered by syscall) */
@@ -191,9 +511,9 @@ unsafe extern "C" fn syscall_entry() {
 		"push r14",
 		"push r15",
 
-		/* 4. Prepare Arguments for syscall_dispatcher (System V ABI) */
+		/* 5. Prepare Arguments for syscall_dispatcher (System V ABI) */
 		/*
-		 * Kernel Function: fn(nr, arg1, arg2, arg3, arg4, arg5)
+		 * Kernel Function: fn(nr, arg1, arg2, arg3, arg4, arg5, arg6)
 		 * Mapping:
 		 * RDI <- RAX (nr)
 		 * RSI <- RDI (arg1)
@@ -201,7 +521,10 @@ unsafe extern "C" fn syscall_entry() {
 		 * RCX <- RDX (arg3)
 		 * R8  <- R10 (arg4 - syscall puts it here)
 		 * R9  <- R8  (arg5)
+		 * [rsp] <- R9 (arg6, on the stack; padded to keep 16-byte alignment)
 		 */
+		"sub rsp, 8",
+		"push r9",               /* arg6 */
 		"mov r9, r8",            /* arg5 */
 		"mov r8, r10",           /* arg4 */
 		"mov rcx, rdx",          /* arg3 */
@@ -209,21 +532,26 @@ unsafe extern "C" fn syscall_entry() {
 		"mov rsi, rdi",          /* arg1 */
 		"mov rdi, rax",          /* syscall number */
 
-		/* 5. Call Dispatcher */
+		/* 6. Call Dispatcher */
 		"call {syscall_handler}",
+		"add rsp, 16",           /* Drop arg6 and its padding */
 
-		/* RAX now holds the return value. We must NOT overwrite it when restoring. */
+		/* Handlers may have re-enabled interrupts; the exit path must not be preempted */
+		"cli",
 
-		/* 6. Restore Context */
+		/* 7. Store the return value in the frame and deliver pending signals */
+		"mov [rsp + 48], rax",
+		"mov rdi, rsp",
+		"call {signal_handler}",
+
+		/* 8. Restore Context (RAX = return value, or a signal handler's state) */
 		"pop r15",
 		"pop r14",
 		"pop r13",
 		"pop r12",
 		"pop rbx",
 		"pop rbp",
-
-		/* Skip RAX on stack (we want to keep the new return value in real RAX) */
-		"add rsp, 8",
+		"pop rax",
 
 		"pop rdi",
 		"pop rsi",
@@ -235,11 +563,11 @@ unsafe extern "C" fn syscall_entry() {
 		"pop rcx",               /* User RIP */
 		"pop r11",               /* User RFLAGS */
 
-		/* 7. Return to Userspace */
-		"mov rsp, gs:[16]",      /* Restore User Stack */
-		"swapgs",                /* Restore User GS */
+		/* 9. Return to Userspace */
+		"pop rsp",               /* Restore User Stack */
 		"sysretq",
 		syscall_handler = sym syscall_dispatcher,
+		signal_handler = sym crate::signal::deliver_syscall,
 	);
 }
 
@@ -249,10 +577,12 @@ unsafe extern "C" fn syscall_entry() {
  * @arg1: First argument
  * @arg2: Second argument
  * @arg3: Third argument
- * @arg4: Fourth argument (optional, for future use)
- * @arg5: Fifth argument (optional, for future use)
+ * @arg4: Fourth argument
+ * @arg5: Fifth argument
+ * @arg6: Sixth argument
  *
- * Dispatches system calls to appropriate handlers based on the syscall number.
+ * Routes tasks running under the Linux personality to linux::dispatch()
+ * and everything else to the native table in dispatch().
  * Returns the syscall result in RAX (0 or positive on success, negative errno on error).
  */
 #[unsafe(no_mangle)]
@@ -262,7 +592,36 @@ extern "C" fn syscall_dispatcher(
 	arg2: u64,
 	arg3: u64,
 	arg4: u64,
-	_arg5: u64,
+	arg5: u64,
+	arg6: u64,
+) -> u64 {
+	let personality = task::scheduler::current_task_arc()
+		.map(|t| t.lock().personality)
+		.unwrap_or_default();
+	match personality {
+		task::Personality::Linux => crate::linux::dispatch(nr, [arg1, arg2, arg3, arg4, arg5, arg6]),
+		task::Personality::Serix => dispatch(nr, arg1, arg2, arg3, arg4, arg5, arg6),
+	}
+}
+
+/*
+ * dispatch - Native Serix system call table
+ * @nr: System call number (SYS_*)
+ * @arg1..@arg6: Arguments as passed in RDI, RSI, RDX, R10, R8, R9
+ *
+ * Also called by the Linux personality for calls whose native
+ * counterpart has the same semantics.
+ *
+ * Return: Result or negative errno
+ */
+pub(crate) fn dispatch(
+	nr: u64,
+	arg1: u64,
+	arg2: u64,
+	arg3: u64,
+	arg4: u64,
+	arg5: u64,
+	arg6: u64,
 ) -> u64 {
 	match nr {
 		SYS_READ => {
@@ -280,10 +639,18 @@ extern "C" fn syscall_dispatcher(
 
 			let task_id = task::scheduler::current_task_id();
 			if let Some(file) = crate::fd::get(task_id, fd) {
-				let mut off = file.offset.lock();
+				/*
+				 * Pipe and console reads block, and the OpenFile may be
+				 * shared with other tasks, which would spin on the offset
+				 * lock with interrupts off: never hold it across read().
+				 */
+				let off = *file.offset.lock();
 				let buf = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
-				let n = file.inode.read(*off, buf);
-				*off += n;
+				let n = file.inode.read(off, buf);
+				if n == crate::signal::READ_INTERRUPTED {
+					return ERRNO_EINTR;
+				}
+				*file.offset.lock() += n;
 				n as u64
 			} else {
 				ERRNO_EBADF
@@ -313,78 +680,20 @@ extern "C" fn syscall_dispatcher(
 
 		SYS_EXIT => {
 			/*
-			 * Exit — terminate the calling task.
-			 * Marks the task Zombie, closes all fds, wakes any waiting parent,
-			 * then context-switches away. Never returns to the caller.
+			 * Exit — terminate the calling process.
+			 * Every thread is killed, the main thread becomes a Zombie,
+			 * fds are closed and any waiting parent is woken. Never
+			 * returns to the caller.
 			 */
 			let status = arg1 as i32;
 			hal::serial_println!("[SYSCALL] SYS_EXIT status={}", status);
-
-			x86_64::instructions::interrupts::without_interrupts(|| {
-				let old_arc = match task::scheduler::take_current() {
-					Some(t) => t,
-					None => loop { hal::cpu::halt(); },
-				};
-
-				{
-					let mut t = old_arc.lock();
-					t.exit_status = Some(status);
-					t.set_state(task::TaskState::Zombie);
-				}
-
-				let task_id = old_arc.lock().id.0;
-				crate::fd::cleanup(task_id);
-
-				/* Wake parent if it is blocked in SYS_WAIT4 */
-				let parent_id = old_arc.lock().parent_id;
-				if parent_id != 0 {
-					if let Some(par) = task::scheduler::find_task_by_id(parent_id) {
-						let waiting = par.lock().waiting_for_child;
-						if waiting {
-							par.lock().waiting_for_child = false;
-							task::scheduler::wake_task(par);
-						}
-					}
-				}
-
-				task::scheduler::push_zombie(old_arc);
-
-				/* Switch to next task */
-				if let Some(new_arc) = task::scheduler::pick_next_task() {
-					if let Some(hook) = task::SWITCH_HOOK.get() {
-						let ks = new_arc.lock().kstack;
-						if ks.as_u64() != 0 { hook(ks); }
-					}
-					let new_ctx = {
-						let g = new_arc.lock();
-						&g.context as *const task::CPUContext
-					};
-					/* Use a dummy old context (never resumed) */
-					static mut DUMMY_CTX: task::CPUContext = task::CPUContext {
-						rsp: 0, rbp: 0, rbx: 0, r12: 0, r13: 0,
-						r14: 0, r15: 0, rip: 0, rflags: 0,
-						cs: 0, ss: 0, fs: 0, gs: 0, ds: 0, es: 0,
-						fs_base: 0, gs_base: 0, cr3: 0,
-					};
-					unsafe {
-						/*
-						 * Restore normal kernel GS state before context_switch.
-						 * syscall_entry swapgs'd GS_BASE to PER_CPU_DATA; we must
-						 * swap back (GS_BASE→0, KernelGsBase→PER_CPU_DATA) so that
-						 * user_entry_trampoline always sees GS_BASE=0.
-						 */
-						core::arch::asm!("swapgs");
-						task::context_switch::context_switch(
-							core::ptr::addr_of_mut!(DUMMY_CTX),
-							new_ctx,
-						);
-					}
-				}
-			});
-			loop { hal::cpu::halt(); }
+			crate::process::exit_group(crate::signal::wait_status_exited(status));
 		}
 
-		SYS_GETPID => task::scheduler::current_task_id(),
+		SYS_GETPID => match task::scheduler::current_task_arc() {
+			Some(arc) => arc.lock().tgid,
+			None => 0,
+		},
 
 		SYS_GETPPID => {
 			let id = task::scheduler::current_task_id();
@@ -433,16 +742,17 @@ extern "C" fn syscall_dispatcher(
 						(t.id.0, t.exit_status.unwrap_or(0))
 					};
 
-					/* Remove from parent's children list */
+					/* Remove from parent's children list and free what it holds */
 					if let Some(par) = task::scheduler::current_task_arc() {
 						par.lock().children.retain(|&id| id != child_pid);
 					}
+					crate::process::release_task(zombie);
 
 					/* Write status to userspace if pointer is valid */
 					if !status_ptr.is_null()
 						&& is_user_accessible(status_ptr as *const u8, 4)
 					{
-						unsafe { *status_ptr = (exit_status & 0xFF) << 8; }
+						unsafe { *status_ptr = exit_status; }
 					}
 
 					return child_pid;
@@ -452,49 +762,73 @@ extern "C" fn syscall_dispatcher(
 					return 0;
 				}
 
-				/* No zombie yet — block until a child exits */
-				x86_64::instructions::interrupts::without_interrupts(|| {
+				/* No zombie yet — block until a child exits or a signal arrives */
+				let interrupted = x86_64::instructions::interrupts::without_interrupts(|| {
+					if crate::signal::interrupted() {
+						return true;
+					}
 					if let Some(arc) = task::scheduler::current_task_arc() {
 						arc.lock().waiting_for_child = true;
 					}
-					/*
-					 * Restore normal kernel GS before blocking. syscall_entry
-					 * swapgs'd GS_BASE to PER_CPU_DATA; swap back so that
-					 * user_entry_trampoline (reached via context_switch inside
-					 * block_current_and_switch) always sees GS_BASE=0.
-					 */
-					unsafe { core::arch::asm!("swapgs"); }
 					task::block_current_and_switch();
-					/*
-					 * When this task is woken and rescheduled, execution
-					 * resumes here — still inside the syscall handler, so
-					 * we must re-establish the syscall GS state.
-					 */
-					unsafe { core::arch::asm!("swapgs"); }
+					if let Some(arc) = task::scheduler::current_task_arc() {
+						arc.lock().waiting_for_child = false;
+					}
+					false
 				});
+				if interrupted {
+					return ERRNO_EINTR;
+				}
 			}
 		}
 
 		SYS_SPAWN => {
 			/*
-			 * Spawn a new user process from an ELF on the VFS.
-			 * arg1: path pointer, arg2: path length
+			 * Spawn a new user process from an ELF or "#!" script on the VFS.
+			 * arg1: path pointer, arg2: path length,
+			 * arg3: argv UserStr array (0 = [path]),
+			 * arg4: envp UserStr array (0 = empty),
+			 * arg5: UserFileAction array (0 = none),
+			 * arg6: SPAWN_* flags
+			 *
+			 * The child inherits the caller's fds except close-on-exec
+			 * ones. File actions (dup2 / close / open) are applied to the
+			 * child's table first, posix_spawn style, so a shell can wire
+			 * up pipes and redirections without forking.
+			 *
 			 * Returns: child pid on success, errno on failure
 			 */
-			let ptr = arg1 as *const u8;
-			let len = arg2 as usize;
-
-			if !is_user_accessible(ptr, len) {
-				return ERRNO_EFAULT;
+			if arg6 & !SPAWN_LINUX != 0 {
+				return ERRNO_EINVAL;
 			}
-			let slice = unsafe { core::slice::from_raw_parts(ptr, len) };
-			let path = match core::str::from_utf8(slice) {
-				Ok(s) => s,
-				Err(_) => return ERRNO_EINVAL,
+			let personality = if arg6 & SPAWN_LINUX != 0 {
+				task::Personality::Linux
+			} else {
+				task::Personality::Serix
+			};
+
+			let raw_path = match copy_user_str(arg1, arg2) {
+				Ok(p) => p,
+				Err(e) => return e,
+			};
+			let path = resolve(&raw_path);
+
+			let (argv, envp) = match copy_exec_args(&raw_path, arg3, arg4) {
+				Ok(v) => v,
+				Err(e) => return e,
+			};
+
+			let actions = match copy_file_actions(arg5) {
+				Ok(a) => a,
+				Err(e) => return e,
 			};
 
 			let parent_id = task::scheduler::current_task_id();
-			match crate::process::spawn_user_process(path, parent_id) {
+			let fds = match crate::fd::spawn_table(parent_id, &actions) {
+				Ok(t) => t,
+				Err(e) => return e,
+			};
+			match crate::process::spawn_user_process(&path, &argv, &envp, parent_id, fds, personality) {
 				Ok(child_id) => child_id,
 				Err(msg) => {
 					hal::serial_println!("[SPAWN] failed: {}", msg);
@@ -503,28 +837,104 @@ extern "C" fn syscall_dispatcher(
 			}
 		}
 
-		SYS_OPEN => {
+		SYS_FORK => {
+			/*
+			 * Fork the calling process.
+			 * Returns: child pid in the parent, 0 in the child
+			 */
+			let task_id = task::scheduler::current_task_id();
+			let kstack = match task::scheduler::current_task_arc() {
+				Some(arc) => arc.lock().kstack,
+				None => return ERRNO_EINVAL,
+			};
+			let frame = unsafe { *frame_at(kstack) };
+			match crate::process::fork_process(task_id, &frame) {
+				Ok(child_id) => child_id,
+				Err(msg) => {
+					hal::serial_println!("[FORK] failed: {}", msg);
+					ERRNO_ENOMEM
+				}
+			}
+		}
+
+		SYS_EXEC => {
 			/*
-			 * Open system call: path_ptr, path_len
-			 * Returns: fd on success, ENOENT if path not found
+			 * Replace the calling process's image.
+			 * arg1: path pointer, arg2: path length,
+			 * arg3: argv UserStr array (0 = [path]),
+			 * arg4: envp UserStr array (0 = empty)
+			 * Returns: does not return on success, errno on failure
 			 */
-			let ptr = arg1 as *const u8;
-			let len = arg2 as usize;
+			/* Copy everything out before the old image is unmapped */
+			let raw_path = match copy_user_str(arg1, arg2) {
+				Ok(p) => p,
+				Err(e) => return e,
+			};
+			let path = resolve(&raw_path);
+			let (argv, envp) = match copy_exec_args(&raw_path, arg3, arg4) {
+				Ok(v) => v,
+				Err(e) => return e,
+			};
 
-			if !is_user_accessible(ptr, len) {
-				return ERRNO_EFAULT;
+			let task_id = task::scheduler::current_task_id();
+			let kstack = match task::scheduler::current_task_arc() {
+				Some(arc) => arc.lock().kstack,
+				None => return ERRNO_EINVAL,
+			};
+			let frame = unsafe { &mut *frame_at(kstack) };
+			match crate::process::exec_process(task_id, &path, &argv, &envp, frame) {
+				Ok(()) => 0,
+				Err(e) => e,
+			}
+		}
+
+		SYS_ARCH_PRCTL => {
+			/*
+			 * Set or read the thread pointer.
+			 * arg1: ARCH_SET_FS or ARCH_GET_FS,
+			 * arg2: new FS base, or pointer to a u64 receiving it
+			 * Returns: 0 on success, errno on failure
+			 *
+			 * Only the MSR is written; context_switch saves it into
+			 * the task's CPUContext whenever the task is switched out.
+			 */
+			use x86_64::registers::model_specific::FsBase;
+			match arg1 {
+				ARCH_SET_FS => {
+					if arg2 >= USER_SPACE_END {
+						return ERRNO_EPERM;
+					}
+					FsBase::write(VirtAddr::new(arg2));
+					0
+				}
+				ARCH_GET_FS => {
+					let out = arg2 as *mut u64;
+					if !is_user_accessible(out as *const u8, 8) {
+						return ERRNO_EFAULT;
+					}
+					unsafe { out.write_unaligned(FsBase::read().as_u64()); }
+					0
+				}
+				_ => ERRNO_EINVAL,
 			}
+		}
 
-			let slice = unsafe { core::slice::from_raw_parts(ptr, len) };
-			let path = match core::str::from_utf8(slice) {
-				Ok(s) => s,
-				Err(_) => return ERRNO_EINVAL,
+		SYS_OPEN => {
+			/*
+			 * Open system call: path_ptr, path_len, flags
+			 * flags: O_CLOEXEC
+			 * Returns: lowest free fd on success, ENOENT if path not found,
+			 *          EMFILE if the fd table is full
+			 */
+			let path = match copy_user_str(arg1, arg2) {
+				Ok(p) => resolve(&p),
+				Err(e) => return e,
 			};
 
 			let task_id = task::scheduler::current_task_id();
-			match crate::fd::open(task_id, path) {
-				Some(fd) => fd,
-				None => ERRNO_ENOENT,
+			match crate::fd::open(task_id, &path, arg3 & O_CLOEXEC) {
+				Ok(fd) => fd,
+				Err(e) => e,
 			}
 		}
 
@@ -667,32 +1077,64 @@ extern "C" fn syscall_dispatcher(
 			msg.id
 		}
 		SYS_DUP => {
+			/*
+			 * Duplicate arg1 to the lowest free fd. The new fd does not
+			 * inherit close-on-exec.
+			 */
 			let task_id = task::scheduler::current_task_id();
-			match crate::fd::dup(task_id, arg1) {
-				Some(new_fd) => new_fd,
-				None => ERRNO_EBADF,
+			match crate::fd::dup(task_id, arg1, 0, 0) {
+				Ok(new_fd) => new_fd,
+				Err(e) => e,
 			}
 		}
 
 		SYS_DUP2 => {
+			/*
+			 * Duplicate arg1 to exactly arg2, closing arg2 first if open.
+			 * arg3: O_CLOEXEC to mark arg2 close-on-exec (as dup3)
+			 */
 			let task_id = task::scheduler::current_task_id();
-			match crate::fd::dup2(task_id, arg1, arg2) {
-				Some(new_fd) => new_fd,
-				None => ERRNO_EBADF,
+			match crate::fd::dup2(task_id, arg1, arg2, arg3 & O_CLOEXEC) {
+				Ok(new_fd) => new_fd,
+				Err(e) => e,
+			}
+		}
+
+		SYS_FCNTL => {
+			/*
+			 * File descriptor control. arg1: fd, arg2: command, arg3: argument
+			 *   F_DUPFD / F_DUPFD_CLOEXEC  dup to the lowest free fd >= arg3
+			 *   F_GETFD                    return FD_CLOEXEC or 0
+			 *   F_SETFD                    set FD_CLOEXEC from arg3
+			 */
+			let task_id = task::scheduler::current_task_id();
+			let res = match arg2 {
+				F_DUPFD => crate::fd::dup(task_id, arg1, arg3, 0),
+				F_DUPFD_CLOEXEC => crate::fd::dup(task_id, arg1, arg3, O_CLOEXEC),
+				F_GETFD => crate::fd::get_flags(task_id, arg1),
+				F_SETFD => crate::fd::set_flags(task_id, arg1, arg3).map(|_| 0),
+				_ => Err(ERRNO_EINVAL),
+			};
+			match res {
+				Ok(v) => v,
+				Err(e) => e,
 			}
 		}
 
 		SYS_PIPE => {
 			/*
 			 * Create a pipe. arg1 points to a [u64; 2] user buffer that
-			 * receives [read_fd, write_fd].
+			 * receives [read_fd, write_fd]. arg2: flags (O_CLOEXEC).
 			 */
 			let pipefd_ptr = arg1 as *mut u64;
 			if !is_user_accessible(pipefd_ptr as *const u8, 16) {
 				return ERRNO_EFAULT;
 			}
 			let task_id = task::scheduler::current_task_id();
-			let (rfd, wfd) = crate::pipe::create_pipe(task_id);
+			let (rfd, wfd) = match crate::pipe::create_pipe(task_id, arg2 & O_CLOEXEC) {
+				Ok(fds) => fds,
+				Err(e) => return e,
+			};
 			unsafe {
 				*pipefd_ptr = rfd;
 				*pipefd_ptr.add(1) = wfd;
@@ -780,23 +1222,11 @@ extern "C" fn syscall_dispatcher(
 		}
 
 		SYS_MKDIR => {
-			let ptr = arg1 as *const u8;
-			let len = arg2 as usize;
-
-			if !is_user_accessible(ptr, len) {
-				return ERRNO_EFAULT;
-			}
-			let slice = unsafe { core::slice::from_raw_parts(ptr, len) };
-			let path = match core::str::from_utf8(slice) {
-				Ok(s) => s,
-				Err(_) => return ERRNO_EINVAL,
-			};
-
-			let (parent_path, name) = match path.rfind('/') {
-				Some(0) => ("/", &path[1..]),
-				Some(pos) => (&path[..pos], &path[pos + 1..]),
-				None => ("/", path),
+			let path = match copy_user_str(arg1, arg2) {
+				Ok(p) => resolve(&p),
+				Err(e) => return e,
 			};
+			let (parent_path, name) = vfs::split_parent(&path);
 
 			match vfs::lookup_path(parent_path) {
 				Some(dir) => match dir.mkdir(name) {
@@ -808,23 +1238,11 @@ extern "C" fn syscall_dispatcher(
 		}
 
 		SYS_UNLINK => {
-			let ptr = arg1 as *const u8;
-			let len = arg2 as usize;
-
-			if !is_user_accessible(ptr, len) {
-				return ERRNO_EFAULT;
-			}
-			let slice = unsafe { core::slice::from_raw_parts(ptr, len) };
-			let path = match core::str::from_utf8(slice) {
-				Ok(s) => s,
-				Err(_) => return ERRNO_EINVAL,
-			};
-
-			let (parent_path, name) = match path.rfind('/') {
-				Some(0) => ("/", &path[1..]),
-				Some(pos) => (&path[..pos], &path[pos + 1..]),
-				None => ("/", path),
+			let path = match copy_user_str(arg1, arg2) {
+				Ok(p) => resolve(&p),
+				Err(e) => return e,
 			};
+			let (parent_path, name) = vfs::split_parent(&path);
 
 			match vfs::lookup_path(parent_path) {
 				Some(dir) => match dir.unlink(name) {
@@ -835,6 +1253,367 @@ extern "C" fn syscall_dispatcher(
 			}
 		}
 
+		SYS_CHDIR => {
+			/*
+			 * Change the working directory.
+			 * arg1: path pointer, arg2: path length
+			 * Returns: 0 on success, errno on failure
+			 */
+			let path = match copy_user_str(arg1, arg2) {
+				Ok(p) => resolve(&p),
+				Err(e) => return e,
+			};
+			match vfs::lookup_path(&path) {
+				Some(node) if node.metadata() == vfs::FileType::Directory => {}
+				Some(_) => return ERRNO_ENOTDIR,
+				None => return ERRNO_ENOENT,
+			}
+			match task::scheduler::current_task_arc() {
+				Some(t) => {
+					t.lock().cwd = path;
+					0
+				}
+				None => ERRNO_ESRCH,
+			}
+		}
+
+		SYS_GETCWD => {
+			/*
+			 * Get the working directory.
+			 * arg1: buffer pointer, arg2: buffer size
+			 * Returns: bytes written including the NUL, or errno
+			 */
+			let cwd = match task::scheduler::current_task_arc() {
+				Some(t) => t.lock().cwd.clone(),
+				None => return ERRNO_ESRCH,
+			};
+			let needed = cwd.len() + 1;
+			if (arg2 as usize) < needed {
+				return ERRNO_ERANGE;
+			}
+			if !is_user_accessible(arg1 as *const u8, needed) {
+				return ERRNO_EFAULT;
+			}
+			unsafe {
+				let dst = core::slice::from_raw_parts_mut(arg1 as *mut u8, needed);
+				dst[..cwd.len()].copy_from_slice(cwd.as_bytes());
+				dst[cwd.len()] = 0;
+			}
+			needed as u64
+		}
+
+		SYS_MMAP => {
+			/*
+			 * Map anonymous memory.
+			 * arg1: address hint, arg2: length, arg3: PROT_*, arg4: MAP_*
+			 * Returns: start address on success, errno on failure
+			 */
+			crate::mm::mmap(arg1, arg2, arg3, arg4).unwrap_or_else(|e| e)
+		}
+
+		SYS_MUNMAP => {
+			/*
+			 * Unmap memory.
+			 * arg1: page-aligned address, arg2: length
+			 * Returns: 0 on success, errno on failure
+			 */
+			crate::mm::munmap(arg1, arg2).unwrap_or_else(|e| e)
+		}
+
+		SYS_MPROTECT => {
+			/*
+			 * Change memory protection.
+			 * arg1: page-aligned address, arg2: length, arg3: PROT_*
+			 * Returns: 0 on success, errno on failure
+			 */
+			crate::mm::mprotect(arg1, arg2, arg3).unwrap_or_else(|e| e)
+		}
+
+		SYS_SHM_CREATE => {
+			/*
+			 * Create a shared memory object and map it.
+			 * arg1: size in bytes, arg2: PROT_*, arg3: pointer to a
+			 * 16-byte buffer receiving the MemoryRegion capability key
+			 * Returns: start address on success, errno on failure
+			 */
+			crate::shm::create(arg1, arg2, arg3).unwrap_or_else(|e| e)
+		}
+
+		SYS_SHM_MAP => {
+			/*
+			 * Map a shared memory object.
+			 * arg1: pointer to a capability key held by the caller, arg2: PROT_*
+			 * Returns: start address on success, errno on failure
+			 */
+			crate::shm::map(arg1, arg2).unwrap_or_else(|e| e)
+		}
+
+		SYS_SHM_GRANT => {
+			/*
+			 * Give another process a capability for a shared memory object.
+			 * arg1: pointer to a capability key held by the caller,
+			 * arg2: task ID of the receiver, arg3: pointer to a 16-byte
+			 * buffer receiving the receiver's key
+			 * Returns: 0 on success, errno on failure (EPERM, ESRCH)
+			 */
+			crate::shm::grant(arg1, arg2, arg3).unwrap_or_else(|e| e)
+		}
+
+		SYS_SHM_CLOSE => {
+			/*
+			 * Drop a shared memory capability; mappings stay.
+			 * arg1: pointer to a capability key held by the caller
+			 * Returns: 0 on success, errno on failure
+			 */
+			crate::shm::close(arg1).unwrap_or_else(|e| e)
+		}
+
+		SYS_KILL => {
+			/*
+			 * Send a signal.
+			 * arg1: target pid (> 0), arg2: signal number (0 = probe)
+			 * Returns: 0 on success, errno on failure
+			 */
+			if arg1 as i64 <= 0 || arg2 > u8::MAX as u64 {
+				return ERRNO_EINVAL;
+			}
+			match crate::signal::send(arg1, arg2 as u8) {
+				Ok(()) => 0,
+				Err(e) => e,
+			}
+		}
+
+		SYS_SIGACTION => {
+			/*
+			 * Install or query a signal handler.
+			 * arg1: signal number, arg2: new SigAction (0 = query only),
+			 * arg3: buffer for the old SigAction (0 = discard)
+			 * Returns: 0 on success, errno on failure
+			 */
+			let size = core::mem::size_of::<task::signal::SigAction>();
+			let act_ptr = arg2 as *const task::signal::SigAction;
+			let old_ptr = arg3 as *mut task::signal::SigAction;
+			if (arg2 != 0 && !is_user_accessible(act_ptr as *const u8, size))
+				|| (arg3 != 0 && !is_user_accessible(old_ptr as *const u8, size))
+			{
+				return ERRNO_EFAULT;
+			}
+			if arg1 > u8::MAX as u64 {
+				return ERRNO_EINVAL;
+			}
+			let act = if arg2 != 0 { Some(unsafe { act_ptr.read_unaligned() }) } else { None };
+			match crate::signal::sigaction(arg1 as u8, act) {
+				Ok(old) => {
+					if arg3 != 0 {
+						unsafe { old_ptr.write_unaligned(old); }
+					}
+					0
+				}
+				Err(e) => e,
+			}
+		}
+
+		SYS_SIGPROCMASK => {
+			/*
+			 * Change the blocked signal mask.
+			 * arg1: SIG_BLOCK / SIG_UNBLOCK / SIG_SETMASK,
+			 * arg2: pointer to the u64 set (0 = query only),
+			 * arg3: buffer for the old u64 mask (0 = discard)
+			 * Returns: 0 on success, errno on failure
+			 */
+			let set_ptr = arg2 as *const u64;
+			let old_ptr = arg3 as *mut u64;
+			if (arg2 != 0 && !is_user_accessible(set_ptr as *const u8, 8))
+				|| (arg3 != 0 && !is_user_accessible(old_ptr as *const u8, 8))
+			{
+				return ERRNO_EFAULT;
+			}
+			let set = if arg2 != 0 { Some(unsafe { set_ptr.read_unaligned() }) } else { None };
+			match crate::signal::sigprocmask(arg1, set) {
+				Ok(old) => {
+					if arg3 != 0 {
+						unsafe { old_ptr.write_unaligned(old); }
+					}
+					0
+				}
+				Err(e) => e,
+			}
+		}
+
+		SYS_SIGRETURN => {
+			/*
+			 * Return from a signal handler. Only valid from the restorer
+			 * a handler returns into; does not return to the caller.
+			 */
+			let kstack = match task::scheduler::current_task_arc() {
+				Some(arc) => arc.lock().kstack,
+				None => return ERRNO_EINVAL,
+			};
+			let frame = unsafe { *frame_at(kstack) };
+			crate::signal::sigreturn(&frame);
+		}
+
+		SYS_NANOSLEEP => {
+			/*
+			 * Sleep for a duration.
+			 * arg1: pointer to the requested Timespec,
+			 * arg2: Timespec receiving the unslept time on EINTR (0 = none)
+			 * Returns: 0 on success, errno on failure
+			 */
+			let size = core::mem::size_of::<crate::time::Timespec>();
+			let req_ptr = arg1 as *const crate::time::Timespec;
+			let rem_ptr = arg2 as *mut crate::time::Timespec;
+			if !is_user_accessible(req_ptr as *const u8, size)
+				|| (arg2 != 0 && !is_user_accessible(rem_ptr as *const u8, size))
+			{
+				return ERRNO_EFAULT;
+			}
+			let req = unsafe { req_ptr.read_unaligned() };
+			match crate::time::nanosleep(&req) {
+				Ok(r) => r,
+				Err((e, rem)) => {
+					if arg2 != 0 && e == ERRNO_EINTR {
+						unsafe { rem_ptr.write_unaligned(rem); }
+					}
+					e
+				}
+			}
+		}
+
+		SYS_CLOCK_GETTIME => {
+			/*
+			 * Read a clock.
+			 * arg1: clock ID (CLOCK_REALTIME or CLOCK_MONOTONIC),
+			 * arg2: pointer to the Timespec receiving the time
+			 * Returns: 0 on success, errno on failure
+			 */
+			let size = core::mem::size_of::<crate::time::Timespec>();
+			let tp = arg2 as *mut crate::time::Timespec;
+			if !is_user_accessible(tp as *const u8, size) {
+				return ERRNO_EFAULT;
+			}
+			match crate::time::clock_gettime(arg1) {
+				Ok(ts) => {
+					unsafe { tp.write_unaligned(ts); }
+					0
+				}
+				Err(e) => e,
+			}
+		}
+
+		SYS_THREAD_CREATE => {
+			/*
+			 * Start a thread in the calling process.
+			 * arg1: entry point, arg2: top of the thread's user stack,
+			 * arg3: argument passed to the entry point in RDI,
+			 * arg4: thread pointer for FS_BASE (0 = no TLS)
+			 * Returns: tid on success, errno on failure
+			 *
+			 * The thread shares the address space, fd table and
+			 * capabilities of the process. Its stack and TLS block
+			 * belong to the caller, which frees them after the join.
+			 */
+			if arg1 == 0 || arg1 >= USER_SPACE_END
+				|| arg2 < 4096 || arg2 >= USER_SPACE_END
+				|| arg4 >= USER_SPACE_END
+			{
+				return ERRNO_EINVAL;
+			}
+			match crate::process::thread_create(arg1, arg2, arg3, arg4) {
+				Ok(tid) => tid,
+				Err(msg) => {
+					hal::serial_println!("[THREAD] failed: {}", msg);
+					ERRNO_ENOMEM
+				}
+			}
+		}
+
+		SYS_THREAD_EXIT => {
+			/*
+			 * Terminate the calling thread.
+			 * arg1: exit code, reported to SYS_THREAD_JOIN
+			 * From the main thread this ends the whole process like
+			 * SYS_EXIT. Never returns to the caller.
+			 */
+			crate::process::exit_current(crate::signal::wait_status_exited(arg1 as i32));
+		}
+
+		SYS_THREAD_JOIN => {
+			/*
+			 * Wait for a thread of the calling process to exit.
+			 * arg1: tid, arg2: pointer to i32 for its wait status (may be null)
+			 * Returns: 0 on success; ESRCH if @tid is no live or unjoined
+			 *          thread of this process; EINVAL for the caller, the
+			 *          main thread, or a thread someone else is joining
+			 */
+			let tid = arg1;
+			let status_ptr = arg2 as *mut i32;
+			let (self_id, tgid) = match task::scheduler::current_task_arc() {
+				Some(arc) => {
+					let t = arc.lock();
+					(t.id.0, t.tgid)
+				}
+				None => return ERRNO_ESRCH,
+			};
+			if tid == self_id || tid == tgid {
+				return ERRNO_EINVAL;
+			}
+
+			loop {
+				let res = x86_64::instructions::interrupts::without_interrupts(|| {
+					if let Some(zombie) = task::scheduler::find_zombie_thread(tgid, tid) {
+						let status = zombie.lock().exit_status.unwrap_or(0);
+						crate::process::release_task(zombie);
+						return Ok(Some(status));
+					}
+					let target = match task::scheduler::find_task_by_id(tid) {
+						Some(t) if t.lock().tgid == tgid => t,
+						_ => return Err(ERRNO_ESRCH),
+					};
+					if crate::signal::interrupted() {
+						return Err(ERRNO_EINTR);
+					}
+					{
+						let mut t = target.lock();
+						if t.joiner != 0 && t.joiner != self_id {
+							return Err(ERRNO_EINVAL);
+						}
+						t.joiner = self_id;
+					}
+					/* exit_current() wakes us once the thread is a zombie */
+					task::block_current_and_switch();
+					target.lock().joiner = 0;
+					Ok(None)
+				});
+				match res {
+					Ok(Some(status)) => {
+						if !status_ptr.is_null()
+							&& is_user_accessible(status_ptr as *const u8, 4)
+						{
+							unsafe { *status_ptr = status; }
+						}
+						return 0;
+					}
+					Ok(None) => continue,
+					Err(e) => return e,
+				}
+			}
+		}
+
+		SYS_GETTID => task::scheduler::current_task_id(),
+
+		SYS_FUTEX => {
+			/*
+			 * Sleep on or wake a futex word.
+			 * arg1: address of an aligned u32, arg2: FUTEX_WAIT or FUTEX_WAKE,
+			 * arg3: expected value (WAIT) or most tasks to wake (WAKE),
+			 * arg4: pointer to a relative Timespec timeout (WAIT, 0 = none)
+			 * Returns: 0 or number of tasks woken, errno on failure
+			 * (EAGAIN if the word changed, ETIMEDOUT, EINTR)
+			 */
+			crate::futex::futex(arg1, arg2, arg3, arg4)
+		}
+
 		_ => {
 			/* Unknown system call */
 			hal::serial_println!("[SYSCALL] Unknown syscall: {}", nr);
@@ -0,0 +1,98 @@
+/*
+ * time.rs - Time System Calls
+ *
+ * Implements SYS_NANOSLEEP on top of the kernel timer queue
+ * (task::timer), which is advanced by the LAPIC timer tick. Sleeps have
+ * tick granularity and are rounded up, so a task never wakes early by
+ * the tick count; the tick itself is the nominal, uncalibrated
+ * task::timer::TICK_HZ.
+ *
+ * SYS_CLOCK_GETTIME reads hal::clock: CLOCK_MONOTONIC is the calibrated
+ * TSC since boot, CLOCK_REALTIME adds the RTC time read at boot.
+ */
+
+use crate::syscall::{ERRNO_EINTR, ERRNO_EINVAL};
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/* Clock IDs (Linux values) */
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+/*
+ * struct Timespec - Seconds and nanoseconds (Linux struct timespec)
+ * @sec: Whole seconds
+ * @nsec: Nanoseconds, 0..1_000_000_000
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timespec {
+	pub sec: i64,
+	pub nsec: i64,
+}
+
+impl Timespec {
+	/*
+	 * to_ns - Total nanoseconds
+	 *
+	 * Return: The duration, or None if the value is negative or malformed
+	 */
+	pub fn to_ns(&self) -> Option<u64> {
+		if self.sec < 0 || !(0..NS_PER_SEC as i64).contains(&self.nsec) {
+			return None;
+		}
+		(self.sec as u64).checked_mul(NS_PER_SEC)?.checked_add(self.nsec as u64)
+	}
+
+	/*
+	 * from_ns - Build a Timespec from nanoseconds
+	 * @ns: Duration in nanoseconds
+	 */
+	pub fn from_ns(ns: u64) -> Self {
+		Self { sec: (ns / NS_PER_SEC) as i64, nsec: (ns % NS_PER_SEC) as i64 }
+	}
+}
+
+/*
+ * nanosleep - Suspend the calling task (SYS_NANOSLEEP)
+ * @req: Requested duration
+ *
+ * Sleeps in TaskState::Sleeping until the deadline. A signal cuts the
+ * sleep short.
+ *
+ * Return: Ok(0), Err((ERRNO_EINTR, remaining)) if interrupted, or
+ *         Err((ERRNO_EINVAL, _)) for a malformed @req
+ */
+pub fn nanosleep(req: &Timespec) -> Result<u64, (u64, Timespec)> {
+	let ns = req.to_ns().ok_or((ERRNO_EINVAL, Timespec::default()))?;
+	let deadline = task::timer::now().saturating_add(task::timer::ns_to_ticks(ns));
+
+	loop {
+		if task::timer::now() >= deadline {
+			return Ok(0);
+		}
+		if crate::signal::interrupted() {
+			let left = deadline.saturating_sub(task::timer::now());
+			return Err((ERRNO_EINTR, Timespec::from_ns(task::timer::ticks_to_ns(left))));
+		}
+		if !task::timer::sleep_until(deadline) && !crate::signal::interrupted() {
+			/* Nothing else was runnable: wait for the next tick */
+			x86_64::instructions::interrupts::enable_and_hlt();
+			x86_64::instructions::interrupts::disable();
+		}
+	}
+}
+
+/*
+ * clock_gettime - Read a clock (SYS_CLOCK_GETTIME)
+ * @clock: CLOCK_REALTIME or CLOCK_MONOTONIC
+ *
+ * Return: The current time of @clock, or ERRNO_EINVAL for an unknown ID
+ */
+pub fn clock_gettime(clock: u64) -> Result<Timespec, u64> {
+	match clock {
+		CLOCK_REALTIME => Ok(Timespec::from_ns(hal::clock::realtime_ns())),
+		CLOCK_MONOTONIC => Ok(Timespec::from_ns(hal::clock::monotonic_ns())),
+		_ => Err(ERRNO_EINVAL),
+	}
+}
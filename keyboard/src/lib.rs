@@ -7,7 +7,8 @@
 
 #![no_std]
 
-use spin::Mutex;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, Once};
 
 /*
  * US QWERTY scancode Set 1 to ASCII mapping table
@@ -21,6 +22,13 @@ const SCANDCODE_TO_ASCII: [u8; 128] = [
 	0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
+/* Left Ctrl make/break codes (Set 1) */
+const SC_CTRL: u8 = 0x1D;
+const SC_CTRL_RELEASE: u8 = 0x9D;
+
+/* ASCII ETX, produced by Ctrl+C */
+const CTRL_C: u8 = 0x03;
+
 /*
  * Ring Buffer Implementation
  * Fixed size, no allocation, interrupt safe with Mutex.
@@ -86,14 +94,47 @@ impl RingBuffer {
 // Global static instance
 static INPUT_BUF: Mutex<RingBuffer> = Mutex::new(RingBuffer::new());
 
+// Ctrl key state, tracked from make/break codes
+static CTRL_DOWN: AtomicBool = AtomicBool::new(false);
+
+/*
+ * INTERRUPT_HOOK - Called on Ctrl+C instead of buffering the key
+ *
+ * Registered by the kernel (which raises SIGINT) to avoid a circular
+ * dependency between the keyboard driver and the kernel crate.
+ */
+static INTERRUPT_HOOK: Once<fn()> = Once::new();
+
+/*
+ * register_interrupt_hook - Install the Ctrl+C handler
+ * @hook: Runs in interrupt context; must not block
+ */
+pub fn register_interrupt_hook(hook: fn()) {
+	INTERRUPT_HOOK.call_once(|| hook);
+}
+
 /*
  * handle_scancode - Process keyboard scancode
  * @scancode: Raw scancode from keyboard controller
  *
  * Translates scancode to ASCII and buffers printable characters.
- * Ignores break codes (key release events).
+ * With Ctrl held, letters become control characters; Ctrl+C goes to
+ * the interrupt hook if one is registered. Other break codes (key
+ * release events) are ignored.
  */
 pub fn handle_scancode(scancode: u8) {
+	match scancode {
+		SC_CTRL => {
+			CTRL_DOWN.store(true, Ordering::Relaxed);
+			return;
+		}
+		SC_CTRL_RELEASE => {
+			CTRL_DOWN.store(false, Ordering::Relaxed);
+			return;
+		}
+		_ => {}
+	}
+
 	// Ignore break codes (bit 7 set)
 	if scancode & 0x80 != 0 {
 		return;
@@ -101,6 +142,17 @@ pub fn handle_scancode(scancode: u8) {
 
 	// Translate and buffer printable characters
 	if let Some(&ascii) = SCANDCODE_TO_ASCII.get(scancode as usize) {
+		let ascii = if CTRL_DOWN.load(Ordering::Relaxed) && ascii.is_ascii_lowercase() {
+			ascii & 0x1F
+		} else {
+			ascii
+		};
+		if ascii == CTRL_C {
+			if let Some(hook) = INTERRUPT_HOOK.get() {
+				hook();
+				return;
+			}
+		}
 		if ascii != 0 {
 			// Push to ring buffer (interrupt safe)
 			x86_64::instructions::interrupts::without_interrupts(|| {
@@ -70,6 +70,57 @@ pub struct ProgramHeader {
 	pub p_align: u64,  // Segment alignment
 }
 
+/*
+ * Object file types (e_type)
+ */
+pub const ET_EXEC: u16 = 2; // Fixed-address executable
+pub const ET_DYN: u16 = 3;  // Position-independent (static-PIE) executable
+
+/*
+ * struct Elf64Dyn - Entry of the PT_DYNAMIC array
+ */
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct Elf64Dyn {
+	pub d_tag: i64,
+	pub d_val: u64, // Value or virtual address, depending on d_tag
+}
+
+/*
+ * Dynamic array tags
+ */
+pub const DT_NULL: i64 = 0;
+pub const DT_RELA: i64 = 7;    // Address of the RELA table
+pub const DT_RELASZ: i64 = 8;  // Size of the RELA table in bytes
+pub const DT_RELAENT: i64 = 9; // Size of one RELA entry
+pub const DT_RELR: i64 = 36;   // Packed relative relocations
+
+/*
+ * struct Elf64Rela - Relocation with explicit addend
+ */
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct Elf64Rela {
+	pub r_offset: u64, // Virtual address of the word to patch
+	pub r_info: u64,   // Symbol index (high 32 bits) and type (low 32)
+	pub r_addend: i64,
+}
+
+/*
+ * x86_64 relocation types
+ */
+pub const R_X86_64_NONE: u32 = 0;
+pub const R_X86_64_RELATIVE: u32 = 8; // B + A
+
+/*
+ * Values identifying a Linux binary: EI_OSABI, and the GNU ABI tag note
+ * (owner "GNU", type 1, first descriptor word = OS)
+ */
+pub const EI_OSABI: usize = 7;
+pub const ELFOSABI_GNU: u8 = 3;
+pub const NT_GNU_ABI_TAG: u32 = 1;
+pub const ELF_NOTE_OS_LINUX: u32 = 0;
+
 /*
  * Permission flags for program headers
  */
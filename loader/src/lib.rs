@@ -2,6 +2,16 @@
  * lib.rs - ELF loader
  *
  * Parses and loads ELF executables into memory.
+ *
+ * Fixed-address ET_EXEC images load where they were linked. ET_DYN
+ * static-PIE images are slid to a caller-chosen base: every address in
+ * the result is biased, and the R_X86_64_RELATIVE relocations listed
+ * in PT_DYNAMIC are applied to the segment data before it is mapped.
+ * Images needing an interpreter (PT_INTERP) are rejected.
+ *
+ * A PT_TLS template becomes the initial thread's TLS block, laid out
+ * for x86_64 TLS variant II and appended as an extra segment after the
+ * image; the caller points FS_BASE at LoadedImage.thread_pointer.
  */
 
 #![no_std]
@@ -10,7 +20,11 @@ extern crate alloc;
 pub mod elf;
 
 use alloc::vec::Vec;
-use elf::{Elf64Header, PF_R, PF_W, PF_X, ProgramHeader, SegmentType};
+use elf::{
+	DT_NULL, DT_RELA, DT_RELAENT, DT_RELASZ, DT_RELR, EI_OSABI, ELF_NOTE_OS_LINUX, ELFOSABI_GNU,
+	ET_DYN, ET_EXEC, Elf64Dyn, Elf64Header, Elf64Rela, NT_GNU_ABI_TAG, PF_R, PF_W, PF_X,
+	ProgramHeader, R_X86_64_NONE, R_X86_64_RELATIVE, SegmentType,
+};
 use x86_64::VirtAddr;
 
 /*
@@ -41,13 +55,222 @@ pub struct SegmentFlags {
 	pub executable: bool,
 }
 
+/*
+ * struct LoadedImage - Result of parsing an ELF executable
+ * @entry_point: Virtual address of the first instruction
+ * @segments: PT_LOAD segments to map
+ * @phdr: Virtual address of the program header table once loaded
+ *        (None if it is not covered by any segment)
+ * @phent: Size of one program header entry
+ * @phnum: Number of program header entries
+ * @linux_abi: The image declares the Linux ABI (EI_OSABI or a GNU ABI
+ *             tag note), so it should run under the Linux personality
+ * @load_bias: Offset added to every link-time address (0 for ET_EXEC)
+ * @thread_pointer: Initial FS_BASE if the image has a PT_TLS segment
+ */
 #[derive(Debug)]
 pub struct LoadedImage {
 	pub entry_point: VirtAddr,
 	pub segments: Vec<LoadableSegment>,
+	pub phdr: Option<VirtAddr>,
+	pub phent: u16,
+	pub phnum: u16,
+	pub linux_abi: bool,
+	pub load_bias: u64,
+	pub thread_pointer: Option<VirtAddr>,
+}
+
+/* Bytes above the thread pointer: the TCB, holding only its own address */
+const TCB_SIZE: u64 = 8;
+
+/*
+ * has_linux_abi_tag - Look for a GNU ABI tag note naming Linux
+ * @notes: Contents of a PT_NOTE segment
+ */
+fn has_linux_abi_tag(notes: &[u8]) -> bool {
+	let word = |off: usize| u32::from_le_bytes([notes[off], notes[off + 1], notes[off + 2], notes[off + 3]]);
+	let mut off = 0usize;
+	while off + 12 <= notes.len() {
+		let namesz = word(off) as usize;
+		let descsz = word(off + 4) as usize;
+		let name_off = off + 12;
+		let desc_off = name_off + ((namesz + 3) & !3);
+		let next = desc_off + ((descsz + 3) & !3);
+		if next > notes.len() {
+			break;
+		}
+		if word(off + 8) == NT_GNU_ABI_TAG
+			&& &notes[name_off..name_off + namesz] == b"GNU\0"
+			&& descsz >= 4
+			&& word(desc_off) == ELF_NOTE_OS_LINUX
+		{
+			return true;
+		}
+		off = next;
+	}
+	false
+}
+
+/*
+ * read_struct - Copy a plain ELF structure out of the file
+ * @data: Whole file
+ * @off:  Byte offset of the structure
+ *
+ * Return: The structure, or None if it runs past the end of @data
+ */
+fn read_struct<T: Copy>(data: &[u8], off: usize) -> Option<T> {
+	let end = off.checked_add(core::mem::size_of::<T>())?;
+	if end > data.len() {
+		return None;
+	}
+	Some(unsafe { core::ptr::read_unaligned(data.as_ptr().add(off) as *const T) })
+}
+
+/*
+ * vaddr_to_offset - File offset of a link-time virtual address
+ * @phdrs: Program headers of the image
+ * @vaddr: Address inside some PT_LOAD segment's file-backed part
+ */
+fn vaddr_to_offset(phdrs: &[ProgramHeader], vaddr: u64) -> Option<usize> {
+	phdrs.iter()
+		.filter(|ph| ph.p_type == SegmentType::Load as u32)
+		.find(|ph| vaddr >= ph.p_vaddr && vaddr - ph.p_vaddr < ph.p_filesz)
+		.map(|ph| (ph.p_offset + (vaddr - ph.p_vaddr)) as usize)
+}
+
+/*
+ * apply_relocations - Process the RELA table of a static-PIE image
+ * @data:     Whole file
+ * @phdrs:    Program headers of the image
+ * @dynamic:  The PT_DYNAMIC header
+ * @segments: Loaded segments, already biased, patched in place
+ * @bias:     Load bias
+ *
+ * Only R_X86_64_RELATIVE (and NONE) may appear: a static-PIE has no
+ * symbols left to resolve. RELATIVE writes are absolute (B + A), so a
+ * C library that relocates itself again at startup is unaffected.
+ */
+fn apply_relocations(
+	data: &[u8],
+	phdrs: &[ProgramHeader],
+	dynamic: &ProgramHeader,
+	segments: &mut [LoadableSegment],
+	bias: u64,
+) -> Result<(), &'static str> {
+	let (mut rela, mut relasz, mut relaent) = (None, 0u64, core::mem::size_of::<Elf64Rela>() as u64);
+	let count = dynamic.p_filesz as usize / core::mem::size_of::<Elf64Dyn>();
+	for i in 0..count {
+		let off = dynamic.p_offset as usize + i * core::mem::size_of::<Elf64Dyn>();
+		let dyn_ent: Elf64Dyn = read_struct(data, off).ok_or("PT_DYNAMIC truncated")?;
+		match dyn_ent.d_tag {
+			DT_NULL => break,
+			DT_RELA => rela = Some(dyn_ent.d_val),
+			DT_RELASZ => relasz = dyn_ent.d_val,
+			DT_RELAENT => relaent = dyn_ent.d_val,
+			DT_RELR => return Err("DT_RELR relocations not supported"),
+			_ => {}
+		}
+	}
+	let Some(rela) = rela else { return Ok(()) };
+	if relaent < core::mem::size_of::<Elf64Rela>() as u64 {
+		return Err("Bad DT_RELAENT");
+	}
+	let table = vaddr_to_offset(phdrs, rela).ok_or("DT_RELA outside the image")?;
+
+	for i in 0..(relasz / relaent) as usize {
+		let r: Elf64Rela = read_struct(data, table + i * relaent as usize)
+			.ok_or("Relocation table truncated")?;
+		match r.r_info as u32 {
+			R_X86_64_NONE => continue,
+			R_X86_64_RELATIVE => {}
+			_ => return Err("Unsupported relocation type"),
+		}
+		let target = r.r_offset.wrapping_add(bias);
+		let value = bias.wrapping_add(r.r_addend as u64);
+		let seg = segments.iter_mut()
+			.find(|s| {
+				let start = s.virtual_address.as_u64();
+				target >= start && target + 8 <= start + s.data.len() as u64
+			})
+			.ok_or("Relocation outside the image")?;
+		let at = (target - seg.virtual_address.as_u64()) as usize;
+		seg.data[at..at + 8].copy_from_slice(&value.to_le_bytes());
+	}
+	Ok(())
+}
+
+/*
+ * build_tls - Lay out the initial thread's TLS block
+ * @tls:      The PT_TLS header
+ * @segments: Loaded segments, already biased and relocated
+ * @bias:     Load bias
+ *
+ * Variant II: the block ends at the thread pointer, which is aligned to
+ * the template's p_align, so a variable at TLS offset o lives at
+ * tp - round_up(p_memsz, p_align) + o. The TCB at the thread pointer
+ * starts with a pointer to itself, since %fs:0 must read back the
+ * thread pointer. The template is copied from the loaded segments so
+ * that relocations in .tdata are already applied.
+ *
+ * Return: The segment holding block and TCB, placed on the first page
+ *         past the image, and the thread pointer
+ */
+fn build_tls(
+	tls: &ProgramHeader,
+	segments: &[LoadableSegment],
+	bias: u64,
+) -> Result<(LoadableSegment, u64), &'static str> {
+	let align = tls.p_align.max(8);
+	if !align.is_power_of_two() || tls.p_filesz > tls.p_memsz {
+		return Err("Bad PT_TLS segment");
+	}
+	let image_end = segments.iter()
+		.map(|s| s.virtual_address.as_u64() + s.size)
+		.max()
+		.ok_or("PT_TLS without loadable segments")?;
+
+	let block = (tls.p_memsz + align - 1) & !(align - 1);
+	let start = (image_end + 0xFFF) & !0xFFF;
+	let tp = (start + block + align - 1) & !(align - 1);
+	let first = (tp - block - start) as usize;
+
+	let mut data = Vec::new();
+	data.resize((tp - start + TCB_SIZE) as usize, 0);
+
+	let template = tls.p_vaddr.wrapping_add(bias);
+	let filesz = tls.p_filesz as usize;
+	if filesz > 0 {
+		let seg = segments.iter()
+			.find(|s| {
+				let base = s.virtual_address.as_u64();
+				template >= base && template + filesz as u64 <= base + s.data.len() as u64
+			})
+			.ok_or("PT_TLS template outside the image")?;
+		let at = (template - seg.virtual_address.as_u64()) as usize;
+		data[first..first + filesz].copy_from_slice(&seg.data[at..at + filesz]);
+	}
+	let self_ptr = (tp - start) as usize;
+	data[self_ptr..self_ptr + 8].copy_from_slice(&tp.to_le_bytes());
+
+	let segment = LoadableSegment {
+		virtual_address: VirtAddr::try_new(start).map_err(|_| "TLS block not canonical")?,
+		size: data.len() as u64,
+		flags: SegmentFlags { readable: true, writable: true, executable: false },
+		data,
+	};
+	Ok((segment, tp))
 }
 
-pub fn load_elf(data: &[u8]) -> Result<LoadedImage, &'static str> {
+/*
+ * load_elf - Parse an executable into loadable segments
+ * @data:     Whole ELF file
+ * @pie_base: Where to place an ET_DYN image, rounded up to its largest
+ *            segment alignment; ignored for ET_EXEC
+ *
+ * Return: The image with all addresses final, or Err string if the file
+ *         is malformed or needs features the loader lacks
+ */
+pub fn load_elf(data: &[u8], pie_base: u64) -> Result<LoadedImage, &'static str> {
 	// 1. Safety check: ensure data is large enough for header
 	if data.len() < core::mem::size_of::<Elf64Header>() {
 		return Err("File too small");
@@ -56,21 +279,79 @@ pub fn load_elf(data: &[u8]) -> Result<LoadedImage, &'static str> {
 	// 2. Transmute raw bytes to ELF Header struct
 	let header = unsafe { &*(data.as_ptr() as *const Elf64Header) };
 	header.validate()?;
+	let e_type = header.e_type;
+	if e_type != ET_EXEC && e_type != ET_DYN {
+		return Err("Not an executable");
+	}
 
 	// 3. Parse Program Headers
 	let ph_offset = header.e_phoff as usize;
 	let ph_count = header.e_phnum as usize;
 	let ph_size = header.e_phentsize as usize;
 
-	if data.len() < ph_offset + (ph_count * ph_size) {
+	if ph_size < core::mem::size_of::<ProgramHeader>() {
+		return Err("Bad program header size");
+	}
+	let ph_end = ph_count.checked_mul(ph_size).and_then(|len| ph_offset.checked_add(len));
+	if ph_end.is_none_or(|end| end > data.len()) {
 		return Err("Program headers truncated");
 	}
 
+	let phdrs: Vec<ProgramHeader> = (0..ph_count)
+		.map(|i| read_struct(data, ph_offset + i * ph_size).ok_or("Bad program header"))
+		.collect::<Result<_, _>>()?;
+
+	if phdrs.iter().any(|ph| ph.p_type == SegmentType::Interp as u32) {
+		return Err("Dynamically linked executables not supported");
+	}
+
+	// 4. Pick the load bias: slide a PIE so its lowest page lands on
+	// the (suitably aligned) base
+	let bias = if e_type == ET_DYN {
+		let loads = || phdrs.iter().filter(|ph| ph.p_type == SegmentType::Load as u32);
+		let align = loads().map(|ph| ph.p_align).fold(0x1000, u64::max);
+		if !align.is_power_of_two() {
+			return Err("Bad segment alignment");
+		}
+		let lowest = loads().map(|ph| ph.p_vaddr).min().ok_or("No loadable segments")?;
+		((pie_base + align - 1) & !(align - 1)).wrapping_sub(lowest & !(align - 1))
+	} else {
+		0
+	};
+
 	let mut segments = Vec::new();
+	let mut phdr = None;
+	let mut dynamic = None;
+	let mut tls = None;
+	let mut linux_abi = header.e_ident[EI_OSABI] == ELFOSABI_GNU;
 
-	for i in 0..ph_count {
-		let ptr = unsafe { data.as_ptr().add(ph_offset + i * ph_size) };
-		let ph = unsafe { &*(ptr as *const ProgramHeader) };
+	for ph in &phdrs {
+		// Locate the program headers in memory (AT_PHDR): PT_PHDR if
+		// present, otherwise the LOAD segment whose file range covers them
+		let phoff = ph_offset as u64;
+		if ph.p_type == SegmentType::Phdr as u32 {
+			phdr = VirtAddr::try_new(ph.p_vaddr.wrapping_add(bias)).ok();
+		} else if phdr.is_none()
+			&& ph.p_type == SegmentType::Load as u32
+			&& (ph.p_offset..ph.p_offset + ph.p_filesz).contains(&phoff)
+		{
+			phdr = VirtAddr::try_new(ph.p_vaddr.wrapping_add(bias) + (phoff - ph.p_offset)).ok();
+		}
+
+		if ph.p_type == SegmentType::Dynamic as u32 {
+			dynamic = Some(*ph);
+		}
+		if ph.p_type == SegmentType::Tls as u32 {
+			tls = Some(*ph);
+		}
+
+		// A GNU ABI tag note selects the Linux personality
+		if ph.p_type == SegmentType::Note as u32 {
+			let start = ph.p_offset as usize;
+			if let Some(notes) = data.get(start..start.saturating_add(ph.p_filesz as usize)) {
+				linux_abi |= has_linux_abi_tag(notes);
+			}
+		}
 
 		// We only care about LOAD segments
 		if ph.p_type == SegmentType::Load as u32 {
@@ -92,7 +373,8 @@ pub fn load_elf(data: &[u8]) -> Result<LoadedImage, &'static str> {
 			segment_data.resize(segment_data.len() + zero_fill, 0);
 
 			segments.push(LoadableSegment {
-				virtual_address: VirtAddr::new(ph.p_vaddr),
+				virtual_address: VirtAddr::try_new(ph.p_vaddr.wrapping_add(bias))
+					.map_err(|_| "Segment address not canonical")?,
 				size: ph.p_memsz,
 				flags: SegmentFlags {
 					readable: ph.p_flags & PF_R != 0,
@@ -104,8 +386,30 @@ pub fn load_elf(data: &[u8]) -> Result<LoadedImage, &'static str> {
 		}
 	}
 
+	// 5. A PIE carries its own absolute addresses as relocations
+	if e_type == ET_DYN {
+		if let Some(dynamic) = dynamic {
+			apply_relocations(data, &phdrs, &dynamic, &mut segments, bias)?;
+		}
+	}
+
+	// 6. The initial thread's TLS block goes after everything else
+	let mut thread_pointer = None;
+	if let Some(tls) = tls {
+		let (segment, tp) = build_tls(&tls, &segments, bias)?;
+		segments.push(segment);
+		thread_pointer = Some(VirtAddr::new(tp));
+	}
+
 	Ok(LoadedImage {
-		entry_point: VirtAddr::new(header.e_entry),
+		entry_point: VirtAddr::try_new(header.e_entry.wrapping_add(bias))
+			.map_err(|_| "Entry point not canonical")?,
 		segments,
+		phdr,
+		phent: header.e_phentsize,
+		phnum: header.e_phnum,
+		linux_abi,
+		load_bias: bias,
+		thread_pointer,
 	})
 }
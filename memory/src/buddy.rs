@@ -0,0 +1,268 @@
+/*
+ * buddy.rs - Buddy Physical Frame Allocator
+ *
+ * Manages every USABLE frame of the Limine memory map in power-of-two
+ * blocks of 2^order frames, from order 0 (4 KiB) up to MAX_ORDER
+ * (2 MiB). A larger block is split in halves ("buddies") to satisfy a
+ * smaller request, and a freed block is merged with its buddy whenever
+ * that one is free as well, so contiguous ranges form again as memory
+ * is returned.
+ *
+ * Free blocks sit on one doubly linked list per order. The links live
+ * in the first bytes of each free block and are reached through the
+ * HHDM, so the allocator needs no heap and runs before init_heap().
+ * One state byte per frame, carved out of a usable region at boot,
+ * marks the frames that head a free block and its order; free() reads
+ * it to decide whether a buddy can be merged.
+ */
+
+use limine::memory_map::{Entry, EntryType};
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+/* Largest block: 2^9 frames = 2 MiB */
+pub const MAX_ORDER: usize = 9;
+
+const FRAME_SIZE: u64 = 4096;
+
+/* Free list terminator (no frame has this number) */
+const LIST_END: u64 = u64::MAX;
+
+/* State byte of the first frame of a free block; the low bits hold its order */
+const FREE_HEAD: u8 = 0x80;
+
+/*
+ * struct Links - Free list node kept at the start of a free block
+ * @next: Frame number of the next free block of this order
+ * @prev: Frame number of the previous one
+ */
+#[repr(C)]
+struct Links {
+	next: u64,
+	prev: u64,
+}
+
+/*
+ * struct BuddyAllocator - Physical frame allocator
+ * @offset: HHDM offset
+ * @free_lists: First free block of each order, or LIST_END
+ * @state: One byte per frame from @base_pfn to @end_pfn
+ * @base_pfn: Lowest usable frame number
+ * @end_pfn: One past the highest usable frame number
+ * @total: Frames under management
+ * @free: Frames not handed out
+ */
+pub struct BuddyAllocator {
+	offset: VirtAddr,
+	free_lists: [u64; MAX_ORDER + 1],
+	state: &'static mut [u8],
+	base_pfn: u64,
+	end_pfn: u64,
+	total: usize,
+	free: usize,
+}
+
+/*
+ * order_for - Smallest order whose block holds @bytes
+ * @bytes: Size of the range needed
+ */
+pub fn order_for(bytes: usize) -> usize {
+	let frames = bytes.div_ceil(FRAME_SIZE as usize).max(1);
+	frames.next_power_of_two().trailing_zeros() as usize
+}
+
+impl BuddyAllocator {
+	/*
+	 * new - Build the allocator from the Limine memory map
+	 * @memory_map: Limine memory map entries
+	 * @offset: HHDM offset
+	 *
+	 * Every USABLE frame is handed to the allocator except those taken
+	 * for the state array, which come from the first region that can
+	 * hold it. Panics if there is no such region.
+	 */
+	pub fn new(memory_map: &[&Entry], offset: VirtAddr) -> Self {
+		let usable = || {
+			memory_map
+				.iter()
+				.filter(|r| r.entry_type == EntryType::USABLE)
+				.map(|r| (r.base.div_ceil(FRAME_SIZE), (r.base + r.length) / FRAME_SIZE))
+				.filter(|(start, end)| start < end)
+		};
+		let base_pfn = usable().map(|(start, _)| start).min().unwrap_or(0);
+		let end_pfn = usable().map(|(_, end)| end).max().unwrap_or(0);
+		let state_len = (end_pfn - base_pfn) as usize;
+		let state_frames = (state_len as u64).div_ceil(FRAME_SIZE);
+		let (state_pfn, _) = usable()
+			.find(|(start, end)| end - start >= state_frames)
+			.expect("No usable region for the frame state array");
+
+		let state = unsafe {
+			let ptr = (offset + state_pfn * FRAME_SIZE).as_mut_ptr::<u8>();
+			core::ptr::write_bytes(ptr, 0, state_len);
+			core::slice::from_raw_parts_mut(ptr, state_len)
+		};
+		let mut buddy = BuddyAllocator {
+			offset,
+			free_lists: [LIST_END; MAX_ORDER + 1],
+			state,
+			base_pfn,
+			end_pfn,
+			total: 0,
+			free: 0,
+		};
+		for (start, end) in usable() {
+			let start = if start == state_pfn { start + state_frames } else { start };
+			buddy.add_range(start, end);
+		}
+		buddy
+	}
+
+	/*
+	 * add_range - Hand frames [@start, @end) to the allocator
+	 *
+	 * Splits the range into the largest naturally aligned blocks.
+	 */
+	fn add_range(&mut self, mut start: u64, end: u64) {
+		while start < end {
+			let mut order = MAX_ORDER;
+			while start & ((1 << order) - 1) != 0 || start + (1 << order) > end {
+				order -= 1;
+			}
+			self.total += 1 << order;
+			unsafe { self.free(pfn_frame(start), order) };
+			start += 1 << order;
+		}
+	}
+
+	fn links(&self, pfn: u64) -> *mut Links {
+		(self.offset + pfn * FRAME_SIZE).as_mut_ptr()
+	}
+
+	fn state_index(&self, pfn: u64) -> Option<usize> {
+		(self.base_pfn..self.end_pfn)
+			.contains(&pfn)
+			.then(|| (pfn - self.base_pfn) as usize)
+	}
+
+	/* push - Put a free block at the head of its list */
+	fn push(&mut self, pfn: u64, order: usize) {
+		let head = self.free_lists[order];
+		unsafe {
+			self.links(pfn).write(Links { next: head, prev: LIST_END });
+			if head != LIST_END {
+				(*self.links(head)).prev = pfn;
+			}
+		}
+		self.free_lists[order] = pfn;
+		if let Some(i) = self.state_index(pfn) {
+			self.state[i] = FREE_HEAD | order as u8;
+		}
+	}
+
+	/* unlink - Take a free block off its list, wherever it is */
+	fn unlink(&mut self, pfn: u64, order: usize) {
+		let Links { next, prev } = unsafe { self.links(pfn).read() };
+		if prev == LIST_END {
+			self.free_lists[order] = next;
+		} else {
+			unsafe { (*self.links(prev)).next = next };
+		}
+		if next != LIST_END {
+			unsafe { (*self.links(next)).prev = prev };
+		}
+		if let Some(i) = self.state_index(pfn) {
+			self.state[i] = 0;
+		}
+	}
+
+	fn is_free_block(&self, pfn: u64, order: usize) -> bool {
+		self.state_index(pfn)
+			.is_some_and(|i| self.state[i] == FREE_HEAD | order as u8)
+	}
+
+	/*
+	 * alloc - Allocate 2^@order physically contiguous frames
+	 * @order: 0 (4 KiB) to MAX_ORDER (2 MiB)
+	 *
+	 * The block is aligned to its own size. Its contents are undefined.
+	 *
+	 * Return: First frame of the block, or None if no block is large enough
+	 */
+	pub fn alloc(&mut self, order: usize) -> Option<PhysFrame> {
+		let mut k = (order..=MAX_ORDER).find(|&k| self.free_lists[k] != LIST_END)?;
+		let pfn = self.free_lists[k];
+		self.unlink(pfn, k);
+		/* Split down to size, keeping the low half each time */
+		while k > order {
+			k -= 1;
+			self.push(pfn + (1 << k), k);
+		}
+		self.free -= 1 << order;
+		Some(pfn_frame(pfn))
+	}
+
+	/*
+	 * free - Return a block, merging it with free buddies
+	 * @frame: First frame of the block, as returned by alloc()
+	 * @order: Order it was allocated with
+	 *
+	 * Safety: The block must be allocated and no longer in use.
+	 */
+	pub unsafe fn free(&mut self, frame: PhysFrame, order: usize) {
+		self.free += 1 << order;
+		let mut pfn = frame.start_address().as_u64() / FRAME_SIZE;
+		let mut order = order;
+		while order < MAX_ORDER {
+			let buddy = pfn ^ (1 << order);
+			if !self.is_free_block(buddy, order) {
+				break;
+			}
+			self.unlink(buddy, order);
+			pfn = pfn.min(buddy);
+			order += 1;
+		}
+		self.push(pfn, order);
+	}
+
+	/*
+	 * total_frames - Number of frames this allocator manages
+	 */
+	pub fn total_frames(&self) -> usize {
+		self.total
+	}
+
+	/*
+	 * free_frames - Number of frames not currently handed out
+	 */
+	pub fn free_frames(&self) -> usize {
+		self.free
+	}
+
+	/*
+	 * used_frames - Number of frames currently handed out
+	 */
+	pub fn used_frames(&self) -> usize {
+		self.total - self.free
+	}
+}
+
+fn pfn_frame(pfn: u64) -> PhysFrame {
+	PhysFrame::containing_address(PhysAddr::new(pfn * FRAME_SIZE))
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BuddyAllocator {
+	fn allocate_frame(&mut self) -> Option<PhysFrame> {
+		self.alloc(0)
+	}
+}
+
+impl FrameDeallocator<Size4KiB> for BuddyAllocator {
+	/*
+	 * deallocate_frame - Return a single frame
+	 * @frame: Frame no longer referenced by any mapping
+	 */
+	unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+		unsafe { self.free(frame, 0) };
+	}
+}
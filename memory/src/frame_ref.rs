@@ -0,0 +1,63 @@
+/*
+ * frame_ref.rs - Physical Frame Reference Counts
+ *
+ * Tracks how many user mappings point at a physical frame. Only shared
+ * frames are stored: a frame missing from the table has an implicit
+ * count of one, so private pages cost nothing. Copy-on-write fork uses
+ * the count to decide whether a write fault must copy the page or can
+ * simply take ownership of it.
+ */
+
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use x86_64::structures::paging::PhysFrame;
+
+/* FRAME_REFS - Physical address -> reference count (always >= 2) */
+static FRAME_REFS: Mutex<BTreeMap<u64, u32>> = Mutex::new(BTreeMap::new());
+
+/*
+ * count - Get the reference count of a frame
+ * @frame: Physical frame
+ *
+ * Return: Number of mappings referencing @frame (1 if untracked)
+ */
+pub fn count(frame: PhysFrame) -> u32 {
+	FRAME_REFS
+		.lock()
+		.get(&frame.start_address().as_u64())
+		.copied()
+		.unwrap_or(1)
+}
+
+/*
+ * share - Record one additional mapping of a frame
+ * @frame: Physical frame being mapped again
+ */
+pub fn share(frame: PhysFrame) {
+	let mut refs = FRAME_REFS.lock();
+	let cnt = refs.entry(frame.start_address().as_u64()).or_insert(1);
+	*cnt += 1;
+}
+
+/*
+ * release - Drop one mapping of a frame
+ * @frame: Physical frame being unmapped
+ *
+ * Return: true if the caller held the last reference and the frame
+ *         may be returned to the allocator
+ */
+pub fn release(frame: PhysFrame) -> bool {
+	let mut refs = FRAME_REFS.lock();
+	let key = frame.start_address().as_u64();
+	match refs.get_mut(&key) {
+		Some(cnt) if *cnt > 2 => {
+			*cnt -= 1;
+			false
+		}
+		Some(_) => {
+			refs.remove(&key);
+			false
+		}
+		None => true,
+	}
+}
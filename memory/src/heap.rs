@@ -3,27 +3,88 @@
  *
  * Provides dynamic memory allocation for the kernel using a linked list allocator.
  * Maps a contiguous virtual address range to physical frames for the heap.
+ *
+ * The heap starts with HEAP_INITIAL_SIZE mapped and grows on demand: an
+ * allocation that does not fit maps more frames at the top of the range
+ * and extends the allocator over them, up to a ceiling (HEAP_MAX_SIZE
+ * by default, see set_heap_limit()). Memory the heap has grown into is
+ * kept mapped; it is reused but never returned to the frame allocator.
+ *
+ * With the "heap-debug" feature every block is wrapped by heap_debug.rs
+ * (redzones, poisoning, quarantine).
  */
 
-use linked_list_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use linked_list_allocator::Heap;
+use spin::Mutex;
 use x86_64::VirtAddr;
 use x86_64::structures::paging::{
-	FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame, Size4KiB,
+	FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB,
 };
 
 /* Kernel heap virtual address range (must be in higher half for user page table compatibility) */
 const HEAP_START: usize = 0xFFFF_C000_0000_0000;
-const HEAP_SIZE: usize = 1024 * 1024; /* 1 MiB heap */
+const HEAP_INITIAL_SIZE: usize = 1024 * 1024; /* 1 MiB mapped at boot */
+const HEAP_VA_SIZE: usize = 64 * 1024 * 1024 * 1024; /* Reserved range, caps the limit */
+
+/* Default ceiling for the heap's growth */
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+/* Smallest step the heap grows by, so small allocations don't map page by page */
+const HEAP_GROW_MIN: usize = 64 * 1024;
+
+const HEAP_PAGE_FLAGS: PageTableFlags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+
+/*
+ * struct HeapStats - Kernel heap statistics
+ * @size: Bytes mapped for the heap
+ * @used: Bytes allocated
+ * @limit: Most bytes the heap may grow to
+ * @grows: Times the heap was extended after boot
+ * @failed: Allocations refused even after trying to grow
+ */
+#[derive(Clone, Copy)]
+pub struct HeapStats {
+	pub size: usize,
+	pub used: usize,
+	pub limit: usize,
+	pub grows: usize,
+	pub failed: usize,
+}
 
-/* Maximum number of boot frames to pre-allocate */
-pub const MAX_BOOT_FRAMES: usize = 65536;
+/*
+ * struct HeapInner - State behind the heap lock
+ * @heap: The linked list allocator over [HEAP_START, HEAP_START + @mapped)
+ * @mapped: Bytes of the heap range backed by frames
+ * @limit, @grows, @failed: As in HeapStats
+ */
+struct HeapInner {
+	heap: Heap,
+	mapped: usize,
+	limit: usize,
+	grows: usize,
+	failed: usize,
+}
 
-/* Static array of pre-allocated physical frames */
-pub static mut BOOT_FRAMES: [Option<PhysFrame>; MAX_BOOT_FRAMES] = [None; MAX_BOOT_FRAMES];
+/*
+ * struct KernelHeap - The kernel's global allocator
+ */
+pub struct KernelHeap {
+	inner: Mutex<HeapInner>,
+}
 
 /* Global heap allocator instance */
 #[global_allocator]
-pub static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+pub static HEAP_ALLOCATOR: KernelHeap = KernelHeap {
+	inner: Mutex::new(HeapInner {
+		heap: Heap::empty(),
+		mapped: 0,
+		limit: HEAP_MAX_SIZE,
+		grows: 0,
+		failed: 0,
+	}),
+};
 
 /*
  * init_heap - Initialize the kernel heap
@@ -38,7 +99,7 @@ pub fn init_heap(
 ) {
 	let page_range = {
 		let heap_start = VirtAddr::new(HEAP_START as u64);
-		let heap_end = VirtAddr::new((HEAP_START + HEAP_SIZE - 1) as u64);
+		let heap_end = VirtAddr::new((HEAP_START + HEAP_INITIAL_SIZE - 1) as u64);
 		let start_page = Page::containing_address(heap_start);
 		let end_page = Page::containing_address(heap_end);
 		Page::range_inclusive(start_page, end_page)
@@ -49,57 +110,148 @@ pub fn init_heap(
 		let frame = frame_allocator
 			.allocate_frame()
 			.expect("No frames available");
-		let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
 		unsafe {
 			mapper
-				.map_to(page, frame, flags, frame_allocator)
+				.map_to(page, frame, HEAP_PAGE_FLAGS, frame_allocator)
 				.expect("Mapping failed")
 				.flush();
 		}
 	}
 
 	/* Initialize the heap allocator */
+	let mut inner = HEAP_ALLOCATOR.inner.lock();
 	unsafe {
-		HEAP_ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
+		inner.heap.init(HEAP_START as *mut u8, HEAP_INITIAL_SIZE);
 	}
+	inner.mapped = HEAP_INITIAL_SIZE;
 }
 
-/*
- * struct StaticBootFrameAllocator - Frame allocator using static array
- * @next: Index of next frame to allocate
- * @limit: Total number of frames available
- *
- * Allocates from the pre-populated BOOT_FRAMES array.
- */
-pub struct StaticBootFrameAllocator {
-	next: usize,
-	limit: usize,
-}
-
-impl StaticBootFrameAllocator {
+impl HeapInner {
 	/*
-	 * new - Create a frame allocator
-	 * @frame_count: Number of frames available in BOOT_FRAMES
+	 * grow - Map more pages at the top of the heap
+	 * @need: Bytes the failed allocation needs
+	 *
+	 * Grows by at least HEAP_GROW_MIN, never past @limit. The page
+	 * allocator is only try-locked: the heap can be entered while it is
+	 * held (a Vec growing during a mapping), and waiting would deadlock.
+	 *
+	 * Return: true if any pages were added
 	 */
-	pub fn new(frame_count: usize) -> Self {
-		StaticBootFrameAllocator {
-			next: 0,
-			limit: frame_count,
+	fn grow(&mut self, need: usize) -> bool {
+		let want = (need.max(HEAP_GROW_MIN) + 0xFFF) & !0xFFF;
+		let by = want.min(self.limit.saturating_sub(self.mapped));
+		if by < need {
+			return false;
+		}
+		let Some(pa) = crate::PAGE_ALLOC.get() else { return false };
+		let Some(mut guard) = pa.try_lock() else { return false };
+		let pa = &mut *guard;
+
+		let mut added = 0;
+		while added < by {
+			let addr = VirtAddr::new((HEAP_START + self.mapped + added) as u64);
+			let page = Page::<Size4KiB>::containing_address(addr);
+			let Some(frame) = pa.frame_alloc.allocate_frame() else { break };
+			match unsafe { pa.mapper.map_to(page, frame, HEAP_PAGE_FLAGS, &mut pa.frame_alloc) } {
+				Ok(flush) => flush.flush(),
+				Err(_) => {
+					unsafe { pa.frame_alloc.deallocate_frame(frame) };
+					break;
+				}
+			}
+			added += 4096;
+		}
+		if added == 0 {
+			return false;
 		}
+		unsafe { self.heap.extend(added) };
+		self.mapped += added;
+		self.grows += 1;
+		true
 	}
 }
 
-unsafe impl FrameAllocator<Size4KiB> for StaticBootFrameAllocator {
-	fn allocate_frame(&mut self) -> Option<PhysFrame> {
-		while self.next < self.limit {
-			unsafe {
-				if let Some(frame) = BOOT_FRAMES[self.next].take() {
-					self.next += 1;
-					return Some(frame);
-				}
+impl KernelHeap {
+	fn alloc_raw(&self, layout: Layout) -> *mut u8 {
+		let mut inner = self.inner.lock();
+		if let Ok(ptr) = inner.heap.allocate_first_fit(layout) {
+			return ptr.as_ptr();
+		}
+		/* Enough for the block wherever its alignment lands in the new pages */
+		if inner.grow(layout.size() + layout.align()) {
+			if let Ok(ptr) = inner.heap.allocate_first_fit(layout) {
+				return ptr.as_ptr();
 			}
-			self.next += 1;
 		}
-		None
+		inner.failed += 1;
+		ptr::null_mut()
+	}
+
+	fn dealloc_raw(&self, ptr: *mut u8, layout: Layout) {
+		unsafe {
+			self.inner.lock().heap.deallocate(NonNull::new_unchecked(ptr), layout);
+		}
+	}
+}
+
+/*
+ * The heap-debug hooks are inlined into the allocator shims so the
+ * return address they record is that of the shim's caller. That is
+ * __rust_alloc's caller, usually inside liballoc (RawVec, Box, Arc)
+ * rather than kernel code; see heap_debug.rs.
+ */
+unsafe impl GlobalAlloc for KernelHeap {
+	#[inline(always)]
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		#[cfg(feature = "heap-debug")]
+		return crate::heap_debug::alloc(layout, core::intrinsics::return_address() as usize, |l| {
+			self.alloc_raw(l)
+		});
+		#[cfg(not(feature = "heap-debug"))]
+		self.alloc_raw(layout)
+	}
+
+	#[inline(always)]
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		#[cfg(feature = "heap-debug")]
+		crate::heap_debug::dealloc(ptr, layout, core::intrinsics::return_address() as usize, |p, l| {
+			self.dealloc_raw(p, l)
+		});
+		#[cfg(not(feature = "heap-debug"))]
+		self.dealloc_raw(ptr, layout)
+	}
+}
+
+/*
+ * contains - Check whether a pointer lies in the kernel heap range
+ * @ptr: Pointer to test
+ */
+pub fn contains(ptr: *const u8) -> bool {
+	(HEAP_START..HEAP_START + HEAP_VA_SIZE).contains(&(ptr as usize))
+}
+
+/*
+ * set_heap_limit - Change how far the kernel heap may grow
+ * @bytes: New ceiling; rounded up to a page, never below what is
+ *         already mapped nor above the reserved range
+ *
+ * Set from kshell's `heaplimit` command.
+ */
+pub fn set_heap_limit(bytes: usize) {
+	let mut inner = HEAP_ALLOCATOR.inner.lock();
+	inner.limit = ((bytes + 0xFFF) & !0xFFF).clamp(inner.mapped, HEAP_VA_SIZE);
+}
+
+/*
+ * heap_stats - Kernel heap statistics
+ */
+pub fn heap_stats() -> HeapStats {
+	let inner = HEAP_ALLOCATOR.inner.lock();
+	HeapStats {
+		size: inner.mapped,
+		used: inner.heap.used(),
+		limit: inner.limit,
+		grows: inner.grows,
+		failed: inner.failed,
 	}
 }
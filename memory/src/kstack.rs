@@ -8,13 +8,20 @@
  * Allocates from a fixed bump pointer in the range 0xFFFF_B000_0000_0000,
  * just below SLUB_VA_START. These addresses are in the shared kernel-upper
  * half and will be inherited by all user page tables via PML4 entry 511.
+ *
+ * Stacks of exited tasks are unmapped by free_kernel_stack(), which
+ * returns their frames and keeps the range for the next stack of the
+ * same size.
  */
 
+use alloc::vec::Vec;
 use spin::Mutex;
-use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB};
+use x86_64::structures::paging::{
+	FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB,
+};
 use x86_64::{PhysAddr, VirtAddr};
 
-use crate::heap::StaticBootFrameAllocator;
+use crate::buddy::BuddyAllocator;
 
 /* Kernel stack region: just below SLUB */
 pub const KSTACK_VA_START: u64 = 0xFFFF_B000_0000_0000;
@@ -24,6 +31,7 @@ pub const KSTACK_REGION_SIZE: u64 = KSTACK_VA_END - KSTACK_VA_START;
 /*
  * struct KStackAllocator - Simple bump allocator for kernel stacks
  * @next_va: Next virtual address to allocate
+ * @free: Unmapped (base, size) ranges of freed stacks, reused first
  *
  * Allocates kernel stacks from a fixed range. The region is pre-mapped
  * by the kernel, so all allocations are immediately visible.
@@ -32,12 +40,14 @@ pub const KSTACK_REGION_SIZE: u64 = KSTACK_VA_END - KSTACK_VA_START;
  */
 pub struct KStackAllocator {
 	next_va: u64,
+	free: Vec<(u64, usize)>,
 }
 
 impl KStackAllocator {
 	pub fn new() -> Self {
 		KStackAllocator {
 			next_va: KSTACK_VA_START,
+			free: Vec::new(),
 		}
 	}
 
@@ -86,6 +96,7 @@ impl KStackAllocator {
 
 static KSTACK_ALLOC: Mutex<KStackAllocator> = Mutex::new(KStackAllocator {
 	next_va: KSTACK_VA_START,
+	free: Vec::new(),
 });
 
 /*
@@ -117,12 +128,17 @@ pub fn alloc_kernel_stack(size: usize) -> Option<VirtAddr> {
 		return None;
 	}
 
-	let base = alloc.next_va;
-	if base + size as u64 > KSTACK_VA_END {
-		return None; /* Out of stack space */
-	}
-
-	alloc.next_va += size as u64;
+	let base = match alloc.free.iter().position(|&(_, s)| s == size) {
+		Some(i) => alloc.free.swap_remove(i).0,
+		None => {
+			let base = alloc.next_va;
+			if base + size as u64 > KSTACK_VA_END {
+				return None; /* Out of stack space */
+			}
+			alloc.next_va += size as u64;
+			base
+		}
+	};
 	let stack_top = base + size as u64;
 
 	/* Release the alloc lock before taking PAGE_ALLOC lock to avoid deadlock */
@@ -151,7 +167,7 @@ pub fn alloc_kernel_stack(size: usize) -> Option<VirtAddr> {
 			unsafe {
 				/* Use raw pointers to work around borrow checker limitations */
 				let mapper_ptr: *mut OffsetPageTable = &mut pa_guard.mapper as *mut _;
-				let frame_alloc_ptr: *mut StaticBootFrameAllocator =
+				let frame_alloc_ptr: *mut BuddyAllocator =
 					&mut pa_guard.frame_alloc as *mut _;
 
 				(*mapper_ptr)
@@ -164,3 +180,28 @@ pub fn alloc_kernel_stack(size: usize) -> Option<VirtAddr> {
 
 	Some(VirtAddr::new(stack_top))
 }
+
+/*
+ * free_kernel_stack - Unmap a kernel stack and return its frames
+ * @top: Stack top returned by alloc_kernel_stack()
+ * @size: Size it was allocated with
+ *
+ * The stack must be dead: its task has switched away for good and
+ * nothing will run on it again.
+ */
+pub fn free_kernel_stack(top: VirtAddr, size: usize) {
+	let Some(pa) = crate::PAGE_ALLOC.get() else { return };
+	let base = top.as_u64() - size as u64;
+	{
+		let mut guard = pa.lock();
+		let pa_guard = &mut *guard;
+		for i in 0..size / 4096 {
+			let page = Page::<Size4KiB>::containing_address(VirtAddr::new(base + (i as u64) * 4096));
+			if let Ok((frame, flush)) = pa_guard.mapper.unmap(page) {
+				flush.flush();
+				unsafe { pa_guard.frame_alloc.deallocate_frame(frame) };
+			}
+		}
+	}
+	KSTACK_ALLOC.lock().free.push((base, size));
+}
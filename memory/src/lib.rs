@@ -5,19 +5,27 @@
  */
 
 #![no_std]
+#![feature(allocator_api)]
+#![cfg_attr(feature = "heap-debug", feature(core_intrinsics))]
+#![cfg_attr(feature = "heap-debug", allow(internal_features))]
 extern crate alloc;
+pub mod buddy;
+pub mod frame_ref;
 pub mod heap;
+#[cfg(feature = "heap-debug")]
+pub mod heap_debug;
+pub mod slab;
 pub mod slub;
+pub mod swap;
 pub mod kstack;
+pub mod uvm;
 
-use alloc::boxed::Box;
-use limine::memory_map::Entry;
 use spin::{Mutex, Once};
 use x86_64::registers::control::Cr3;
 use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB};
-use x86_64::{PhysAddr, VirtAddr};
+use x86_64::VirtAddr;
 
-use crate::heap::StaticBootFrameAllocator;
+use crate::buddy::BuddyAllocator;
 
 /*
  * struct PageAllocator - Global page mapping resources
@@ -30,11 +38,14 @@ use crate::heap::StaticBootFrameAllocator;
  */
 pub struct PageAllocator {
 	pub mapper: OffsetPageTable<'static>,
-	pub frame_alloc: StaticBootFrameAllocator,
+	pub frame_alloc: BuddyAllocator,
 }
 
 pub static PAGE_ALLOC: Once<Mutex<PageAllocator>> = Once::new();
 
+/* PML4 the kernel booted on, for tasks that have no address space of their own */
+static KERNEL_PML4: Once<PhysFrame> = Once::new();
+
 static HHDM_OFFSET: Once<VirtAddr> = Once::new();
 
 pub fn set_hhdm_offset(offset: VirtAddr) {
@@ -48,18 +59,30 @@ pub fn hhdm_offset() -> VirtAddr {
 /*
  * init_page_allocator - Store mapper and frame allocator globally
  * @mapper:     OffsetPageTable for the active address space
- * @frame_alloc: Boot frame allocator with remaining frames
+ * @frame_alloc: Buddy allocator holding the remaining frames
  *
  * Call after all early MMIO mappings are done. Transfers ownership
- * so the SLUB and other subsystems can allocate pages on demand.
+ * so the SLUB and other subsystems can allocate pages on demand. The
+ * active PML4 is remembered as the kernel's (see kernel_pml4()).
  */
 pub fn init_page_allocator(
 	mapper: OffsetPageTable<'static>,
-	frame_alloc: StaticBootFrameAllocator,
+	frame_alloc: BuddyAllocator,
 ) {
+	KERNEL_PML4.call_once(|| Cr3::read().0);
 	PAGE_ALLOC.call_once(|| Mutex::new(PageAllocator { mapper, frame_alloc }));
 }
 
+/*
+ * kernel_pml4 - The kernel's own PML4
+ *
+ * Kernel tasks run on whatever CR3 they find; before a user address
+ * space is freed, CR3 is moved here if it still points at it.
+ */
+pub fn kernel_pml4() -> PhysFrame {
+	*KERNEL_PML4.get().expect("page allocator not initialised")
+}
+
 /*
  * active_level_table - Get mutable reference to active level-4 page table
  * @offset: Higher Half Direct Map offset
@@ -83,60 +106,6 @@ pub unsafe fn init_offset_page_table(offset: VirtAddr) -> OffsetPageTable<'stati
 	OffsetPageTable::new(active_level_table(offset), offset)
 }
 
-/*
- * struct BootFrameAllocator - Frame allocator using heap-allocated array
- * @frames: Static slice of available physical frames
- * @next: Index of next frame to allocate
- *
- * Allocates physical frames from a pre-populated list.
- */
-pub struct BootFrameAllocator {
-	frames: &'static [PhysFrame],
-	next: usize,
-}
-
-impl BootFrameAllocator {
-	/*
-	 * new - Create a frame allocator from memory map
-	 * @memory_map: Array of Limine memory map entries
-	 *
-	 * Collects all usable physical frames from the memory map.
-	 */
-	pub fn new(memory_map: &[&Entry]) -> Self {
-		let mut frames = alloc::vec::Vec::new();
-		for region in memory_map
-			.iter()
-			.filter(|r| r.entry_type == limine::memory_map::EntryType::USABLE)
-		{
-			let start = region.base;
-			let end = region.base + region.length;
-			let start_frame = PhysFrame::containing_address(PhysAddr::new(start));
-			let end_frame = PhysFrame::containing_address(PhysAddr::new(end - 1));
-			for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
-				frames.push(frame);
-			}
-		}
-		let boxed = frames.into_boxed_slice();
-		let static_frames = Box::leak(boxed);
-		BootFrameAllocator {
-			frames: static_frames,
-			next: 0,
-		}
-	}
-}
-
-unsafe impl FrameAllocator<Size4KiB> for BootFrameAllocator {
-	fn allocate_frame(&mut self) -> Option<PhysFrame> {
-		if self.next < self.frames.len() {
-			let frame = self.frames[self.next];
-			self.next += 1;
-			Some(frame)
-		} else {
-			None
-		}
-	}
-}
-
 pub unsafe fn create_user_page_table(
 	frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 	offset: VirtAddr,
@@ -0,0 +1,425 @@
+/*
+ * slab.rs - Slab Caches for Small Kernel Objects
+ *
+ * A KmemCache hands out objects of one type (one size and alignment),
+ * packed into slabs: physically contiguous buddy blocks reached through
+ * the HHDM, each starting with a Slab header followed by as many
+ * objects as fit. Free objects of a slab are chained through a link
+ * word, so alloc() and free() are a pop and a push.
+ *
+ * Each cache keeps its slabs on three lists: partial (some objects
+ * free, allocated from first), full, and empty. One empty slab is kept
+ * to absorb alloc/free ping-pong; further ones go back to the buddy
+ * allocator.
+ *
+ * A cache can run a constructor over every object when its slab is
+ * created. Objects must then be freed in their constructed state, and
+ * the link word moves behind the object so it does not clobber it.
+ *
+ * KmemCache implements core::alloc::Allocator, so a static cache puts
+ * an Arc or Box on slabs: Arc::new_in(value, &TASK_CACHE). When the
+ * layout does not fit the cache, or no slab can be had (the page
+ * allocator is busy or out of memory), the object comes from the
+ * kernel heap instead and is returned there on free.
+ *
+ * Page-granular blocks (kernel stacks) are served by slub.rs.
+ */
+
+use alloc::alloc::Global;
+use alloc::vec::Vec;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+use spin::Mutex;
+
+use crate::buddy::MAX_ORDER;
+
+/* Largest slab: 2^3 frames = 32 KiB */
+const SLAB_MAX_ORDER: usize = 3;
+
+/* Fewest objects worth a slab; larger objects get larger slabs */
+const SLAB_MIN_OBJECTS: usize = 8;
+
+/*
+ * struct Slab - Header at the start of every slab
+ * @next: Next slab on the same list
+ * @prev: Previous slab on the same list
+ * @free: First free object, or null when the slab is full
+ * @inuse: Objects handed out
+ */
+#[repr(C)]
+struct Slab {
+	next: *mut Slab,
+	prev: *mut Slab,
+	free: *mut u8,
+	inuse: usize,
+}
+
+/*
+ * struct SlabList - Doubly linked list of slabs
+ * @head: First slab, or null
+ * @len: Number of slabs on the list
+ */
+struct SlabList {
+	head: *mut Slab,
+	len: usize,
+}
+
+impl SlabList {
+	const fn new() -> Self {
+		SlabList { head: ptr::null_mut(), len: 0 }
+	}
+
+	unsafe fn push(&mut self, slab: *mut Slab) {
+		unsafe {
+			(*slab).prev = ptr::null_mut();
+			(*slab).next = self.head;
+			if !self.head.is_null() {
+				(*self.head).prev = slab;
+			}
+		}
+		self.head = slab;
+		self.len += 1;
+	}
+
+	unsafe fn remove(&mut self, slab: *mut Slab) {
+		unsafe {
+			let (next, prev) = ((*slab).next, (*slab).prev);
+			if prev.is_null() {
+				self.head = next;
+			} else {
+				(*prev).next = next;
+			}
+			if !next.is_null() {
+				(*next).prev = prev;
+			}
+		}
+		self.len -= 1;
+	}
+}
+
+/*
+ * struct CacheInner - Mutable state of a cache, behind its lock
+ * @partial, @full, @empty: Slabs by occupancy
+ * @active: Objects handed out from slabs
+ * @allocs: Objects allocated from slabs since boot
+ * @frees: Objects returned to slabs since boot
+ * @fallbacks: Allocations served by the kernel heap instead
+ * @registered: Cache is listed in CACHES
+ */
+struct CacheInner {
+	partial: SlabList,
+	full: SlabList,
+	empty: SlabList,
+	active: usize,
+	allocs: u64,
+	frees: u64,
+	fallbacks: u64,
+	registered: bool,
+}
+
+/* Slab pointers are only followed under the cache lock */
+unsafe impl Send for CacheInner {}
+
+/*
+ * struct KmemCache - Named cache of same-sized objects
+ * @name: Shown in /proc/slabinfo
+ * @size: Object size in bytes
+ * @align: Object alignment (at least 8)
+ * @stride: Distance between objects in a slab
+ * @link: Offset of the free-list link inside an object slot
+ * @first: Offset of the first object from the slab start
+ * @order: Slab size as a buddy order
+ * @per_slab: Objects per slab
+ * @ctor: Constructor run on each object when its slab is created
+ * @inner: Slab lists and counters
+ */
+pub struct KmemCache {
+	name: &'static str,
+	size: usize,
+	align: usize,
+	stride: usize,
+	link: usize,
+	first: usize,
+	order: usize,
+	per_slab: usize,
+	ctor: Option<fn(*mut u8)>,
+	inner: Mutex<CacheInner>,
+}
+
+/*
+ * struct CacheStats - Snapshot of one cache, for /proc/slabinfo
+ */
+#[derive(Clone, Copy)]
+pub struct CacheStats {
+	pub name: &'static str,
+	pub object_size: usize,
+	pub per_slab: usize,
+	pub pages_per_slab: usize,
+	pub slabs: usize,
+	pub active: usize,
+	pub total: usize,
+	pub allocs: u64,
+	pub frees: u64,
+	pub fallbacks: u64,
+}
+
+/* Every cache that has had a slab, in order of first use */
+static CACHES: Mutex<Vec<&'static KmemCache>> = Mutex::new(Vec::new());
+
+const fn align_up(x: usize, align: usize) -> usize {
+	(x + align - 1) & !(align - 1)
+}
+
+/*
+ * arc_layout - Size and alignment Arc::new_in() asks for to hold a T
+ *
+ * The two reference counts come first, then the value.
+ *
+ * Return: (size, align) to create a cache for Arc<T> with
+ */
+pub const fn arc_layout<T>() -> (usize, usize) {
+	let align = if align_of::<T>() > align_of::<usize>() { align_of::<T>() } else { align_of::<usize>() };
+	let offset = align_up(2 * size_of::<usize>(), align);
+	(align_up(offset + size_of::<T>(), align), align)
+}
+
+impl KmemCache {
+	/*
+	 * new - Describe a cache
+	 * @name: Name for /proc/slabinfo
+	 * @size: Object size in bytes
+	 * @align: Object alignment, a power of two
+	 * @ctor: Optional constructor, run on each object of a new slab
+	 *
+	 * Nothing is allocated until the first object is; caches are meant
+	 * to be statics.
+	 */
+	pub const fn new(name: &'static str, size: usize, align: usize, ctor: Option<fn(*mut u8)>) -> Self {
+		let align = if align < 8 { 8 } else { align };
+		let size = if size == 0 { 1 } else { size };
+		/* Without a constructor a free object's first word holds the link */
+		let link = if ctor.is_some() { align_up(size, 8) } else { 0 };
+		let slot = if ctor.is_some() { link + 8 } else if size < 8 { 8 } else { size };
+		let stride = align_up(slot, align);
+		let first = align_up(size_of::<Slab>(), align);
+
+		let mut order = 0;
+		while order < SLAB_MAX_ORDER && ((4096 << order) - first) / stride < SLAB_MIN_OBJECTS {
+			order += 1;
+		}
+		let per_slab = ((4096 << order) - first) / stride;
+		assert!(order <= MAX_ORDER && per_slab > 0, "slab object too large");
+
+		KmemCache {
+			name,
+			size,
+			align,
+			stride,
+			link,
+			first,
+			order,
+			per_slab,
+			ctor,
+			inner: Mutex::new(CacheInner {
+				partial: SlabList::new(),
+				full: SlabList::new(),
+				empty: SlabList::new(),
+				active: 0,
+				allocs: 0,
+				frees: 0,
+				fallbacks: 0,
+				registered: false,
+			}),
+		}
+	}
+
+	/* name - The cache's name */
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	fn slab_bytes(&self) -> u64 {
+		4096 << self.order
+	}
+
+	fn link_of(&self, obj: *mut u8) -> *mut *mut u8 {
+		obj.wrapping_add(self.link) as *mut *mut u8
+	}
+
+	/*
+	 * slab_of - Slab holding an object
+	 *
+	 * Slabs are buddy blocks, so they are aligned to their size in
+	 * physical memory.
+	 */
+	fn slab_of(&self, obj: *mut u8) -> *mut Slab {
+		let hhdm = crate::hhdm_offset().as_u64();
+		let phys = obj as u64 - hhdm;
+		(hhdm + (phys & !(self.slab_bytes() - 1))) as *mut Slab
+	}
+
+	/*
+	 * new_slab - Take a block from the buddy allocator and carve it up
+	 *
+	 * Return: The slab with every object free, or None if no block was
+	 *         available (or the page allocator is held by this CPU)
+	 */
+	fn new_slab(&self) -> Option<*mut Slab> {
+		let frame = crate::PAGE_ALLOC.get()?.try_lock()?.frame_alloc.alloc(self.order)?;
+		let base = (crate::hhdm_offset() + frame.start_address().as_u64()).as_mut_ptr::<u8>();
+
+		let mut free = ptr::null_mut();
+		for i in (0..self.per_slab).rev() {
+			let obj = base.wrapping_add(self.first + i * self.stride);
+			if let Some(ctor) = self.ctor {
+				ctor(obj);
+			}
+			unsafe { self.link_of(obj).write(free) };
+			free = obj;
+		}
+		let slab = base as *mut Slab;
+		unsafe {
+			slab.write(Slab { next: ptr::null_mut(), prev: ptr::null_mut(), free, inuse: 0 });
+		}
+		Some(slab)
+	}
+
+	/*
+	 * release_slab - Give an empty slab back to the buddy allocator
+	 *
+	 * Return: false if the page allocator was busy; the slab is untouched
+	 */
+	fn release_slab(&self, slab: *mut Slab) -> bool {
+		let Some(pa) = crate::PAGE_ALLOC.get() else { return false };
+		let Some(mut pa) = pa.try_lock() else { return false };
+		let phys = slab as u64 - crate::hhdm_offset().as_u64();
+		let frame = x86_64::structures::paging::PhysFrame::containing_address(x86_64::PhysAddr::new(phys));
+		unsafe { pa.frame_alloc.free(frame, self.order) };
+		true
+	}
+
+	/*
+	 * alloc - Take an object from the cache
+	 *
+	 * Partial slabs are used first, then the kept empty slab, then a
+	 * new one.
+	 *
+	 * Return: Uninitialised object (or constructed, with a ctor), or
+	 *         None if no slab could be had
+	 */
+	pub fn alloc(&self) -> Option<NonNull<u8>> {
+		let mut inner = self.inner.lock();
+		let slab = if !inner.partial.head.is_null() {
+			inner.partial.head
+		} else if !inner.empty.head.is_null() {
+			let slab = inner.empty.head;
+			unsafe {
+				inner.empty.remove(slab);
+				inner.partial.push(slab);
+			}
+			slab
+		} else {
+			let slab = self.new_slab()?;
+			if !inner.registered {
+				inner.registered = true;
+				/* Caches are statics (see new()) */
+				CACHES.lock().push(unsafe { &*(self as *const Self) });
+			}
+			unsafe { inner.partial.push(slab) };
+			slab
+		};
+
+		unsafe {
+			let obj = (*slab).free;
+			(*slab).free = self.link_of(obj).read();
+			(*slab).inuse += 1;
+			if (*slab).free.is_null() {
+				inner.partial.remove(slab);
+				inner.full.push(slab);
+			}
+			inner.active += 1;
+			inner.allocs += 1;
+			Some(NonNull::new_unchecked(obj))
+		}
+	}
+
+	/*
+	 * free - Return an object to its slab
+	 * @obj: Object from alloc() on this cache
+	 *
+	 * Safety: @obj must be allocated from this cache and no longer used;
+	 * with a constructor it must be back in its constructed state.
+	 */
+	pub unsafe fn free(&self, obj: NonNull<u8>) {
+		let obj = obj.as_ptr();
+		let slab = self.slab_of(obj);
+		let mut inner = self.inner.lock();
+		unsafe {
+			let was_full = (*slab).free.is_null();
+			self.link_of(obj).write((*slab).free);
+			(*slab).free = obj;
+			(*slab).inuse -= 1;
+			inner.active -= 1;
+			inner.frees += 1;
+			if was_full {
+				inner.full.remove(slab);
+				inner.partial.push(slab);
+			}
+			if (*slab).inuse == 0 {
+				inner.partial.remove(slab);
+				if inner.empty.len == 0 || !self.release_slab(slab) {
+					inner.empty.push(slab);
+				}
+			}
+		}
+	}
+
+	/*
+	 * stats - Snapshot of the cache's counters
+	 */
+	pub fn stats(&self) -> CacheStats {
+		let inner = self.inner.lock();
+		let slabs = inner.partial.len + inner.full.len + inner.empty.len;
+		CacheStats {
+			name: self.name,
+			object_size: self.size,
+			per_slab: self.per_slab,
+			pages_per_slab: 1 << self.order,
+			slabs,
+			active: inner.active,
+			total: slabs * self.per_slab,
+			allocs: inner.allocs,
+			frees: inner.frees,
+			fallbacks: inner.fallbacks,
+		}
+	}
+}
+
+unsafe impl Allocator for KmemCache {
+	fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+		if layout.size() <= self.size && layout.align() <= self.align {
+			if let Some(obj) = self.alloc() {
+				return Ok(NonNull::slice_from_raw_parts(obj, layout.size()));
+			}
+		}
+		self.inner.lock().fallbacks += 1;
+		Global.allocate(layout)
+	}
+
+	unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+		if crate::heap::contains(ptr.as_ptr()) {
+			unsafe { Global.deallocate(ptr, layout) };
+		} else {
+			unsafe { self.free(ptr) };
+		}
+	}
+}
+
+/*
+ * stats - Snapshot of every cache in use
+ */
+pub fn stats() -> Vec<CacheStats> {
+	let caches: Vec<&'static KmemCache> = CACHES.lock().clone();
+	caches.iter().map(|c| c.stats()).collect()
+}
@@ -11,10 +11,17 @@
  *
  * Size classes: 4K, 8K, 16K, 32K, 64K, 128K, 256K, 512K, 1M
  *
+ * With the "heap-debug" feature, blocks are poisoned on free, checked on
+ * reuse and reused oldest first (see heap_debug.rs).
+ *
  * TODO(SMP): Per-CPU magazine caches to reduce lock contention
  */
 
+#[cfg(feature = "heap-debug")]
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+#[cfg(feature = "heap-debug")]
+use core::panic::Location;
 use spin::{Mutex, Once};
 use x86_64::VirtAddr;
 use x86_64::structures::paging::{
@@ -56,6 +63,8 @@ const SLUB_VA_START: u64 = 0xFFFF_D000_0000_0000;
  * struct SlubCache - Free list for a single size class
  * @size:      Allocation size in bytes (matches SIZE_CLASSES entry)
  * @free_list: Previously freed blocks available for reuse
+ * @sites:     heap-debug: who last allocated or freed each block, and the
+ *             size asked for when it was allocated
  *
  * Blocks on the free list retain their virtual-to-physical mappings.
  * Reallocation is O(1) pop from the Vec tail.
@@ -63,6 +72,8 @@ const SLUB_VA_START: u64 = 0xFFFF_D000_0000_0000;
 struct SlubCache {
 	size: usize,
 	free_list: Vec<*mut u8>,
+	#[cfg(feature = "heap-debug")]
+	sites: BTreeMap<usize, (&'static Location<'static>, usize)>,
 }
 
 impl SlubCache {
@@ -70,6 +81,8 @@ impl SlubCache {
 		SlubCache {
 			size,
 			free_list: Vec::new(),
+			#[cfg(feature = "heap-debug")]
+			sites: BTreeMap::new(),
 		}
 	}
 }
@@ -126,14 +139,24 @@ impl SlubAllocator {
 	 *
 	 * Safety: Caller must eventually call free() with the same size class
 	 */
+	#[cfg_attr(feature = "heap-debug", track_caller)]
 	pub fn alloc(&mut self, size: usize) -> Option<*mut u8> {
 		let idx = Self::size_class_index(size)?;
 		let cache = &mut self.caches[idx];
 
 		/* Fast path: reuse a cached block */
+		#[cfg(not(feature = "heap-debug"))]
 		if let Some(ptr) = cache.free_list.pop() {
 			return Some(ptr);
 		}
+		/* Oldest block first, so a freed block stays poisoned as long as possible */
+		#[cfg(feature = "heap-debug")]
+		if !cache.free_list.is_empty() {
+			let ptr = cache.free_list.remove(0);
+			let freed_by = cache.sites.insert(ptr as usize, (Location::caller(), size));
+			crate::heap_debug::slub_alloc(ptr, cache.size, size, true, freed_by.map(|(at, _)| at));
+			return Some(ptr);
+		}
 
 		/* Slow path: allocate and map new pages */
 		let alloc_size = cache.size;
@@ -162,6 +185,11 @@ impl SlubAllocator {
 		}
 
 		self.next_va += alloc_size as u64;
+		#[cfg(feature = "heap-debug")]
+		{
+			self.caches[idx].sites.insert(va_start as usize, (Location::caller(), size));
+			crate::heap_debug::slub_alloc(va_start as *mut u8, alloc_size, size, false, None);
+		}
 		Some(va_start as *mut u8)
 	}
 
@@ -175,6 +203,7 @@ impl SlubAllocator {
 	 *
 	 * Safety: ptr must have been returned by alloc() with matching size class
 	 */
+	#[cfg_attr(feature = "heap-debug", track_caller)]
 	pub fn free(&mut self, ptr: *mut u8, size: usize) {
 		let idx = match Self::size_class_index(size) {
 			Some(i) => i,
@@ -182,6 +211,20 @@ impl SlubAllocator {
 		};
 		debug_assert!(ptr as u64 >= SLUB_VA_START);
 		debug_assert!((ptr as u64) % 4096 == 0);
+		#[cfg(feature = "heap-debug")]
+		{
+			let cache = &mut self.caches[idx];
+			if cache.free_list.contains(&ptr) {
+				panic!("heap-debug: SLUB block {:p} freed twice", ptr);
+			}
+			/* The redzone starts after the size asked for at allocation */
+			let allocated = cache.sites.insert(ptr as usize, (Location::caller(), size));
+			let (allocated_by, live_size) = match allocated {
+				Some((at, live_size)) => (Some(at), live_size),
+				None => (None, size),
+			};
+			crate::heap_debug::slub_free(ptr, cache.size, live_size, allocated_by);
+		}
 		self.caches[idx].free_list.push(ptr);
 	}
 }
@@ -199,6 +242,19 @@ pub fn init() {
 	SLUB.call_once(|| Mutex::new(SlubAllocator::new()));
 }
 
+/*
+ * usage - SLUB occupancy
+ *
+ * Return: (bytes mapped, bytes of those sitting free in the caches),
+ *         or None before init()
+ */
+pub fn usage() -> Option<(usize, usize)> {
+	let slub = SLUB.get()?.lock();
+	let mapped = (slub.next_va - SLUB_VA_START) as usize;
+	let cached = slub.caches.iter().map(|c| c.size * c.free_list.len()).sum();
+	Some((mapped, cached))
+}
+
 /*
  * alloc_kernel_object - Allocate a kernel object
  * @size: Size in bytes
@@ -208,6 +264,7 @@ pub fn init() {
  *
  * Return: Pointer to zeroed memory, or None on OOM
  */
+#[cfg_attr(feature = "heap-debug", track_caller)]
 pub fn alloc_kernel_object(size: usize) -> Option<*mut u8> {
 	if size < 4096 {
 		let layout = core::alloc::Layout::from_size_align(size, 8).ok()?;
@@ -240,6 +297,7 @@ pub fn alloc_kernel_object(size: usize) -> Option<*mut u8> {
  * Return: Stack top VirtAddr (caller passes this to TaskCB::new),
  *         or None on OOM
  */
+#[cfg_attr(feature = "heap-debug", track_caller)]
 pub fn alloc_kernel_stack(size: usize) -> Option<VirtAddr> {
 	let base = alloc_kernel_object(size)? as u64;
 	/* FIXME: Guard page disabled for now (causes page faults during ring 3 entry)
@@ -254,6 +312,7 @@ pub fn alloc_kernel_stack(size: usize) -> Option<VirtAddr> {
 	Some(VirtAddr::new(base + size as u64))
 }
 
+#[cfg_attr(feature = "heap-debug", track_caller)]
 pub fn free_kernel_object(ptr: *mut u8, size: usize) {
 	if size < 4096 {
 		if let Ok(layout) = core::alloc::Layout::from_size_align(size, 8) {
@@ -0,0 +1,127 @@
+/*
+ * swap.rs - Swap Slots and Swap Entries
+ *
+ * A user page that was swapped out keeps a not-present PTE recording
+ * where its contents went: PTE_SWAP set and the slot number in the
+ * address bits. The CPU ignores every other bit of a not-present
+ * entry, so the encoding is free for the kernel to choose.
+ *
+ * This module owns the slot map of the swap area: which slots are in
+ * use and by how many PTEs, since fork copies a swap entry into the
+ * child just like a present page. Reading and writing the slots and
+ * choosing pages to evict is the kernel's job (kernel/src/swap.rs);
+ * uvm.rs only copies and drops swap entries with the page tables.
+ */
+
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::PhysAddr;
+
+/* PTE_SWAP - Software PTE bit marking a not-present swap entry */
+pub const PTE_SWAP: PageTableFlags = PageTableFlags::BIT_10;
+
+/*
+ * struct SlotMap - Usage of the swap area's page slots
+ * @refs: PTEs referencing each slot (0 = free)
+ * @used: Slots with a non-zero count
+ * @hint: Where the search for a free slot starts
+ */
+struct SlotMap {
+	refs: Vec<u32>,
+	used: usize,
+	hint: usize,
+}
+
+static SLOTS: Mutex<SlotMap> = Mutex::new(SlotMap { refs: Vec::new(), used: 0, hint: 0 });
+
+/*
+ * init - Set up the slot map for a new swap area
+ * @slots: Number of page-sized slots in the area
+ *
+ * Return: false if a swap area is already active
+ */
+pub fn init(slots: usize) -> bool {
+	let mut map = SLOTS.lock();
+	if !map.refs.is_empty() {
+		return false;
+	}
+	map.refs = alloc::vec![0; slots];
+	map.used = 0;
+	map.hint = 0;
+	true
+}
+
+/*
+ * alloc_slot - Reserve a free slot for one page
+ *
+ * Return: Slot number with a count of one, or None if the area is full
+ *         or there is none
+ */
+pub fn alloc_slot() -> Option<u64> {
+	let mut map = SLOTS.lock();
+	let len = map.refs.len();
+	let hint = map.hint;
+	let slot = (hint..len).chain(0..hint).find(|&s| map.refs[s] == 0)?;
+	map.refs[slot] = 1;
+	map.used += 1;
+	map.hint = slot + 1;
+	Some(slot as u64)
+}
+
+/*
+ * share - Record one additional swap entry for a slot (fork)
+ * @slot: Slot in use
+ */
+pub fn share(slot: u64) {
+	SLOTS.lock().refs[slot as usize] += 1;
+}
+
+/*
+ * release - Drop one swap entry of a slot
+ * @slot: Slot in use
+ *
+ * The slot becomes free once its last entry is gone.
+ */
+pub fn release(slot: u64) {
+	let mut map = SLOTS.lock();
+	let refs = &mut map.refs[slot as usize];
+	*refs -= 1;
+	if *refs == 0 {
+		map.used -= 1;
+	}
+}
+
+/*
+ * usage - Size and use of the swap area
+ *
+ * Return: (total slots, slots in use); (0, 0) without a swap area
+ */
+pub fn usage() -> (usize, usize) {
+	let map = SLOTS.lock();
+	(map.refs.len(), map.used)
+}
+
+/*
+ * set_entry - Turn a PTE into a swap entry
+ * @entry: Leaf PTE whose page was written to @slot
+ * @slot: Slot holding the page
+ */
+pub fn set_entry(entry: &mut PageTableEntry, slot: u64) {
+	entry.set_addr(PhysAddr::new(slot << 12), PTE_SWAP);
+}
+
+/*
+ * entry_slot - Decode a swap entry
+ * @entry: Leaf PTE
+ *
+ * Return: The slot if @entry is a swap entry, None otherwise
+ */
+pub fn entry_slot(entry: &PageTableEntry) -> Option<u64> {
+	let flags = entry.flags();
+	if flags.contains(PageTableFlags::PRESENT) || !flags.contains(PTE_SWAP) {
+		return None;
+	}
+	Some(entry.addr().as_u64() >> 12)
+}
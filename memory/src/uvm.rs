@@ -0,0 +1,465 @@
+/*
+ * uvm.rs - User Address Space Management
+ *
+ * Walks the lower half (PML4 entries 0..256) of user page tables to
+ * clone address spaces for fork, resolve copy-on-write faults, populate
+ * and reprotect anonymous memory and tear mappings down for munmap,
+ * exec and process teardown. The kernel half is shared by every address
+ * space and never touched.
+ *
+ * Swap entries (see swap.rs) are not-present leaf PTEs; they are copied
+ * into a fork child and release their slot when unmapped, like pages.
+ */
+
+use crate::{frame_ref, swap};
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+	FrameAllocator, FrameDeallocator, Mapper, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+/* PTE_COW - Software PTE bit marking a read-only copy-on-write page */
+pub const PTE_COW: PageTableFlags = PageTableFlags::BIT_9;
+
+/* PTE_SHARED - Software PTE bit marking a page of a shared memory object */
+pub const PTE_SHARED: PageTableFlags = PageTableFlags::BIT_11;
+
+/* Number of PML4 slots covering the user half */
+const USER_PML4_ENTRIES: usize = 256;
+
+/*
+ * table_at - Access a page table frame through the HHDM
+ * @frame: Physical frame holding the table
+ * @offset: HHDM offset
+ */
+unsafe fn table_at(frame: PhysFrame, offset: VirtAddr) -> &'static mut PageTable {
+	let virt = offset + frame.start_address().as_u64();
+	unsafe { &mut *(virt.as_mut_ptr() as *mut PageTable) }
+}
+
+/*
+ * leaf_entry - Find the level-1 entry mapping a user address
+ * @pml4: Address space to walk
+ * @addr: Virtual address
+ * @offset: HHDM offset
+ *
+ * Return: The 4 KiB PTE for @addr, or None if an intermediate table is
+ *         missing or the address is covered by a huge page
+ */
+pub unsafe fn leaf_entry(
+	pml4: PhysFrame,
+	addr: VirtAddr,
+	offset: VirtAddr,
+) -> Option<&'static mut PageTableEntry> {
+	let page = Page::<Size4KiB>::containing_address(addr);
+	let mut table = unsafe { table_at(pml4, offset) };
+
+	for index in [page.p4_index(), page.p3_index(), page.p2_index()] {
+		let entry = &table[index];
+		let flags = entry.flags();
+		if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE) {
+			return None;
+		}
+		table = unsafe { table_at(PhysFrame::containing_address(entry.addr()), offset) };
+	}
+	Some(&mut table[page.p1_index()])
+}
+
+/*
+ * clone_table - Recursively copy one level of a user page table
+ * @src: Source table frame
+ * @level: Paging level of @src (3 = PDPT ... 1 = PT)
+ * @alloc: Frame allocator for the new tables
+ * @offset: HHDM offset
+ *
+ * Intermediate tables are always private to the copy. Present leaf
+ * pages are shared; writable ones are downgraded to read-only and
+ * tagged PTE_COW in both the source and the copy, except PTE_SHARED
+ * pages, which both keep writing to. Swap entries are copied and share
+ * their slot.
+ *
+ * Running out of frames frees the partial copy again, dropping the
+ * shares it took. Source pages already tagged PTE_COW stay so; their
+ * next write fault finds them unshared and just restores WRITABLE.
+ */
+unsafe fn clone_table<A>(
+	src: PhysFrame,
+	level: u8,
+	alloc: &mut A,
+	offset: VirtAddr,
+) -> Option<PhysFrame>
+where
+	A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+	let dst = alloc.allocate_frame()?;
+	let dst_table = unsafe { table_at(dst, offset) };
+	dst_table.zero();
+	let src_table = unsafe { table_at(src, offset) };
+
+	for (i, entry) in src_table.iter_mut().enumerate() {
+		if entry.is_unused() {
+			continue;
+		}
+		let mut flags = entry.flags();
+
+		if level == 1 {
+			if flags.contains(PageTableFlags::PRESENT) {
+				if !flags.contains(PTE_SHARED) && flags.intersects(PageTableFlags::WRITABLE | PTE_COW) {
+					flags.remove(PageTableFlags::WRITABLE);
+					flags.insert(PTE_COW);
+					entry.set_flags(flags);
+				}
+				frame_ref::share(PhysFrame::containing_address(entry.addr()));
+			} else if let Some(slot) = swap::entry_slot(entry) {
+				swap::share(slot);
+			}
+			dst_table[i] = entry.clone();
+		} else if flags.contains(PageTableFlags::HUGE_PAGE) {
+			/* User mappings are always 4 KiB; share anything else as-is */
+			dst_table[i] = entry.clone();
+		} else {
+			let next = PhysFrame::containing_address(entry.addr());
+			let Some(child) = (unsafe { clone_table(next, level - 1, alloc, offset) }) else {
+				unsafe { free_table(dst, level, alloc, offset) };
+				return None;
+			};
+			flags.remove(PageTableFlags::ACCESSED);
+			dst_table[i].set_frame(child, flags);
+		}
+	}
+	Some(dst)
+}
+
+/*
+ * clone_address_space - Duplicate a user address space for fork
+ * @src: PML4 frame of the parent
+ * @alloc: Frame allocator for the new page tables
+ * @offset: HHDM offset
+ *
+ * Builds a new PML4 sharing the kernel half and mirroring every user
+ * mapping copy-on-write. The caller must flush the TLB if @src is the
+ * active address space, since its writable pages are now read-only.
+ * It must flush even on failure: the pages are only freed again.
+ *
+ * Return: New PML4 frame, or None if out of frames; a partial copy is
+ *         freed first
+ */
+pub unsafe fn clone_address_space<A>(
+	src: PhysFrame,
+	alloc: &mut A,
+	offset: VirtAddr,
+) -> Option<PhysFrame>
+where
+	A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+	let dst = unsafe { crate::create_user_page_table(alloc, offset)? };
+	let src_l4 = unsafe { table_at(src, offset) };
+	let dst_l4 = unsafe { table_at(dst, offset) };
+
+	for i in 0..USER_PML4_ENTRIES {
+		let entry = &src_l4[i];
+		if !entry.flags().contains(PageTableFlags::PRESENT) {
+			continue;
+		}
+		let next = PhysFrame::containing_address(entry.addr());
+		let Some(l3) = (unsafe { clone_table(next, 3, alloc, offset) }) else {
+			unsafe { free_address_space(dst, alloc, offset) };
+			return None;
+		};
+		dst_l4[i].set_frame(l3, entry.flags());
+	}
+	Some(dst)
+}
+
+/*
+ * handle_cow_fault - Resolve a write fault on a copy-on-write page
+ * @pml4: Address space the fault occurred in
+ * @addr: Faulting virtual address
+ * @alloc: Frame allocator for the private copy
+ * @offset: HHDM offset
+ *
+ * If the frame is still shared, copies it into a fresh frame and drops
+ * one reference on the original. If this mapping is the last owner,
+ * the page is simply made writable again.
+ *
+ * Return: true if @addr was a COW page and the fault is resolved
+ */
+pub unsafe fn handle_cow_fault(
+	pml4: PhysFrame,
+	addr: VirtAddr,
+	alloc: &mut impl FrameAllocator<Size4KiB>,
+	offset: VirtAddr,
+) -> bool {
+	let entry = match unsafe { leaf_entry(pml4, addr, offset) } {
+		Some(e) => e,
+		None => return false,
+	};
+	let flags = entry.flags();
+	if !flags.contains(PageTableFlags::PRESENT | PTE_COW) {
+		return false;
+	}
+
+	let old = PhysFrame::containing_address(entry.addr());
+	let new_flags = (flags - PTE_COW) | PageTableFlags::WRITABLE;
+
+	if frame_ref::count(old) > 1 {
+		let new = match alloc.allocate_frame() {
+			Some(f) => f,
+			None => return false,
+		};
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				(offset + old.start_address().as_u64()).as_ptr::<u8>(),
+				(offset + new.start_address().as_u64()).as_mut_ptr::<u8>(),
+				4096,
+			);
+		}
+		frame_ref::release(old);
+		entry.set_addr(new.start_address(), new_flags);
+	} else {
+		entry.set_flags(new_flags);
+	}
+
+	tlb::flush(addr);
+	true
+}
+
+/*
+ * map_zeroed_page - Back a user page with a fresh zero-filled frame
+ * @pml4: Address space to map into
+ * @addr: Any address inside the page
+ * @flags: Leaf PTE flags
+ * @alloc: Frame allocator for the page and any missing tables
+ * @offset: HHDM offset
+ *
+ * Used for demand paging of anonymous memory.
+ *
+ * Return: true if the page is now mapped
+ */
+pub unsafe fn map_zeroed_page<A>(
+	pml4: PhysFrame,
+	addr: VirtAddr,
+	flags: PageTableFlags,
+	alloc: &mut A,
+	offset: VirtAddr,
+) -> bool
+where
+	A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+	let page = Page::<Size4KiB>::containing_address(addr);
+	let frame = match alloc.allocate_frame() {
+		Some(f) => f,
+		None => return false,
+	};
+	unsafe {
+		(offset + frame.start_address().as_u64()).as_mut_ptr::<u8>().write_bytes(0, 4096);
+	}
+
+	let mut mapper = unsafe { crate::create_mapper(pml4, offset) };
+	match unsafe { mapper.map_to(page, frame, flags, alloc) } {
+		Ok(flush) => {
+			flush.flush();
+			true
+		}
+		Err(_) => {
+			unsafe { alloc.deallocate_frame(frame) };
+			false
+		}
+	}
+}
+
+/*
+ * for_each_leaf - Visit every 4 KiB PTE in a user range
+ * @pml4: Address space to walk
+ * @start: First address (page aligned)
+ * @end: End of the range (page aligned)
+ * @offset: HHDM offset
+ * @f: Called with the page address and its PTE
+ *
+ * Ranges without a page table are skipped 2 MiB at a time.
+ */
+pub unsafe fn for_each_leaf(
+	pml4: PhysFrame,
+	start: u64,
+	end: u64,
+	offset: VirtAddr,
+	mut f: impl FnMut(VirtAddr, &mut PageTableEntry),
+) {
+	const PT_SPAN: u64 = 512 * 4096;
+	let mut addr = start;
+	while addr < end {
+		let va = VirtAddr::new(addr);
+		match unsafe { leaf_entry(pml4, va, offset) } {
+			Some(entry) => {
+				f(va, entry);
+				addr += 4096;
+			}
+			None => addr = (addr & !(PT_SPAN - 1)) + PT_SPAN,
+		}
+	}
+}
+
+/*
+ * unmap_range - Remove the user mappings in [start, end)
+ * @pml4: Active address space
+ * @start: First address (page aligned)
+ * @end: End of the range (page aligned)
+ * @dealloc: Allocator receiving frames whose last reference is dropped
+ * @offset: HHDM offset
+ *
+ * Swap entries in the range release their slot. Page tables are kept;
+ * they are reclaimed when the address space is torn down.
+ */
+pub unsafe fn unmap_range(
+	pml4: PhysFrame,
+	start: u64,
+	end: u64,
+	dealloc: &mut impl FrameDeallocator<Size4KiB>,
+	offset: VirtAddr,
+) {
+	unsafe {
+		for_each_leaf(pml4, start, end, offset, |va, entry| {
+			if let Some(slot) = swap::entry_slot(entry) {
+				swap::release(slot);
+				entry.set_unused();
+				return;
+			}
+			if !entry.flags().contains(PageTableFlags::PRESENT) {
+				return;
+			}
+			let frame = PhysFrame::containing_address(entry.addr());
+			if frame_ref::release(frame) {
+				dealloc.deallocate_frame(frame);
+			}
+			entry.set_unused();
+			tlb::flush(va);
+		});
+	}
+}
+
+/*
+ * protect_range - Rewrite the flags of the present pages in [start, end)
+ * @pml4: Active address space
+ * @start: First address (page aligned)
+ * @end: End of the range (page aligned)
+ * @flags: New leaf PTE flags
+ * @offset: HHDM offset
+ *
+ * Copy-on-write pages keep PTE_COW and stay read-only, so the next
+ * write still breaks the sharing first. PTE_SHARED is kept as well.
+ */
+pub unsafe fn protect_range(
+	pml4: PhysFrame,
+	start: u64,
+	end: u64,
+	flags: PageTableFlags,
+	offset: VirtAddr,
+) {
+	unsafe {
+		for_each_leaf(pml4, start, end, offset, |va, entry| {
+			let old = entry.flags();
+			if !old.contains(PageTableFlags::PRESENT) {
+				return;
+			}
+			let mut new = flags | (old & PTE_SHARED);
+			if old.contains(PTE_COW) {
+				new.remove(PageTableFlags::WRITABLE);
+				new.insert(PTE_COW);
+			}
+			entry.set_flags(new);
+			tlb::flush(va);
+		});
+	}
+}
+
+/*
+ * free_table - Recursively release one level of a user page table
+ * @table_frame: Table to free
+ * @level: Paging level of @table_frame (3 = PDPT ... 1 = PT)
+ * @dealloc: Allocator receiving the freed frames
+ * @offset: HHDM offset
+ *
+ * Leaf frames are only freed once their last reference is dropped, so
+ * pages still shared with a fork sibling survive. Swap entries release
+ * their slot.
+ */
+unsafe fn free_table(
+	table_frame: PhysFrame,
+	level: u8,
+	dealloc: &mut impl FrameDeallocator<Size4KiB>,
+	offset: VirtAddr,
+) {
+	let table = unsafe { table_at(table_frame, offset) };
+
+	for entry in table.iter_mut() {
+		let flags = entry.flags();
+		if level == 1 {
+			if let Some(slot) = swap::entry_slot(entry) {
+				swap::release(slot);
+				entry.set_unused();
+				continue;
+			}
+		}
+		if !flags.contains(PageTableFlags::PRESENT) {
+			continue;
+		}
+		let frame = PhysFrame::containing_address(entry.addr());
+		if level == 1 {
+			if frame_ref::release(frame) {
+				unsafe { dealloc.deallocate_frame(frame) };
+			}
+		} else if !flags.contains(PageTableFlags::HUGE_PAGE) {
+			unsafe { free_table(frame, level - 1, dealloc, offset) };
+		}
+		entry.set_unused();
+	}
+	unsafe { dealloc.deallocate_frame(table_frame) };
+}
+
+/*
+ * unmap_user_space - Remove every user mapping from an address space
+ * @pml4: Address space to clear (the PML4 itself is kept)
+ * @dealloc: Allocator receiving the freed frames
+ * @offset: HHDM offset
+ *
+ * Frees all user pages and intermediate tables below the PML4. The
+ * caller must flush the TLB if @pml4 is the active address space.
+ */
+pub unsafe fn unmap_user_space(
+	pml4: PhysFrame,
+	dealloc: &mut impl FrameDeallocator<Size4KiB>,
+	offset: VirtAddr,
+) {
+	let l4 = unsafe { table_at(pml4, offset) };
+
+	for i in 0..USER_PML4_ENTRIES {
+		let entry = &mut l4[i];
+		if !entry.flags().contains(PageTableFlags::PRESENT) {
+			continue;
+		}
+		let l3 = PhysFrame::containing_address(entry.addr());
+		unsafe { free_table(l3, 3, dealloc, offset) };
+		entry.set_unused();
+	}
+}
+
+/*
+ * free_address_space - Release a whole user address space
+ * @pml4: Address space to free; must not be the active one
+ * @dealloc: Allocator receiving the freed frames
+ * @offset: HHDM offset
+ *
+ * unmap_user_space() plus the PML4 frame itself. Called when the last
+ * task using @pml4 is reaped.
+ */
+pub unsafe fn free_address_space(
+	pml4: PhysFrame,
+	dealloc: &mut impl FrameDeallocator<Size4KiB>,
+	offset: VirtAddr,
+) {
+	unsafe {
+		unmap_user_space(pml4, dealloc, offset);
+		dealloc.deallocate_frame(pml4);
+	}
+}
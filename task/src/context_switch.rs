@@ -4,9 +4,10 @@
  * Low-level assembly routine for switching between task contexts.
  * Saves and restores callee-saved registers (SysV ABI) and RIP.
  *
- * Segment selectors, MSR bases, and CR3 are NOT switched here because
- * all kernel tasks share the same address space and GDT selectors.
- * CR3 switching will be added when userspace process isolation is needed.
+ * Segment selectors and GS_BASE are NOT switched here because all tasks
+ * share the same GDT selectors and GS_BASE is always 0 outside
+ * syscall_entry. CR3 is switched for user tasks, and FS_BASE (the user
+ * thread pointer) is saved and reloaded with the other registers.
  */
 
 #![feature(asm_sym)]
@@ -23,6 +24,9 @@ use core::arch::{asm, naked_asm};
  * then restores them from the new context.  Returns when this task
  * is next scheduled.
  *
+ * FS_BASE is saved too, so CPUContext.fs_base is only current while
+ * the task is switched out; read the MSR for the running task.
+ *
  * Stack convention: the saved RSP is the caller's RSP BEFORE the
  * `call context_switch` instruction (i.e., after popping the return
  * address). On restore, the saved RIP is pushed and `ret` is used,
@@ -46,6 +50,12 @@ pub unsafe extern "C" fn context_switch(old: *mut CPUContext, new: *const CPUCon
 		"mov rax, cr3",
 		"mov [rdi + 136], rax",
 
+		/* Save FS_BASE — CPUContext.fs_base is at offset 120 */
+		"mov ecx, 0xC0000100",
+		"rdmsr",
+		"mov [rdi + 120], eax",
+		"mov [rdi + 124], edx",
+
 		/* Load new context from *new (RSI) */
 		"mov rsp, [rsi + 0]",
 		"mov rbp, [rsi + 8]",
@@ -65,6 +75,12 @@ pub unsafe extern "C" fn context_switch(old: *mut CPUContext, new: *const CPUCon
 		"mov cr3, rax",
 		"2:",
 
+		/* Load FS_BASE — CPUContext.fs_base is at offset 120 */
+		"mov ecx, 0xC0000100",
+		"mov eax, [rsi + 120]",
+		"mov edx, [rsi + 124]",
+		"wrmsr",
+
 		/* Jump to new RIP — push it so `ret` pops it and adjusts RSP */
 		"push qword ptr [rsi + 56]",
 		"ret",
@@ -6,6 +6,7 @@
  */
 
 #![no_std]
+#![feature(allocator_api)]
 
 extern crate alloc;
 pub mod async_task;
@@ -14,12 +15,18 @@ pub mod executor;
 pub mod waker;
 pub mod yield_now;
 pub mod scheduler;
+pub mod signal;
+pub mod timer;
+pub mod vma;
 
 use crate::async_task::AsyncTask;
 use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 use core::task::{Context, Poll};
+use memory::slab::{KmemCache, arc_layout};
 use spin::{Mutex, Once};
 use x86_64::VirtAddr;
 use x86_64::structures::paging::PhysFrame;
@@ -174,6 +181,22 @@ impl Default for SchedClass {
 	}
 }
 
+/*
+ * enum Personality - System call ABI spoken by a user task
+ * @Serix: Native syscall numbers and structures
+ * @Linux: Linux x86_64 syscall numbers and structures, translated by
+ *         the kernel onto the native implementations
+ *
+ * Inherited across fork and exec; chosen at spawn from the SYS_SPAWN
+ * flags or the ELF's ABI note.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Personality {
+	#[default]
+	Serix,
+	Linux,
+}
+
 /*
  * struct CPUContext - CPU context for task switching
  * @rsp: Stack pointer
@@ -259,11 +282,22 @@ impl Default for CPUContext {
  * @kstack: Kernel stack pointer
  * @ustack: Optional user stack pointer
  * @name: Task name (static string)
+ * @tgid: Thread group (process) ID: the ID of the process's main thread,
+ *        equal to @id for the main thread itself
  * @parent_id: ID of creating task (0 = kernel/boot task)
- * @exit_status: Exit code set by SYS_EXIT; None while alive
+ * @exit_status: Wait status (exit code << 8, or the number of the signal
+ *               that killed the task); None while alive, unless another
+ *               thread has already begun a group exit
  * @pml4_frame: User address space PML4 frame (None for kernel tasks)
  * @children: Task IDs of spawned child processes
  * @waiting_for_child: True when task is blocked in SYS_WAIT4
+ * @joiner: Task blocked in SYS_THREAD_JOIN on this thread (0 = none)
+ * @vmas: Valid user memory areas (empty for kernel tasks), shared by
+ *        all threads of a process
+ * @signals: Pending/blocked masks, and signal handlers shared by all
+ *           threads of a process
+ * @cwd: Canonical absolute path of the working directory
+ * @personality: Syscall ABI of the task (Serix for kernel tasks)
  */
 #[derive(Debug, Clone)]
 pub struct TaskCB {
@@ -274,13 +308,30 @@ pub struct TaskCB {
 	pub kstack: VirtAddr,
 	pub ustack: Option<VirtAddr>,
 	pub name: &'static str,
+	pub tgid: u64,
 	pub parent_id: u64,
 	pub exit_status: Option<i32>,
 	pub pml4_frame: Option<PhysFrame>,
 	pub children: Vec<u64>,
 	pub waiting_for_child: bool,
+	pub joiner: u64,
+	pub vmas: Arc<Mutex<vma::VmaList>>,
+	pub signals: signal::SignalState,
+	pub cwd: String,
+	pub personality: Personality,
 }
 
+/*
+ * TaskArc - Shared handle to a task, allocated from TASK_CACHE
+ */
+pub type TaskArc = Arc<Mutex<TaskCB>, &'static KmemCache>;
+
+/* Slab cache backing every TaskArc */
+pub static TASK_CACHE: KmemCache = {
+	let (size, align) = arc_layout::<Mutex<TaskCB>>();
+	KmemCache::new("task_struct", size, align, None)
+};
+
 /*
  * task_trampoline - Trampoline function called via context switch
  * @entry_point: Function to execute
@@ -333,19 +384,26 @@ impl TaskCB {
 			context.cr3 = frame.start_address().as_u64();
 		}
 
+		let id = TaskId::new();
 		Self {
-			id: TaskId::new(),
+			id,
 			state: TaskState::Ready,
 			sched_class,
 			context,
 			kstack: stack,
 			ustack: None,
 			name,
+			tgid: id.0,
 			parent_id: 0,
 			exit_status: None,
 			pml4_frame: None,
 			children: Vec::new(),
 			waiting_for_child: false,
+			joiner: 0,
+			vmas: Arc::new(Mutex::new(vma::VmaList::new())),
+			signals: signal::SignalState::new(),
+			cwd: String::from("/"),
+			personality: Personality::Serix,
 		}
 	}
 
@@ -355,22 +413,46 @@ impl TaskCB {
 	 * Return: TaskCB for the current kernel main task
 	 */
 	pub fn running_task() -> Self {
+		let id = TaskId::new();
 		Self {
-			id: TaskId::new(),
+			id,
 			state: TaskState::Running,
 			sched_class: SchedClass::default(),
 			context: CPUContext::default(),
 			kstack: VirtAddr::zero(),
 			ustack: None,
 			name: "kernel_main",
+			tgid: id.0,
 			parent_id: 0,
 			exit_status: None,
 			pml4_frame: None,
 			children: Vec::new(),
 			waiting_for_child: false,
+			joiner: 0,
+			vmas: Arc::new(Mutex::new(vma::VmaList::new())),
+			signals: signal::SignalState::new(),
+			cwd: String::from("/"),
+			personality: Personality::Serix,
 		}
 	}
 
+	/*
+	 * into_arc - Move the task into a TaskArc for the scheduler
+	 */
+	pub fn into_arc(self) -> TaskArc {
+		Arc::new_in(Mutex::new(self), &TASK_CACHE)
+	}
+
+	/*
+	 * is_main_thread - Check whether the task is its process's main thread
+	 *
+	 * Return: true for the first thread of a process (and for kernel
+	 *         tasks), false for threads made with SYS_THREAD_CREATE
+	 */
+	pub fn is_main_thread(&self) -> bool {
+		self.id.0 == self.tgid
+	}
+
 	/*
 	 * set_state - Set the task state
 	 * @state: New state
@@ -626,7 +708,6 @@ impl Scheduler {
 pub fn schedule() {
 	use crate::scheduler::{global_or_none, reschedule_current, pick_next_task};
 	use alloc::sync::Arc;
-	use spin::Mutex;
 
 	let rq_ref = match global_or_none() {
 		Some(r) => r,
@@ -634,7 +715,7 @@ pub fn schedule() {
 	};
 
 	// Grab the old (current) task BEFORE re-enqueuing it
-	let old_arc: Option<Arc<Mutex<TaskCB>>> = rq_ref.lock().current.clone();
+	let old_arc: Option<TaskArc> = rq_ref.lock().current.clone();
 
 	// Round-robin: re-enqueue current at tail, dequeue next from front
 	reschedule_current();
@@ -707,7 +788,16 @@ pub fn task_yield() {
  *         back as current and returns without switching.
  */
 pub fn block_current_and_switch() {
+	block_current_as(TaskState::Blocked);
+}
+
+/*
+ * block_current_as - block_current_and_switch() with an explicit state
+ * @state: Blocked (waiting for an event) or Sleeping (waiting for time)
+ */
+pub(crate) fn block_current_as(state: TaskState) {
 	use crate::scheduler::{take_current, pick_next_task};
+	use alloc::sync::Arc;
 
 	/* Remove current task from RunQueue without re-enqueuing */
 	let old_arc = match take_current() {
@@ -715,15 +805,18 @@ pub fn block_current_and_switch() {
 		None => return,
 	};
 
-	/* Mark task as blocked */
-	old_arc.lock().set_state(TaskState::Blocked);
+	/* Mark task as blocked and keep it findable while it sleeps */
+	old_arc.lock().set_state(state);
+	crate::scheduler::global().lock().blocked.push(Arc::clone(&old_arc));
 
 	/* Pick next runnable task */
 	let new_arc = match pick_next_task() {
 		Some(t) => t,
 		None => {
 			/* No other task — put ourselves back as current */
-			crate::scheduler::global().lock().current = Some(old_arc);
+			let mut rq = crate::scheduler::global().lock();
+			rq.unblock(&old_arc);
+			rq.current = Some(old_arc);
 			return;
 		}
 	};
@@ -8,7 +8,7 @@
  * TODO(SMP): Replace global RunQueue with per-CPU run queues indexed via GS_BASE
  */
 
-use super::{CURRENT_TASK, TaskCB, TaskState};
+use super::{CURRENT_TASK, TaskArc, TaskState};
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -30,18 +30,22 @@ pub const TIME_SLICE_TICKS: u64 = 10;
  * struct RunQueue - Single-CPU run queue for ready tasks
  * @queue: Deque fo tasks ready to run (front = next to run)
  * @current: Currently running task (None during early boot)
+ * @zombies: Exited tasks not yet reaped by wait4
+ * @blocked: Tasks sleeping in block_current_and_switch()
  *
- * Holds Arc<Mutex<TaskCB>> so tasks have stable heap addresses
+ * Holds TaskArcs so tasks have stable slab addresses
  * regardless of queue reordering. The Mutex allows state mutation
  * (Ready <-> Running) under the run queue lock
  *
  * TODO(SMP): Per-CPU run queues with GS_BASE
  */
 pub struct RunQueue {
-	queue: VecDeque<Arc<Mutex<TaskCB>>>,
-	pub current: Option<Arc<Mutex<TaskCB>>>,
+	queue: VecDeque<TaskArc>,
+	pub current: Option<TaskArc>,
 	/* zombies - Tasks that have exited but not yet been reaped by wait4 */
-	pub zombies: Vec<Arc<Mutex<TaskCB>>>,
+	pub zombies: Vec<TaskArc>,
+	/* blocked - Sleeping tasks, kept here so they stay findable by ID */
+	pub blocked: Vec<TaskArc>,
 }
 
 /* Global single-CPU run queue */
@@ -58,6 +62,7 @@ impl RunQueue {
 			queue: VecDeque::new(),
 			current: None,
 			zombies: Vec::new(),
+			blocked: Vec::new(),
 		}
 	}
 
@@ -70,7 +75,7 @@ impl RunQueue {
 	 *
 	 * Safety: Caller must hold RunQueue lock
 	 */
-	pub fn enqueue(&mut self, task: Arc<Mutex<TaskCB>>) {
+	pub fn enqueue(&mut self, task: TaskArc) {
 		task.lock().set_state(TaskState::Ready);
 		self.queue.push_back(task);
 	}
@@ -85,7 +90,7 @@ impl RunQueue {
 	 *
 	 * Safety: Caller must hold the RunQueue lock.
 	 */
-	pub fn dequeue(&mut self) -> Option<Arc<Mutex<TaskCB>>> {
+	pub fn dequeue(&mut self) -> Option<TaskArc> {
 		self.queue.pop_front()
 	}
 
@@ -96,9 +101,19 @@ impl RunQueue {
 	 *
 	 * Safety: Caller must hold the RunQueue lock
 	 */
-	pub fn peek(&self) -> Option<&Arc<Mutex<TaskCB>>> {
+	pub fn peek(&self) -> Option<&TaskArc> {
 		self.queue.front()
 	}
+	/*
+	 * unblock - Forget a task on the blocked list
+	 * @task: Task about to become runnable again
+	 *
+	 * Safety: Caller must hold the RunQueue lock
+	 */
+	pub fn unblock(&mut self, task: &TaskArc) {
+		self.blocked.retain(|t| !Arc::ptr_eq(t, task));
+	}
+
 	/*
 	 * is_empty - Check whether the run queue has no tasks
 	 *
@@ -147,7 +162,7 @@ pub fn global() -> &'static Mutex<RunQueue> {
  *
  * Convenience wrapper around global().lock().enqueue().
  */
-pub fn enqueue_task(task: Arc<Mutex<TaskCB>>) {
+pub fn enqueue_task(task: TaskArc) {
 	global().lock().enqueue(task);
 }
 
@@ -155,14 +170,35 @@ pub fn enqueue_task(task: Arc<Mutex<TaskCB>>) {
  * wake_task - Wake a blocked task by setting it Ready and enqueuing it
  * @task: Arc-wrapped task to wake
  *
- * Used by subsystems (IPC, timers) to unblock a waiting task.
- * The task's state is set to Ready by enqueue().
+ * Used by subsystems (IPC, futexes) to unblock a waiting task.
+ * The task's state is set to Ready by enqueue() and it is dropped from
+ * the blocked list.
  *
  * Safety: Acquires RunQueue lock. Must not be called while RunQueue
  *         lock is already held.
  */
-pub fn wake_task(task: Arc<Mutex<TaskCB>>) {
-	global().lock().enqueue(task);
+pub fn wake_task(task: TaskArc) {
+	let mut rq = global().lock();
+	rq.unblock(&task);
+	rq.enqueue(task);
+}
+
+/*
+ * try_wake_task - wake_task() for interrupt context
+ * @task: Task to wake; its own lock must be free
+ *
+ * The interrupt may have cut into a holder of the RunQueue lock, so it
+ * is only tried.
+ *
+ * Return: false, leaving @task untouched, if the lock is held
+ */
+pub fn try_wake_task(task: &TaskArc) -> bool {
+	let Some(mut rq) = global().try_lock() else {
+		return false;
+	};
+	rq.unblock(task);
+	rq.enqueue(Arc::clone(task));
+	true
 }
 
 /*
@@ -177,12 +213,12 @@ pub fn current_task_id() -> u64 {
 /*
  * current_task_arc - Get Arc reference to the currently running task
  *
- * Return: Some(Arc<Mutex<TaskCB>>) if a task is running, None otherwise
+ * Return: Some(TaskArc) if a task is running, None otherwise
  *
  * Safety: Acquires RunQueue lock briefly. Must not be called while
  *         RunQueue lock is already held.
  */
-pub fn current_task_arc() -> Option<Arc<Mutex<TaskCB>>> {
+pub fn current_task_arc() -> Option<TaskArc> {
 	global().lock().current.clone()
 }
 
@@ -193,12 +229,12 @@ pub fn current_task_arc() -> Option<Arc<Mutex<TaskCB>>> {
  * RunQueue entirely. The caller is responsible for holding onto the
  * returned Arc (e.g., placing it on a wait queue).
  *
- * Return: Some(Arc<Mutex<TaskCB>>) if a task was running, None otherwise
+ * Return: Some(TaskArc) if a task was running, None otherwise
  *
  * Safety: Must be called with interrupts disabled.
  *         Caller must ensure the task is eventually re-enqueued or destroyed.
  */
-pub fn take_current() -> Option<Arc<Mutex<TaskCB>>> {
+pub fn take_current() -> Option<TaskArc> {
 	global().lock().current.take()
 }
 
@@ -213,7 +249,7 @@ pub fn take_current() -> Option<Arc<Mutex<TaskCB>>> {
  * Called with interrupts disabled (inside timer interrupt handler)
  * Safety: Must not be called concurrently - single-CPU invariant
  */
-pub fn pick_next_task()-> Option<Arc<Mutex<TaskCB>>> {
+pub fn pick_next_task()-> Option<TaskArc> {
 	let mut rq = global().lock();
 	let next = rq.dequeue()?;
 	{
@@ -262,7 +298,7 @@ pub fn reschedule_current(){
 
 	Safety: Must be called with interrupts disabled (timer IRQ handler context)
  */
-pub fn schedule()->Option<Arc<Mutex<TaskCB>>>{
+pub fn schedule()->Option<TaskArc>{
 	reschedule_current();
 	pick_next_task()
 }
@@ -282,7 +318,7 @@ pub fn global_or_none() -> Option<&'static Mutex<RunQueue>>{
  *
  * Zombies are held here until a parent calls wait4 to reap them.
  */
-pub fn push_zombie(task: Arc<Mutex<TaskCB>>) {
+pub fn push_zombie(task: TaskArc) {
 	global().lock().zombies.push(task);
 }
 
@@ -290,17 +326,18 @@ pub fn push_zombie(task: Arc<Mutex<TaskCB>>) {
  * find_task_by_id - Look up any live task by numeric ID
  * @id: TaskId value to find
  *
- * Searches the current task and the run queue. Does not search zombies.
+ * Searches the current task, the run queue and blocked tasks. Does not
+ * search zombies.
  * Return: Some(Arc) if found, None otherwise.
  */
-pub fn find_task_by_id(id: u64) -> Option<Arc<Mutex<TaskCB>>> {
+pub fn find_task_by_id(id: u64) -> Option<TaskArc> {
 	let rq = global().lock();
 	if let Some(ref current) = rq.current {
 		if current.lock().id.0 == id {
 			return Some(Arc::clone(current));
 		}
 	}
-	for task in rq.queue.iter() {
+	for task in rq.queue.iter().chain(rq.blocked.iter()) {
 		if task.lock().id.0 == id {
 			return Some(Arc::clone(task));
 		}
@@ -308,19 +345,74 @@ pub fn find_task_by_id(id: u64) -> Option<Arc<Mutex<TaskCB>>> {
 	None
 }
 
+/*
+ * all_tasks - Snapshot of every task the scheduler knows about
+ *
+ * Covers the current task, the run queue, blocked tasks and zombies,
+ * in that order. The run queue lock is released before returning, so
+ * callers may lock the individual tasks.
+ */
+pub fn all_tasks() -> Vec<TaskArc> {
+	let rq = global().lock();
+	rq.current.iter()
+		.chain(rq.queue.iter())
+		.chain(rq.blocked.iter())
+		.chain(rq.zombies.iter())
+		.cloned()
+		.collect()
+}
+
 /*
  * find_zombie_child - Find and remove a zombie child of the given parent
  * @parent_id: Task ID of the parent
  * @child_pid: Specific child to wait for (-1 = any child)
  *
+ * Only main threads count as children; exited threads are reaped with
+ * find_zombie_thread().
+ *
  * Return: Some(Arc) of the zombie TaskCB if found and removed, None otherwise.
  */
-pub fn find_zombie_child(parent_id: u64, child_pid: i64) -> Option<Arc<Mutex<TaskCB>>> {
+pub fn find_zombie_child(parent_id: u64, child_pid: i64) -> Option<TaskArc> {
 	let mut rq = global().lock();
 	let pos = rq.zombies.iter().position(|z| {
 		let task = z.lock();
 		task.parent_id == parent_id
+			&& task.is_main_thread()
 			&& (child_pid == -1 || task.id.0 == child_pid as u64)
 	})?;
 	Some(rq.zombies.remove(pos))
-}
\ No newline at end of file
+}
+
+/*
+ * find_zombie_thread - Find and remove an exited thread of a process
+ * @tgid: Thread group ID of the process
+ * @tid: Task ID of the thread
+ *
+ * Return: Some(Arc) of the zombie TaskCB if found and removed, None otherwise.
+ */
+pub fn find_zombie_thread(tgid: u64, tid: u64) -> Option<TaskArc> {
+	let mut rq = global().lock();
+	let pos = rq.zombies.iter().position(|z| {
+		let task = z.lock();
+		task.tgid == tgid && task.id.0 == tid && !task.is_main_thread()
+	})?;
+	Some(rq.zombies.remove(pos))
+}
+
+/*
+ * take_zombie_threads - Remove every exited, unjoined thread of a process
+ * @tgid: Thread group ID of the process
+ *
+ * Called when the main thread exits, after which nothing can join them.
+ *
+ * Return: The removed zombies, for the caller to release
+ */
+pub fn take_zombie_threads(tgid: u64) -> Vec<TaskArc> {
+	let mut rq = global().lock();
+	let (threads, rest) = core::mem::take(&mut rq.zombies).into_iter().partition(|z| {
+		let task = z.lock();
+		task.tgid == tgid && !task.is_main_thread()
+	});
+	rq.zombies = rest;
+	threads
+}
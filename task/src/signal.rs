@@ -0,0 +1,143 @@
+/*
+ * signal.rs - Per-task Signal State
+ *
+ * Holds the pending and blocked masks of a task and the installed
+ * handlers, which are shared by every thread of a process. Signal
+ * numbers, default actions and delivery live in the kernel; this module
+ * only stores the state so it can be carried in TaskCB across fork,
+ * thread creation and exec.
+ */
+
+use alloc::sync::Arc;
+use spin::Mutex;
+
+/* Number of signal slots; valid signals are 1..NSIG */
+pub const NSIG: usize = 32;
+
+/* Special handler values */
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+/* SigAction flags (Linux values) */
+pub const SA_RESTORER: u64 = 0x0400_0000;
+pub const SA_NODEFER: u64 = 0x4000_0000;
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+
+/*
+ * struct SigAction - Disposition of one signal
+ * @handler: SIG_DFL, SIG_IGN or the user address of the handler
+ * @flags: SA_* flags
+ * @restorer: User trampoline the handler returns into (calls SIGRETURN)
+ * @mask: Extra signals blocked while the handler runs
+ *
+ * Same layout as the struct passed to SYS_SIGACTION.
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigAction {
+	pub handler: u64,
+	pub flags: u64,
+	pub restorer: u64,
+	pub mask: u64,
+}
+
+/*
+ * sig_bit - Mask bit for a signal number
+ * @sig: Signal number (1..NSIG)
+ */
+pub const fn sig_bit(sig: u8) -> u64 {
+	1u64 << sig
+}
+
+/* Disposition per signal number */
+pub type SigActions = [SigAction; NSIG];
+
+/*
+ * struct SignalState - Signal bookkeeping of one task
+ * @pending: Signals raised but not yet delivered
+ * @blocked: Signals whose delivery is deferred (sigprocmask)
+ * @actions: Disposition per signal number, shared by all threads of a
+ *           process
+ */
+#[derive(Debug, Clone)]
+pub struct SignalState {
+	pub pending: u64,
+	pub blocked: u64,
+	pub actions: Arc<Mutex<SigActions>>,
+}
+
+impl Default for SignalState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl SignalState {
+	/*
+	 * new - Fresh state: nothing pending or blocked, all SIG_DFL
+	 */
+	pub fn new() -> Self {
+		Self {
+			pending: 0,
+			blocked: 0,
+			actions: Arc::new(Mutex::new([SigAction { handler: SIG_DFL, flags: 0, restorer: 0, mask: 0 }; NSIG])),
+		}
+	}
+
+	/*
+	 * deliverable - Pending signals that are not blocked
+	 */
+	pub fn deliverable(&self) -> u64 {
+		self.pending & !self.blocked
+	}
+
+	/*
+	 * take_next - Dequeue the lowest-numbered deliverable signal
+	 *
+	 * Return: Signal number, or None if nothing can be delivered
+	 */
+	pub fn take_next(&mut self) -> Option<u8> {
+		let ready = self.deliverable();
+		if ready == 0 {
+			return None;
+		}
+		let sig = ready.trailing_zeros() as u8;
+		self.pending &= !sig_bit(sig);
+		Some(sig)
+	}
+
+	/*
+	 * fork_copy - State inherited by a forked child
+	 *
+	 * Handlers and the blocked mask are inherited, pending signals are
+	 * not. The child gets its own copy of the handlers.
+	 */
+	pub fn fork_copy(&self) -> Self {
+		let actions = *self.actions.lock();
+		Self { pending: 0, blocked: self.blocked, actions: Arc::new(Mutex::new(actions)) }
+	}
+
+	/*
+	 * thread_copy - State of a new thread in the same process
+	 *
+	 * The blocked mask is inherited and nothing is pending; the handlers
+	 * are shared with the creator.
+	 */
+	pub fn thread_copy(&self) -> Self {
+		Self { pending: 0, blocked: self.blocked, actions: self.actions.clone() }
+	}
+
+	/*
+	 * reset_on_exec - Drop handlers that point into the old image
+	 *
+	 * Caught signals revert to SIG_DFL; ignored ones stay ignored. The
+	 * blocked mask and pending signals survive exec.
+	 */
+	pub fn reset_on_exec(&mut self) {
+		for act in self.actions.lock().iter_mut() {
+			if act.handler != SIG_IGN {
+				*act = SigAction::default();
+			}
+		}
+	}
+}
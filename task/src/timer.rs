@@ -0,0 +1,188 @@
+/*
+ * timer.rs - Kernel Timer Queue
+ *
+ * Deadlines are expressed in LAPIC timer ticks. The timer interrupt
+ * calls tick() on every tick, which wakes every task whose deadline has
+ * passed. It only try_lock()s the timer queue, the task and the run
+ * queue, since it may have interrupted their holder; a timer whose locks
+ * are busy fires on a later tick instead. Tasks wait with sleep_until() (TaskState::Sleeping) or, for
+ * blocking primitives that want a timeout, block_current_until()
+ * (TaskState::Blocked, woken either by the primitive or the timer).
+ *
+ * A timer only ever wakes a task that is still Sleeping or Blocked, and
+ * the waiter cancels its timer as soon as it runs again, so an early
+ * wakeup (data arrived, signal) never leaves a stale wakeup behind.
+ */
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::{TaskArc, TaskState};
+
+/*
+ * TICK_HZ - Nominal LAPIC timer frequency
+ *
+ * 100_000 initial count / 16 divider, see scheduler::TIME_SLICE_TICKS.
+ * The LAPIC timer is not calibrated: this is the rate under QEMU's
+ * 1 GHz bus clock, and sleeps and timeouts run fast or slow by the same
+ * factor on hardware whose bus clock differs. CLOCK_MONOTONIC (the
+ * calibrated TSC) is not affected.
+ */
+pub const TICK_HZ: u64 = 625;
+
+/* Length of one tick in nanoseconds */
+pub const NS_PER_TICK: u64 = 1_000_000_000 / TICK_HZ;
+
+/*
+ * struct Timer - One pending wakeup
+ * @id: Handle used to cancel the timer
+ * @deadline: Tick at which @task is woken
+ * @task: Task to wake
+ */
+struct Timer {
+	id: u64,
+	deadline: u64,
+	task: TaskArc,
+}
+
+/* Pending timers, sorted by deadline (earliest first) */
+static TIMERS: Mutex<Vec<Timer>> = Mutex::new(Vec::new());
+
+/* Tick count as of the last tick() */
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/*
+ * now - Current time in ticks since the timer started
+ */
+pub fn now() -> u64 {
+	NOW.load(Ordering::Relaxed)
+}
+
+/*
+ * ns_to_ticks - Convert a duration to ticks, rounding up
+ * @ns: Duration in nanoseconds
+ */
+pub fn ns_to_ticks(ns: u64) -> u64 {
+	ns.div_ceil(NS_PER_TICK)
+}
+
+/*
+ * ticks_to_ns - Convert ticks to nanoseconds
+ * @ticks: Number of ticks
+ */
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+	ticks.saturating_mul(NS_PER_TICK)
+}
+
+/*
+ * add - Arm a timer waking a task at a deadline
+ * @deadline: Tick to fire at
+ * @task: Task to wake
+ *
+ * Return: Timer ID for cancel()
+ */
+pub fn add(deadline: u64, task: TaskArc) -> u64 {
+	let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		let mut timers = TIMERS.lock();
+		let idx = timers.partition_point(|t| t.deadline <= deadline);
+		timers.insert(idx, Timer { id, deadline, task });
+	});
+	id
+}
+
+/*
+ * cancel - Disarm a timer
+ * @id: ID returned by add(); unknown or already fired IDs are ignored
+ */
+pub fn cancel(id: u64) {
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		TIMERS.lock().retain(|t| t.id != id);
+	});
+}
+
+/*
+ * tick - Advance the clock and fire expired timers
+ * @ticks: Current tick count
+ *
+ * Called from the timer interrupt with interrupts disabled. Stops at
+ * the first expired timer whose locks are held and leaves it, and those
+ * after it, for the next tick.
+ */
+pub fn tick(ticks: u64) {
+	NOW.store(ticks, Ordering::Relaxed);
+
+	let Some(mut timers) = TIMERS.try_lock() else {
+		return;
+	};
+	while let Some(t) = timers.first() {
+		if t.deadline > ticks {
+			return;
+		}
+		let waiting = match t.task.try_lock() {
+			Some(task) => matches!(task.state, TaskState::Sleeping | TaskState::Blocked),
+			None => return,
+		};
+		if waiting && !crate::scheduler::try_wake_task(&t.task) {
+			return;
+		}
+		timers.remove(0);
+	}
+}
+
+/*
+ * sleep_until - Put the current task to sleep until a deadline
+ * @deadline: Tick to wake up at
+ *
+ * The task is Sleeping meanwhile and may be woken early, e.g. by a
+ * signal. If no other task is runnable the call returns straight away.
+ *
+ * Return: true if the deadline has passed
+ */
+pub fn sleep_until(deadline: u64) -> bool {
+	wait_until(deadline, TaskState::Sleeping)
+}
+
+/*
+ * block_current_until - Block the current task with a timeout
+ * @deadline: Tick at which to give up, or None to wait indefinitely
+ *
+ * For blocking primitives: the caller registers the task on its wait
+ * queue first, exactly as for block_current_and_switch(). On return it
+ * must drop itself from that queue if it was not woken through it.
+ *
+ * Return: true if the deadline has passed
+ */
+pub fn block_current_until(deadline: Option<u64>) -> bool {
+	match deadline {
+		Some(d) => wait_until(d, TaskState::Blocked),
+		None => {
+			crate::block_current_and_switch();
+			false
+		}
+	}
+}
+
+/*
+ * wait_until - Common body of sleep_until() and block_current_until()
+ * @deadline: Tick to wake up at
+ * @state: Sleeping or Blocked
+ */
+fn wait_until(deadline: u64, state: TaskState) -> bool {
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		if now() >= deadline {
+			return true;
+		}
+		let task = match crate::scheduler::current_task_arc() {
+			Some(t) => t,
+			None => return false,
+		};
+		let id = add(deadline, task);
+		crate::block_current_as(state);
+		cancel(id);
+		now() >= deadline
+	})
+}
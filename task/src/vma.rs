@@ -0,0 +1,254 @@
+/*
+ * vma.rs - Virtual Memory Areas
+ *
+ * Every user process carries a VmaList describing which parts of its
+ * address space are valid and with what protection. Page table entries
+ * are only a cache of this: anonymous areas are populated lazily by the
+ * page fault handler, which consults the list to decide whether a
+ * fault is legal.
+ */
+
+use alloc::vec::Vec;
+use x86_64::structures::paging::PageTableFlags;
+
+/* Protection bits (SYS_MMAP / SYS_MPROTECT, Linux values) */
+pub const PROT_NONE: u64 = 0x0;
+pub const PROT_READ: u64 = 0x1;
+pub const PROT_WRITE: u64 = 0x2;
+pub const PROT_EXEC: u64 = 0x4;
+
+/*
+ * enum VmaKind - What backs a memory area
+ * @Image: ELF PT_LOAD segment, mapped eagerly at spawn/exec
+ * @Stack: Initial user stack, mapped eagerly at spawn/exec
+ * @Anon: Anonymous memory from SYS_MMAP, zero-filled on first touch
+ * @Shared: Shared memory object (SYS_SHM_CREATE / SYS_SHM_MAP), mapped eagerly
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaKind {
+	Image,
+	Stack,
+	Anon,
+	Shared,
+}
+
+/*
+ * struct Vma - One contiguous, page-aligned memory area
+ * @start: First address (inclusive)
+ * @end: Last address (exclusive)
+ * @prot: PROT_* bits
+ * @kind: Backing type
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vma {
+	pub start: u64,
+	pub end: u64,
+	pub prot: u64,
+	pub kind: VmaKind,
+}
+
+impl Vma {
+	/*
+	 * contains - Check whether an address falls inside the area
+	 * @addr: Virtual address
+	 */
+	pub fn contains(&self, addr: u64) -> bool {
+		addr >= self.start && addr < self.end
+	}
+
+	/*
+	 * page_flags - Page table flags implementing this area's protection
+	 *
+	 * PROT_NONE pages stay present but lose USER_ACCESSIBLE, so their
+	 * contents survive a later mprotect() back to an accessible mode.
+	 *
+	 * Return: Leaf PTE flags
+	 */
+	pub fn page_flags(&self) -> PageTableFlags {
+		let mut flags = PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE;
+		if self.prot == PROT_NONE {
+			return flags;
+		}
+		flags |= PageTableFlags::USER_ACCESSIBLE;
+		if self.prot & PROT_WRITE != 0 {
+			flags |= PageTableFlags::WRITABLE;
+		}
+		if self.prot & PROT_EXEC != 0 {
+			flags.remove(PageTableFlags::NO_EXECUTE);
+		}
+		flags
+	}
+}
+
+/*
+ * struct VmaList - Sorted, non-overlapping set of memory areas
+ * @heap_start: Page-aligned end of the image, where the brk heap starts
+ * @brk: Current program break (SYS_BRK), heap_start when empty
+ */
+#[derive(Debug, Clone, Default)]
+pub struct VmaList {
+	areas: Vec<Vma>,
+	pub heap_start: u64,
+	pub brk: u64,
+}
+
+impl VmaList {
+	/*
+	 * new - Create an empty list
+	 */
+	pub const fn new() -> Self {
+		Self { areas: Vec::new(), heap_start: 0, brk: 0 }
+	}
+
+	/*
+	 * iter - Iterate over the areas in address order
+	 */
+	pub fn iter(&self) -> core::slice::Iter<'_, Vma> {
+		self.areas.iter()
+	}
+
+	/*
+	 * clear - Forget every area and the program break (exec)
+	 */
+	pub fn clear(&mut self) {
+		self.areas.clear();
+		self.heap_start = 0;
+		self.brk = 0;
+	}
+
+	/*
+	 * find - Find the area containing an address
+	 * @addr: Virtual address
+	 */
+	pub fn find(&self, addr: u64) -> Option<&Vma> {
+		let idx = self.areas.partition_point(|v| v.end <= addr);
+		self.areas.get(idx).filter(|v| v.contains(addr))
+	}
+
+	/*
+	 * is_free - Check that no area overlaps [start, end)
+	 */
+	pub fn is_free(&self, start: u64, end: u64) -> bool {
+		!self.areas.iter().any(|v| v.start < end && start < v.end)
+	}
+
+	/*
+	 * covers - Check that [start, end) is entirely inside areas
+	 */
+	pub fn covers(&self, start: u64, end: u64) -> bool {
+		let mut cur = start;
+		for v in self.areas.iter().filter(|v| v.end > start && v.start < end) {
+			if v.start > cur {
+				return false;
+			}
+			cur = v.end;
+		}
+		cur >= end
+	}
+
+	/*
+	 * find_free - Find the lowest free gap of a given size
+	 * @len: Gap size in bytes (page multiple)
+	 * @lo: Lowest acceptable start address
+	 * @hi: Highest acceptable end address
+	 *
+	 * Return: Start of the gap, or None if the range is full
+	 */
+	pub fn find_free(&self, len: u64, lo: u64, hi: u64) -> Option<u64> {
+		let mut cand = lo;
+		for v in self.areas.iter().filter(|v| v.end > lo) {
+			if v.start >= cand.checked_add(len)? {
+				break;
+			}
+			cand = cand.max(v.end);
+		}
+		if cand.checked_add(len)? <= hi { Some(cand) } else { None }
+	}
+
+	/*
+	 * insert - Add an area
+	 * @vma: Area to add; must not overlap an existing one
+	 *
+	 * Adjacent anonymous areas with the same protection are merged.
+	 */
+	pub fn insert(&mut self, vma: Vma) {
+		debug_assert!(self.is_free(vma.start, vma.end));
+		let idx = self.areas.partition_point(|v| v.start < vma.start);
+		self.areas.insert(idx, vma);
+		self.merge();
+	}
+
+	/*
+	 * remove - Remove [start, end) from the list
+	 * @start: First address to remove (page aligned)
+	 * @end: End of the range (page aligned)
+	 *
+	 * Areas straddling a boundary are split and only the part inside
+	 * the range is dropped.
+	 */
+	pub fn remove(&mut self, start: u64, end: u64) {
+		let mut out = Vec::with_capacity(self.areas.len() + 1);
+		for v in self.areas.drain(..) {
+			if v.end <= start || v.start >= end {
+				out.push(v);
+				continue;
+			}
+			if v.start < start {
+				out.push(Vma { end: start, ..v });
+			}
+			if v.end > end {
+				out.push(Vma { start: end, ..v });
+			}
+		}
+		self.areas = out;
+	}
+
+	/*
+	 * protect - Change the protection of [start, end)
+	 * @start: First address (page aligned)
+	 * @end: End of the range (page aligned)
+	 * @prot: New PROT_* bits
+	 *
+	 * Areas straddling a boundary are split. The caller checks covers()
+	 * first; gaps inside the range are left untouched.
+	 */
+	pub fn protect(&mut self, start: u64, end: u64, prot: u64) {
+		let mut out = Vec::with_capacity(self.areas.len() + 2);
+		for v in self.areas.drain(..) {
+			if v.end <= start || v.start >= end {
+				out.push(v);
+				continue;
+			}
+			let (lo, hi) = (v.start.max(start), v.end.min(end));
+			if v.start < lo {
+				out.push(Vma { end: lo, ..v });
+			}
+			out.push(Vma { start: lo, end: hi, prot, ..v });
+			if v.end > hi {
+				out.push(Vma { start: hi, ..v });
+			}
+		}
+		self.areas = out;
+		self.merge();
+	}
+
+	/*
+	 * merge - Coalesce adjacent anonymous areas with equal protection
+	 */
+	fn merge(&mut self) {
+		let mut i = 1;
+		while i < self.areas.len() {
+			let (prev, cur) = (self.areas[i - 1], self.areas[i]);
+			if prev.end == cur.start
+				&& prev.kind == VmaKind::Anon
+				&& cur.kind == VmaKind::Anon
+				&& prev.prot == cur.prot
+			{
+				self.areas[i - 1].end = cur.end;
+				self.areas.remove(i);
+			} else {
+				i += 1;
+			}
+		}
+	}
+}
@@ -4,15 +4,17 @@
  * Demonstrates process management syscalls:
  * - getpid/getppid: query task IDs
  * - getdents64: directory listing
- * - spawn/wait4: process creation and reaping
+ * - spawn/wait4: process creation and reaping, including orphans the
+ *   kernel hands to init when their parent exits
+ * - argv/envp: arguments passed on the initial stack
  */
 
 #![no_std]
 #![no_main]
 
 use core::panic::PanicInfo;
-use ulib::{STDOUT, exit, read, serix_close, serix_getpid, serix_getppid, serix_open, serix_spawn,
-	serix_wait, write};
+use ulib::{STDOUT, args, exit, read, serix_close, serix_getpid, serix_getppid, serix_open,
+	serix_spawn_args, serix_wait, wexitstatus, wifsignaled, write, wtermsig};
 
 /*
  * panic - User panic handler
@@ -51,13 +53,25 @@ fn print_u64(mut val: u64) {
 
 /*
  * _start - Userspace entry point
+ * @sp: Initial stack pointer (argc, argv, envp, auxv)
  *
  * Demonstrates process lifecycle: prints pid, lists /, then enters echo loop.
  */
 #[unsafe(no_mangle)]
-pub extern "C" fn _start() -> ! {
+pub extern "C" fn _start(sp: *const usize) -> ! {
+	unsafe { ulib::env::init(sp); }
+
 	write(STDOUT, b"\n=== Serix User Shell (init) ===\n");
 
+	/* Print our own argument vector */
+	for (i, arg) in args().enumerate() {
+		write(STDOUT, b"[init] argv[");
+		print_u64(i as u64);
+		write(STDOUT, b"] = ");
+		write(STDOUT, arg.as_bytes());
+		write(STDOUT, b"\n");
+	}
+
 	/* Print process IDs */
 	write(STDOUT, b"[init] PID: ");
 	let pid = serix_getpid();
@@ -69,7 +83,7 @@ pub extern "C" fn _start() -> ! {
 
 	/* File I/O test: open /hello.txt, read contents, print */
 	let fd = serix_open("/hello.txt");
-	if fd >= 3 {
+	if fd >= 0 {
 		let fd = fd as usize;
 		let mut rbuf = [0u8; 64];
 		let n = read(fd, &mut rbuf);
@@ -81,15 +95,29 @@ pub extern "C" fn _start() -> ! {
 	}
 
 	/* Spawn the rsh shell */
-	let child = serix_spawn("/rsh");
+	let child = serix_spawn_args("/rsh", &["rsh"], &["HOME=/"]);
 	if child > 0 {
 		write(STDOUT, b"[init] spawned rsh, pid=");
 		print_u64(child as u64);
 		write(STDOUT, b"\n");
-		let (_pid, status) = serix_wait(-1);
-		write(STDOUT, b"[init] rsh exited, status=");
-		print_u64(status as u64);
-		write(STDOUT, b"\n");
+		/* Reap rsh and any orphans adopted meanwhile, until no child is left */
+		loop {
+			let (pid, status) = serix_wait(-1);
+			if pid < 0 {
+				break;
+			}
+			if pid != child {
+				write(STDOUT, b"[init] reaped orphan pid=");
+				print_u64(pid as u64);
+			} else if wifsignaled(status) {
+				write(STDOUT, b"[init] rsh killed by signal ");
+				print_u64(wtermsig(status) as u64);
+			} else {
+				write(STDOUT, b"[init] rsh exited, status=");
+				print_u64(wexitstatus(status) as u64);
+			}
+			write(STDOUT, b"\n");
+		}
 	} else {
 		write(STDOUT, b"[init] failed to spawn /rsh\n");
 	}
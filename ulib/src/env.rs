@@ -0,0 +1,157 @@
+/*
+ * env.rs - Process Arguments and Environment
+ *
+ * The kernel starts every program with the System V x86_64 initial
+ * stack and passes its address to _start in RDI:
+ *
+ *   sp -> argc
+ *         argv[0] ... argv[argc-1], NULL
+ *         envp[0] ... NULL
+ *         (type, value) auxv pairs ... AT_NULL
+ *
+ * A program calls init(sp) once from _start; args(), env(), getenv()
+ * and auxv() then read the vectors in place.
+ */
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/* Auxiliary vector entry types */
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_ENTRY: usize = 9;
+pub const AT_RANDOM: usize = 25;
+
+/* Initial stack pointer handed to _start (0 = init() not called) */
+static INITIAL_SP: AtomicUsize = AtomicUsize::new(0);
+
+/*
+ * init - Record the initial stack pointer
+ * @sp: Value the kernel passed to _start in RDI
+ *
+ * Safety: @sp must be the untouched initial stack pointer of this process.
+ */
+pub unsafe fn init(sp: *const usize) {
+	INITIAL_SP.store(sp as usize, Ordering::Relaxed);
+}
+
+/*
+ * cstr - Borrow a NUL-terminated string from the initial stack
+ * @ptr: Address of the first byte
+ */
+unsafe fn cstr(ptr: *const u8) -> &'static str {
+	let mut len = 0;
+	unsafe {
+		while *ptr.add(len) != 0 {
+			len += 1;
+		}
+		core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap_or("")
+	}
+}
+
+/*
+ * struct StrVec - Iterator over a NULL-terminated vector of C strings
+ */
+pub struct StrVec {
+	next: *const usize,
+}
+
+impl Iterator for StrVec {
+	type Item = &'static str;
+
+	fn next(&mut self) -> Option<&'static str> {
+		if self.next.is_null() {
+			return None;
+		}
+		let p = unsafe { *self.next };
+		if p == 0 {
+			return None;
+		}
+		self.next = unsafe { self.next.add(1) };
+		Some(unsafe { cstr(p as *const u8) })
+	}
+}
+
+/* base - Initial stack pointer, or null before init() */
+fn base() -> *const usize {
+	INITIAL_SP.load(Ordering::Relaxed) as *const usize
+}
+
+/* argc - Number of command-line arguments (0 before init()) */
+pub fn argc() -> usize {
+	let sp = base();
+	if sp.is_null() { 0 } else { unsafe { *sp } }
+}
+
+/*
+ * args - Iterate over the command-line arguments
+ *
+ * Return: argv[0..argc], argv[0] being the program path
+ */
+pub fn args() -> StrVec {
+	let sp = base();
+	let next = if sp.is_null() { sp } else { unsafe { sp.add(1) } };
+	StrVec { next }
+}
+
+/* envp - Address of the environment vector, or null before init() */
+fn envp() -> *const usize {
+	let sp = base();
+	if sp.is_null() {
+		return sp;
+	}
+	unsafe { sp.add(1 + argc() + 1) }
+}
+
+/*
+ * env - Iterate over the environment
+ *
+ * Return: "KEY=value" strings
+ */
+pub fn env() -> StrVec {
+	StrVec { next: envp() }
+}
+
+/*
+ * getenv - Look up an environment variable
+ * @key: Variable name
+ *
+ * Return: Its value, or None if unset
+ */
+pub fn getenv(key: &str) -> Option<&'static str> {
+	env().find_map(|kv| {
+		let (k, v) = kv.split_once('=')?;
+		if k == key { Some(v) } else { None }
+	})
+}
+
+/*
+ * auxv - Look up an auxiliary vector entry
+ * @key: AT_* type
+ *
+ * Return: The entry's value, or None if the kernel did not supply it
+ */
+pub fn auxv(key: usize) -> Option<usize> {
+	let mut p = envp();
+	if p.is_null() {
+		return None;
+	}
+	unsafe {
+		while *p != 0 {
+			p = p.add(1);
+		}
+		p = p.add(1);
+		loop {
+			let (k, v) = (*p, *p.add(1));
+			if k == AT_NULL {
+				return None;
+			}
+			if k == key {
+				return Some(v);
+			}
+			p = p.add(2);
+		}
+	}
+}
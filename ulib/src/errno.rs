@@ -0,0 +1,34 @@
+/*
+ * errno.rs - Error numbers returned by Serix system calls
+ *
+ * Syscalls fail with a negative errno. The numbers are Linux's, so the
+ * native ABI and the Linux personality agree on them; they must stay in
+ * sync with the ERRNO_* constants in kernel/src/syscall.rs.
+ *
+ * ENOENT was -3 and EAGAIN -12 in older kernels, the same values as
+ * ESRCH and ENOMEM.
+ */
+
+pub const EPERM: isize = -1;
+pub const ENOENT: isize = -2;
+pub const ESRCH: isize = -3;
+pub const EINTR: isize = -4;
+pub const E2BIG: isize = -7;
+pub const ENOEXEC: isize = -8;
+pub const EBADF: isize = -9;
+pub const ECHILD: isize = -10;
+pub const EAGAIN: isize = -11;
+pub const ENOMEM: isize = -12;
+pub const EACCES: isize = -13;
+pub const EFAULT: isize = -14;
+pub const EEXIST: isize = -17;
+pub const ENODEV: isize = -19;
+pub const ENOTDIR: isize = -20;
+pub const EISDIR: isize = -21;
+pub const EINVAL: isize = -22;
+pub const EMFILE: isize = -24;
+pub const ENOTTY: isize = -25;
+pub const EPIPE: isize = -32;
+pub const ERANGE: isize = -34;
+pub const ENOSYS: isize = -38;
+pub const ETIMEDOUT: isize = -110;
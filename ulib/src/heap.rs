@@ -1,24 +1,91 @@
 /*
  * heap.rs - Bump allocator for userspace processes
  *
- * Provides a simple 64 KiB bump allocator backed by a static array.
- * Dealloc is a no-op; memory is never reclaimed. Suitable for short-lived
- * processes like rsh where total allocation is bounded.
+ * Serves allocations from a 64 KiB static array first. Once that is
+ * used up, small allocations are bumped out of 1 MiB chunks obtained
+ * with SYS_MMAP, and large ones get their own mapping which dealloc
+ * returns with SYS_MUNMAP. Other deallocations are a no-op.
  */
 
 use core::alloc::{GlobalAlloc, Layout};
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE, serix_mmap, serix_munmap};
 
 const HEAP_SIZE: usize = 65536;
 
+/* Size of the mmap chunks used once the static heap is full */
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/* Allocations at least this large get a dedicated mapping */
+const LARGE_ALLOC: usize = 64 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
 #[repr(align(16))]
 struct HeapStorage([u8; HEAP_SIZE]);
 
 static mut HEAP: HeapStorage = HeapStorage([0u8; HEAP_SIZE]);
 static HEAP_POS: AtomicUsize = AtomicUsize::new(0);
 
+/* Current mmap chunk: [CHUNK_POS, CHUNK_END), guarded by CHUNK_LOCK */
+static CHUNK_LOCK: AtomicBool = AtomicBool::new(false);
+static CHUNK_POS: AtomicUsize = AtomicUsize::new(0);
+static CHUNK_END: AtomicUsize = AtomicUsize::new(0);
+
 struct BumpAllocator;
 
+/*
+ * map_anon - Map fresh read/write anonymous memory
+ * @len: Length in bytes
+ *
+ * Return: Start address, or None if the kernel refused
+ */
+fn map_anon(len: usize) -> Option<usize> {
+	let ret = serix_mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+	if ret < 0 { None } else { Some(ret as usize) }
+}
+
+/*
+ * in_static_heap - Check whether a pointer came from the static array
+ */
+fn in_static_heap(ptr: *mut u8) -> bool {
+	let base = core::ptr::addr_of!(HEAP) as usize;
+	(base..base + HEAP_SIZE).contains(&(ptr as usize))
+}
+
+/*
+ * chunk_alloc - Bump-allocate from the current mmap chunk
+ * @layout: Requested layout (smaller than LARGE_ALLOC)
+ */
+fn chunk_alloc(layout: Layout) -> *mut u8 {
+	while CHUNK_LOCK
+		.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+		.is_err()
+	{
+		core::hint::spin_loop();
+	}
+
+	let align_up = |p: usize| (p + layout.align() - 1) & !(layout.align() - 1);
+	let mut aligned = align_up(CHUNK_POS.load(Ordering::Relaxed));
+	if aligned == 0 || aligned + layout.size() > CHUNK_END.load(Ordering::Relaxed) {
+		match map_anon(CHUNK_SIZE) {
+			Some(base) => {
+				aligned = align_up(base);
+				CHUNK_END.store(base + CHUNK_SIZE, Ordering::Relaxed);
+			}
+			None => {
+				CHUNK_LOCK.store(false, Ordering::Release);
+				return core::ptr::null_mut();
+			}
+		}
+	}
+	CHUNK_POS.store(aligned + layout.size(), Ordering::Relaxed);
+
+	CHUNK_LOCK.store(false, Ordering::Release);
+	aligned as *mut u8
+}
+
 unsafe impl GlobalAlloc for BumpAllocator {
 	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
 		loop {
@@ -26,7 +93,7 @@ unsafe impl GlobalAlloc for BumpAllocator {
 			let aligned = (pos + layout.align() - 1) & !(layout.align() - 1);
 			let new_pos = aligned + layout.size();
 			if new_pos > HEAP_SIZE {
-				return core::ptr::null_mut();
+				break;
 			}
 			if HEAP_POS
 				.compare_exchange(pos, new_pos, Ordering::SeqCst, Ordering::Relaxed)
@@ -35,10 +102,22 @@ unsafe impl GlobalAlloc for BumpAllocator {
 				return unsafe { core::ptr::addr_of_mut!(HEAP).cast::<u8>().add(aligned) };
 			}
 		}
+
+		/* Static heap exhausted: fall back to mmap (always page aligned) */
+		if layout.align() > PAGE_SIZE {
+			return core::ptr::null_mut();
+		}
+		if layout.size() >= LARGE_ALLOC {
+			return map_anon(layout.size()).map_or(core::ptr::null_mut(), |a| a as *mut u8);
+		}
+		chunk_alloc(layout)
 	}
 
-	unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-		/* bump allocator: dealloc is a no-op */
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		/* Only dedicated large mappings are returned to the kernel */
+		if layout.size() >= LARGE_ALLOC && !in_static_heap(ptr) {
+			serix_munmap(ptr as usize, layout.size());
+		}
 	}
 }
 
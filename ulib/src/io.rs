@@ -7,6 +7,7 @@
 
 extern crate alloc;
 use alloc::string::String;
+use crate::signal::EINTR;
 use crate::{STDIN, STDOUT, read, write as sys_write};
 
 /*
@@ -14,14 +15,19 @@ use crate::{STDIN, STDOUT, read, write as sys_write};
  *
  * Reads bytes one at a time from STDIN, echoing each printable byte back.
  * Handles backspace (0x08, 0x7F) by removing the last character.
- * Returns on CR or LF. Returns None if stdin yields zero bytes (EOF).
+ * Returns on CR or LF. Returns None if stdin yields zero bytes (EOF) or
+ * an error; reads interrupted by a caught signal are retried.
  */
 pub fn read_line() -> Option<String> {
 	let mut line = String::new();
 	let mut buf = [0u8; 1];
 	loop {
-		let n = read(STDIN, &mut buf);
-		if n == 0 {
+		let n = read(STDIN, &mut buf) as isize;
+		if n == EINTR {
+			/* A signal handler ran; keep reading the same line */
+			continue;
+		}
+		if n <= 0 {
 			return None;
 		}
 		match buf[0] {
@@ -2,9 +2,27 @@
 
 use core::arch::asm;
 
+pub mod env;
+pub mod errno;
 pub mod fmt;
 pub mod heap;
 pub mod io;
+pub mod shm;
+pub mod signal;
+pub mod spawn;
+pub mod sync;
+pub mod thread;
+pub mod time;
+pub mod tls;
+
+pub use env::{args, env, getenv};
+pub use shm::{ShmCap, serix_shm_close, serix_shm_create, serix_shm_grant, serix_shm_map};
+pub use signal::{serix_kill, serix_sigaction, serix_signal, serix_sigprocmask};
+pub use spawn::{FileActions, SPAWN_LINUX, serix_spawn_actions};
+pub use sync::serix_futex;
+pub use thread::{Thread, serix_gettid, serix_thread_create, serix_thread_exit, serix_thread_join};
+pub use time::{serix_clock_gettime, serix_nanosleep, sleep};
+pub use tls::{serix_arch_prctl, set_thread_pointer, thread_pointer};
 
 /*
  * Serix System Call Numbers
@@ -18,6 +36,9 @@ const SYS_GETPID: usize      =  2;
 const SYS_GETPPID: usize     =  3;
 const SYS_SPAWN: usize       =  4;
 const SYS_WAIT: usize        =  5;
+const SYS_FORK: usize        =  6;
+const SYS_EXEC: usize        =  7;
+const SYS_ARCH_PRCTL: usize  =  8;
 
 const SYS_OPEN: usize        = 10;
 const SYS_CLOSE: usize       = 11;
@@ -28,14 +49,66 @@ const SYS_DUP: usize         = 15;
 const SYS_DUP2: usize        = 16;
 const SYS_PIPE: usize        = 17;
 const SYS_GETDENTS: usize    = 18;
+const SYS_FCNTL: usize       = 19;
 
 const SYS_MKDIR: usize       = 20;
 const SYS_UNLINK: usize      = 21;
+const SYS_CHDIR: usize       = 22;
+const SYS_GETCWD: usize      = 23;
 
 const SYS_SEND: usize        = 30;
 const SYS_RECV: usize        = 31;
 const SYS_RECV_BLOCK: usize  = 32;
 
+const SYS_MMAP: usize        = 40;
+const SYS_MUNMAP: usize      = 41;
+const SYS_MPROTECT: usize    = 42;
+const SYS_SHM_CREATE: usize  = 43;
+const SYS_SHM_MAP: usize     = 44;
+const SYS_SHM_GRANT: usize   = 45;
+const SYS_SHM_CLOSE: usize   = 46;
+
+const SYS_KILL: usize        = 50;
+const SYS_SIGACTION: usize   = 51;
+const SYS_SIGPROCMASK: usize = 52;
+const SYS_SIGRETURN: usize   = 53;
+
+const SYS_NANOSLEEP: usize   = 60;
+const SYS_CLOCK_GETTIME: usize = 61;
+
+const SYS_THREAD_CREATE: usize = 70;
+const SYS_THREAD_EXIT: usize = 71;
+const SYS_THREAD_JOIN: usize = 72;
+const SYS_GETTID: usize      = 73;
+const SYS_FUTEX: usize       = 74;
+
+/* Open flags (see SYS_OPEN) */
+pub const O_CLOEXEC: usize = 0x8_0000;
+
+/* serix_fcntl commands and per-fd flags */
+pub const F_DUPFD: usize = 0;
+pub const F_GETFD: usize = 1;
+pub const F_SETFD: usize = 2;
+pub const F_DUPFD_CLOEXEC: usize = 1030;
+pub const FD_CLOEXEC: usize = 1;
+
+/* Memory protection (see SYS_MMAP / SYS_MPROTECT) */
+pub const PROT_NONE: usize = 0x0;
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+
+/* mmap flags (see SYS_MMAP) */
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/* Maximum argv/envp entries accepted by serix_spawn_args / serix_exec_args */
+pub const MAX_EXEC_ARGS: usize = 32;
+
+/* Argument list too long (negative errno) */
+const E2BIG: i64 = errno::E2BIG as i64;
+
 pub const STDIN: usize = 0;
 pub const STDOUT: usize = 1;
 pub const STDERR: usize = 2;
@@ -135,6 +208,38 @@ unsafe fn syscall4(nr: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize
 	}
 }
 
+/*
+ * syscall6 - Generic syscall wrapper for 6 arguments
+ */
+#[inline(always)]
+unsafe fn syscall6(
+	nr: usize,
+	arg1: usize,
+	arg2: usize,
+	arg3: usize,
+	arg4: usize,
+	arg5: usize,
+	arg6: usize,
+) -> usize {
+	unsafe {
+		let ret: usize;
+		asm!(
+		"syscall",
+		in("rax") nr,
+		in("rdi") arg1,
+		in("rsi") arg2,
+		in("rdx") arg3,
+		in("r10") arg4,
+		in("r8") arg5,
+		in("r9") arg6,
+		lateout("rax") ret,
+		out("rcx") _,
+		out("r11") _,
+		);
+		ret
+	}
+}
+
 /*
  * syscall0 - Generic syscall wrapper for 0 arguments
  */
@@ -183,13 +288,24 @@ pub fn read(fd: usize, buf: &mut [u8]) -> usize {
  */
 /*
  * serix_open - Open a file by path
- * @path: Absolute path string
+ * @path: VFS path, relative to the cwd or absolute
  *
- * Return: fd (>= 3) on success, or negative errno
+ * Return: Lowest free fd on success, or negative errno
  */
 pub fn serix_open(path: &str) -> isize {
+	serix_open_flags(path, 0)
+}
+
+/*
+ * serix_open_flags - Open a file by path with flags
+ * @path: VFS path, relative to the cwd or absolute
+ * @flags: O_CLOEXEC or 0
+ *
+ * Return: Lowest free fd on success, or negative errno
+ */
+pub fn serix_open_flags(path: &str, flags: usize) -> isize {
 	unsafe {
-		syscall2(SYS_OPEN, path.as_ptr() as usize, path.len()) as isize
+		syscall3(SYS_OPEN, path.as_ptr() as usize, path.len(), flags) as isize
 	}
 }
 
@@ -222,7 +338,7 @@ pub fn serix_seek(fd: usize, offset: usize) -> isize {
  */
 /*
  * serix_mkdir - Create a directory
- * @path: Absolute path of directory to create
+ * @path: Path of directory to create, relative to the cwd or absolute
  *
  * Return: 0 on success, negative errno on error
  */
@@ -234,7 +350,7 @@ pub fn serix_mkdir(path: &str) -> isize {
 
 /*
  * serix_unlink - Delete a file
- * @path: Absolute path of file to delete
+ * @path: Path of file to delete, relative to the cwd or absolute
  *
  * Return: 0 on success, negative errno on error
  */
@@ -244,6 +360,31 @@ pub fn serix_unlink(path: &str) -> isize {
 	}
 }
 
+/*
+ * serix_chdir - Change the working directory
+ * @path: Directory path, relative to the cwd or absolute
+ *
+ * Return: 0 on success, negative errno on error
+ */
+pub fn serix_chdir(path: &str) -> isize {
+	unsafe {
+		syscall2(SYS_CHDIR, path.as_ptr() as usize, path.len()) as isize
+	}
+}
+
+/*
+ * serix_getcwd - Get the working directory
+ * @buf: Buffer for the NUL-terminated absolute path
+ *
+ * Return: Bytes written including the NUL, or negative errno (-ERANGE if
+ *         @buf is too small)
+ */
+pub fn serix_getcwd(buf: &mut [u8]) -> isize {
+	unsafe {
+		syscall2(SYS_GETCWD, buf.as_mut_ptr() as usize, buf.len()) as isize
+	}
+}
+
 pub fn exit(code: i32) -> ! {
 	unsafe {
 		syscall1(SYS_EXIT, code as usize);
@@ -258,7 +399,7 @@ pub fn serix_yield() {
 	unsafe { syscall0(SYS_YIELD); }
 }
 
-/* serix_getpid - Return the calling task's ID */
+/* serix_getpid - Return the calling process's ID */
 pub fn serix_getpid() -> u64 {
 	unsafe { syscall0(SYS_GETPID) as u64 }
 }
@@ -268,15 +409,71 @@ pub fn serix_getppid() -> u64 {
 	unsafe { syscall0(SYS_GETPPID) as u64 }
 }
 
+/*
+ * struct UserStr - argv/envp entry understood by SYS_SPAWN / SYS_EXEC
+ *
+ * Arrays of these end with an entry whose ptr is null.
+ */
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UserStr {
+	ptr: usize,
+	len: usize,
+}
+
+/*
+ * str_array - Build a null-terminated UserStr array on the stack
+ * @strs: Strings to describe (at most MAX_EXEC_ARGS)
+ *
+ * Return: The array, or None if @strs is too long
+ */
+fn str_array(strs: &[&str]) -> Option<[UserStr; MAX_EXEC_ARGS + 1]> {
+	if strs.len() > MAX_EXEC_ARGS {
+		return None;
+	}
+	let mut arr = [UserStr { ptr: 0, len: 0 }; MAX_EXEC_ARGS + 1];
+	for (slot, s) in arr.iter_mut().zip(strs) {
+		*slot = UserStr { ptr: s.as_ptr() as usize, len: s.len() };
+	}
+	Some(arr)
+}
+
 /*
  * serix_spawn - Create a new user process from an ELF on the VFS.
- * @path: Absolute path to the ELF binary
+ * @path: Path to the ELF binary, relative to the cwd or absolute
+ *
+ * The child gets argv = [path] and an empty environment.
  *
  * Return: child pid (> 0) on success, negative errno on failure
  */
 pub fn serix_spawn(path: &str) -> i64 {
 	unsafe {
-		syscall2(SYS_SPAWN, path.as_ptr() as usize, path.len()) as i64
+		syscall6(SYS_SPAWN, path.as_ptr() as usize, path.len(), 0, 0, 0, 0) as i64
+	}
+}
+
+/*
+ * serix_spawn_args - Create a new user process with arguments.
+ * @path: Path to the ELF binary, relative to the cwd or absolute
+ * @argv: Argument vector, argv[0] conventionally the program name
+ * @envp: Environment as "KEY=value" strings
+ *
+ * Return: child pid (> 0) on success, negative errno on failure
+ */
+pub fn serix_spawn_args(path: &str, argv: &[&str], envp: &[&str]) -> i64 {
+	let (Some(av), Some(ev)) = (str_array(argv), str_array(envp)) else {
+		return E2BIG;
+	};
+	unsafe {
+		syscall6(
+			SYS_SPAWN,
+			path.as_ptr() as usize,
+			path.len(),
+			av.as_ptr() as usize,
+			ev.as_ptr() as usize,
+			0,
+			0,
+		) as i64
 	}
 }
 
@@ -284,7 +481,8 @@ pub fn serix_spawn(path: &str) -> i64 {
  * serix_wait - Wait for a child process to exit.
  * @pid: Child pid (-1 = any child)
  *
- * Return: (child_pid, exit_status) on success
+ * Return: (child_pid, wait_status) on success; decode the status with
+ *         wifexited()/wexitstatus() and wifsignaled()/wtermsig()
  */
 pub fn serix_wait(pid: i64) -> (i64, i32) {
 	let mut status: i32 = 0;
@@ -300,6 +498,77 @@ pub fn serix_wait(pid: i64) -> (i64, i32) {
 	(ret, status)
 }
 
+/* wifexited - True if the child terminated through exit() */
+pub fn wifexited(status: i32) -> bool {
+	status & 0x7F == 0
+}
+
+/* wexitstatus - Exit code of a child for which wifexited() holds */
+pub fn wexitstatus(status: i32) -> i32 {
+	(status >> 8) & 0xFF
+}
+
+/* wifsignaled - True if the child was killed by a signal (e.g. a fault) */
+pub fn wifsignaled(status: i32) -> bool {
+	status & 0x7F != 0
+}
+
+/* wtermsig - Signal that killed a child for which wifsignaled() holds */
+pub fn wtermsig(status: i32) -> i32 {
+	status & 0x7F
+}
+
+/*
+ * serix_fork - Duplicate the calling process.
+ *
+ * The child gets a copy-on-write copy of the address space and shares
+ * all open file descriptors with the parent.
+ *
+ * Return: child pid in the parent, 0 in the child, negative errno on failure
+ */
+pub fn serix_fork() -> i64 {
+	unsafe { syscall0(SYS_FORK) as i64 }
+}
+
+/*
+ * serix_exec - Replace the calling process's image with an ELF.
+ * @path: Path to the ELF binary, relative to the cwd or absolute
+ *
+ * The task keeps its pid, parent, children and every fd not opened
+ * with O_CLOEXEC. The new image gets argv = [path] and an empty
+ * environment.
+ *
+ * Return: only returns on failure, with a negative errno
+ */
+pub fn serix_exec(path: &str) -> i64 {
+	unsafe {
+		syscall4(SYS_EXEC, path.as_ptr() as usize, path.len(), 0, 0) as i64
+	}
+}
+
+/*
+ * serix_exec_args - Replace the calling process's image, with arguments.
+ * @path: Path to the ELF binary, relative to the cwd or absolute
+ * @argv: Argument vector for the new image
+ * @envp: Environment for the new image
+ *
+ * Return: only returns on failure, with a negative errno
+ */
+pub fn serix_exec_args(path: &str, argv: &[&str], envp: &[&str]) -> i64 {
+	let (Some(av), Some(ev)) = (str_array(argv), str_array(envp)) else {
+		return E2BIG;
+	};
+	unsafe {
+		syscall4(
+			SYS_EXEC,
+			path.as_ptr() as usize,
+			path.len(),
+			av.as_ptr() as usize,
+			ev.as_ptr() as usize,
+		) as i64
+	}
+}
+
 /*
  * serix_getdents - Read directory entries into a buffer.
  * @fd: Open directory fd
@@ -313,14 +582,31 @@ pub fn serix_getdents(fd: usize, buf: &mut [u8]) -> isize {
 	}
 }
 
-/* serix_dup - Duplicate fd to the next available descriptor */
+/* serix_dup - Duplicate fd to the lowest free descriptor */
 pub fn serix_dup(fd: usize) -> isize {
 	unsafe { syscall1(SYS_DUP, fd) as isize }
 }
 
 /* serix_dup2 - Duplicate old_fd to new_fd */
 pub fn serix_dup2(old_fd: usize, new_fd: usize) -> isize {
-	unsafe { syscall2(SYS_DUP2, old_fd, new_fd) as isize }
+	serix_dup3(old_fd, new_fd, 0)
+}
+
+/* serix_dup3 - Duplicate old_fd to new_fd with flags (O_CLOEXEC or 0) */
+pub fn serix_dup3(old_fd: usize, new_fd: usize, flags: usize) -> isize {
+	unsafe { syscall3(SYS_DUP2, old_fd, new_fd, flags) as isize }
+}
+
+/*
+ * serix_fcntl - Manipulate a file descriptor
+ * @fd: File descriptor
+ * @cmd: F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD or F_SETFD
+ * @arg: Minimum new fd for F_DUPFD*, FD_CLOEXEC or 0 for F_SETFD
+ *
+ * Return: new fd / flags / 0 on success, negative errno on error
+ */
+pub fn serix_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+	unsafe { syscall3(SYS_FCNTL, fd, cmd, arg) as isize }
 }
 
 /*
@@ -330,5 +616,54 @@ pub fn serix_dup2(old_fd: usize, new_fd: usize) -> isize {
  * Return: 0 on success, negative errno on error
  */
 pub fn serix_pipe(fds: &mut [u64; 2]) -> isize {
-	unsafe { syscall2(SYS_PIPE, fds.as_mut_ptr() as usize, 0) as isize }
+	serix_pipe2(fds, 0)
+}
+
+/*
+ * serix_pipe2 - Create a pipe with flags.
+ * @fds: [read_fd, write_fd] output array
+ * @flags: O_CLOEXEC or 0
+ *
+ * Return: 0 on success, negative errno on error
+ */
+pub fn serix_pipe2(fds: &mut [u64; 2], flags: usize) -> isize {
+	unsafe { syscall2(SYS_PIPE, fds.as_mut_ptr() as usize, flags) as isize }
+}
+
+/*
+ * serix_mmap - Map anonymous memory.
+ * @addr: Placement hint (0 = anywhere), or exact address with MAP_FIXED
+ * @len: Length in bytes (rounded up to pages)
+ * @prot: PROT_* bits
+ * @flags: MAP_PRIVATE | MAP_ANONYMOUS, optionally MAP_FIXED
+ *
+ * Pages are zero-filled on first access.
+ *
+ * Return: start address on success, negative errno on failure
+ */
+pub fn serix_mmap(addr: usize, len: usize, prot: usize, flags: usize) -> isize {
+	unsafe { syscall4(SYS_MMAP, addr, len, prot, flags) as isize }
+}
+
+/*
+ * serix_munmap - Remove a range of mappings.
+ * @addr: Page-aligned start address
+ * @len: Length in bytes
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_munmap(addr: usize, len: usize) -> isize {
+	unsafe { syscall2(SYS_MUNMAP, addr, len) as isize }
+}
+
+/*
+ * serix_mprotect - Change the protection of mapped memory.
+ * @addr: Page-aligned start address
+ * @len: Length in bytes
+ * @prot: New PROT_* bits
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+	unsafe { syscall3(SYS_MPROTECT, addr, len, prot) as isize }
 }
@@ -0,0 +1,76 @@
+/*
+ * shm.rs - Shared memory objects for userspace programs
+ *
+ * Wrappers for SYS_SHM_CREATE, SYS_SHM_MAP, SYS_SHM_GRANT and
+ * SYS_SHM_CLOSE. An object is named by a MemoryRegion capability, a
+ * 16-byte key that only the process it was made for can use. To share
+ * an object, grant a key to the receiving task and send it the key,
+ * e.g. as the payload of an IPC message; the receiver maps it with
+ * serix_shm_map().
+ *
+ * The memory stays valid as long as any process has it mapped, even
+ * after every capability is closed.
+ */
+
+use crate::{SYS_SHM_CLOSE, SYS_SHM_CREATE, SYS_SHM_GRANT, SYS_SHM_MAP, syscall1, syscall2, syscall3};
+
+/*
+ * struct ShmCap - A MemoryRegion capability key
+ */
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShmCap {
+	pub key: [u8; 16],
+}
+
+/*
+ * serix_shm_create - Create a shared memory object and map it
+ * @size: Size in bytes (rounded up to pages, at most 64 MiB)
+ * @prot: PROT_* bits of the mapping
+ * @cap: Receives the caller's capability
+ *
+ * The memory starts zero-filled.
+ *
+ * Return: start address on success, negative errno on failure
+ */
+pub fn serix_shm_create(size: usize, prot: usize, cap: &mut ShmCap) -> isize {
+	unsafe { syscall3(SYS_SHM_CREATE, size, prot, cap as *mut ShmCap as usize) as isize }
+}
+
+/*
+ * serix_shm_map - Map the object a capability names
+ * @cap: Capability held by the caller
+ * @prot: PROT_* bits of the mapping
+ *
+ * Return: start address on success, negative errno on failure
+ */
+pub fn serix_shm_map(cap: &ShmCap, prot: usize) -> isize {
+	unsafe { syscall2(SYS_SHM_MAP, cap as *const ShmCap as usize, prot) as isize }
+}
+
+/*
+ * serix_shm_grant - Make a capability for another process
+ * @cap: Capability held by the caller
+ * @tid: Any task of the receiving process
+ * @out: Receives the new capability, for the caller to send on
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_shm_grant(cap: &ShmCap, tid: u64, out: &mut ShmCap) -> isize {
+	unsafe {
+		syscall3(SYS_SHM_GRANT, cap as *const ShmCap as usize, tid as usize, out as *mut ShmCap as usize)
+			as isize
+	}
+}
+
+/*
+ * serix_shm_close - Drop a capability
+ * @cap: Capability held by the caller
+ *
+ * Mappings made with it stay; unmap them with serix_munmap().
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_shm_close(cap: &ShmCap) -> isize {
+	unsafe { syscall1(SYS_SHM_CLOSE, cap as *const ShmCap as usize) as isize }
+}
@@ -0,0 +1,156 @@
+/*
+ * signal.rs - Signals for userspace programs
+ *
+ * Wrappers for SYS_KILL, SYS_SIGACTION and SYS_SIGPROCMASK. Handlers
+ * installed through SigAction::handler() return into sigreturn_restorer,
+ * which issues SYS_SIGRETURN so the kernel can resume the interrupted
+ * code.
+ */
+
+use core::arch::naked_asm;
+
+use crate::{SYS_KILL, SYS_SIGACTION, SYS_SIGPROCMASK, SYS_SIGRETURN, syscall2, syscall3};
+
+/* Signal numbers (Linux values) */
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+
+/* Special handler values */
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+/* SigAction flags */
+pub const SA_RESTORER: u64 = 0x0400_0000;
+pub const SA_NODEFER: u64 = 0x4000_0000;
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+
+/* serix_sigprocmask operations */
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/* Interrupted by a signal (negative errno) */
+pub use crate::errno::EINTR;
+
+/* Signature of a signal handler; receives the signal number */
+pub type SigHandler = extern "C" fn(i32);
+
+/*
+ * struct SigAction - Disposition of one signal (kernel layout)
+ * @handler: SIG_DFL, SIG_IGN or the handler address
+ * @flags: SA_* flags
+ * @restorer: Code the handler returns into
+ * @mask: Extra signals blocked while the handler runs
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigAction {
+	pub handler: u64,
+	pub flags: u64,
+	pub restorer: u64,
+	pub mask: u64,
+}
+
+impl SigAction {
+	/* handler - Catch the signal with @f */
+	pub fn handler(f: SigHandler) -> Self {
+		Self {
+			handler: f as usize as u64,
+			flags: SA_RESTORER,
+			restorer: sigreturn_restorer as *const () as u64,
+			mask: 0,
+		}
+	}
+
+	/* ignore - Discard the signal */
+	pub fn ignore() -> Self {
+		Self { handler: SIG_IGN, ..Self::default() }
+	}
+}
+
+/* sigmask - Mask bit of a signal, for SigAction::mask and sigprocmask */
+pub const fn sigmask(sig: i32) -> u64 {
+	1u64 << sig
+}
+
+/*
+ * sigreturn_restorer - Return path of every handler
+ *
+ * The kernel pushes this address as the handler's return address.
+ * SYS_SIGRETURN does not come back here.
+ */
+#[unsafe(naked)]
+unsafe extern "C" fn sigreturn_restorer() -> ! {
+	naked_asm!(
+		"mov eax, {nr}",
+		"syscall",
+		"ud2",
+		nr = const SYS_SIGRETURN,
+	)
+}
+
+/*
+ * serix_kill - Send a signal to a process.
+ * @pid: Target process ID
+ * @sig: Signal number, or 0 to check that @pid exists
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_kill(pid: u64, sig: i32) -> isize {
+	unsafe { syscall2(SYS_KILL, pid as usize, sig as usize) as isize }
+}
+
+/*
+ * serix_sigaction - Install and/or query the disposition of a signal.
+ * @sig: Signal number (not SIGKILL or SIGSTOP when installing)
+ * @act: New disposition, or None to leave it unchanged
+ * @old: Receives the previous disposition if Some
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_sigaction(sig: i32, act: Option<&SigAction>, old: Option<&mut SigAction>) -> isize {
+	let act = act.map_or(0, |a| a as *const SigAction as usize);
+	let old = old.map_or(0, |o| o as *mut SigAction as usize);
+	unsafe { syscall3(SYS_SIGACTION, sig as usize, act, old) as isize }
+}
+
+/*
+ * serix_signal - Catch a signal with a handler.
+ * @sig: Signal number
+ * @f: Handler, run with @sig blocked
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_signal(sig: i32, f: SigHandler) -> isize {
+	serix_sigaction(sig, Some(&SigAction::handler(f)), None)
+}
+
+/*
+ * serix_sigprocmask - Change the set of blocked signals.
+ * @how: SIG_BLOCK, SIG_UNBLOCK or SIG_SETMASK
+ * @set: Signal set built with sigmask(), or None to only query
+ * @old: Receives the previous mask if Some
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_sigprocmask(how: usize, set: Option<u64>, old: Option<&mut u64>) -> isize {
+	let set_ref = set.as_ref().map_or(0, |s| s as *const u64 as usize);
+	let old = old.map_or(0, |o| o as *mut u64 as usize);
+	unsafe { syscall3(SYS_SIGPROCMASK, how, set_ref, old) as isize }
+}
@@ -0,0 +1,150 @@
+/*
+ * spawn.rs - posix_spawn-style file actions for userspace programs
+ *
+ * A FileActions list tells SYS_SPAWN how to rewire the child's file
+ * descriptors before it starts: dup2 a parent fd onto a child fd, close
+ * an fd, or open a path as an fd. With pipes this is enough for a shell
+ * to build pipelines and redirections without fork():
+ *
+ *   let mut fa = FileActions::new();
+ *   fa.add_dup2(pipe_write_fd, 1);
+ *   fa.add_close(pipe_write_fd);
+ *   serix_spawn_actions("/bin/ls", &["ls"], &[], &fa, 0);
+ *
+ * Actions run in order on a copy of the caller's descriptor table;
+ * close-on-exec descriptors are dropped afterwards, as after exec.
+ */
+
+use core::marker::PhantomData;
+
+use crate::{E2BIG, SYS_SPAWN, str_array, syscall6};
+
+/* serix_spawn_actions() flag: run the child under the Linux syscall ABI */
+pub const SPAWN_LINUX: usize = 0x1;
+
+/* Maximum number of actions in one FileActions list */
+pub const MAX_FILE_ACTIONS: usize = 16;
+
+/* Action kinds (kernel ABI) */
+const SPAWN_FA_END: usize = 0;
+const SPAWN_FA_DUP2: usize = 1;
+const SPAWN_FA_CLOSE: usize = 2;
+const SPAWN_FA_OPEN: usize = 3;
+
+/*
+ * struct UserFileAction - One action as understood by SYS_SPAWN
+ *
+ * Arrays of these end with a SPAWN_FA_END entry.
+ */
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UserFileAction {
+	kind: usize,
+	fd: usize,
+	arg: usize,
+	path_ptr: usize,
+	path_len: usize,
+}
+
+const END: UserFileAction = UserFileAction { kind: SPAWN_FA_END, fd: 0, arg: 0, path_ptr: 0, path_len: 0 };
+
+/*
+ * struct FileActions - File actions for serix_spawn_actions()
+ *
+ * Borrows the paths given to add_open() until the spawn.
+ */
+pub struct FileActions<'a> {
+	actions: [UserFileAction; MAX_FILE_ACTIONS + 1],
+	len: usize,
+	_paths: PhantomData<&'a str>,
+}
+
+impl Default for FileActions<'_> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<'a> FileActions<'a> {
+	/* new - Empty list: the child inherits the caller's fds unchanged */
+	pub const fn new() -> Self {
+		Self { actions: [END; MAX_FILE_ACTIONS + 1], len: 0, _paths: PhantomData }
+	}
+
+	fn push(&mut self, action: UserFileAction) -> bool {
+		if self.len >= MAX_FILE_ACTIONS {
+			return false;
+		}
+		self.actions[self.len] = action;
+		self.len += 1;
+		true
+	}
+
+	/*
+	 * add_dup2 - Make child fd @newfd refer to parent fd @fd
+	 *
+	 * Return: false if the list is full
+	 */
+	pub fn add_dup2(&mut self, fd: usize, newfd: usize) -> bool {
+		self.push(UserFileAction { kind: SPAWN_FA_DUP2, fd: newfd, arg: fd, ..END })
+	}
+
+	/*
+	 * add_close - Close @fd in the child
+	 *
+	 * Return: false if the list is full
+	 */
+	pub fn add_close(&mut self, fd: usize) -> bool {
+		self.push(UserFileAction { kind: SPAWN_FA_CLOSE, fd, ..END })
+	}
+
+	/*
+	 * add_open - Open @path as child fd @fd
+	 * @flags: O_CLOEXEC or 0
+	 *
+	 * Return: false if the list is full
+	 */
+	pub fn add_open(&mut self, fd: usize, path: &'a str, flags: usize) -> bool {
+		self.push(UserFileAction {
+			kind: SPAWN_FA_OPEN,
+			fd,
+			arg: flags,
+			path_ptr: path.as_ptr() as usize,
+			path_len: path.len(),
+		})
+	}
+}
+
+/*
+ * serix_spawn_actions - Create a new process with rewired descriptors.
+ * @path: Path to the ELF binary, relative to the cwd or absolute
+ * @argv: Argument vector, argv[0] conventionally the program name
+ * @envp: Environment as "KEY=value" strings
+ * @actions: File actions applied to the child's fd table
+ * @flags: SPAWN_LINUX to run a Linux (e.g. static musl) binary, or 0
+ *
+ * Return: child pid (> 0) on success, negative errno on failure
+ *         (e.g. EBADF if an action names an fd that is not open)
+ */
+pub fn serix_spawn_actions(
+	path: &str,
+	argv: &[&str],
+	envp: &[&str],
+	actions: &FileActions,
+	flags: usize,
+) -> i64 {
+	let (Some(av), Some(ev)) = (str_array(argv), str_array(envp)) else {
+		return E2BIG;
+	};
+	unsafe {
+		syscall6(
+			SYS_SPAWN,
+			path.as_ptr() as usize,
+			path.len(),
+			av.as_ptr() as usize,
+			ev.as_ptr() as usize,
+			actions.actions.as_ptr() as usize,
+			flags,
+		) as i64
+	}
+}
@@ -0,0 +1,218 @@
+/*
+ * sync.rs - Blocking locks for userspace programs
+ *
+ * serix_futex() wraps SYS_FUTEX; Mutex and Condvar are built on it.
+ * Both stay in userspace while uncontended and only call the kernel to
+ * sleep when they have to wait, or to wake a thread known to be waiting.
+ *
+ * They work between the threads of a process (ulib::thread), which
+ * share all memory.
+ */
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use crate::time::Timespec;
+use crate::{SYS_FUTEX, syscall4};
+
+/* serix_futex operations */
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+/* Negative errnos returned by FUTEX_WAIT */
+pub use crate::errno::{EAGAIN, ETIMEDOUT};
+
+/*
+ * serix_futex - Sleep on or wake a futex word
+ * @word: The futex word
+ * @op: FUTEX_WAIT or FUTEX_WAKE
+ * @val: Expected value (WAIT) or most threads to wake (WAKE)
+ * @timeout: Longest time to sleep (WAIT only, None = until woken)
+ *
+ * Return: 0 or the number of threads woken, negative errno on failure
+ *         (EAGAIN if the word did not hold @val, ETIMEDOUT, EINTR)
+ */
+pub fn serix_futex(word: &AtomicU32, op: usize, val: u32, timeout: Option<&Timespec>) -> isize {
+	let ts = timeout.map_or(0, |t| t as *const Timespec as usize);
+	unsafe {
+		syscall4(SYS_FUTEX, word as *const AtomicU32 as usize, op, val as usize, ts) as isize
+	}
+}
+
+fn futex_wait(word: &AtomicU32, val: u32, timeout: Option<&Timespec>) -> isize {
+	serix_futex(word, FUTEX_WAIT, val, timeout)
+}
+
+fn futex_wake(word: &AtomicU32, count: u32) -> isize {
+	serix_futex(word, FUTEX_WAKE, count, None)
+}
+
+/* Mutex states */
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2; /* Locked, and someone may be asleep on it */
+
+/*
+ * struct Mutex - Sleeping mutual exclusion lock
+ * @state: UNLOCKED, LOCKED or CONTENDED; the futex word
+ * @data: Protected value
+ *
+ * unlock() only enters the kernel if the lock was CONTENDED.
+ */
+pub struct Mutex<T> {
+	state: AtomicU32,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+/*
+ * struct MutexGuard - Proof of holding a Mutex; unlocks on drop
+ */
+pub struct MutexGuard<'a, T> {
+	mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+	pub const fn new(data: T) -> Self {
+		Self { state: AtomicU32::new(UNLOCKED), data: UnsafeCell::new(data) }
+	}
+
+	/*
+	 * lock - Take the lock, sleeping while another thread holds it
+	 */
+	pub fn lock(&self) -> MutexGuard<'_, T> {
+		if self.state
+			.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+			.is_err()
+		{
+			self.lock_contended();
+		}
+		MutexGuard { mutex: self }
+	}
+
+	/*
+	 * try_lock - Take the lock if it is free
+	 *
+	 * Return: The guard, or None if another thread holds the lock
+	 */
+	pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+		self.state
+			.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+			.ok()
+			.map(|_| MutexGuard { mutex: self })
+	}
+
+	/*
+	 * lock_contended - Slow path of lock()
+	 *
+	 * Marks the lock CONTENDED before sleeping so that the holder's
+	 * unlock() wakes us. Having slept once we cannot know whether others
+	 * still wait, so the lock is taken as CONTENDED too.
+	 */
+	fn lock_contended(&self) {
+		while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+			futex_wait(&self.state, CONTENDED, None);
+		}
+	}
+
+	fn unlock(&self) {
+		if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+			futex_wake(&self.state, 1);
+		}
+	}
+
+	/* into_inner - Consume the mutex and return the value */
+	pub fn into_inner(self) -> T {
+		self.data.into_inner()
+	}
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+	fn drop(&mut self) {
+		self.mutex.unlock();
+	}
+}
+
+/*
+ * struct Condvar - Condition variable for use with Mutex
+ * @seq: Bumped by every notify; the futex word waiters sleep on
+ *
+ * A waiter samples @seq while still holding the mutex, so a notify
+ * issued after it unlocked changes the word and FUTEX_WAIT returns at
+ * once instead of missing it. Wakeups may be spurious: always re-check
+ * the condition in a loop.
+ */
+pub struct Condvar {
+	seq: AtomicU32,
+}
+
+impl Condvar {
+	pub const fn new() -> Self {
+		Self { seq: AtomicU32::new(0) }
+	}
+
+	/*
+	 * wait - Unlock, sleep until notified, lock again
+	 * @guard: Guard of the mutex protecting the condition
+	 */
+	pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+		self.wait_inner(guard, None).0
+	}
+
+	/*
+	 * wait_timeout - wait() giving up after @dur
+	 * @guard: Guard of the mutex protecting the condition
+	 * @dur: Longest time to sleep
+	 *
+	 * Return: The guard again, and true if the time ran out
+	 */
+	pub fn wait_timeout<'a, T>(&self, guard: MutexGuard<'a, T>, dur: Duration) -> (MutexGuard<'a, T>, bool) {
+		self.wait_inner(guard, Some(&Timespec::from(dur)))
+	}
+
+	fn wait_inner<'a, T>(&self, guard: MutexGuard<'a, T>, timeout: Option<&Timespec>) -> (MutexGuard<'a, T>, bool) {
+		let mutex = guard.mutex;
+		let seq = self.seq.load(Ordering::Relaxed);
+		drop(guard);
+		let timed_out = futex_wait(&self.seq, seq, timeout) == ETIMEDOUT;
+		/* Others may be asleep on the mutex too, so take it as contended */
+		mutex.lock_contended();
+		(MutexGuard { mutex }, timed_out)
+	}
+
+	/* notify_one - Wake one thread waiting on the condition */
+	pub fn notify_one(&self) {
+		self.seq.fetch_add(1, Ordering::Release);
+		futex_wake(&self.seq, 1);
+	}
+
+	/* notify_all - Wake every thread waiting on the condition */
+	pub fn notify_all(&self) {
+		self.seq.fetch_add(1, Ordering::Release);
+		futex_wake(&self.seq, u32::MAX);
+	}
+}
+
+impl Default for Condvar {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,162 @@
+/*
+ * thread.rs - Threads for userspace programs
+ *
+ * Wrappers for SYS_THREAD_CREATE, SYS_THREAD_EXIT, SYS_THREAD_JOIN and
+ * SYS_GETTID, plus spawn(), which maps a stack and a TLS block for the
+ * new thread and unmaps them again in Thread::join().
+ *
+ * Threads share the address space, descriptors and capabilities of the
+ * process. exit(), a fatal signal or a fault in any thread ends all of
+ * them; serix_thread_exit() ends only the caller.
+ */
+
+use core::mem::size_of;
+
+use crate::signal::EINTR;
+use crate::{
+	MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE, SYS_GETTID, SYS_THREAD_CREATE,
+	SYS_THREAD_EXIT, SYS_THREAD_JOIN, serix_mmap, serix_munmap, syscall0, syscall1, syscall2,
+	syscall4, wexitstatus,
+};
+
+/* Stack size of threads started with spawn() */
+pub const STACK_SIZE: usize = 64 * 1024;
+
+/*
+ * struct StartInfo - What thread_start() needs, stored above the stack
+ * @f: Thread body
+ * @arg: Its argument
+ */
+#[repr(C)]
+struct StartInfo {
+	f: fn(usize) -> i32,
+	arg: usize,
+}
+
+/*
+ * serix_thread_create - Start a thread in the calling process
+ * @entry: Entry point; called with @arg in RDI and must never return
+ * @stack_top: Top of the thread's stack
+ * @arg: Argument for @entry
+ * @tp: Thread pointer for FS_BASE, or 0 for none
+ *
+ * Return: Thread ID on success, negative errno on failure
+ */
+pub fn serix_thread_create(entry: usize, stack_top: usize, arg: usize, tp: usize) -> i64 {
+	unsafe { syscall4(SYS_THREAD_CREATE, entry, stack_top, arg, tp) as i64 }
+}
+
+/*
+ * serix_thread_exit - Terminate the calling thread
+ * @code: Exit code reported to serix_thread_join()
+ *
+ * From the main thread this exits the process. Does not return.
+ */
+pub fn serix_thread_exit(code: i32) -> ! {
+	unsafe { syscall1(SYS_THREAD_EXIT, code as usize); }
+	unreachable!()
+}
+
+/*
+ * serix_thread_join - Wait for a thread of this process to exit
+ * @tid: Thread to wait for
+ *
+ * Return: (0, wait_status) on success, or (negative errno, 0)
+ */
+pub fn serix_thread_join(tid: u64) -> (i64, i32) {
+	let mut status: i32 = 0;
+	let ret = unsafe {
+		syscall2(SYS_THREAD_JOIN, tid as usize, &mut status as *mut i32 as usize) as i64
+	};
+	(ret, status)
+}
+
+/* serix_gettid - Return the calling thread's ID */
+pub fn serix_gettid() -> u64 {
+	unsafe { syscall0(SYS_GETTID) as u64 }
+}
+
+/*
+ * thread_start - First code a spawn()ed thread runs
+ * @info: StartInfo left above the stack by spawn()
+ */
+extern "C" fn thread_start(info: *const StartInfo) -> ! {
+	let info = unsafe { &*info };
+	serix_thread_exit((info.f)(info.arg))
+}
+
+/*
+ * struct Thread - Handle to a thread started with spawn()
+ * @tid: Thread ID
+ * @base: Start of the stack/TLS mapping
+ * @len: Length of the mapping
+ *
+ * Dropping the handle without joining leaks the mapping.
+ */
+pub struct Thread {
+	tid: u64,
+	base: usize,
+	len: usize,
+}
+
+/*
+ * spawn - Run @f(@arg) in a new thread
+ * @f: Thread body; its return value is the thread's exit code
+ * @arg: Argument for @f
+ *
+ * One mapping holds, from the bottom, a STACK_SIZE stack, the StartInfo
+ * and the thread's TLS block, so a stack overflow runs into unmapped
+ * memory rather than the TLS.
+ *
+ * Return: The thread's handle, or negative errno
+ */
+pub fn spawn(f: fn(usize) -> i32, arg: usize) -> Result<Thread, isize> {
+	let tls = crate::tls::template();
+	let tls_size = tls.map_or(0, |t| t.size());
+	let len = (STACK_SIZE + size_of::<StartInfo>() + tls_size + 0xFFF) & !0xFFF;
+	let base = serix_mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+	if base < 0 {
+		return Err(base);
+	}
+	let base = base as usize;
+
+	let info = (base + STACK_SIZE) as *mut StartInfo;
+	unsafe { info.write(StartInfo { f, arg }); }
+	let tp = match tls {
+		Some(t) => unsafe { t.init_block(base + STACK_SIZE + size_of::<StartInfo>()) },
+		None => 0,
+	};
+
+	let tid = serix_thread_create(thread_start as usize, base + STACK_SIZE, info as usize, tp);
+	if tid < 0 {
+		serix_munmap(base, len);
+		return Err(tid as isize);
+	}
+	Ok(Thread { tid: tid as u64, base, len })
+}
+
+impl Thread {
+	/* tid - The thread's ID */
+	pub fn tid(&self) -> u64 {
+		self.tid
+	}
+
+	/*
+	 * join - Wait for the thread to finish and free its stack
+	 *
+	 * Return: Low 8 bits of the thread's exit code, or negative errno
+	 */
+	pub fn join(self) -> Result<i32, isize> {
+		loop {
+			let (ret, status) = serix_thread_join(self.tid);
+			if ret as isize == EINTR {
+				continue;
+			}
+			if ret < 0 {
+				return Err(ret as isize);
+			}
+			serix_munmap(self.base, self.len);
+			return Ok(wexitstatus(status));
+		}
+	}
+}
@@ -0,0 +1,103 @@
+/*
+ * time.rs - Clocks and sleeping for userspace programs
+ *
+ * Wrappers for SYS_NANOSLEEP and SYS_CLOCK_GETTIME, with Duration-based
+ * sleep(), monotonic() and realtime() on top of them.
+ */
+
+use core::time::Duration;
+
+use crate::signal::EINTR;
+use crate::{SYS_CLOCK_GETTIME, SYS_NANOSLEEP, syscall2};
+
+/* Clock IDs for serix_clock_gettime */
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/*
+ * struct Timespec - Seconds and nanoseconds (kernel layout)
+ * @sec: Whole seconds
+ * @nsec: Nanoseconds, 0..1_000_000_000
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timespec {
+	pub sec: i64,
+	pub nsec: i64,
+}
+
+impl From<Duration> for Timespec {
+	fn from(d: Duration) -> Self {
+		Self {
+			sec: d.as_secs().min(i64::MAX as u64) as i64,
+			nsec: d.subsec_nanos() as i64,
+		}
+	}
+}
+
+impl From<Timespec> for Duration {
+	fn from(ts: Timespec) -> Self {
+		Duration::new(ts.sec.max(0) as u64, ts.nsec.clamp(0, 999_999_999) as u32)
+	}
+}
+
+/*
+ * serix_nanosleep - Suspend the calling process.
+ * @req: Requested duration
+ * @rem: Receives the unslept time if a signal interrupts the sleep
+ *
+ * Return: 0 on success, negative errno on failure (EINTR if interrupted)
+ */
+pub fn serix_nanosleep(req: &Timespec, rem: Option<&mut Timespec>) -> isize {
+	let rem = rem.map_or(0, |r| r as *mut Timespec as usize);
+	unsafe { syscall2(SYS_NANOSLEEP, req as *const Timespec as usize, rem) as isize }
+}
+
+/*
+ * sleep - Suspend the calling process for at least @dur
+ * @dur: Duration to sleep
+ *
+ * Resumes sleeping for the remaining time after a signal handler runs.
+ * Resolution is one timer tick (1.6 ms).
+ */
+pub fn sleep(dur: Duration) {
+	let mut req = Timespec::from(dur);
+	loop {
+		let mut rem = Timespec::default();
+		if serix_nanosleep(&req, Some(&mut rem)) != EINTR {
+			return;
+		}
+		req = rem;
+	}
+}
+
+/*
+ * serix_clock_gettime - Read a clock.
+ * @clock: CLOCK_REALTIME or CLOCK_MONOTONIC
+ * @tp: Receives the current time
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_clock_gettime(clock: usize, tp: &mut Timespec) -> isize {
+	unsafe { syscall2(SYS_CLOCK_GETTIME, clock, tp as *mut Timespec as usize) as isize }
+}
+
+/*
+ * monotonic - Time since boot
+ *
+ * Never goes backwards; use it to measure intervals.
+ */
+pub fn monotonic() -> Duration {
+	let mut ts = Timespec::default();
+	serix_clock_gettime(CLOCK_MONOTONIC, &mut ts);
+	ts.into()
+}
+
+/*
+ * realtime - Time since the Unix epoch (UTC)
+ */
+pub fn realtime() -> Duration {
+	let mut ts = Timespec::default();
+	serix_clock_gettime(CLOCK_REALTIME, &mut ts);
+	ts.into()
+}
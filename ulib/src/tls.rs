@@ -0,0 +1,161 @@
+/*
+ * tls.rs - Thread-Local Storage
+ *
+ * The kernel builds the initial thread's TLS block from the PT_TLS
+ * template (.tdata/.tbss, kept together by user.ld) and points FS_BASE
+ * at it before _start runs. Programs built with
+ * #![feature(thread_local)] can therefore use #[thread_local] statics
+ * straight away; with the static relocation model they compile to
+ * %fs-relative accesses (local-exec).
+ *
+ * The layout is x86_64 TLS variant II: the block sits just below the
+ * thread pointer (FS_BASE) and the word at the thread pointer holds its
+ * own address, so %fs:0 reads it back.
+ *
+ * Threads started with ulib::thread get a block of the same layout,
+ * built here from the PT_TLS header found through AT_PHDR.
+ */
+
+use crate::env::{AT_PHDR, AT_PHNUM, auxv};
+use crate::{SYS_ARCH_PRCTL, syscall2};
+
+/* ELF program header types */
+const PT_PHDR: u32 = 6;
+const PT_TLS: u32 = 7;
+
+/* The TCB is only the self-pointer */
+const TCB_SIZE: usize = 8;
+
+/* serix_arch_prctl codes */
+pub const ARCH_SET_FS: usize = 0x1002;
+pub const ARCH_GET_FS: usize = 0x1003;
+
+/*
+ * serix_arch_prctl - Set or read the FS base
+ * @code: ARCH_SET_FS or ARCH_GET_FS
+ * @addr: New FS base, or address of a u64 receiving the current one
+ *
+ * Return: 0 on success, negative errno on failure
+ */
+pub fn serix_arch_prctl(code: usize, addr: usize) -> isize {
+	unsafe { syscall2(SYS_ARCH_PRCTL, code, addr) as isize }
+}
+
+/*
+ * thread_pointer - FS base of the calling thread
+ *
+ * Return: The thread pointer, or 0 if the program has no TLS block
+ */
+pub fn thread_pointer() -> usize {
+	let mut base: u64 = 0;
+	serix_arch_prctl(ARCH_GET_FS, &mut base as *mut u64 as usize);
+	base as usize
+}
+
+/*
+ * set_thread_pointer - Switch the calling thread to another TLS block
+ * @tp: Thread pointer of the new block; its first word must hold @tp
+ *
+ * Return: 0 on success, negative errno on failure
+ *
+ * Safety: #[thread_local] statics resolve through the new block from
+ * now on, so it must be laid out like the one the kernel built.
+ */
+pub unsafe fn set_thread_pointer(tp: usize) -> isize {
+	serix_arch_prctl(ARCH_SET_FS, tp)
+}
+
+/*
+ * struct ProgramHeader - ELF64 program header, as mapped by the kernel
+ */
+#[repr(C)]
+struct ProgramHeader {
+	p_type: u32,
+	p_flags: u32,
+	p_offset: u64,
+	p_vaddr: u64,
+	p_paddr: u64,
+	p_filesz: u64,
+	p_memsz: u64,
+	p_align: u64,
+}
+
+/*
+ * struct TlsTemplate - The program's PT_TLS image
+ * @init: Initialised part (.tdata) in the loaded image
+ * @memsz: Size of the block including .tbss
+ * @align: Alignment of the thread pointer
+ */
+#[derive(Clone, Copy)]
+pub struct TlsTemplate {
+	init: &'static [u8],
+	memsz: usize,
+	align: usize,
+}
+
+/*
+ * template - Find the program's TLS template
+ *
+ * The load bias is AT_PHDR minus PT_PHDR's p_vaddr; an image without
+ * PT_PHDR is taken to be non-PIE, which is how user.ld links.
+ *
+ * Return: The template, or None if the program has no PT_TLS
+ */
+pub fn template() -> Option<TlsTemplate> {
+	let phdr = auxv(AT_PHDR)?;
+	let phnum = auxv(AT_PHNUM)?;
+	let phdrs = unsafe {
+		core::slice::from_raw_parts(phdr as *const ProgramHeader, phnum)
+	};
+	let bias = phdrs.iter()
+		.find(|p| p.p_type == PT_PHDR)
+		.map_or(0, |p| phdr.wrapping_sub(p.p_vaddr as usize));
+	let tls = phdrs.iter().find(|p| p.p_type == PT_TLS)?;
+	let init = unsafe {
+		core::slice::from_raw_parts(
+			(tls.p_vaddr as usize).wrapping_add(bias) as *const u8,
+			tls.p_filesz as usize,
+		)
+	};
+	Some(TlsTemplate {
+		init,
+		memsz: tls.p_memsz as usize,
+		align: (tls.p_align as usize).max(8),
+	})
+}
+
+fn round_up(x: usize, align: usize) -> usize {
+	(x + align - 1) & !(align - 1)
+}
+
+impl TlsTemplate {
+	/*
+	 * size - Bytes a block needs, with TCB and slack for aligning it
+	 */
+	pub fn size(&self) -> usize {
+		round_up(self.memsz, self.align) + self.align + TCB_SIZE
+	}
+
+	/*
+	 * init_block - Lay out a fresh TLS block
+	 * @area: Start of at least size() writable bytes
+	 *
+	 * Copies .tdata, zeroes .tbss and writes the self-pointer, as the
+	 * kernel does for the initial thread.
+	 *
+	 * Return: The thread pointer to load into FS_BASE
+	 *
+	 * Safety: @area must be valid for writes of size() bytes.
+	 */
+	pub unsafe fn init_block(&self, area: usize) -> usize {
+		let block = round_up(self.memsz, self.align);
+		let tp = round_up(area + block, self.align);
+		let start = (tp - block) as *mut u8;
+		unsafe {
+			core::ptr::copy_nonoverlapping(self.init.as_ptr(), start, self.init.len());
+			core::ptr::write_bytes(start.add(self.init.len()), 0, block - self.init.len());
+			*(tp as *mut usize) = tp;
+		}
+		tp
+	}
+}
@@ -7,6 +7,11 @@
  *
  * Mount table is kept sorted longest-path-first so the first match is
  * always the most specific one.
+ *
+ * Paths are canonicalised lexically (resolve_path) before the mount
+ * table is consulted, so "." and ".." never reach a filesystem driver
+ * and ".." out of a mounted filesystem lands in the directory holding
+ * its mount point.
  */
 
 #![no_std]
@@ -75,6 +80,21 @@ pub trait INode: Send + Sync {
 
 	fn size(&self) -> usize { 0 }
 
+	/*
+	 * is_tty - Whether this node is a terminal
+	 *
+	 * Terminal ioctls (TCGETS and friends) succeed only on nodes that
+	 * return true; everything else reports ENOTTY.
+	 */
+	fn is_tty(&self) -> bool { false }
+
+	/*
+	 * fs_type - Filesystem type name, as listed in /proc/mounts
+	 *
+	 * Only consulted on the root node of a mount.
+	 */
+	fn fs_type(&self) -> &'static str { "none" }
+
 	fn readdir(&self) -> Option<Vec<(String, FileType)>> { None }
 }
 
@@ -143,6 +163,25 @@ pub fn umount(path: &str) -> Result<(), &'static str> {
 	}
 }
 
+/*
+ * mounts - Snapshot of the mount table
+ *
+ * Return: (mount point, filesystem root) pairs, shortest path first so
+ *         a mount is listed before anything mounted beneath it. Mount
+ *         points have no trailing slash except "/".
+ */
+pub fn mounts() -> Vec<(String, Arc<dyn INode>)> {
+	let table = MOUNT_TABLE.lock();
+	let mut list: Vec<(String, Arc<dyn INode>)> = table.iter()
+		.map(|e| {
+			let path = if e.path == "/" { "/" } else { e.path.trim_end_matches('/') };
+			(path.to_string(), Arc::clone(&e.root))
+		})
+		.collect();
+	list.reverse();
+	list
+}
+
 /*
  * set_root - Convenience wrapper: mount an inode at "/"
  *
@@ -152,16 +191,69 @@ pub fn set_root(root: Arc<dyn INode>) {
 	mount("/", root);
 }
 
+/*
+ * resolve_path - Turn a path into a canonical absolute path
+ * @cwd:  Absolute directory that relative paths start from
+ * @path: Absolute or relative path
+ *
+ * Collapses repeated slashes and resolves "." and ".." lexically; ".."
+ * at "/" stays at "/". The result starts with '/' and has no trailing
+ * slash except for "/" itself.
+ */
+pub fn resolve_path(cwd: &str, path: &str) -> String {
+	let mut parts: Vec<&str> = Vec::new();
+	let base = if path.starts_with('/') { "" } else { cwd };
+	for component in base.split('/').chain(path.split('/')) {
+		match component {
+			"" | "." => {}
+			".." => {
+				parts.pop();
+			}
+			c => parts.push(c),
+		}
+	}
+
+	let mut out = String::new();
+	for p in &parts {
+		out.push('/');
+		out.push_str(p);
+	}
+	if out.is_empty() {
+		out.push('/');
+	}
+	out
+}
+
+/*
+ * split_parent - Split a path into its parent directory and final name
+ * @path: Canonical absolute path, as returned by resolve_path()
+ *
+ * Return: ("/a/b", "c") for "/a/b/c", ("/", "c") for "/c", ("/", "") for "/"
+ */
+pub fn split_parent(path: &str) -> (&str, &str) {
+	match path.rfind('/') {
+		Some(0) | None => ("/", path.trim_start_matches('/')),
+		Some(pos) => (&path[..pos], &path[pos + 1..]),
+	}
+}
+
 /*
  * lookup_path - Resolve an absolute path to an INode
  * @path: Absolute path (must start with '/')
  *
- * Finds the longest matching mount prefix, uses that mount's root as
- * the starting INode, then traverses remaining path components.
+ * Canonicalises @path, finds the longest matching mount prefix, uses
+ * that mount's root as the starting INode, then traverses remaining
+ * path components.
  *
  * Return: Some(inode) if found, None if path or any component is absent.
  */
 pub fn lookup_path(path: &str) -> Option<Arc<dyn INode>> {
+	if !path.starts_with('/') {
+		return None;
+	}
+	let canonical = resolve_path("/", path);
+	let path = canonical.as_str();
+
 	let table = MOUNT_TABLE.lock();
 	if table.is_empty() {
 		return None;
@@ -267,6 +359,7 @@ impl INode for RamDir {
 	fn read(&self, _offset: usize, _buf: &mut [u8]) -> usize { 0 }
 	fn write(&self, _offset: usize, _buf: &[u8]) -> usize { 0 }
 	fn metadata(&self) -> FileType { FileType::Directory }
+	fn fs_type(&self) -> &'static str { "ramfs" }
 
 	fn lookup(&self, name: &str) -> Option<Arc<dyn INode>> {
 		self.children.lock()